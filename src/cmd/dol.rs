@@ -1,19 +1,22 @@
 use std::{
     borrow::Cow,
     cmp::min,
-    collections::{btree_map::Entry, hash_map, BTreeMap, HashMap},
+    collections::{btree_map::Entry, hash_map, BTreeMap, HashMap, HashSet},
     ffi::OsStr,
     fs,
     fs::DirBuilder,
     io::{Cursor, Write},
+    iter::once,
     mem::take,
     path::{Path, PathBuf},
     time::Instant,
 };
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use argp::FromArgs;
 use itertools::Itertools;
+use object::{Object, ObjectSection, ObjectSymbol, RelocationTarget};
+use path_slash::PathBufExt;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, info_span};
@@ -36,22 +39,46 @@ use crate::{
         ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, ObjSymbolScope, SymbolIndex,
     },
     util::{
+        align_up,
         asm::write_asm,
-        bin2c::bin2c,
+        bin2c::{bin2c, obj2c, Bin2COptions, DataType},
+        bmg::read_bmg,
         comment::MWComment,
         config::{
             apply_splits_file, apply_symbols_file, is_auto_symbol, write_splits_file,
             write_symbols_file,
         },
+        debug_info::{
+            apply_debug_splits, apply_debug_symbols, debug_data_layouts, read_debug_elf,
+            read_debug_elf_lines, DataLayouts,
+        },
         dep::DepFile,
-        dol::process_dol,
-        elf::{process_elf, write_elf},
-        file::{buf_reader, buf_writer, map_file, touch, verify_hash, FileIterator, FileReadInfo},
-        lcf::{asm_path_for_unit, generate_ldscript, obj_path_for_unit},
-        map::apply_map_file,
-        rel::{process_rel, process_rel_header, update_rel_section_alignment},
+        dol::{
+            process_dol, write_dol, DolFile, DolHeader, DolSectionKind, DolSectionWrite,
+            MAX_DATA_SECTIONS, MAX_TEXT_SECTIONS,
+        },
+        elf::{process_elf, to_obj_reloc_kind, write_elf},
+        file::{
+            buf_reader, buf_writer, map_file, process_rsp, touch, verify_hash, FileIterator,
+            FileReadInfo,
+        },
+        gecko::GeckoCodes,
+        interface::{interface_header, interface_obj, interface_symbols},
+        lcf::{
+            asm_path_for_unit, c_path_for_unit, generate_ldscript, generate_ldscript_gnu,
+            obj_path_for_unit,
+        },
+        map::{apply_map_file, write_map},
+        patch::{branch_instruction, link_patch, LinkedPatch},
+        reader::{Endian, FromReader},
+        rel::{
+            is_permitted_section, process_rel, process_rel_header, update_rel_section_alignment,
+            write_rel, RelReloc, RelWriteInfo,
+        },
         rso::{process_rso, DOL_SECTION_ABS, DOL_SECTION_ETI, DOL_SECTION_NAMES},
         split::{is_linker_generated_object, split_obj, update_splits},
+        symbol_map::{apply_symbol_map_file, write_symbol_map, SymbolMapFormat},
+        texture::{encode_png, read_bti, read_tpl},
         IntoCow, ToCow,
     },
 };
@@ -72,6 +99,11 @@ enum SubCommand {
     Diff(DiffArgs),
     Apply(ApplyArgs),
     Config(ConfigArgs),
+    Progress(ProgressArgs),
+    Import(ImportArgs),
+    Export(ExportArgs),
+    Patch(PatchArgs),
+    Gecko(GeckoArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -140,6 +172,96 @@ pub struct ConfigArgs {
     out_file: PathBuf,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Calculates decompilation progress from the split output.
+#[argp(subcommand, name = "progress")]
+pub struct ProgressArgs {
+    #[argp(positional)]
+    /// input configuration file
+    config: PathBuf,
+    #[argp(positional)]
+    /// split output directory (containing config.json)
+    out_dir: PathBuf,
+    #[argp(option, short = 'c')]
+    /// completed unit names (or @rspfile)
+    completed: Vec<PathBuf>,
+    #[argp(option, short = 'e')]
+    /// linked ELF (units not built from assembly are considered completed)
+    elf_file: Option<PathBuf>,
+    #[argp(option, short = 'o')]
+    /// output progress JSON file
+    out_file: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Imports symbols from a Dolphin, CSV or JSON symbol map into the project configuration.
+#[argp(subcommand, name = "import")]
+pub struct ImportArgs {
+    #[argp(positional)]
+    /// input configuration file
+    config: PathBuf,
+    #[argp(positional)]
+    /// symbol map file
+    map_file: PathBuf,
+    #[argp(option, short = 'f')]
+    /// symbol map format: dolphin, csv or json (default: guessed from extension)
+    format: Option<SymbolMapFormat>,
+    #[argp(switch)]
+    /// replace existing symbols at the same address
+    replace: bool,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Exports project symbols as a Dolphin, CSV or JSON symbol map.
+#[argp(subcommand, name = "export")]
+pub struct ExportArgs {
+    #[argp(positional)]
+    /// input configuration file
+    config: PathBuf,
+    #[argp(option, short = 'o')]
+    /// output symbol map file
+    out_file: PathBuf,
+    #[argp(option, short = 'f')]
+    /// symbol map format: dolphin, csv or json (default: guessed from extension)
+    format: Option<SymbolMapFormat>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Patches a DOL with hooks and code from relocatable objects.
+#[argp(subcommand, name = "patch")]
+pub struct PatchArgs {
+    #[argp(positional)]
+    /// input configuration file
+    config: PathBuf,
+    #[argp(positional)]
+    /// patch configuration file
+    patch_config: PathBuf,
+    #[argp(option, short = 'o')]
+    /// output DOL
+    out_file: PathBuf,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Generates Gecko or Action Replay codes from a patch configuration.
+#[argp(subcommand, name = "gecko")]
+pub struct GeckoArgs {
+    #[argp(positional)]
+    /// input configuration file
+    config: PathBuf,
+    #[argp(positional)]
+    /// patch configuration file
+    patch_config: PathBuf,
+    #[argp(option, short = 'o')]
+    /// output code list
+    out_file: PathBuf,
+    #[argp(option, short = 'n')]
+    /// code name (writes a `$name` line for Dolphin)
+    name: Option<String>,
+    #[argp(switch)]
+    /// write unencrypted Action Replay codes (insert hooks are unsupported)
+    action_replay: bool,
+}
+
 #[inline]
 fn bool_true() -> bool { true }
 
@@ -201,6 +323,10 @@ pub struct ProjectConfig {
     pub selfile: Option<PathBuf>,
     #[serde(skip_serializing_if = "is_default")]
     pub selfile_hash: Option<String>,
+    /// Unstripped debug ELF. Function and variable names, sizes, scopes and data kinds are
+    /// applied from its DWARF info, and compile units are used as initial splits.
+    #[serde(with = "path_slash_serde_option", default, skip_serializing_if = "is_default")]
+    pub debug_elf: Option<PathBuf>,
     /// Version of the MW `.comment` section format.
     /// If not present, no `.comment` sections will be written.
     #[serde(skip_serializing_if = "is_default")]
@@ -218,6 +344,9 @@ pub struct ProjectConfig {
     pub detect_strings: bool,
     #[serde(default = "bool_true", skip_serializing_if = "is_true")]
     pub write_asm: bool,
+    /// Writes C source for units that only contain data.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub write_data_c: bool,
     /// Specifies the start of the common BSS section.
     #[serde(skip_serializing_if = "is_default")]
     pub common_start: Option<u32>,
@@ -228,6 +357,16 @@ pub struct ProjectConfig {
     /// Fills gaps between symbols with
     #[serde(default = "bool_true", skip_serializing_if = "is_true")]
     pub fill_gaps: bool,
+    /// Writes a CodeWarrior-style link map for each module.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub write_map: bool,
+    /// Additionally writes a GNU ld linker script (`ldscript.ld`) for each module.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub write_gnu_ldscript: bool,
+    /// Writes an interface object and header for each REL, defining the symbols
+    /// referenced by other modules as weak stubs.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub write_module_interfaces: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -255,6 +394,10 @@ pub struct ModuleConfig {
     pub links: Option<Vec<String>>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub extract: Vec<ExtractConfig>,
+    /// Module path written to the REL string table by `rel make --str-file`.
+    /// If not specified, the object's file name will be used.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub str_name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -269,6 +412,99 @@ pub struct ExtractConfig {
     /// Path is relative to `out_dir/include`.
     #[serde(with = "path_slash_serde_option", default, skip_serializing_if = "Option::is_none")]
     pub header: Option<PathBuf>,
+    /// Layout of the symbol's data, used to write typed initializers in the header.
+    /// Either a scalar type (`u8`, `s16`, `u32`, `f32`, `string`, ...) or a structure.
+    /// Defaults to a byte array.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<DataType>,
+    /// Element type name used in the header, e.g. a structure defined elsewhere.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_type: Option<String>,
+    /// Overrides whether the array in the header is `const`.
+    #[serde(rename = "const", default, skip_serializing_if = "Option::is_none")]
+    pub is_const: Option<bool>,
+    /// Overrides whether the array in the header is `static`.
+    #[serde(rename = "static", default, skip_serializing_if = "Option::is_none")]
+    pub is_static: Option<bool>,
+    /// If specified, the symbol's data will be decoded as `format` and written to the given file.
+    /// Path is relative to `out_dir/bin`.
+    #[serde(with = "path_slash_serde_option", default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<PathBuf>,
+    /// Format of the embedded asset. Required when `asset` is specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<AssetFormat>,
+}
+
+/// Configuration for `dol patch`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PatchConfig {
+    /// Relocatable objects to link into the DOL.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub objects: Vec<PathBuf>,
+    /// Load address of the linked objects. Required if any objects are specified,
+    /// unless writing a REL.
+    /// Must not overlap the DOL, BSS or memory used at runtime (e.g. the arena).
+    #[serde(skip_serializing_if = "is_default")]
+    pub address: Option<u32>,
+    /// Writes the objects as a REL instead of appending them to the DOL.
+    /// Only supported by `dol patch`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub rel: Option<PatchRelConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<PatchHook>,
+}
+
+/// REL output for `dol patch`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PatchRelConfig {
+    /// Path to the output REL.
+    #[serde(with = "path_slash_serde")]
+    pub object: PathBuf,
+    /// Module ID. Must not be used by any other module.
+    pub module_id: u32,
+    /// REL version. Defaults to 3.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub version: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PatchHook {
+    /// Writes a branch to `target` at `address`.
+    Branch {
+        address: u32,
+        target: String,
+        /// Writes `bl` instead of `b`.
+        #[serde(default, skip_serializing_if = "is_default")]
+        link: bool,
+    },
+    /// Writes a branch to `target` at the start of `function`.
+    Replace { function: String, target: String },
+    /// Inserts the code of `symbol` at `address`, replacing the instruction there.
+    /// A trailing `blr` is removed. Only supported by `dol gecko` (C2 code).
+    Insert { address: u32, symbol: String },
+    /// Adds the contents of `file` as a new section at `address`.
+    Section {
+        address: u32,
+        #[serde(with = "path_slash_serde")]
+        file: PathBuf,
+        /// Adds a text section instead of a data section.
+        #[serde(default, skip_serializing_if = "is_default")]
+        text: bool,
+    },
+}
+
+/// Embedded asset formats supported by `extract`.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetFormat {
+    /// TPL texture file, written as PNG. Additional images are written with the
+    /// image index appended to the file name.
+    Tpl,
+    /// BTI texture, written as PNG.
+    Bti,
+    /// BMG message file, written as JSON.
+    Bmg,
 }
 
 impl ModuleConfig {
@@ -308,6 +544,14 @@ pub struct OutputModule {
     pub module_id: u32,
     #[serde(with = "path_slash_serde")]
     pub ldscript: PathBuf,
+    #[serde(with = "path_slash_serde_option", default, skip_serializing_if = "is_default")]
+    pub gnu_ldscript: Option<PathBuf>,
+    /// Weak stubs for symbols referenced by other modules.
+    #[serde(with = "path_slash_serde_option", default, skip_serializing_if = "is_default")]
+    pub interface: Option<PathBuf>,
+    /// Declarations for symbols referenced by other modules.
+    #[serde(with = "path_slash_serde_option", default, skip_serializing_if = "is_default")]
+    pub interface_header: Option<PathBuf>,
     pub entry: Option<String>,
     pub units: Vec<OutputUnit>,
 }
@@ -333,6 +577,11 @@ pub fn run(args: Args) -> Result<()> {
         SubCommand::Diff(c_args) => diff(c_args),
         SubCommand::Apply(c_args) => apply(c_args),
         SubCommand::Config(c_args) => config(c_args),
+        SubCommand::Progress(c_args) => progress(c_args),
+        SubCommand::Import(c_args) => import(c_args),
+        SubCommand::Export(c_args) => export(c_args),
+        SubCommand::Patch(c_args) => patch(c_args),
+        SubCommand::Gecko(c_args) => gecko(c_args),
     }
 }
 
@@ -488,6 +737,7 @@ struct ModuleInfo<'a> {
     config: &'a ModuleConfig,
     symbols_cache: Option<FileReadInfo>,
     splits_cache: Option<FileReadInfo>,
+    data_layouts: Option<DataLayouts>,
 }
 
 type ModuleMapByName<'a> = BTreeMap<String, ModuleInfo<'a>>;
@@ -721,6 +971,7 @@ struct AnalyzeResult {
     dep: Vec<PathBuf>,
     symbols_cache: Option<FileReadInfo>,
     splits_cache: Option<FileReadInfo>,
+    data_layouts: Option<DataLayouts>,
 }
 
 fn load_analyze_dol(config: &ProjectConfig) -> Result<AnalyzeResult> {
@@ -743,6 +994,16 @@ fn load_analyze_dol(config: &ProjectConfig) -> Result<AnalyzeResult> {
         dep.push(map_path.clone());
     }
 
+    let debug_tags = if let Some(debug_elf) = &config.debug_elf {
+        log::info!("Loading {}", debug_elf.display());
+        let tags = read_debug_elf(debug_elf)?;
+        apply_debug_symbols(&mut obj, &tags)?;
+        dep.push(debug_elf.clone());
+        Some(tags)
+    } else {
+        None
+    };
+
     let splits_cache = if let Some(splits_path) = &config.base.splits {
         dep.push(splits_path.clone());
         apply_splits_file(splits_path, &mut obj)?
@@ -750,6 +1011,11 @@ fn load_analyze_dol(config: &ProjectConfig) -> Result<AnalyzeResult> {
         None
     };
 
+    // Applied after the splits file, so that existing splits take precedence
+    if let Some(tags) = &debug_tags {
+        apply_debug_splits(&mut obj, tags)?;
+    }
+
     let symbols_cache = if let Some(symbols_path) = &config.base.symbols {
         dep.push(symbols_path.clone());
         apply_symbols_file(symbols_path, &mut obj)?
@@ -787,7 +1053,8 @@ fn load_analyze_dol(config: &ProjectConfig) -> Result<AnalyzeResult> {
     // Create _ctors and _dtors symbols if missing
     update_ctors_dtors(&mut obj)?;
 
-    Ok(AnalyzeResult { obj, dep, symbols_cache, splits_cache })
+    let data_layouts = debug_tags.as_ref().map(debug_data_layouts).transpose()?;
+    Ok(AnalyzeResult { obj, dep, symbols_cache, splits_cache, data_layouts })
 }
 
 fn split_write_obj(
@@ -796,6 +1063,7 @@ fn split_write_obj(
     base_dir: &Path,
     out_dir: &Path,
     no_update: bool,
+    interface: Option<&[SymbolIndex]>,
 ) -> Result<OutputModule> {
     debug!("Performing relocation analysis");
     let mut tracker = Tracker::new(&module.obj);
@@ -855,6 +1123,9 @@ fn split_write_obj(
         name: module.config.name().to_string(),
         module_id,
         ldscript: out_dir.join("ldscript.lcf"),
+        gnu_ldscript: config.write_gnu_ldscript.then(|| out_dir.join("ldscript.ld")),
+        interface: None,
+        interface_header: None,
         units: Vec::with_capacity(split_objs.len()),
         entry,
     };
@@ -896,13 +1167,49 @@ fn split_write_obj(
         }
 
         if let Some(header) = &extract.header {
-            let header_string = bin2c(symbol, section, data);
+            let header_string = bin2c(symbol, section, data, &Bin2COptions {
+                data_type: extract.data_type.as_ref(),
+                type_name: extract.header_type.as_deref(),
+                is_const: extract.is_const,
+                is_static: extract.is_static,
+            })?;
             let out_path = base_dir.join("include").join(header);
             if let Some(parent) = out_path.parent() {
                 DirBuilder::new().recursive(true).create(parent)?;
             }
             write_if_changed(&out_path, header_string.as_bytes())?;
         }
+
+        if let Some(asset) = &extract.asset {
+            let format = extract
+                .format
+                .with_context(|| format!("Missing asset format for symbol '{}'", extract.symbol))?;
+            let out_path = base_dir.join("bin").join(asset);
+            if let Some(parent) = out_path.parent() {
+                DirBuilder::new().recursive(true).create(parent)?;
+            }
+            write_asset(&out_path, format, data).with_context(|| {
+                format!("Failed to extract {:?} asset from symbol '{}'", format, extract.symbol)
+            })?;
+        }
+    }
+
+    // Write module interface
+    if let Some(symbols) = interface.filter(|s| !s.is_empty()) {
+        debug!("Writing module interface ({} symbols)", symbols.len());
+        let interface_obj = interface_obj(&module.obj, symbols)?;
+        let out_path = out_dir.join("interface.o");
+        write_if_changed(&out_path, &write_elf(&interface_obj)?)?;
+        out_config.interface = Some(out_path);
+
+        let header_string = interface_header(&module.obj, symbols)?;
+        let out_path =
+            base_dir.join("include").join("modules").join(format!("{}.h", module.config.name()));
+        if let Some(parent) = out_path.parent() {
+            DirBuilder::new().recursive(true).create(parent)?;
+        }
+        write_if_changed(&out_path, header_string.as_bytes())?;
+        out_config.interface_header = Some(out_path);
     }
 
     // Generate ldscript.lcf
@@ -916,6 +1223,17 @@ fn split_write_obj(
     let ldscript_string =
         generate_ldscript(&module.obj, ldscript_template.as_deref(), &module.config.force_active)?;
     write_if_changed(&out_config.ldscript, ldscript_string.as_bytes())?;
+    if let Some(gnu_ldscript) = &out_config.gnu_ldscript {
+        let ldscript_string = generate_ldscript_gnu(&module.obj, &module.config.force_active)?;
+        write_if_changed(gnu_ldscript, ldscript_string.as_bytes())?;
+    }
+
+    if config.write_map {
+        debug!("Writing link map");
+        let mut map = Vec::new();
+        write_map(&mut map, &module.obj)?;
+        write_if_changed(&out_dir.join(format!("{}.MAP", module.config.name())), &map)?;
+    }
 
     if config.write_asm {
         debug!("Writing disassembly");
@@ -924,14 +1242,58 @@ fn split_write_obj(
             let out_path = asm_dir.join(asm_path_for_unit(&unit.name));
 
             let mut w = buf_writer(&out_path)?;
-            write_asm(&mut w, split_obj)
+            write_asm(&mut w, split_obj, module.data_layouts.as_ref())
                 .with_context(|| format!("Failed to write {}", out_path.display()))?;
             w.flush()?;
         }
     }
+
+    if config.write_data_c {
+        debug!("Writing data sources");
+        let src_dir = out_dir.join("src");
+        for (unit, split_obj) in module.obj.link_order.iter().zip(&split_objs) {
+            if split_obj.sections.iter().any(|(_, s)| s.kind == ObjSectionKind::Code && s.size > 0)
+            {
+                continue;
+            }
+            let out_path = src_dir.join(c_path_for_unit(&unit.name));
+            let source = obj2c(split_obj)
+                .with_context(|| format!("Failed to write {}", out_path.display()))?;
+            if let Some(parent) = out_path.parent() {
+                DirBuilder::new().recursive(true).create(parent)?;
+            }
+            write_if_changed(&out_path, source.as_bytes())?;
+        }
+    }
     Ok(out_config)
 }
 
+fn write_asset(out_path: &Path, format: AssetFormat, data: &[u8]) -> Result<()> {
+    match format {
+        AssetFormat::Tpl => {
+            for (i, image) in read_tpl(data)?.iter().enumerate() {
+                let path = if i == 0 {
+                    out_path.to_path_buf()
+                } else {
+                    let stem = out_path.file_stem().unwrap_or_default().to_string_lossy();
+                    let name = match out_path.extension() {
+                        Some(ext) => format!("{}_{}.{}", stem, i, ext.to_string_lossy()),
+                        None => format!("{}_{}", stem, i),
+                    };
+                    out_path.with_file_name(name)
+                };
+                write_if_changed(&path, &encode_png(image)?)?;
+            }
+        }
+        AssetFormat::Bti => write_if_changed(out_path, &encode_png(&read_bti(data)?)?)?,
+        AssetFormat::Bmg => {
+            let bmg = read_bmg(data)?;
+            write_if_changed(out_path, serde_json::to_string_pretty(&bmg)?.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
 fn write_if_changed(path: &Path, contents: &[u8]) -> Result<()> {
     if path.is_file() {
         let old_file = map_file(path)?;
@@ -1000,7 +1362,7 @@ fn load_analyze_rel(config: &ProjectConfig, module_config: &ModuleConfig) -> Res
     // Determine REL section alignment
     update_rel_section_alignment(&mut module_obj, &header)?;
 
-    Ok(AnalyzeResult { obj: module_obj, dep, symbols_cache, splits_cache })
+    Ok(AnalyzeResult { obj: module_obj, dep, symbols_cache, splits_cache, data_layouts: None })
 }
 
 fn split(args: SplitArgs) -> Result<()> {
@@ -1026,6 +1388,7 @@ fn split(args: SplitArgs) -> Result<()> {
 
     let out_config_path = args.out_dir.join("config.json");
     let mut dep = DepFile::new(out_config_path.clone());
+    dep.push(args.config.clone());
 
     let module_count = config.modules.len() + 1;
     let num_threads = min(rayon::current_num_threads(), module_count);
@@ -1073,6 +1436,7 @@ fn split(args: SplitArgs) -> Result<()> {
             config: &config.base,
             symbols_cache: result.symbols_cache,
             splits_cache: result.splits_cache,
+            data_layouts: result.data_layouts,
         }
     };
     let mut function_count = dol.obj.symbols.by_kind(ObjSymbolKind::Function).count();
@@ -1087,6 +1451,7 @@ fn split(args: SplitArgs) -> Result<()> {
                 config: &config.modules[idx],
                 symbols_cache: result.symbols_cache,
                 splits_cache: result.splits_cache,
+                data_layouts: result.data_layouts,
             }),
             Entry::Occupied(_) => bail!("Duplicate module name {}", result.obj.name),
         };
@@ -1138,6 +1503,7 @@ fn split(args: SplitArgs) -> Result<()> {
         Ok(map)
     }
 
+    let mut interfaces = BTreeMap::<String, Vec<SymbolIndex>>::new();
     if !modules.is_empty() {
         let module_names = modules.keys().cloned().collect_vec();
 
@@ -1150,6 +1516,14 @@ fn split(args: SplitArgs) -> Result<()> {
             modules.insert(module_name.clone(), module);
         }
 
+        // Collect symbols referenced from other modules for module interfaces
+        if config.write_module_interfaces {
+            let objs = once(&dol.obj).chain(modules.values().map(|m| &m.obj)).collect_vec();
+            for (module_name, module) in &modules {
+                interfaces.insert(module_name.clone(), interface_symbols(&module.obj, &objs)?);
+            }
+        }
+
         // Create relocations to symbols in other modules
         for module_name in &module_names {
             let mut module = modules.remove(module_name).unwrap();
@@ -1184,14 +1558,21 @@ fn split(args: SplitArgs) -> Result<()> {
             let _span =
                 info_span!("module", name = %config.base.name(), id = dol.obj.module_id).entered();
             dol_result = Some(
-                split_write_obj(&mut dol, &config, &args.out_dir, &args.out_dir, args.no_update)
-                    .with_context(|| {
-                        format!(
-                            "While processing object '{}' (module ID {})",
-                            config.base.file_name(),
-                            dol.obj.module_id
-                        )
-                    }),
+                split_write_obj(
+                    &mut dol,
+                    &config,
+                    &args.out_dir,
+                    &args.out_dir,
+                    args.no_update,
+                    None,
+                )
+                .with_context(|| {
+                    format!(
+                        "While processing object '{}' (module ID {})",
+                        config.base.file_name(),
+                        dol.obj.module_id
+                    )
+                }),
             );
         });
         // Modules
@@ -1204,7 +1585,8 @@ fn split(args: SplitArgs) -> Result<()> {
                             info_span!("module", name = %module.config.name(), id = module.obj.module_id)
                                 .entered();
                         let out_dir = args.out_dir.join(module.config.name().as_ref());
-                        split_write_obj(module, &config, &args.out_dir, &out_dir, args.no_update).with_context(
+                        let interface = interfaces.get(module_name).map(Vec::as_slice);
+                        split_write_obj(module, &config, &args.out_dir, &out_dir, args.no_update, interface).with_context(
                             || {
                                 format!(
                                     "While processing object '{}' (module {} ID {})",
@@ -1572,6 +1954,19 @@ fn diff(args: DiffArgs) -> Result<()> {
             );
             log::error!("Original: {}", hex::encode_upper(orig_data));
             log::error!("Linked:   {}", hex::encode_upper(linked_data));
+            if let Some(offset) = orig_data.iter().zip(linked_data).position(|(a, b)| a != b) {
+                let address = linked_sym.address as u32 + offset as u32;
+                let lines = read_debug_elf_lines(&args.elf_file).unwrap_or_else(|e| {
+                    log::warn!("Failed to read line information: {:?}", e);
+                    None
+                });
+                match lines.as_ref().and_then(|lines| lines.lookup(address)) {
+                    Some(location) => {
+                        log::error!("First difference at {:#010X} ({})", address, location)
+                    }
+                    None => log::error!("First difference at {:#010X}", address),
+                }
+            }
             return Ok(());
         }
     }
@@ -1738,6 +2133,63 @@ fn apply(args: ApplyArgs) -> Result<()> {
     Ok(())
 }
 
+fn import(args: ImportArgs) -> Result<()> {
+    log::info!("Loading {}", args.config.display());
+    let mut config_file = buf_reader(&args.config)?;
+    let config: ProjectConfig = serde_yaml::from_reader(&mut config_file)?;
+
+    log::info!("Loading {}", config.base.object.display());
+    let mut obj = {
+        let file = map_file(&config.base.object)?;
+        if let Some(hash_str) = &config.base.hash {
+            verify_hash(file.as_slice(), hash_str)?;
+        }
+        process_dol(file.as_slice(), config.base.name().as_ref())?
+    };
+
+    let Some(symbols_path) = &config.base.symbols else {
+        bail!("No symbols file specified in config");
+    };
+    let symbols_cache = apply_symbols_file(symbols_path, &mut obj)?;
+
+    log::info!("Loading {}", args.map_file.display());
+    let count = apply_symbol_map_file(&args.map_file, &mut obj, args.format, args.replace)?;
+    log::info!("Imported {} symbols", count);
+
+    write_symbols_file(symbols_path, &obj, symbols_cache)?;
+    Ok(())
+}
+
+fn export(args: ExportArgs) -> Result<()> {
+    let Some(format) = args.format.or_else(|| SymbolMapFormat::from_path(&args.out_file)) else {
+        bail!("Unknown symbol map format for '{}'", args.out_file.display());
+    };
+
+    log::info!("Loading {}", args.config.display());
+    let mut config_file = buf_reader(&args.config)?;
+    let config: ProjectConfig = serde_yaml::from_reader(&mut config_file)?;
+
+    log::info!("Loading {}", config.base.object.display());
+    let mut obj = {
+        let file = map_file(&config.base.object)?;
+        if let Some(hash_str) = &config.base.hash {
+            verify_hash(file.as_slice(), hash_str)?;
+        }
+        process_dol(file.as_slice(), config.base.name().as_ref())?
+    };
+    if let Some(splits_path) = &config.base.splits {
+        apply_splits_file(splits_path, &mut obj)?;
+    }
+    if let Some(symbols_path) = &config.base.symbols {
+        apply_symbols_file(symbols_path, &mut obj)?;
+    }
+
+    let mut out = buf_writer(&args.out_file)?;
+    write_symbol_map(&mut out, &obj, format)?;
+    out.flush()?;
+    Ok(())
+}
+
 fn config(args: ConfigArgs) -> Result<()> {
     let mut config = ProjectConfig {
         base: ModuleConfig {
@@ -1751,18 +2203,24 @@ fn config(args: ConfigArgs) -> Result<()> {
             ldscript_template: None,
             links: None,
             extract: vec![],
+            str_name: None,
         },
         selfile: None,
         selfile_hash: None,
+        debug_elf: None,
         mw_comment_version: None,
         quick_analysis: false,
         modules: vec![],
         detect_objects: true,
         detect_strings: true,
         write_asm: true,
+        write_data_c: false,
         common_start: None,
         symbols_known: false,
         fill_gaps: true,
+        write_map: false,
+        write_gnu_ldscript: false,
+        write_module_interfaces: false,
     };
 
     let mut modules = Vec::<(u32, ModuleConfig)>::new();
@@ -1788,6 +2246,7 @@ fn config(args: ConfigArgs) -> Result<()> {
                     ldscript_template: None,
                     links: None,
                     extract: vec![],
+                    str_name: None,
                 }));
             }
             Some(ext) if ext.eq_ignore_ascii_case(OsStr::new("sel")) => {
@@ -1806,6 +2265,7 @@ fn config(args: ConfigArgs) -> Result<()> {
                     ldscript_template: None,
                     links: None,
                     extract: vec![],
+                    str_name: None,
                 });
             }
             _ => bail!("Unknown file extension: '{}'", path.display()),
@@ -1822,3 +2282,631 @@ fn config(args: ConfigArgs) -> Result<()> {
     out.flush()?;
     Ok(())
 }
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+struct ProgressMeasures {
+    code: u64,
+    #[serde(rename = "code/total")]
+    code_total: u64,
+    data: u64,
+    #[serde(rename = "data/total")]
+    data_total: u64,
+    functions: u64,
+    #[serde(rename = "functions/total")]
+    functions_total: u64,
+}
+
+impl ProgressMeasures {
+    fn add(&mut self, other: &ProgressMeasures) {
+        self.code += other.code;
+        self.code_total += other.code_total;
+        self.data += other.data;
+        self.data_total += other.data_total;
+        self.functions += other.functions;
+        self.functions_total += other.functions_total;
+    }
+}
+
+#[inline]
+fn percent(value: u64, total: u64) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        value as f64 / total as f64 * 100.0
+    }
+}
+
+/// Whether a file symbol path from a linked ELF refers to the given unit.
+/// File symbols may be absolute, so the unit path must match whole trailing components.
+fn is_unit_path(file_path: &str, unit_name: &str) -> bool {
+    file_path == unit_name
+        || file_path.strip_suffix(unit_name).is_some_and(|prefix| prefix.ends_with('/'))
+}
+
+/// Calculates the measures for each section of a split object.
+fn unit_progress(unit: &OutputUnit, completed: bool) -> Result<BTreeMap<String, ProgressMeasures>> {
+    let obj = process_elf(&unit.object)
+        .with_context(|| format!("Failed to load object '{}'", unit.object.display()))?;
+    let mut sections = BTreeMap::<String, ProgressMeasures>::new();
+    for (section_index, section) in obj.sections.iter() {
+        let measures = sections.entry(section.name.clone()).or_default();
+        if section.kind == ObjSectionKind::Code {
+            measures.code_total += section.size;
+            measures.functions_total += obj
+                .symbols
+                .for_section(section_index)
+                .filter(|(_, s)| s.kind == ObjSymbolKind::Function && s.size > 0)
+                .count() as u64;
+        } else {
+            measures.data_total += section.size;
+        }
+    }
+    let common_size: u64 = obj.symbols.iter().filter(|s| s.flags.is_common()).map(|s| s.size).sum();
+    if common_size > 0 {
+        sections.entry(".bss".to_string()).or_default().data_total += common_size;
+    }
+    if completed {
+        for measures in sections.values_mut() {
+            measures.code = measures.code_total;
+            measures.data = measures.data_total;
+            measures.functions = measures.functions_total;
+        }
+    }
+    Ok(sections)
+}
+
+fn print_progress(name: &str, measures: &ProgressMeasures, indent: usize) {
+    println!(
+        "{:indent$}{}: code {:.2}% ({} / {} bytes), data {:.2}% ({} / {} bytes), \
+        functions {:.2}% ({} / {})",
+        "",
+        name,
+        percent(measures.code, measures.code_total),
+        measures.code,
+        measures.code_total,
+        percent(measures.data, measures.data_total),
+        measures.data,
+        measures.data_total,
+        percent(measures.functions, measures.functions_total),
+        measures.functions,
+        measures.functions_total,
+    );
+}
+
+fn progress(args: ProgressArgs) -> Result<()> {
+    log::info!("Loading {}", args.config.display());
+    let config: ProjectConfig = {
+        let mut config_file = buf_reader(&args.config)?;
+        serde_yaml::from_reader(&mut config_file)?
+    };
+    let out_config_path = args.out_dir.join("config.json");
+    let out_config: OutputConfig = {
+        let mut config_file = buf_reader(&out_config_path)?;
+        serde_json::from_reader(&mut config_file)
+            .with_context(|| format!("Failed to parse '{}'", out_config_path.display()))?
+    };
+
+    // Ensure the split output matches the project configuration
+    let mut modules = vec![&out_config.base];
+    for module_config in &config.modules {
+        let name = module_config.name();
+        let module = out_config.modules.iter().find(|m| m.name == name).ok_or_else(|| {
+            anyhow!(
+                "Module '{}' missing from '{}', is the split output up to date?",
+                name,
+                out_config_path.display()
+            )
+        })?;
+        modules.push(module);
+    }
+
+    let mut completed = HashSet::<String>::new();
+    for path in process_rsp(&args.completed)? {
+        completed.insert(path.to_slash_lossy().into_owned());
+    }
+    // Units linked from source (without the " (asm)" file symbol suffix) are considered completed
+    let mut completed_files = Vec::<String>::new();
+    if let Some(elf_file) = &args.elf_file {
+        log::info!("Loading {}", elf_file.display());
+        let obj = process_elf(elf_file)?;
+        for unit in &obj.link_order {
+            if !unit.name.ends_with(" (asm)") {
+                completed_files.push(unit.name.replace('\\', "/"));
+            }
+        }
+    }
+
+    let mut all = ProgressMeasures::default();
+    let mut categories = BTreeMap::<String, ProgressMeasures>::new();
+    let mut module_results = Vec::with_capacity(modules.len());
+    let mut matched_units = HashSet::<String>::new();
+    for module in modules {
+        let unit_results = module
+            .units
+            .par_iter()
+            .map(|unit| {
+                let is_completed = completed.contains(&unit.name)
+                    || (!unit.autogenerated
+                        && completed_files.iter().any(|path| is_unit_path(path, &unit.name)));
+                unit_progress(unit, is_completed).map(|sections| (unit, is_completed, sections))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut module_measures = ProgressMeasures::default();
+        let mut module_categories = BTreeMap::<String, ProgressMeasures>::new();
+        let mut module_sections = BTreeMap::<String, ProgressMeasures>::new();
+        for (unit, is_completed, sections) in unit_results {
+            if is_completed {
+                matched_units.insert(unit.name.clone());
+            }
+            let mut unit_measures = ProgressMeasures::default();
+            for (section_name, measures) in sections {
+                unit_measures.add(&measures);
+                module_sections.entry(section_name).or_default().add(&measures);
+            }
+            module_measures.add(&unit_measures);
+            // Categorize by top-level directory. Autogenerated units have none.
+            if let Some((category, _)) = unit.name.split_once('/') {
+                module_categories.entry(category.to_string()).or_default().add(&unit_measures);
+            }
+        }
+
+        all.add(&module_measures);
+        categories.insert(module.name.clone(), module_measures);
+        for (category, measures) in &module_categories {
+            categories.insert(format!("{}/{}", module.name, category), *measures);
+        }
+        for (section_name, measures) in &module_sections {
+            categories.insert(format!("{}/{}", module.name, section_name), *measures);
+        }
+        module_results.push((module.name.as_str(), module_measures, module_categories));
+    }
+    for name in &completed {
+        if !matched_units.contains(name) {
+            log::warn!("Completed unit '{}' not found in split output", name);
+        }
+    }
+
+    print_progress("All", &all, 0);
+    for (name, measures, module_categories) in &module_results {
+        print_progress(name, measures, 2);
+        for (category, measures) in module_categories {
+            print_progress(category, measures, 4);
+        }
+    }
+
+    if let Some(out_file) = &args.out_file {
+        let mut progress_json = BTreeMap::<String, ProgressMeasures>::new();
+        progress_json.insert("all".to_string(), all);
+        progress_json.extend(categories);
+        let mut out = buf_writer(out_file)?;
+        serde_json::to_writer_pretty(&mut out, &progress_json)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+struct PatchInput {
+    /// Original DOL data.
+    data: Vec<u8>,
+    /// DOL with project symbols applied.
+    obj: ObjInfo,
+    patch_config: PatchConfig,
+    objects: Vec<ObjInfo>,
+}
+
+fn load_patch_input(config_path: &Path, patch_config_path: &Path) -> Result<PatchInput> {
+    log::info!("Loading {}", config_path.display());
+    let mut config_file = buf_reader(config_path)?;
+    let config: ProjectConfig = serde_yaml::from_reader(&mut config_file)?;
+    log::info!("Loading {}", patch_config_path.display());
+    let mut patch_file = buf_reader(patch_config_path)?;
+    let patch_config: PatchConfig = serde_yaml::from_reader(&mut patch_file)?;
+
+    log::info!("Loading {}", config.base.object.display());
+    let data = fs::read(&config.base.object)
+        .with_context(|| format!("Failed to read '{}'", config.base.object.display()))?;
+    if let Some(hash_str) = &config.base.hash {
+        verify_hash(&data, hash_str)?;
+    }
+    let mut obj = process_dol(&data, config.base.name().as_ref())?;
+    if let Some(symbols_path) = &config.base.symbols {
+        apply_symbols_file(symbols_path, &mut obj)?;
+    }
+
+    let objects = patch_config
+        .objects
+        .iter()
+        .map(|path| {
+            log::info!("Loading {}", path.display());
+            process_elf(path)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(PatchInput { data, obj, patch_config, objects })
+}
+
+/// Resolves a branch or replace hook, returning the hook address and branch instruction.
+fn hook_branch(obj: &ObjInfo, linked: &LinkedPatch, hook: &PatchHook) -> Result<(u32, u32)> {
+    let (address, target, link) = match hook {
+        PatchHook::Branch { address, target, link } => (*address, target, *link),
+        PatchHook::Replace { function, target } => {
+            let (_, symbol) = obj
+                .symbols
+                .by_name(function)?
+                .with_context(|| format!("Failed to locate function '{}'", function))?;
+            (symbol.address as u32, target, false)
+        }
+        PatchHook::Insert { .. } => bail!("Unexpected insert hook"),
+        PatchHook::Section { .. } => bail!("Unexpected section hook"),
+    };
+    let target_address = linked.resolve(obj, target)?;
+    log::info!("Hooking {:#010X} -> {} ({:#010X})", address, target, target_address);
+    Ok((address, branch_instruction(address, target_address, link)?))
+}
+
+/// Writes the patch object as a REL, resolving undefined symbols against the DOL.
+fn write_patch_rel(obj: &ObjInfo, paths: &[PathBuf], config: &PatchRelConfig) -> Result<()> {
+    ensure!(config.module_id != 0, "Module ID 0 is reserved for the DOL");
+    let [path] = paths else {
+        bail!("REL output requires a single object (link the objects with `-r` first)");
+    };
+    let file = map_file(path)?;
+    let module = object::read::File::parse(file.as_slice())?;
+    let mut relocations = vec![];
+    for section in module.sections().filter(is_permitted_section) {
+        let section_index = section.index().0 as u8;
+        for (address, reloc) in section.relocations() {
+            let symbol = match reloc.target() {
+                RelocationTarget::Symbol(idx) => module.symbol_by_index(idx)?,
+                target => bail!("Unsupported relocation target: {target:?}"),
+            };
+            let (module_id, target_section, target_address) = if symbol.is_undefined() {
+                let name = symbol.name()?;
+                let (_, target) = obj
+                    .symbols
+                    .by_name(name)?
+                    .with_context(|| format!("Failed to resolve symbol '{}' in the DOL", name))?;
+                (0, 0, target.address as i64)
+            } else if let Some(target_section) = symbol.section_index() {
+                (config.module_id, target_section.0 as u8, symbol.address() as i64)
+            } else {
+                bail!("Unsupported relocation to symbol '{}'", symbol.name().unwrap_or("[invalid]"))
+            };
+            relocations.push(RelReloc {
+                kind: to_obj_reloc_kind(reloc.kind())?,
+                section: section_index,
+                address: address as u32,
+                module_id,
+                target_section,
+                addend: (target_address + reloc.addend()) as u32,
+                original_section: section_index,
+                original_target_section: target_section,
+            });
+        }
+    }
+
+    let info = RelWriteInfo {
+        module_id: config.module_id,
+        version: config.version.unwrap_or(3),
+        name_offset: None,
+        name_size: None,
+        align: None,
+        bss_align: None,
+        section_count: None,
+        quiet: false,
+        section_align: None,
+        section_map: None,
+    };
+    log::info!("Writing {}", config.object.display());
+    let mut w = buf_writer(&config.object)?;
+    write_rel(&mut w, &info, &module, relocations)
+        .with_context(|| format!("Failed to write '{}'", config.object.display()))?;
+    w.flush()?;
+    Ok(())
+}
+
+fn patch(args: PatchArgs) -> Result<()> {
+    let PatchInput { mut data, obj, patch_config, objects } =
+        load_patch_input(&args.config, &args.patch_config)?;
+    let mut dol = DolFile::from_reader(&mut Cursor::new(&data), Endian::Big)?;
+
+    // Link objects, or write them as a REL
+    let linked = if let Some(rel_config) = &patch_config.rel {
+        ensure!(patch_config.address.is_none(), "REL output doesn't take an address");
+        write_patch_rel(&obj, &patch_config.objects, rel_config)?;
+        // Hooks can only target the DOL, since the REL is loaded at runtime
+        link_patch(&[], &obj, 0)?
+    } else {
+        ensure!(
+            objects.is_empty() || patch_config.address.is_some(),
+            "Patch objects require an address"
+        );
+        link_patch(&objects, &obj, patch_config.address.unwrap_or_default())?
+    };
+
+    // Apply hooks
+    let mut section_files = vec![];
+    for hook in &patch_config.hooks {
+        let target = match hook {
+            PatchHook::Insert { address, .. } => {
+                bail!("Insert hook at {:#010X} is only supported by `dol gecko`", address)
+            }
+            PatchHook::Section { address, file, text } => {
+                let kind = if *text { DolSectionKind::Text } else { DolSectionKind::Data };
+                let file_data = fs::read(file)
+                    .with_context(|| format!("Failed to read '{}'", file.display()))?;
+                section_files.push((file.display().to_string(), kind, *address, file_data));
+                continue;
+            }
+            PatchHook::Branch { target, .. } | PatchHook::Replace { target, .. } => target,
+        };
+        if patch_config.rel.is_some()
+            && objects
+                .iter()
+                .flat_map(|o| o.symbols.iter())
+                .any(|s| s.name == *target && s.section.is_some() && s.flags.is_global())
+        {
+            bail!("Hook target '{}' is in the REL, which is only loaded at runtime", target);
+        }
+        let (address, ins) = hook_branch(&obj, &linked, hook)?;
+        let section = dol
+            .sections
+            .iter()
+            .find(|s| {
+                s.kind != DolSectionKind::Bss
+                    && address >= s.address
+                    && address + 4 <= s.address + s.size
+            })
+            .with_context(|| format!("Hook address {:#010X} is not in a DOL section", address))?;
+        let offset = (section.file_offset + address - section.address) as usize;
+        data[offset..offset + 4].copy_from_slice(&ins.to_be_bytes());
+    }
+
+    // Append linked objects and section hooks as new sections
+    let mut sections = patch_sections(&linked, &dol.header)?;
+    for (name, kind, address, file_data) in &section_files {
+        sections.push(DolSectionWrite {
+            name,
+            kind: *kind,
+            address: *address,
+            data: file_data,
+            offset: None,
+            size: None,
+        });
+    }
+
+    let bss_end = dol.header.bss_addr + dol.header.bss_size;
+    for (index, section) in sections.iter().enumerate() {
+        let start = section.address;
+        let end = start + align_up(section.data.len() as u32, 32);
+        let existing = dol.sections.iter().map(|s| (s.address, s.address + s.size));
+        let added = sections[..index]
+            .iter()
+            .map(|s| (s.address, s.address + align_up(s.data.len() as u32, 32)));
+        if let Some((other_start, other_end)) =
+            existing.chain(added).find(|&(s, e)| start < e && end > s)
+        {
+            bail!(
+                "Section '{}' {:#010X}-{:#010X} overlaps section {:#010X}-{:#010X}",
+                section.name,
+                start,
+                end,
+                other_start,
+                other_end
+            );
+        }
+        if dol.header.bss_size != 0 && start >= bss_end {
+            log::warn!(
+                "Section '{}' at {:#010X} is past the end of BSS ({:#010X}), where the arena \
+                 starts; make sure the game reserves this memory",
+                section.name,
+                start,
+                bss_end
+            );
+        }
+        log::info!(
+            "Adding {:?} section '{}' at {:#010X} ({:#X} bytes)",
+            section.kind,
+            section.name,
+            start,
+            end - start
+        );
+    }
+    write_dol(&mut data, &mut dol.header, &sections)?;
+
+    log::info!("Writing {}", args.out_file.display());
+    let mut w = buf_writer(&args.out_file)?;
+    w.write_all(&data)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Places the linked patch in new DOL sections. Code and data get a section each when both
+/// kinds of slot are free, otherwise everything goes in a single section.
+fn patch_sections<'a>(
+    linked: &'a LinkedPatch,
+    header: &DolHeader,
+) -> Result<Vec<DolSectionWrite<'a>>> {
+    if linked.data.is_empty() {
+        return Ok(vec![]);
+    }
+    let start = linked.address;
+    let text_free = header.free_slot(DolSectionKind::Text).is_some();
+    let data_free = header.free_slot(DolSectionKind::Data).is_some();
+    let (text, rest) = linked.data.split_at(linked.text_size as usize);
+    let section = |kind, address, data| DolSectionWrite {
+        name: "patch",
+        kind,
+        address,
+        data,
+        offset: None,
+        size: None,
+    };
+    if text_free && data_free && !text.is_empty() && !rest.is_empty() {
+        Ok(vec![
+            section(DolSectionKind::Text, start, text),
+            section(DolSectionKind::Data, start + linked.text_size, rest),
+        ])
+    } else if text_free && (!text.is_empty() || !data_free) {
+        Ok(vec![section(DolSectionKind::Text, start, &linked.data)])
+    } else if data_free {
+        if !text.is_empty() {
+            log::warn!("No free text sections, placing code in a data section");
+        }
+        Ok(vec![section(DolSectionKind::Data, start, &linked.data)])
+    } else {
+        bail!(
+            "No free DOL sections (maximum {} text, {} data)",
+            MAX_TEXT_SECTIONS,
+            MAX_DATA_SECTIONS
+        )
+    }
+}
+
+fn gecko(args: GeckoArgs) -> Result<()> {
+    const BLR: [u8; 4] = 0x4E800020u32.to_be_bytes();
+
+    let PatchInput { obj, patch_config, objects, .. } =
+        load_patch_input(&args.config, &args.patch_config)?;
+    ensure!(patch_config.rel.is_none(), "REL output is only supported by `dol patch`");
+    // Without an address, the linked objects aren't written, and only inserted code can be used
+    let linked = link_patch(&objects, &obj, patch_config.address.unwrap_or_default())?;
+    let requires_address =
+        |target: u32| patch_config.address.is_none() && target < linked.data.len() as u32;
+
+    let mut codes = GeckoCodes::default();
+    if patch_config.address.is_some() && !linked.data.is_empty() {
+        log::info!("Writing {:#X} bytes at {:#010X}", linked.data.len(), linked.address);
+        codes.write_bytes(linked.address, &linked.data)?;
+    }
+    for hook in &patch_config.hooks {
+        match hook {
+            PatchHook::Insert { address, symbol } => {
+                let size = objects
+                    .iter()
+                    .flat_map(|o| o.symbols.iter())
+                    .find(|s| s.name == *symbol && s.section.is_some() && s.flags.is_global())
+                    .map(|s| s.size as u32)
+                    .with_context(|| format!("Failed to locate global symbol '{}'", symbol))?;
+                let start = linked.symbols[symbol];
+                let end = start + size;
+                let mut branches = vec![];
+                for reloc in linked.relocations.iter().filter(|r| (start..end).contains(&r.address))
+                {
+                    match reloc.kind {
+                        _ if requires_address(reloc.target) => bail!(
+                            "Inserted code '{}' references patch objects without an address",
+                            symbol
+                        ),
+                        ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14
+                            if !(start..end).contains(&reloc.target) =>
+                        {
+                            branches.push((reloc.address - start, reloc.kind, reloc.target));
+                        }
+                        _ => {}
+                    }
+                }
+                let mut code = &linked.data
+                    [(start - linked.address) as usize..(end - linked.address) as usize];
+                if code.ends_with(&BLR) {
+                    code = &code[..code.len() - 4];
+                }
+                log::info!(
+                    "Inserting {} at {:#010X} ({} instructions, {} external branches)",
+                    symbol,
+                    address,
+                    code.len() / 4,
+                    branches.len()
+                );
+                codes.insert_asm(*address, code, &branches)?;
+            }
+            PatchHook::Section { address, file, .. } => {
+                let data = fs::read(file)
+                    .with_context(|| format!("Failed to read '{}'", file.display()))?;
+                log::info!(
+                    "Writing {} at {:#010X} ({:#X} bytes)",
+                    file.display(),
+                    address,
+                    data.len()
+                );
+                codes.write_bytes(*address, &data)?;
+            }
+            PatchHook::Branch { target, .. } | PatchHook::Replace { target, .. } => {
+                let (address, ins) = hook_branch(&obj, &linked, hook)?;
+                ensure!(
+                    !linked.symbols.get(target).is_some_and(|&t| requires_address(t)),
+                    "Hook target '{}' is in the patch objects, which requires an address",
+                    target
+                );
+                codes.write_u32(address, ins)?;
+            }
+        }
+    }
+
+    if args.action_replay {
+        codes = codes.to_action_replay()?;
+    }
+    let mut out = String::new();
+    if let Some(name) = &args.name {
+        out.push_str(&format!("${}\n", name));
+    }
+    out.push_str(&codes.to_string());
+    log::info!("Writing {} ({} lines)", args.out_file.display(), codes.lines.len());
+    let mut w = buf_writer(&args.out_file)?;
+    w.write_all(out.as_bytes())?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: u32 = 0x80400000;
+
+    fn linked(text_size: u32, size: usize) -> LinkedPatch {
+        LinkedPatch {
+            address: ADDRESS,
+            text_size,
+            data: vec![0; size],
+            symbols: Default::default(),
+            relocations: vec![],
+        }
+    }
+
+    fn placement(linked: &LinkedPatch, header: &DolHeader) -> Vec<(DolSectionKind, u32, usize)> {
+        patch_sections(linked, header)
+            .unwrap()
+            .iter()
+            .map(|s| (s.kind, s.address, s.data.len()))
+            .collect()
+    }
+
+    #[test]
+    fn test_patch_sections() {
+        let free = DolHeader::default();
+        let text_full = DolHeader { text_sizes: [0x20; MAX_TEXT_SECTIONS], ..Default::default() };
+        let data_full = DolHeader { data_sizes: [0x20; MAX_DATA_SECTIONS], ..Default::default() };
+        let full = DolHeader { data_sizes: data_full.data_sizes, ..text_full.clone() };
+
+        let patch = linked(0x20, 0x30);
+        assert_eq!(placement(&patch, &free), [
+            (DolSectionKind::Text, ADDRESS, 0x20),
+            (DolSectionKind::Data, ADDRESS + 0x20, 0x10)
+        ]);
+        // Falls back to a single section when either kind runs out
+        assert_eq!(placement(&patch, &text_full), [(DolSectionKind::Data, ADDRESS, 0x30)]);
+        assert_eq!(placement(&patch, &data_full), [(DolSectionKind::Text, ADDRESS, 0x30)]);
+        assert!(patch_sections(&patch, &full).is_err());
+
+        // Code or data only
+        assert_eq!(placement(&linked(0x20, 0x20), &free), [(DolSectionKind::Text, ADDRESS, 0x20)]);
+        assert_eq!(placement(&linked(0, 0x10), &free), [(DolSectionKind::Data, ADDRESS, 0x10)]);
+        assert_eq!(placement(&linked(0, 0x10), &data_full), [(
+            DolSectionKind::Text,
+            ADDRESS,
+            0x10
+        )]);
+        assert!(placement(&linked(0, 0), &full).is_empty());
+    }
+}
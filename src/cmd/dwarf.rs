@@ -1,28 +1,30 @@
 use std::{
     collections::{btree_map, BTreeMap},
-    io::{stdout, Cursor, Read, Write},
-    path::PathBuf,
+    io::{stdout, Read, Write},
+    path::{Path, PathBuf},
     str::from_utf8,
 };
 
 use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
-use object::{elf, Object, ObjectSection, ObjectSymbol, RelocationKind, RelocationTarget, Section};
 use syntect::{
     highlighting::{Color, HighlightIterator, HighlightState, Highlighter, Theme, ThemeSet},
     parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet},
 };
 
 use crate::util::{
+    debug_info::{read_debug_lines, read_debug_tags, DebugObject, LineInfo, SourceLocation},
     dwarf::{
-        process_root_tag, read_debug_section, should_skip_tag, tag_type_string, AttributeKind,
-        TagKind,
+        process_root_tag, should_skip_tag, subroutine_frame_string, tag_type_string, AttributeKind,
+        TagKind, TagMap, TagType, UserDefinedType,
     },
+    dwarf_headers::generate_headers,
+    dwarf_types::build_type_database,
     file::{buf_writer, map_file},
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
-/// Commands for processing DWARF 1.1 information.
+/// Commands for processing DWARF 1.1 and 2+ information.
 #[argp(subcommand, name = "dwarf")]
 pub struct Args {
     #[argp(subcommand)]
@@ -33,10 +35,13 @@ pub struct Args {
 #[argp(subcommand)]
 enum SubCommand {
     Dump(DumpArgs),
+    Headers(HeadersArgs),
+    Types(TypesArgs),
+    Frames(FramesArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
-/// Dumps DWARF 1.1 info from an object or archive.
+/// Dumps DWARF info from an object or archive.
 #[argp(subcommand, name = "dump")]
 pub struct DumpArgs {
     #[argp(positional)]
@@ -48,11 +53,53 @@ pub struct DumpArgs {
     #[argp(switch)]
     /// Disable color output.
     no_color: bool,
+    #[argp(switch)]
+    /// Include line number tables. (DWARF 1.1 only)
+    lines: bool,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Generates C/C++ header files from DWARF info in an object or archive.
+#[argp(subcommand, name = "headers")]
+pub struct HeadersArgs {
+    #[argp(positional)]
+    /// Input object. (ELF or archive)
+    in_file: PathBuf,
+    #[argp(option, short = 'o')]
+    /// Output directory.
+    out_dir: PathBuf,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Exports DWARF type information from an object or archive as JSON.
+#[argp(subcommand, name = "types")]
+pub struct TypesArgs {
+    #[argp(positional)]
+    /// Input object. (ELF or archive)
+    in_file: PathBuf,
+    #[argp(option, short = 'o')]
+    /// Output file. (Or stdout)
+    out: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Reports the stack frame layout and register allocation of each function.
+#[argp(subcommand, name = "frames")]
+pub struct FramesArgs {
+    #[argp(positional)]
+    /// Input object. (ELF or archive)
+    in_file: PathBuf,
+    #[argp(option, short = 'o')]
+    /// Output file. (Or stdout)
+    out: Option<PathBuf>,
 }
 
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::Dump(c_args) => dump(c_args),
+        SubCommand::Headers(c_args) => headers(c_args),
+        SubCommand::Types(c_args) => types(c_args),
+        SubCommand::Frames(c_args) => frames(c_args),
     }
 }
 
@@ -79,84 +126,196 @@ fn dump(args: DumpArgs) -> Result<()> {
             let mut data = vec![0u8; e.header().size() as usize];
             e.read_exact(&mut data)?;
             let obj_file = object::read::File::parse(&*data)?;
-            let debug_section = match obj_file.section_by_name(".debug") {
-                Some(section) => {
+            let tags = match read_debug_tags(&obj_file)? {
+                Some(tags) => {
                     log::info!("Processing '{}'", name);
-                    section
+                    tags
                 }
                 None => {
-                    log::warn!("Object '{}' missing .debug section", name);
+                    log::warn!("Object '{}' missing debug info", name);
                     continue;
                 }
             };
+            let lines = read_lines(&obj_file, &tags, args.lines)?;
             if let Some(out_path) = &args.out {
                 // TODO make a basename method
                 let name = name.trim_start_matches("D:").replace('\\', "/");
                 let name = name.rsplit_once('/').map(|(_, b)| b).unwrap_or(&name);
                 let file_path = out_path.join(format!("{}.txt", name));
                 let mut file = buf_writer(file_path)?;
-                dump_debug_section(&mut file, &obj_file, debug_section)?;
+                dump_tags(&mut file, &tags, lines.as_ref())?;
                 file.flush()?;
             } else if args.no_color {
                 println!("\n// File {}:", name);
-                dump_debug_section(&mut stdout(), &obj_file, debug_section)?;
+                dump_tags(&mut stdout(), &tags, lines.as_ref())?;
             } else {
                 let mut writer = HighlightWriter::new(syntax_set.clone(), syntax.clone(), theme);
                 writeln!(writer, "\n// File {}:", name)?;
-                dump_debug_section(&mut writer, &obj_file, debug_section)?;
+                dump_tags(&mut writer, &tags, lines.as_ref())?;
             }
         }
     } else {
         let obj_file = object::read::File::parse(buf)?;
-        let debug_section = obj_file
-            .section_by_name(".debug")
-            .ok_or_else(|| anyhow!("Failed to locate .debug section"))?;
+        let tags = read_debug_tags(&obj_file)?
+            .ok_or_else(|| anyhow!("Failed to locate .debug or .debug_info section"))?;
+        let lines = read_lines(&obj_file, &tags, args.lines)?;
         if let Some(out_path) = &args.out {
             let mut file = buf_writer(out_path)?;
-            dump_debug_section(&mut file, &obj_file, debug_section)?;
+            dump_tags(&mut file, &tags, lines.as_ref())?;
             file.flush()?;
         } else if args.no_color {
-            dump_debug_section(&mut stdout(), &obj_file, debug_section)?;
+            dump_tags(&mut stdout(), &tags, lines.as_ref())?;
         } else {
             let mut writer = HighlightWriter::new(syntax_set, syntax, theme);
-            dump_debug_section(&mut writer, &obj_file, debug_section)?;
+            dump_tags(&mut writer, &tags, lines.as_ref())?;
         }
     }
     Ok(())
 }
 
-fn dump_debug_section<W>(
-    w: &mut W,
-    obj_file: &object::File<'_>,
-    debug_section: Section,
-) -> Result<()>
-where
-    W: Write + ?Sized,
-{
-    let mut data = debug_section.uncompressed_data()?.into_owned();
-
-    // Apply relocations to data
-    for (addr, reloc) in debug_section.relocations() {
-        match reloc.kind() {
-            RelocationKind::Absolute | RelocationKind::Elf(elf::R_PPC_UADDR32) => {
-                let target = match reloc.target() {
-                    RelocationTarget::Symbol(symbol_idx) => {
-                        let symbol = obj_file.symbol_by_index(symbol_idx)?;
-                        (symbol.address() as i64 + reloc.addend()) as u32
+/// Reads debug info and line numbers from an object, or each object in an archive.
+fn read_debug_objects(path: &Path) -> Result<Vec<DebugObject>> {
+    let file = map_file(path)?;
+    let buf = file.as_slice();
+    let mut objects = vec![];
+    if buf.starts_with(b"!<arch>\n") {
+        let mut archive = ar::Archive::new(buf);
+        while let Some(result) = archive.next_entry() {
+            let mut e = match result {
+                Ok(e) => e,
+                Err(e) => bail!("Failed to read archive entry: {:?}", e),
+            };
+            let name = String::from_utf8_lossy(e.header().identifier()).to_string();
+            let mut data = vec![0u8; e.header().size() as usize];
+            e.read_exact(&mut data)?;
+            let obj_file = object::read::File::parse(&*data)?;
+            let Some(tags) = read_debug_tags(&obj_file)? else {
+                log::warn!("Object '{}' missing debug info", name);
+                continue;
+            };
+            let lines = read_debug_lines(&obj_file, &tags)?;
+            objects.push(DebugObject { name, tags, lines });
+        }
+    } else {
+        let obj_file = object::read::File::parse(buf)?;
+        let tags = read_debug_tags(&obj_file)?
+            .ok_or_else(|| anyhow!("Failed to locate .debug or .debug_info section"))?;
+        let lines = read_debug_lines(&obj_file, &tags)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        objects.push(DebugObject { name, tags, lines });
+    }
+    Ok(objects)
+}
+
+fn headers(args: HeadersArgs) -> Result<()> {
+    let objects = read_debug_objects(&args.in_file)?;
+    let headers = generate_headers(&objects)?;
+    for (path, contents) in &headers {
+        let mut w = buf_writer(args.out_dir.join(path))?;
+        w.write_all(contents.as_bytes())?;
+        w.flush()?;
+    }
+    log::info!("Wrote {} headers to {}", headers.len(), args.out_dir.display());
+    Ok(())
+}
+
+fn types(args: TypesArgs) -> Result<()> {
+    let objects = read_debug_objects(&args.in_file)?;
+    let database = build_type_database(&objects)?;
+    if let Some(out_path) = &args.out {
+        let mut w = buf_writer(out_path)?;
+        serde_json::to_writer_pretty(&mut w, &database)?;
+        w.flush()?;
+    } else {
+        let mut w = stdout().lock();
+        serde_json::to_writer_pretty(&mut w, &database)?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+fn frames(args: FramesArgs) -> Result<()> {
+    let objects = read_debug_objects(&args.in_file)?;
+    if let Some(out_path) = &args.out {
+        let mut w = buf_writer(out_path)?;
+        dump_frames(&mut w, &objects)?;
+        w.flush()?;
+    } else {
+        dump_frames(&mut stdout().lock(), &objects)?;
+    }
+    Ok(())
+}
+
+fn dump_frames<W>(w: &mut W, objects: &[DebugObject]) -> Result<()>
+where W: Write + ?Sized {
+    for object in objects {
+        let tags = &object.tags;
+        let Some((_, mut tag)) = tags.first_key_value() else {
+            continue;
+        };
+        while tag.kind == TagKind::CompileUnit {
+            let unit = tag.string_attribute(AttributeKind::Name).cloned().unwrap_or_default();
+            writeln!(w, "\n// Compile unit: {}", unit)?;
+            let mut typedefs = BTreeMap::<u32, Vec<u32>>::new();
+            for child in tag.children(tags) {
+                let tag_type = match process_root_tag(tags, child) {
+                    Ok(tag_type) => tag_type,
+                    Err(e) => {
+                        log::error!("Failed to process tag {} (unit {}): {}", child.key, unit, e);
+                        continue;
                     }
-                    _ => bail!("Invalid .debug relocation target"),
                 };
-                data[addr as usize..addr as usize + 4].copy_from_slice(&target.to_be_bytes());
+                match &tag_type {
+                    TagType::UserDefined(UserDefinedType::Subroutine(t))
+                        if t.start_address.is_some() && t.end_address.is_some() =>
+                    {
+                        match subroutine_frame_string(tags, &typedefs, t) {
+                            Ok(s) => writeln!(w, "{}", s)?,
+                            Err(e) => log::error!(
+                                "Failed to emit frame for tag {} (unit {}): {}",
+                                child.key,
+                                unit,
+                                e
+                            ),
+                        }
+                    }
+                    TagType::Typedef(_) => {
+                        if let Some(ud_type_ref) =
+                            child.reference_attribute(AttributeKind::UserDefType)
+                        {
+                            typedefs.entry(ud_type_ref).or_default().push(child.key);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            match tag.next_sibling(tags) {
+                Some(next) => tag = next,
+                None => break,
             }
-            RelocationKind::Elf(elf::R_PPC_NONE) => {}
-            _ => bail!("Unhandled .debug relocation type {:?}", reloc.kind()),
         }
     }
+    Ok(())
+}
 
-    let mut reader = Cursor::new(&*data);
-    let tags = read_debug_section(&mut reader)?;
+fn read_lines(
+    obj_file: &object::File<'_>,
+    tags: &TagMap,
+    enabled: bool,
+) -> Result<Option<LineInfo>> {
+    if !enabled {
+        return Ok(None);
+    }
+    let lines = read_debug_lines(obj_file, tags)?;
+    if lines.is_none() {
+        log::warn!("Missing .line section, skipping line numbers");
+    }
+    Ok(lines)
+}
 
-    for (&addr, tag) in &tags {
+fn dump_tags<W>(w: &mut W, tags: &TagMap, lines: Option<&LineInfo>) -> Result<()>
+where W: Write + ?Sized {
+    for (&addr, tag) in tags {
         log::debug!("{}: {:?}", addr, tag);
     }
 
@@ -175,10 +334,10 @@ where
                     }
                     writeln!(w, "\n// Compile unit: {}", unit)?;
 
-                    let children = tag.children(&tags);
+                    let children = tag.children(tags);
                     let mut typedefs = BTreeMap::<u32, Vec<u32>>::new();
                     for child in children {
-                        let tag_type = match process_root_tag(&tags, child) {
+                        let tag_type = match process_root_tag(tags, child) {
                             Ok(tag_type) => tag_type,
                             Err(e) => {
                                 log::error!(
@@ -198,7 +357,7 @@ where
                         if should_skip_tag(&tag_type) {
                             continue;
                         }
-                        match tag_type_string(&tags, &typedefs, &tag_type) {
+                        match tag_type_string(tags, &typedefs, &tag_type) {
                             Ok(s) => writeln!(w, "{}", s)?,
                             Err(e) => {
                                 log::error!(
@@ -232,13 +391,17 @@ where
                             }
                         }
                     }
+
+                    if let Some(lines) = lines {
+                        dump_unit_lines(w, lines, tag.key)?;
+                    }
                 }
                 _ => {
                     log::warn!("Expected CompileUnit, got {:?}", tag.kind);
                     break;
                 }
             }
-            if let Some(next) = tag.next_sibling(&tags) {
+            if let Some(next) = tag.next_sibling(tags) {
                 tag = next;
             } else {
                 break;
@@ -252,6 +415,26 @@ where
     Ok(())
 }
 
+fn dump_unit_lines<W>(w: &mut W, lines: &LineInfo, unit_key: u32) -> Result<()>
+where W: Write + ?Sized {
+    let Some(unit) = lines.units.iter().find(|u| u.key == unit_key) else {
+        return Ok(());
+    };
+    for function in &unit.functions {
+        writeln!(
+            w,
+            "\n// Lines for {} ({:#010X}-{:#010X}):",
+            function.name, function.start_address, function.end_address
+        )?;
+        for row in &function.rows {
+            let location =
+                SourceLocation { file: lines.file(row.file), line: row.line, column: row.column };
+            writeln!(w, "//   {:#010X}: {}", row.address, location)?;
+        }
+    }
+    Ok(())
+}
+
 struct HighlightWriter<'a> {
     line: String,
     highlighter: Highlighter<'a>,
@@ -329,3 +512,352 @@ impl Write for HighlightWriter<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::util::{
+        dwarf::{
+            read_debug_section, Attribute, AttributeValue, FundType, LocationOp, Modifier,
+            SubscriptFormat,
+        },
+        dwarf2::read_debug_info,
+        reader::Endian,
+    };
+
+    // The following source, as described by DWARF 1.1 (MWCC) and DWARF 2 (GCC):
+    //
+    // struct Point { int x; int y; };
+    // enum Color { RED, GREEN = 2 };
+    // int gCounter;
+    // char gName[16];
+    // int GetX(struct Point* p) { int local; ... }
+
+    const ADDR_COUNTER: u32 = 0x80001000;
+    const ADDR_NAME: u32 = 0x80001004;
+    const ADDR_GET_X: u32 = 0x80002000;
+    const ADDR_GET_X_END: u32 = 0x80002010;
+
+    fn attr(kind: AttributeKind, value: AttributeValue) -> Attribute { Attribute { kind, value } }
+
+    fn name(name: &str) -> Attribute {
+        attr(AttributeKind::Name, AttributeValue::String(name.to_string()))
+    }
+
+    fn fund_type(fund_type: FundType) -> Attribute {
+        attr(AttributeKind::FundType, AttributeValue::Data2(fund_type.into()))
+    }
+
+    fn location(ops: &[(LocationOp, u32)]) -> Attribute {
+        let mut block = vec![];
+        for &(op, value) in ops {
+            block.push(op as u8);
+            if op != LocationOp::Add {
+                block.extend(value.to_be_bytes());
+            }
+        }
+        attr(AttributeKind::Location, AttributeValue::Block(block))
+    }
+
+    /// Encodes DWARF 1.1 tags given as (depth, kind, attributes), linking each tag
+    /// with children to its sibling and terminating each child list with a null entry.
+    fn encode_v1(entries: &[(usize, TagKind, Vec<Attribute>)]) -> Vec<u8> {
+        let mut out = vec![];
+        let mut open_lists = vec![];
+        for (i, (depth, kind, attributes)) in entries.iter().enumerate() {
+            let next_depth = entries.get(i + 1).map_or(0, |(depth, _, _)| *depth);
+            let start = out.len();
+            out.extend([0; 4]);
+            out.extend(u16::from(*kind).to_be_bytes());
+            if next_depth > *depth {
+                out.extend(u16::from(AttributeKind::Sibling).to_be_bytes());
+                open_lists.push(out.len());
+                out.extend([0; 4]);
+            }
+            for attribute in attributes {
+                let code = u16::from(attribute.kind);
+                out.extend(code.to_be_bytes());
+                match &attribute.value {
+                    AttributeValue::Address(v) | AttributeValue::Reference(v) => {
+                        out.extend(v.to_be_bytes())
+                    }
+                    AttributeValue::Data2(v) => out.extend(v.to_be_bytes()),
+                    AttributeValue::Data4(v) => out.extend(v.to_be_bytes()),
+                    AttributeValue::Data8(v) => out.extend(v.to_be_bytes()),
+                    // Block4 form
+                    AttributeValue::Block(b) if code & 0xF == 0x4 => {
+                        out.extend((b.len() as u32).to_be_bytes());
+                        out.extend(b);
+                    }
+                    AttributeValue::Block(b) => {
+                        out.extend((b.len() as u16).to_be_bytes());
+                        out.extend(b);
+                    }
+                    AttributeValue::String(s) => {
+                        out.extend(s.as_bytes());
+                        out.push(0);
+                    }
+                }
+            }
+            let size = (out.len() - start) as u32;
+            out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+            for _ in next_depth..*depth {
+                out.extend(4u32.to_be_bytes());
+                let sibling = open_lists.pop().unwrap();
+                let end = out.len() as u32;
+                out[sibling..sibling + 4].copy_from_slice(&end.to_be_bytes());
+            }
+        }
+        out
+    }
+
+    fn dwarf1_tags() -> TagMap {
+        let point_key = 0x1000_0000; // Patched below
+        let mut entries = vec![
+            (0, TagKind::CompileUnit, vec![
+                name("test.c"),
+                attr(AttributeKind::Language, AttributeValue::Data4(1)),
+            ]),
+            (1, TagKind::StructureType, vec![
+                name("Point"),
+                attr(AttributeKind::ByteSize, AttributeValue::Data4(8)),
+            ]),
+            (2, TagKind::Member, vec![
+                name("x"),
+                fund_type(FundType::Integer),
+                location(&[(LocationOp::Const, 0), (LocationOp::Add, 0)]),
+            ]),
+            (2, TagKind::Member, vec![
+                name("y"),
+                fund_type(FundType::Integer),
+                location(&[(LocationOp::Const, 4), (LocationOp::Add, 0)]),
+            ]),
+            (1, TagKind::EnumerationType, vec![
+                name("Color"),
+                attr(AttributeKind::ByteSize, AttributeValue::Data4(4)),
+                attr(
+                    AttributeKind::ElementList,
+                    AttributeValue::Block(b"\0\0\0\0RED\0\0\0\0\x02GREEN\0".to_vec()),
+                ),
+            ]),
+            (1, TagKind::GlobalVariable, vec![
+                name("gCounter"),
+                fund_type(FundType::Integer),
+                location(&[(LocationOp::Address, ADDR_COUNTER)]),
+            ]),
+            (1, TagKind::ArrayType, vec![attr(
+                AttributeKind::SubscrData,
+                AttributeValue::Block(
+                    [
+                        &[SubscriptFormat::FundTypeConstConst as u8][..],
+                        &u16::from(FundType::Long).to_be_bytes(),
+                        &0u32.to_be_bytes(),
+                        &15u32.to_be_bytes(),
+                        &[SubscriptFormat::ElementType as u8],
+                        &u16::from(AttributeKind::FundType).to_be_bytes(),
+                        &u16::from(FundType::Char).to_be_bytes(),
+                    ]
+                    .concat(),
+                ),
+            )]),
+            (1, TagKind::GlobalVariable, vec![
+                name("gName"),
+                attr(AttributeKind::UserDefType, AttributeValue::Reference(0)), // Patched below
+                location(&[(LocationOp::Address, ADDR_NAME)]),
+            ]),
+            (1, TagKind::GlobalSubroutine, vec![
+                name("GetX"),
+                fund_type(FundType::Integer),
+                attr(AttributeKind::Prototyped, AttributeValue::String(String::new())),
+                attr(AttributeKind::LowPc, AttributeValue::Address(ADDR_GET_X)),
+                attr(AttributeKind::HighPc, AttributeValue::Address(ADDR_GET_X_END)),
+            ]),
+            (2, TagKind::FormalParameter, vec![
+                name("p"),
+                attr(
+                    AttributeKind::ModUDType,
+                    AttributeValue::Block(
+                        [&[Modifier::PointerTo as u8][..], &u32::to_be_bytes(point_key)].concat(),
+                    ),
+                ),
+                location(&[(LocationOp::Register, 3)]),
+            ]),
+            (2, TagKind::LocalVariable, vec![
+                name("local"),
+                fund_type(FundType::Integer),
+                location(&[
+                    (LocationOp::BaseRegister, 1),
+                    (LocationOp::Const, 8),
+                    (LocationOp::Add, 0),
+                ]),
+            ]),
+        ];
+
+        // Resolve user-defined type references from a first pass
+        let tags = read_debug_section(&mut Cursor::new(encode_v1(&entries))).unwrap();
+        let key_of = |kind: TagKind| tags.values().find(|t| t.kind == kind).unwrap().key;
+        let (point, array) = (key_of(TagKind::StructureType), key_of(TagKind::ArrayType));
+        for (_, _, attributes) in &mut entries {
+            for attribute in attributes {
+                match (attribute.kind, &mut attribute.value) {
+                    (AttributeKind::UserDefType, AttributeValue::Reference(r)) => *r = array,
+                    (AttributeKind::ModUDType, AttributeValue::Block(b)) => {
+                        b[1..5].copy_from_slice(&point.to_be_bytes())
+                    }
+                    _ => {}
+                }
+            }
+        }
+        read_debug_section(&mut Cursor::new(encode_v1(&entries))).unwrap()
+    }
+
+    fn uleb128(out: &mut Vec<u8>, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                break;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    /// Builds DWARF 2 `.debug_info` and `.debug_abbrev`, with one abbreviation per DIE.
+    #[derive(Default)]
+    struct Dwarf2Builder {
+        info: Vec<u8>,
+        abbrev: Vec<u8>,
+        code: u64,
+    }
+
+    // DW_FORM_*
+    const ADDR: u16 = 0x01;
+    const DATA1: u16 = 0x0b;
+    const FLAG: u16 = 0x0c;
+    const STRING: u16 = 0x08;
+    const BLOCK1: u16 = 0x0a;
+    const REF4: u16 = 0x13;
+
+    impl Dwarf2Builder {
+        /// Adds a DIE, returning its offset. Values are encoded according to the form.
+        fn die(&mut self, tag: u16, children: bool, attributes: &[(u16, u16, &[u8])]) -> u32 {
+            let offset = self.info.len() as u32;
+            self.code += 1;
+            uleb128(&mut self.abbrev, self.code);
+            uleb128(&mut self.abbrev, tag as u64);
+            self.abbrev.push(children as u8);
+            uleb128(&mut self.info, self.code);
+            for &(name, form, value) in attributes {
+                uleb128(&mut self.abbrev, name as u64);
+                uleb128(&mut self.abbrev, form as u64);
+                match form {
+                    STRING => {
+                        self.info.extend(value);
+                        self.info.push(0);
+                    }
+                    BLOCK1 => {
+                        self.info.push(value.len() as u8);
+                        self.info.extend(value);
+                    }
+                    _ => self.info.extend(value),
+                }
+            }
+            self.abbrev.extend([0, 0]);
+            offset
+        }
+
+        fn end_children(&mut self) { self.info.push(0); }
+
+        fn finish(mut self) -> (Vec<u8>, Vec<u8>) {
+            self.abbrev.push(0);
+            let length = (self.info.len() - 4) as u32;
+            self.info[..4].copy_from_slice(&length.to_be_bytes());
+            (self.info, self.abbrev)
+        }
+    }
+
+    fn dwarf2_tags() -> TagMap {
+        let mut b = Dwarf2Builder::default();
+        // Unit header: length (patched), version, abbrev offset, address size
+        b.info.extend([0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 4]);
+        b.die(0x11, true, &[(0x03, STRING, b"test.c"), (0x13, DATA1, &[0x01])]);
+        let int =
+            b.die(0x24, false, &[(0x03, STRING, b"int"), (0x3e, DATA1, &[5]), (0x0b, DATA1, &[4])]);
+        let point = b.die(0x13, true, &[(0x03, STRING, b"Point"), (0x0b, DATA1, &[8])]);
+        for (member, offset) in [("x", 0), ("y", 4)] {
+            b.die(0x0d, false, &[
+                (0x03, STRING, member.as_bytes()),
+                (0x49, REF4, &int.to_be_bytes()),
+                (0x38, BLOCK1, &[0x23, offset]),
+            ]);
+        }
+        b.end_children();
+        let point_ptr =
+            b.die(0x0f, false, &[(0x0b, DATA1, &[4]), (0x49, REF4, &point.to_be_bytes())]);
+        b.die(0x04, true, &[(0x03, STRING, b"Color"), (0x0b, DATA1, &[4])]);
+        for (enumerator, value) in [("RED", 0), ("GREEN", 2)] {
+            b.die(0x28, false, &[(0x03, STRING, enumerator.as_bytes()), (0x1c, DATA1, &[value])]);
+        }
+        b.end_children();
+        b.die(0x34, false, &[
+            (0x03, STRING, b"gCounter"),
+            (0x49, REF4, &int.to_be_bytes()),
+            (0x3f, FLAG, &[1]),
+            (0x02, BLOCK1, &[[0x03].as_slice(), &ADDR_COUNTER.to_be_bytes()].concat()),
+        ]);
+        let char_type = b.die(0x24, false, &[
+            (0x03, STRING, b"char"),
+            (0x3e, DATA1, &[8]),
+            (0x0b, DATA1, &[1]),
+        ]);
+        let array = b.die(0x01, true, &[(0x49, REF4, &char_type.to_be_bytes())]);
+        b.die(0x21, false, &[(0x2f, DATA1, &[15])]);
+        b.end_children();
+        b.die(0x34, false, &[
+            (0x03, STRING, b"gName"),
+            (0x49, REF4, &array.to_be_bytes()),
+            (0x3f, FLAG, &[1]),
+            (0x02, BLOCK1, &[[0x03].as_slice(), &ADDR_NAME.to_be_bytes()].concat()),
+        ]);
+        b.die(0x2e, true, &[
+            (0x03, STRING, b"GetX"),
+            (0x49, REF4, &int.to_be_bytes()),
+            (0x3f, FLAG, &[1]),
+            (0x27, FLAG, &[1]),
+            (0x11, ADDR, &ADDR_GET_X.to_be_bytes()),
+            (0x12, ADDR, &ADDR_GET_X_END.to_be_bytes()),
+            (0x40, BLOCK1, &[0x51]), // DW_OP_reg1
+        ]);
+        b.die(0x05, false, &[
+            (0x03, STRING, b"p"),
+            (0x49, REF4, &point_ptr.to_be_bytes()),
+            (0x02, BLOCK1, &[0x53]), // DW_OP_reg3
+        ]);
+        b.die(0x34, false, &[
+            (0x03, STRING, b"local"),
+            (0x49, REF4, &int.to_be_bytes()),
+            (0x02, BLOCK1, &[0x91, 8]), // DW_OP_fbreg 8
+        ]);
+        b.end_children();
+        b.end_children();
+        let (info, abbrev) = b.finish();
+        read_debug_info(&info, &abbrev, &[], Endian::Big).unwrap()
+    }
+
+    fn dump_string(tags: &TagMap) -> String {
+        let mut out = vec![];
+        dump_tags(&mut out, tags, None).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_dwarf2_dump_matches_dwarf1() {
+        let expected = dump_string(&dwarf1_tags());
+        assert!(expected.contains("struct Point {"), "{expected}");
+        assert!(expected.contains("GetX("), "{expected}");
+        assert_eq!(dump_string(&dwarf2_tags()), expected);
+    }
+}
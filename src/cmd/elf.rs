@@ -21,6 +21,7 @@ use crate::{
         asm::write_asm,
         comment::{CommentSym, MWComment},
         config::{write_splits_file, write_symbols_file},
+        debug_info::{debug_data_layouts, read_debug_elf},
         elf::{process_elf, write_elf},
         file::{buf_writer, process_rsp},
         reader::{Endian, FromReader},
@@ -145,6 +146,13 @@ fn config(args: ConfigArgs) -> Result<()> {
 fn disasm(args: DisasmArgs) -> Result<()> {
     log::info!("Loading {}", args.elf_file.display());
     let obj = process_elf(&args.elf_file)?;
+    let data_layouts = match read_debug_elf(&args.elf_file) {
+        Ok(tags) => Some(debug_data_layouts(&tags)?),
+        Err(e) => {
+            log::debug!("No data layouts: {}", e);
+            None
+        }
+    };
     match obj.kind {
         ObjKind::Executable => {
             log::info!("Splitting {} objects", obj.link_order.len());
@@ -161,7 +169,7 @@ fn disasm(args: DisasmArgs) -> Result<()> {
                 log::info!("Writing {}", out_path.display());
 
                 let mut w = buf_writer(out_path)?;
-                write_asm(&mut w, split_obj)?;
+                write_asm(&mut w, split_obj, data_layouts.as_ref())?;
                 w.flush()?;
 
                 writeln!(files_out, "{}", file_name_from_unit(&unit.name, ".o"))?;
@@ -170,7 +178,7 @@ fn disasm(args: DisasmArgs) -> Result<()> {
         }
         ObjKind::Relocatable => {
             let mut w = buf_writer(args.out)?;
-            write_asm(&mut w, &obj)?;
+            write_asm(&mut w, &obj, data_layouts.as_ref())?;
             w.flush()?;
         }
     }
@@ -1,13 +1,21 @@
 use std::{
-    io::{Seek, SeekFrom, Write},
+    io::{Cursor, Write},
     path::PathBuf,
 };
 
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use argp::FromArgs;
 use object::{Architecture, Endianness, Object, ObjectKind, ObjectSection, SectionKind};
 
-use crate::util::file::{buf_writer, map_file};
+use crate::{
+    cmd::shasum::file_sha1_string,
+    util::{
+        align_up,
+        dol::{write_dol, DolHeader, DolSectionKind, DolSectionWrite},
+        file::{buf_writer, map_file},
+        reader::{Endian, FromReader},
+    },
+};
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
 /// Converts an ELF file to a DOL file.
@@ -19,30 +27,45 @@ pub struct Args {
     #[argp(positional)]
     /// path to output DOL
     dol_file: PathBuf,
+    #[argp(option, short = 's')]
+    /// text or data section to include, in header order (default: all loadable sections)
+    section: Vec<String>,
+    #[argp(option, short = 'r')]
+    /// reference DOL (for --pad, --keep-bss, --keep-entry and --verify)
+    reference: Option<PathBuf>,
+    #[argp(switch)]
+    /// pad sections to the reference DOL's layout
+    pad: bool,
+    #[argp(switch)]
+    /// use the reference DOL's BSS range
+    keep_bss: bool,
+    #[argp(switch)]
+    /// use the reference DOL's entry point
+    keep_entry: bool,
+    #[argp(switch)]
+    /// verify the header and hash against the reference DOL
+    verify: bool,
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct DolSection {
-    pub offset: u32,
-    pub address: u32,
-    pub size: u32,
-}
-
-#[derive(Debug, Clone, Default)]
-pub struct DolHeader {
-    pub text_section_count: usize,
-    pub data_section_count: usize,
-    pub text_sections: [DolSection; MAX_TEXT_SECTIONS],
-    pub data_sections: [DolSection; MAX_DATA_SECTIONS],
-    pub bss_address: u32,
-    pub bss_size: u32,
-    pub entry_point: u32,
+struct Reference {
+    data: Vec<u8>,
+    header: DolHeader,
 }
 
-const MAX_TEXT_SECTIONS: usize = 7;
-const MAX_DATA_SECTIONS: usize = 11;
-
 pub fn run(args: Args) -> Result<()> {
+    let reference = if let Some(path) = &args.reference {
+        let data = map_file(path)?.as_slice().to_vec();
+        let header = DolHeader::from_reader(&mut Cursor::new(&data), Endian::Big)
+            .with_context(|| format!("Failed to read reference DOL '{}'", path.display()))?;
+        Some(Reference { data, header })
+    } else {
+        ensure!(
+            !(args.pad || args.keep_bss || args.keep_entry || args.verify),
+            "--pad, --keep-bss, --keep-entry and --verify require a reference DOL (-r)"
+        );
+        None
+    };
+
     let file = map_file(&args.elf_file)?;
     let obj_file = object::read::File::parse(file.as_slice())?;
     match obj_file.architecture() {
@@ -55,111 +78,206 @@ pub fn run(args: Args) -> Result<()> {
         kind => bail!("Unexpected ELF type: {kind:?}"),
     }
 
-    let mut header = DolHeader { entry_point: obj_file.entry() as u32, ..Default::default() };
-    let mut offset = 0x100u32;
-    let mut out = buf_writer(&args.dol_file)?;
-    out.seek(SeekFrom::Start(offset as u64))?;
+    let (out, header) = build_dol(&obj_file, &args, reference.as_ref())?;
+    let mut w = buf_writer(&args.dol_file)?;
+    w.write_all(&out)?;
+    w.flush()?;
 
-    // Text sections
-    for section in
-        obj_file.sections().filter(|s| section_kind(s) == SectionKind::Text && is_alloc(s.flags()))
-    {
-        log::debug!("Processing text section '{}'", section.name().unwrap_or("[error]"));
-        let address = section.address() as u32;
-        let size = align32(section.size() as u32);
-        *header.text_sections.get_mut(header.text_section_count).ok_or_else(|| {
-            anyhow!(
-                "Too many text sections (while processing '{}')",
-                section.name().unwrap_or("[error]")
-            )
-        })? = DolSection { offset, address, size };
-        header.text_section_count += 1;
-        write_aligned(&mut out, section.data()?, size)?;
-        offset += size;
+    if args.verify {
+        verify(&out, &header, reference.as_ref().unwrap())?;
     }
+    Ok(())
+}
 
-    // Data sections
-    for section in
-        obj_file.sections().filter(|s| section_kind(s) == SectionKind::Data && is_alloc(s.flags()))
-    {
-        log::debug!("Processing data section '{}'", section.name().unwrap_or("[error]"));
-        let address = section.address() as u32;
-        let size = align32(section.size() as u32);
-        *header.data_sections.get_mut(header.data_section_count).ok_or_else(|| {
-            anyhow!(
-                "Too many data sections (while processing '{}')",
-                section.name().unwrap_or("[error]")
-            )
-        })? = DolSection { offset, address, size };
-        header.data_section_count += 1;
-        write_aligned(&mut out, section.data()?, size)?;
-        offset += size;
-    }
+/// Converts the selected sections of `obj_file` to a DOL, returning its data and header.
+fn build_dol(
+    obj_file: &object::File,
+    args: &Args,
+    reference: Option<&Reference>,
+) -> Result<(Vec<u8>, DolHeader)> {
+    let sections = if args.section.is_empty() {
+        obj_file.sections().filter(|s| is_alloc(s.flags())).collect::<Vec<_>>()
+    } else {
+        args.section
+            .iter()
+            .map(|name| {
+                let section = obj_file
+                    .section_by_name(name)
+                    .ok_or_else(|| anyhow!("Section '{}' not found", name))?;
+                ensure!(
+                    is_alloc(section.flags())
+                        && matches!(
+                            section_kind(&section),
+                            SectionKind::Text | SectionKind::Data | SectionKind::UninitializedData
+                        ),
+                    "Section '{}' is not loadable",
+                    name
+                );
+                Ok(section)
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
 
-    // BSS sections
+    let mut header = DolHeader { entry_point: obj_file.entry() as u32, ..Default::default() };
+
+    // BSS sections, including any not selected with -s
+    let mut bss_start = u32::MAX;
+    let mut bss_end = 0;
     for section in obj_file
         .sections()
-        .filter(|s| section_kind(s) == SectionKind::UninitializedData && is_alloc(s.flags()))
+        .filter(|s| is_alloc(s.flags()) && section_kind(s) == SectionKind::UninitializedData)
     {
         let address = section.address() as u32;
-        let size = section.size() as u32;
-        if header.bss_address == 0 {
-            header.bss_address = address;
-        }
-        header.bss_size = (address + size) - header.bss_address;
+        bss_start = bss_start.min(address);
+        bss_end = bss_end.max(address + section.size() as u32);
     }
-
-    // Offsets
-    out.rewind()?;
-    for section in &header.text_sections {
-        out.write_all(&section.offset.to_be_bytes())?;
+    if bss_end > 0 {
+        header.bss_addr = bss_start;
+        header.bss_size = bss_end - bss_start;
     }
-    for section in &header.data_sections {
-        out.write_all(&section.offset.to_be_bytes())?;
+    if let Some(reference) = reference {
+        if args.keep_bss {
+            header.bss_addr = reference.header.bss_addr;
+            header.bss_size = reference.header.bss_size;
+        }
+        if args.keep_entry {
+            header.entry_point = reference.header.entry_point;
+        }
     }
 
-    // Addresses
-    for section in &header.text_sections {
-        out.write_all(&section.address.to_be_bytes())?;
-    }
-    for section in &header.data_sections {
-        out.write_all(&section.address.to_be_bytes())?;
-    }
+    // Text sections, then data sections
+    let mut dol_sections = vec![];
+    let mut end = DolHeader::STATIC_SIZE as u32;
+    for (kind, dol_kind) in
+        [(SectionKind::Text, DolSectionKind::Text), (SectionKind::Data, DolSectionKind::Data)]
+    {
+        for section in sections.iter().filter(|s| section_kind(s) == kind) {
+            let name = section.name().unwrap_or("[error]");
+            let address = section.address() as u32;
+            let mut offset = end;
+            let mut size = align32(section.size() as u32);
+            log::debug!("Processing {:?} section '{}'", dol_kind, name);
 
-    // Sizes
-    for section in &header.text_sections {
-        out.write_all(&section.size.to_be_bytes())?;
-    }
-    for section in &header.data_sections {
-        out.write_all(&section.size.to_be_bytes())?;
-    }
+            if args.pad {
+                let reference = reference.unwrap();
+                match reference_section(&reference.header, kind, address) {
+                    Some((ref_offset, ref_size)) => {
+                        if ref_size < size {
+                            log::warn!(
+                                "Section '{}' size {:#X} exceeds reference size {:#X}",
+                                name,
+                                size,
+                                ref_size
+                            );
+                        } else {
+                            size = ref_size;
+                        }
+                        if ref_offset < offset {
+                            log::warn!(
+                                "Section '{}' offset {:#X} exceeds reference offset {:#X}",
+                                name,
+                                offset,
+                                ref_offset
+                            );
+                        } else {
+                            offset = ref_offset;
+                        }
+                    }
+                    None => log::warn!(
+                        "Section '{}' at {:#010X} not found in reference DOL",
+                        name,
+                        address
+                    ),
+                }
+            }
 
-    // BSS + entry
-    out.write_all(&header.bss_address.to_be_bytes())?;
-    out.write_all(&header.bss_size.to_be_bytes())?;
-    out.write_all(&header.entry_point.to_be_bytes())?;
+            dol_sections.push(DolSectionWrite {
+                name,
+                kind: dol_kind,
+                address,
+                data: section.data()?,
+                offset: Some(offset),
+                size: Some(size),
+            });
+            end = offset + size;
+        }
+    }
 
-    // Done!
-    out.flush()?;
-    Ok(())
+    let mut out = vec![0u8; DolHeader::STATIC_SIZE];
+    write_dol(&mut out, &mut header, &dol_sections)?;
+    if let Some(reference) = reference {
+        if args.pad && out.len() < reference.data.len() {
+            out.resize(reference.data.len(), 0);
+        }
+    }
+    Ok((out, header))
 }
 
-#[inline]
-const fn align32(x: u32) -> u32 { (x + 31) & !31 }
+/// Finds the file offset and size of the reference section at `address`.
+fn reference_section(header: &DolHeader, kind: SectionKind, address: u32) -> Option<(u32, u32)> {
+    let (offs, addrs, sizes) = if kind == SectionKind::Text {
+        (&header.text_offs[..], &header.text_addrs[..], &header.text_sizes[..])
+    } else {
+        (&header.data_offs[..], &header.data_addrs[..], &header.data_sizes[..])
+    };
+    let index = addrs.iter().zip(sizes).position(|(&a, &s)| s != 0 && a == address)?;
+    Some((offs[index], sizes[index]))
+}
 
-const ZERO_BUF: [u8; 32] = [0u8; 32];
+/// Compares the output against the reference DOL, logging all header differences.
+fn verify(out: &[u8], header: &DolHeader, reference: &Reference) -> Result<()> {
+    let expected = &reference.header;
+    let mut mismatches = 0;
+    for (label, values, expected_values) in [
+        ("text offset", &header.text_offs[..], &expected.text_offs[..]),
+        ("text address", &header.text_addrs[..], &expected.text_addrs[..]),
+        ("text size", &header.text_sizes[..], &expected.text_sizes[..]),
+        ("data offset", &header.data_offs[..], &expected.data_offs[..]),
+        ("data address", &header.data_addrs[..], &expected.data_addrs[..]),
+        ("data size", &header.data_sizes[..], &expected.data_sizes[..]),
+        ("BSS address", &[header.bss_addr][..], &[expected.bss_addr][..]),
+        ("BSS size", &[header.bss_size][..], &[expected.bss_size][..]),
+        ("entry point", &[header.entry_point][..], &[expected.entry_point][..]),
+    ] {
+        for (index, (value, expected_value)) in values.iter().zip(expected_values).enumerate() {
+            if value != expected_value {
+                let label = if values.len() > 1 {
+                    format!("{} {}", label, index)
+                } else {
+                    label.to_string()
+                };
+                log::error!("{}: {:#X} (expected {:#X})", label, value, expected_value);
+                mismatches += 1;
+            }
+        }
+    }
 
-#[inline]
-fn write_aligned<T>(out: &mut T, bytes: &[u8], aligned_size: u32) -> std::io::Result<()>
-where T: Write + ?Sized {
-    out.write_all(bytes)?;
-    let padding = aligned_size - bytes.len() as u32;
-    if padding > 0 {
-        out.write_all(&ZERO_BUF[0..padding as usize])?;
+    let hash = file_sha1_string(&mut Cursor::new(out))?;
+    let expected_hash = file_sha1_string(&mut Cursor::new(&reference.data))?;
+    if hash != expected_hash && mismatches == 0 {
+        match out.iter().zip(&reference.data).position(|(a, b)| a != b) {
+            Some(offset) => log::error!("Data differs at offset {:#X}", offset),
+            None => log::error!(
+                "Size {:#X} differs from reference size {:#X}",
+                out.len(),
+                reference.data.len()
+            ),
+        }
     }
+    ensure!(
+        hash == expected_hash,
+        "Output hash {} does not match reference {} ({} header mismatches)",
+        hash,
+        expected_hash,
+        mismatches
+    );
+    log::info!("Verified against reference ({})", hash);
     Ok(())
 }
 
+#[inline]
+const fn align32(x: u32) -> u32 { align_up(x, 32) }
+
 // Some ELF files don't have the proper section kind set (for small data sections in particular)
 // so we map the section name to the expected section kind when possible.
 #[inline]
@@ -184,3 +302,132 @@ fn section_kind(section: &object::Section) -> SectionKind {
 fn is_alloc(flags: object::SectionFlags) -> bool {
     matches!(flags, object::SectionFlags::Elf { sh_flags } if sh_flags & object::elf::SHF_ALLOC as u64 != 0)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, SeekFrom};
+
+    use super::*;
+    use crate::{
+        obj::{ObjArchitecture, ObjInfo, ObjKind, ObjSection, ObjSectionKind},
+        util::{
+            dol::{MAX_DATA_SECTIONS, MAX_TEXT_SECTIONS},
+            elf::write_elf,
+        },
+    };
+
+    fn section(name: &str, kind: ObjSectionKind, address: u64, size: u64) -> ObjSection {
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address,
+            size,
+            data: if kind == ObjSectionKind::Bss {
+                vec![]
+            } else {
+                (0..size).map(|i| i as u8 + 1).collect()
+            },
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            original_address: 0,
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    /// The section writer from before `write_dol`, which assigns slots in order.
+    fn baseline_elf2dol(obj_file: &object::File) -> Vec<u8> {
+        #[derive(Clone, Default)]
+        struct Section {
+            offset: u32,
+            address: u32,
+            size: u32,
+        }
+
+        let mut text_sections = vec![];
+        let mut data_sections = vec![];
+        let mut bss_address = 0;
+        let mut bss_size = 0;
+        let mut offset = 0x100u32;
+        let mut out = Cursor::new(vec![]);
+        out.seek(SeekFrom::Start(offset as u64)).unwrap();
+        for (kind, sections) in
+            [(SectionKind::Text, &mut text_sections), (SectionKind::Data, &mut data_sections)]
+        {
+            for section in
+                obj_file.sections().filter(|s| section_kind(s) == kind && is_alloc(s.flags()))
+            {
+                let address = section.address() as u32;
+                let size = align32(section.size() as u32);
+                sections.push(Section { offset, address, size });
+                let data = section.data().unwrap();
+                out.write_all(data).unwrap();
+                out.write_all(&vec![0; (size as usize) - data.len()]).unwrap();
+                offset += size;
+            }
+        }
+        for section in obj_file
+            .sections()
+            .filter(|s| section_kind(s) == SectionKind::UninitializedData && is_alloc(s.flags()))
+        {
+            let address = section.address() as u32;
+            if bss_address == 0 {
+                bss_address = address;
+            }
+            bss_size = (address + section.size() as u32) - bss_address;
+        }
+
+        text_sections.resize(MAX_TEXT_SECTIONS, Section::default());
+        data_sections.resize(MAX_DATA_SECTIONS, Section::default());
+        out.rewind().unwrap();
+        for field in [|s: &Section| s.offset, |s: &Section| s.address, |s: &Section| s.size] {
+            for section in text_sections.iter().chain(&data_sections) {
+                out.write_all(&field(section).to_be_bytes()).unwrap();
+            }
+        }
+        out.write_all(&bss_address.to_be_bytes()).unwrap();
+        out.write_all(&bss_size.to_be_bytes()).unwrap();
+        out.write_all(&(obj_file.entry() as u32).to_be_bytes()).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn test_elf2dol_matches_baseline() {
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main".to_string(),
+            vec![],
+            vec![
+                section(".init", ObjSectionKind::Code, 0x80003100, 0x24),
+                section(".text", ObjSectionKind::Code, 0x80003140, 0x40),
+                // Empty sections still take a slot
+                section(".ctors", ObjSectionKind::Data, 0x80003180, 0),
+                section(".data", ObjSectionKind::Data, 0x80003180, 0x14),
+                section(".bss", ObjSectionKind::Bss, 0x800031A0, 0x20),
+                section(".sbss", ObjSectionKind::Bss, 0x800031C0, 8),
+            ],
+        );
+        obj.entry = Some(0x80003100);
+        let elf = write_elf(&obj).unwrap();
+        let obj_file = object::File::parse(&*elf).unwrap();
+
+        let args = Args {
+            elf_file: Default::default(),
+            dol_file: Default::default(),
+            section: vec![],
+            reference: None,
+            pad: false,
+            keep_bss: false,
+            keep_entry: false,
+            verify: false,
+        };
+        let (out, header) = build_dol(&obj_file, &args, None).unwrap();
+        assert_eq!(out, baseline_elf2dol(&obj_file));
+        assert_eq!(header.data_addrs[..2], [0x80003180, 0x80003180]);
+        assert_eq!(header.data_sizes[..2], [0, 0x20]);
+        assert_eq!((header.bss_addr, header.bss_size), (0x800031A0, 0x28));
+    }
+}
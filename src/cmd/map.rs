@@ -1,12 +1,13 @@
-use std::path::PathBuf;
+use std::{io::Write, path::PathBuf};
 
 use anyhow::{bail, ensure, Result};
 use argp::FromArgs;
 use cwdemangle::{demangle, DemangleOptions};
 
 use crate::util::{
-    file::map_file,
-    map::{process_map, SymbolEntry, SymbolRef},
+    elf::process_elf,
+    file::{buf_writer, map_file},
+    map::{process_map, write_map, SymbolEntry, SymbolRef},
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -22,6 +23,7 @@ pub struct Args {
 enum SubCommand {
     Entries(EntriesArgs),
     Symbol(SymbolArgs),
+    Create(CreateArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -48,10 +50,23 @@ pub struct SymbolArgs {
     symbol: String,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Creates a CodeWarrior map from a linked ELF.
+#[argp(subcommand, name = "create")]
+pub struct CreateArgs {
+    #[argp(positional)]
+    /// path to input ELF
+    elf_file: PathBuf,
+    #[argp(option, short = 'o')]
+    /// path to output map
+    out_file: PathBuf,
+}
+
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::Entries(c_args) => entries(c_args),
         SubCommand::Symbol(c_args) => symbol(c_args),
+        SubCommand::Create(c_args) => create(c_args),
     }
 }
 
@@ -160,3 +175,12 @@ fn symbol(args: SymbolArgs) -> Result<()> {
     println!("\n");
     Ok(())
 }
+
+fn create(args: CreateArgs) -> Result<()> {
+    log::info!("Loading {}", args.elf_file.display());
+    let obj = process_elf(&args.elf_file)?;
+    let mut out = buf_writer(&args.out_file)?;
+    write_map(&mut out, &obj)?;
+    out.flush()?;
+    Ok(())
+}
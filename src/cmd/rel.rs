@@ -1,19 +1,24 @@
 use std::{
-    collections::{btree_map, BTreeMap},
+    collections::{btree_map, BTreeMap, BTreeSet},
+    ffi::OsStr,
     fs,
-    io::Write,
+    io::{stdout, Cursor, Write},
     path::PathBuf,
+    str::FromStr,
     time::Instant,
 };
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
-use argp::FromArgs;
+use argp::{FromArgValue, FromArgs};
+use itertools::Itertools;
 use object::{
     Architecture, Endianness, File, Object, ObjectSection, ObjectSymbol, RelocationTarget,
-    SectionIndex, SymbolIndex,
+    SectionIndex, SectionKind, SymbolIndex,
 };
+use petgraph::{algo::tarjan_scc, Graph};
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
+use serde::Serialize;
 use tracing::{info, info_span};
 
 use crate::{
@@ -26,18 +31,22 @@ use crate::{
         signatures::{apply_signatures, apply_signatures_post},
         tracker::Tracker,
     },
-    array_ref_mut,
+    array_ref, array_ref_mut,
     cmd::dol::{ModuleConfig, ProjectConfig},
     obj::{ObjInfo, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol},
     util::{
-        config::{is_auto_symbol, read_splits_sections, SectionDef},
+        align_up,
+        config::{
+            apply_splits_file, apply_symbols_file, is_auto_symbol, read_splits_sections, SectionDef,
+        },
         dol::process_dol,
         elf::{to_obj_reloc_kind, write_elf},
         file::{buf_reader, buf_writer, map_file, process_rsp, verify_hash, FileIterator},
         nested::NestedMap,
         rel::{
-            print_relocations, process_rel, process_rel_header, process_rel_sections, write_rel,
-            RelHeader, RelReloc, RelSectionHeader, RelWriteInfo, PERMITTED_SECTIONS,
+            is_permitted_section, patch_relocation, print_relocations, process_rel,
+            process_rel_header, process_rel_sections, rewrite_rel_header, verify_rel, write_rel,
+            RelHeader, RelReloc, RelSectionHeader, RelSeverity, RelWriteInfo, PERMITTED_SECTIONS,
         },
         IntoCow, ToCow,
     },
@@ -54,9 +63,92 @@ pub struct Args {
 #[derive(FromArgs, PartialEq, Debug)]
 #[argp(subcommand)]
 enum SubCommand {
+    Edit(EditArgs),
+    Graph(GraphArgs),
     Info(InfoArgs),
+    Link(LinkArgs),
     Make(MakeArgs),
     Merge(MergeArgs),
+    Verify(VerifyArgs),
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Edits REL header fields in place.
+#[argp(subcommand, name = "edit")]
+pub struct EditArgs {
+    #[argp(positional)]
+    /// REL file
+    rel_file: PathBuf,
+    #[argp(option, short = 'o')]
+    /// output REL (default: overwrite input)
+    out_file: Option<PathBuf>,
+    #[argp(option)]
+    /// module ID (also updates self imports)
+    module_id: Option<u32>,
+    #[argp(option)]
+    /// section alignment (hex, version >= 2)
+    align: Option<String>,
+    #[argp(option)]
+    /// BSS alignment (hex, version >= 2)
+    bss_align: Option<String>,
+    #[argp(option)]
+    /// OSLinkFixed size (hex, version 3)
+    fix_size: Option<String>,
+    #[argp(option)]
+    /// _prolog entry, as section:offset (hex), or 0:0 to clear
+    prolog: Option<String>,
+    #[argp(option)]
+    /// _epilog entry, as section:offset (hex), or 0:0 to clear
+    epilog: Option<String>,
+    #[argp(option)]
+    /// _unresolved entry, as section:offset (hex), or 0:0 to clear
+    unresolved: Option<String>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Builds the import graph of a DOL + REL(s).
+#[argp(subcommand, name = "graph")]
+pub struct GraphArgs {
+    #[argp(positional)]
+    /// DOL file
+    dol_file: PathBuf,
+    #[argp(positional)]
+    /// REL file(s)
+    rel_files: Vec<PathBuf>,
+    #[argp(option, short = 'c')]
+    /// (optional) project configuration file, used to load symbols
+    config: Option<PathBuf>,
+    #[argp(option, short = 'f')]
+    /// output format: dot or json (default: guessed from extension, or dot)
+    format: Option<GraphFormat>,
+    #[argp(option, short = 'o')]
+    /// output file (or stdout)
+    out: Option<PathBuf>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "dot" | "gv" => Self::Dot,
+            "json" => Self::Json,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl FromArgValue for GraphFormat {
+    fn from_arg_value(value: &OsStr) -> Result<Self, String> {
+        String::from_arg_value(value)
+            .and_then(|s| Self::from_str(&s).map_err(|_| "Invalid graph format".to_string()))
+    }
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -86,6 +178,47 @@ pub struct MergeArgs {
     out_file: PathBuf,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Links REL(s) against a DOL as OSLink would, writing the resulting memory.
+#[argp(subcommand, name = "link")]
+pub struct LinkArgs {
+    #[argp(positional)]
+    /// DOL file
+    dol_file: PathBuf,
+    #[argp(positional)]
+    /// REL file(s)
+    rel_files: Vec<PathBuf>,
+    #[argp(option, short = 'a')]
+    /// load address (hex) for each REL; if fewer are given,
+    /// the remaining RELs are placed after the previous one
+    address: Vec<String>,
+    #[argp(option, short = 'b')]
+    /// (optional) BSS address (hex) for each REL;
+    /// defaults to after the REL (or after `fixSize` with --fixed)
+    bss: Vec<String>,
+    #[argp(switch, short = 'f')]
+    /// link with OSLinkFixed (version 3 RELs only)
+    fixed: bool,
+    #[argp(option, short = 'o')]
+    /// output ELF
+    out_file: PathBuf,
+    #[argp(option, short = 'm')]
+    /// (optional) output flat memory image, starting at 0x80000000
+    image: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Checks REL(s) against the runtime loader's constraints.
+#[argp(subcommand, name = "verify")]
+pub struct VerifyArgs {
+    #[argp(positional)]
+    /// REL file(s)
+    rel_files: Vec<PathBuf>,
+    #[argp(option, short = 'a')]
+    /// (optional) load address (hex), used to check branches to the DOL
+    address: Option<String>,
+}
+
 #[derive(FromArgs, PartialEq, Eq, Debug)]
 /// Creates RELs from an ELF + PLF(s).
 #[argp(subcommand, name = "make")]
@@ -99,6 +232,9 @@ pub struct MakeArgs {
     #[argp(option, short = 'n')]
     /// (optional) module names
     names: Vec<String>,
+    #[argp(option, short = 's')]
+    /// (optional) write module name string table (e.g. framework.str)
+    str_file: Option<PathBuf>,
     #[argp(switch, short = 'w')]
     /// disable warnings
     no_warn: bool,
@@ -109,9 +245,13 @@ pub struct MakeArgs {
 
 pub fn run(args: Args) -> Result<()> {
     match args.command {
+        SubCommand::Edit(c_args) => edit(c_args),
+        SubCommand::Graph(c_args) => graph(c_args),
         SubCommand::Info(c_args) => info(c_args),
+        SubCommand::Link(c_args) => link(c_args),
         SubCommand::Merge(c_args) => merge(c_args),
         SubCommand::Make(c_args) => make(c_args),
+        SubCommand::Verify(c_args) => verify(c_args),
     }
 }
 
@@ -125,41 +265,112 @@ fn load_obj(buf: &[u8]) -> Result<File> {
     Ok(obj)
 }
 
-/// Attempt to match the section index from the ELF to the original REL.
-/// Our built ELFs may be missing sections that were present in the original RELs.
-fn match_section_index(
+/// Matches sections in the built ELF to sections in the original REL, returning a map of
+/// ELF section indices to REL section indices.
+/// Our built ELFs may be missing sections that were present in the original RELs, or order them
+/// differently. Sections are matched by name using the `splits.txt` section definitions when
+/// available, then by kind and size. Empty ELF sections without a match are omitted.
+fn match_sections(
     obj: &File,
-    section_index: SectionIndex,
     rel_sections: &[RelSectionHeader],
-) -> Result<usize> {
-    let (_, _) = (obj, rel_sections);
-    Ok(section_index.0)
-    // TODO
-    // rel_sections
-    //     .iter()
-    //     .enumerate()
-    //     .filter(|(_, s)| s.size() > 0)
-    //     .zip(obj.sections().filter(|s| s.size() > 0))
-    //     .find_map(
-    //         |((rel_section_index, _), obj_section)| {
-    //             if obj_section.index() == section_index {
-    //                 Some(rel_section_index)
-    //             } else {
-    //                 None
-    //             }
-    //         },
-    //     )
-    //     .ok_or_else(|| {
-    //         anyhow!(
-    //             "Failed to find matching section index for {} ({}), REL section count: {}",
-    //             obj.section_by_index(section_index)
-    //                 .ok()
-    //                 .and_then(|s| s.name().ok().map(|s| s.to_string()))
-    //                 .unwrap_or("[invalid]".to_string()),
-    //             section_index.0,
-    //             rel_sections.len()
-    //         )
-    //     })
+    section_defs: Option<&[SectionDef]>,
+) -> Result<BTreeMap<usize, usize>> {
+    struct RelSection<'a> {
+        index: usize,
+        name: Option<&'a str>,
+        size: u32,
+        kind: SectionKind,
+    }
+
+    let mut candidates = rel_sections
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.size() > 0)
+        .map(|(index, s)| RelSection {
+            index,
+            name: None,
+            size: s.size(),
+            kind: if s.offset() == 0 {
+                SectionKind::UninitializedData
+            } else if s.exec() {
+                SectionKind::Text
+            } else {
+                SectionKind::Data
+            },
+        })
+        .collect::<Vec<_>>();
+    // Section definitions only list the non-empty sections, in order
+    match section_defs {
+        Some(defs) if defs.len() == candidates.len() => {
+            for (candidate, def) in candidates.iter_mut().zip(defs) {
+                candidate.name = Some(&def.name);
+            }
+        }
+        Some(defs) => log::warn!(
+            "Section definitions don't match REL: {} != {} sections",
+            defs.len(),
+            candidates.len()
+        ),
+        None => {}
+    }
+
+    let kind_matches = |section: &object::Section, candidate: &RelSection| match section.kind() {
+        SectionKind::Text => candidate.kind == SectionKind::Text,
+        SectionKind::UninitializedData => candidate.kind == SectionKind::UninitializedData,
+        _ => candidate.kind == SectionKind::Data,
+    };
+    let obj_sections = obj.sections().filter(is_permitted_section).collect::<Vec<_>>();
+    let mut used = vec![false; rel_sections.len()];
+    let mut map = BTreeMap::new();
+    let mut assign = |map: &mut BTreeMap<usize, usize>,
+                      section: &object::Section,
+                      predicate: &dyn Fn(&RelSection) -> bool| {
+        if map.contains_key(&section.index().0) {
+            return;
+        }
+        if let Some(candidate) =
+            candidates.iter().find(|c| !used[c.index] && kind_matches(section, c) && predicate(c))
+        {
+            used[candidate.index] = true;
+            map.insert(section.index().0, candidate.index);
+        }
+    };
+    for section in obj_sections.iter().filter(|s| s.size() > 0) {
+        let name = section.name()?;
+        assign(&mut map, section, &|c| c.name == Some(name));
+    }
+    for section in obj_sections.iter().filter(|s| s.size() > 0) {
+        assign(&mut map, section, &|c| c.name.is_none() && c.size == section.size() as u32);
+    }
+    for section in obj_sections.iter().filter(|s| s.size() > 0) {
+        assign(&mut map, section, &|c| c.name.is_none());
+    }
+    for section in obj_sections.iter().filter(|s| s.size() > 0) {
+        ensure!(
+            map.contains_key(&section.index().0),
+            "Failed to find matching REL section for {} ({}), REL section count: {}",
+            section.name().unwrap_or("[invalid]"),
+            section.index().0,
+            rel_sections.len()
+        );
+    }
+
+    // Place empty sections in unused empty slots, preserving order
+    let mut last_index = 0;
+    for section in &obj_sections {
+        if let Some(&index) = map.get(&section.index().0) {
+            last_index = index;
+            continue;
+        }
+        if let Some(index) = (last_index + 1..rel_sections.len())
+            .find(|&i| !used[i] && rel_sections[i].size() == 0 && rel_sections[i].offset() == 0)
+        {
+            used[index] = true;
+            map.insert(section.index().0, index);
+            last_index = index;
+        }
+    }
+    Ok(map)
 }
 
 fn load_rel(module_config: &ModuleConfig) -> Result<RelInfo> {
@@ -180,7 +391,7 @@ fn load_rel(module_config: &ModuleConfig) -> Result<RelInfo> {
 
 fn resolve_relocations(
     module: &File,
-    existing_headers: &BTreeMap<u32, RelInfo>,
+    section_maps: &BTreeMap<u32, BTreeMap<usize, usize>>,
     module_id: usize,
     symbol_map: &FxHashMap<&[u8], (usize, SymbolIndex)>,
     modules: &[(File, PathBuf)],
@@ -191,12 +402,7 @@ fn resolve_relocations(
         if !matches!(section.name(), Ok(name) if PERMITTED_SECTIONS.contains(&name)) {
             continue;
         }
-        let section_index =
-            if let Some((_, sections, _)) = existing_headers.get(&(module_id as u32)) {
-                match_section_index(module, section.index(), sections)?
-            } else {
-                section.index().0
-            } as u8;
+        let section_index = rel_section_index(section_maps, module_id, section.index())?;
         for (address, reloc) in section.relocations() {
             let reloc_target = match reloc.target() {
                 RelocationTarget::Symbol(idx) => {
@@ -223,13 +429,8 @@ fn resolve_relocations(
                 (module_id, reloc_target)
             };
             let target_section_index = target_symbol.section_index().unwrap();
-            let target_section = if let Some((_, sections, _)) =
-                existing_headers.get(&(target_module_id as u32))
-            {
-                match_section_index(&modules[target_module_id].0, target_section_index, sections)?
-            } else {
-                target_section_index.0
-            } as u8;
+            let target_section =
+                rel_section_index(section_maps, target_module_id, target_section_index)?;
             relocations.push(RelReloc {
                 kind: to_obj_reloc_kind(reloc.kind())?,
                 section: section_index,
@@ -246,13 +447,46 @@ fn resolve_relocations(
     Ok(resolved)
 }
 
+/// Maps an ELF section index to the REL section index, using the matched sections if available.
+fn rel_section_index(
+    section_maps: &BTreeMap<u32, BTreeMap<usize, usize>>,
+    module_id: usize,
+    section_index: SectionIndex,
+) -> Result<u8> {
+    let index = match section_maps.get(&(module_id as u32)) {
+        Some(map) => *map.get(&section_index.0).with_context(|| {
+            format!(
+                "No matching REL section for section {} in module {}",
+                section_index.0, module_id
+            )
+        })?,
+        None => section_index.0,
+    };
+    Ok(index as u8)
+}
+
 type RelInfo = (RelHeader, Vec<RelSectionHeader>, Option<Vec<SectionDef>>);
 
+/// Builds the module name string table, returning its data and the name offset and size
+/// of each module.
+fn build_str_table(names: &[(u32, String)]) -> Result<(Vec<u8>, BTreeMap<u32, (u32, u32)>)> {
+    let mut data = Vec::new();
+    let mut entries = BTreeMap::new();
+    for (module_id, name) in names {
+        ensure!(!name.contains('\0'), "Invalid module name '{}'", name);
+        entries.insert(*module_id, (data.len() as u32, name.len() as u32));
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+    }
+    Ok((data, entries))
+}
+
 fn make(args: MakeArgs) -> Result<()> {
     let total = Instant::now();
 
     // Load existing REL headers (if specified)
     let mut existing_headers = BTreeMap::<u32, RelInfo>::new();
+    let mut str_names = BTreeMap::<u32, String>::new();
     if let Some(config_path) = &args.config {
         let config: ProjectConfig = serde_yaml::from_reader(&mut buf_reader(config_path)?)?;
         for module_config in &config.modules {
@@ -263,6 +497,11 @@ fn make(args: MakeArgs) -> Result<()> {
             let info = load_rel(module_config).with_context(|| {
                 format!("While loading REL '{}'", module_config.object.display())
             })?;
+            let str_name = module_config
+                .str_name
+                .clone()
+                .unwrap_or_else(|| module_config.file_name().to_string());
+            str_names.insert(info.0.module_id, str_name);
             match existing_headers.entry(info.0.module_id) {
                 btree_map::Entry::Vacant(e) => e.insert(info),
                 btree_map::Entry::Occupied(_) => {
@@ -301,6 +540,16 @@ fn make(args: MakeArgs) -> Result<()> {
         }
     }
 
+    // Match sections to existing RELs
+    let mut section_maps = BTreeMap::<u32, BTreeMap<usize, usize>>::new();
+    for (module_id, (module, path)) in modules.iter().enumerate().skip(1) {
+        if let Some((_, sections, section_defs)) = existing_headers.get(&(module_id as u32)) {
+            let map = match_sections(module, sections, section_defs.as_deref())
+                .with_context(|| format!("While matching sections in '{}'", path.display()))?;
+            section_maps.insert(module_id as u32, map);
+        }
+    }
+
     // Resolve relocations
     let mut resolved = 0usize;
     let mut relocations = Vec::<Vec<RelReloc>>::with_capacity(modules.len() - 1);
@@ -311,7 +560,7 @@ fn make(args: MakeArgs) -> Result<()> {
         let _span = info_span!("file", path = %path.display()).entered();
         resolved += resolve_relocations(
             module,
-            &existing_headers,
+            &section_maps,
             module_id,
             &symbol_map,
             &modules,
@@ -330,6 +579,31 @@ fn make(args: MakeArgs) -> Result<()> {
         );
     }
 
+    // Generate module name string table
+    let mut str_entries = BTreeMap::<u32, (u32, u32)>::new();
+    if let Some(str_path) = &args.str_file {
+        let names = modules
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(module_id, (_, path))| {
+                let name = match str_names.get(&(module_id as u32)) {
+                    Some(name) => name.clone(),
+                    None => path
+                        .with_extension("rel")
+                        .file_name()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                };
+                (module_id as u32, name)
+            })
+            .collect::<Vec<_>>();
+        let (str_data, entries) = build_str_table(&names)?;
+        fs::write(str_path, str_data)
+            .with_context(|| format!("Failed to write '{}'", str_path.display()))?;
+        str_entries = entries;
+    }
+
     // Write RELs
     let start = Instant::now();
     for ((module_id, (module, path)), relocations) in
@@ -346,18 +620,59 @@ fn make(args: MakeArgs) -> Result<()> {
             section_count: None,
             quiet: args.no_warn,
             section_align: None,
+            section_map: None,
         };
-        if let Some((header, _, section_defs)) = existing_headers.get(&(module_id as u32)) {
+        if let Some((header, sections, section_defs)) = existing_headers.get(&(module_id as u32)) {
             info.version = header.version;
             info.name_offset = Some(header.name_offset);
             info.name_size = Some(header.name_size);
             info.align = header.align;
             info.bss_align = header.bss_align;
             info.section_count = Some(header.num_sections as usize);
-            info.section_align = section_defs
-                .as_ref()
-                .map(|defs| defs.iter().map(|def| def.align).collect())
-                .unwrap_or_default();
+            let section_map = &section_maps[&(module_id as u32)];
+            if let Some(defs) = section_defs {
+                // Section definitions list the non-empty REL sections, in order
+                let def_aligns = sections
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| s.size() > 0)
+                    .zip(defs)
+                    .filter_map(|((index, _), def)| def.align.map(|align| (index, align)))
+                    .collect::<BTreeMap<_, _>>();
+                let mut aligns = section_map
+                    .iter()
+                    .map(|(&elf_index, &rel_index)| -> Result<(usize, u32)> {
+                        let align = match def_aligns.get(&rel_index) {
+                            Some(&align) => align,
+                            None => {
+                                module.section_by_index(SectionIndex(elf_index))?.align() as u32
+                            }
+                        };
+                        Ok((rel_index, align))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                aligns.sort_by_key(|&(rel_index, _)| rel_index);
+                info.section_align = Some(aligns.into_iter().map(|(_, align)| align).collect());
+            }
+            info.section_map = Some(section_map.clone());
+        }
+        if let Some(&(name_offset, name_size)) = str_entries.get(&(module_id as u32)) {
+            if let Some((header, _, _)) = existing_headers.get(&(module_id as u32)) {
+                if (header.name_offset, header.name_size) != (name_offset, name_size)
+                    && !args.no_warn
+                {
+                    log::warn!(
+                        "Module {} name offset/size {:#X}/{:#X} differs from original {:#X}/{:#X}",
+                        module_id,
+                        name_offset,
+                        name_size,
+                        header.name_offset,
+                        header.name_size
+                    );
+                }
+            }
+            info.name_offset = Some(name_offset);
+            info.name_size = Some(name_size);
         }
         let rel_path = path.with_extension("rel");
         let mut w = buf_writer(&rel_path)?;
@@ -376,6 +691,362 @@ fn make(args: MakeArgs) -> Result<()> {
     Ok(())
 }
 
+#[derive(Serialize, Default)]
+struct ImportGraph {
+    modules: Vec<GraphModule>,
+    edges: Vec<GraphEdge>,
+    unresolved: Vec<GraphImport>,
+    non_exported: Vec<GraphImport>,
+    cycles: Vec<Vec<u32>>,
+}
+
+#[derive(Serialize)]
+struct GraphModule {
+    id: u32,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct GraphEdge {
+    from: u32,
+    to: u32,
+    count: usize,
+    sections: Vec<GraphSectionEdge>,
+}
+
+#[derive(Serialize)]
+struct GraphSectionEdge {
+    from: String,
+    to: String,
+    count: usize,
+}
+
+/// A single import, reported when it can't be resolved or targets a local symbol.
+#[derive(Serialize)]
+struct GraphImport {
+    module: u32,
+    section: String,
+    address: u32,
+    kind: ObjRelocKind,
+    target_module: u32,
+    target_section: u8,
+    addend: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symbol: Option<String>,
+    reason: String,
+}
+
+fn graph(args: GraphArgs) -> Result<()> {
+    log::info!("Loading {}", args.dol_file.display());
+    let mut objs = {
+        let file = map_file(&args.dol_file)?;
+        let name = args.dol_file.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+        vec![process_dol(file.as_slice(), name.as_ref())?]
+    };
+    for result in FileIterator::new(&args.rel_files)? {
+        let (path, entry) = result?;
+        log::info!("Loading {}", path.display());
+        let name = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+        let (_, obj) = process_rel(&mut entry.as_reader(), name.as_ref())?;
+        ensure!(
+            !objs.iter().any(|o| o.module_id == obj.module_id),
+            "Duplicate module ID {}",
+            obj.module_id
+        );
+        objs.push(obj);
+    }
+
+    if let Some(config_path) = &args.config {
+        let config: ProjectConfig = serde_yaml::from_reader(&mut buf_reader(config_path)?)?;
+        if let Some(symbols_path) = &config.base.symbols {
+            apply_symbols_file(symbols_path, &mut objs[0])?;
+        }
+        for module_config in &config.modules {
+            let header = process_rel_header(&mut map_file(&module_config.object)?.as_reader())?;
+            let Some(obj) = objs.iter_mut().find(|o| o.module_id == header.module_id) else {
+                continue;
+            };
+            if let Some(splits_path) = &module_config.splits {
+                apply_splits_file(splits_path, obj)?;
+            }
+            if let Some(symbols_path) = &module_config.symbols {
+                apply_symbols_file(symbols_path, obj)?;
+            }
+        }
+    }
+
+    let result = build_import_graph(&objs)?;
+    if !result.unresolved.is_empty() {
+        log::warn!("{} unresolved import(s)", result.unresolved.len());
+    }
+    if !result.non_exported.is_empty() {
+        log::warn!("{} import(s) of local symbols", result.non_exported.len());
+    }
+    for cycle in &result.cycles {
+        log::warn!("Import cycle between modules {}", cycle.iter().join(", "));
+    }
+
+    let format = args
+        .format
+        .or_else(|| {
+            let ext = args.out.as_ref()?.extension()?.to_str()?.to_ascii_lowercase();
+            GraphFormat::from_str(&ext).ok()
+        })
+        .unwrap_or(GraphFormat::Dot);
+    let mut w: Box<dyn Write> = match &args.out {
+        Some(out_path) => Box::new(buf_writer(out_path)?),
+        None => Box::new(stdout()),
+    };
+    match format {
+        GraphFormat::Dot => write_graph_dot(&mut w, &result)?,
+        GraphFormat::Json => {
+            serde_json::to_writer_pretty(&mut w, &result)?;
+            writeln!(w)?;
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Builds the import graph of the DOL (first) and RELs from their unresolved relocations.
+fn build_import_graph(objs: &[ObjInfo]) -> Result<ImportGraph> {
+    let section_name = |obj: &ObjInfo, elf_index: u8| {
+        obj.sections
+            .iter()
+            .find(|(_, s)| s.elf_index == elf_index as usize)
+            .map(|(_, s)| s.name.clone())
+            .unwrap_or_else(|| format!("[{}]", elf_index))
+    };
+    let mut result = ImportGraph {
+        modules: objs
+            .iter()
+            .map(|o| GraphModule { id: o.module_id, name: o.name.clone() })
+            .collect(),
+        ..Default::default()
+    };
+    let mut edges = BTreeMap::<(u32, u32), BTreeMap<(String, String), usize>>::new();
+    for obj in objs.iter().skip(1) {
+        for rel_reloc in &obj.unresolved_relocations {
+            if rel_reloc.module_id == obj.module_id {
+                continue;
+            }
+            let import = |reason: &str, symbol: Option<String>| GraphImport {
+                module: obj.module_id,
+                section: section_name(obj, rel_reloc.section),
+                address: rel_reloc.address,
+                kind: rel_reloc.kind,
+                target_module: rel_reloc.module_id,
+                target_section: rel_reloc.target_section,
+                addend: rel_reloc.addend,
+                symbol,
+                reason: reason.to_string(),
+            };
+            let Some(target_obj) = objs.iter().find(|o| o.module_id == rel_reloc.module_id) else {
+                result.unresolved.push(import("module not found", None));
+                continue;
+            };
+            let target = if rel_reloc.module_id == 0 {
+                target_obj.sections.at_address(rel_reloc.addend).ok()
+            } else {
+                target_obj
+                    .sections
+                    .iter()
+                    .find(|(_, s)| s.elf_index == rel_reloc.target_section as usize)
+                    .filter(|(_, s)| rel_reloc.addend as u64 <= s.size)
+            };
+            let Some((target_section_index, target_section)) = target else {
+                result.unresolved.push(import("target outside of module sections", None));
+                continue;
+            };
+            if let Some((_, symbol)) = target_obj.symbols.for_relocation(
+                SectionAddress::new(target_section_index, rel_reloc.addend),
+                rel_reloc.kind,
+            )? {
+                if symbol.flags.is_local() {
+                    result.non_exported.push(import("local symbol", Some(symbol.name.clone())));
+                }
+            }
+            *edges
+                .entry((obj.module_id, rel_reloc.module_id))
+                .or_default()
+                .entry((section_name(obj, rel_reloc.section), target_section.name.clone()))
+                .or_default() += 1;
+        }
+    }
+
+    // Detect cycles between modules
+    let mut module_graph = Graph::<u32, ()>::new();
+    let nodes = objs
+        .iter()
+        .map(|o| (o.module_id, module_graph.add_node(o.module_id)))
+        .collect::<BTreeMap<_, _>>();
+    for &(from, to) in edges.keys() {
+        module_graph.add_edge(nodes[&from], nodes[&to], ());
+    }
+    for component in tarjan_scc(&module_graph) {
+        if component.len() > 1 {
+            let mut cycle = component.iter().map(|&n| module_graph[n]).collect::<Vec<_>>();
+            cycle.sort_unstable();
+            result.cycles.push(cycle);
+        }
+    }
+    result.cycles.sort();
+
+    result.edges = edges
+        .into_iter()
+        .map(|((from, to), sections)| GraphEdge {
+            from,
+            to,
+            count: sections.values().sum(),
+            sections: sections
+                .into_iter()
+                .map(|((from, to), count)| GraphSectionEdge { from, to, count })
+                .collect(),
+        })
+        .collect();
+    Ok(result)
+}
+
+fn write_graph_dot<W>(w: &mut W, graph: &ImportGraph) -> Result<()>
+where W: Write + ?Sized {
+    let cycle_modules = graph.cycles.iter().flatten().collect::<BTreeSet<_>>();
+    writeln!(w, "digraph modules {{")?;
+    writeln!(w, "    node [shape=box];")?;
+    for module in &graph.modules {
+        write!(w, "    m{} [label=\"{} ({})\"", module.id, module.name, module.id)?;
+        if cycle_modules.contains(&module.id) {
+            write!(w, ", color=orange")?;
+        }
+        writeln!(w, "];")?;
+    }
+    for edge in &graph.edges {
+        let tooltip = edge
+            .sections
+            .iter()
+            .map(|s| format!("{} -> {}: {}", s.from, s.to, s.count))
+            .join("\\n");
+        writeln!(
+            w,
+            "    m{} -> m{} [label=\"{}\", tooltip=\"{}\"];",
+            edge.from, edge.to, edge.count, tooltip
+        )?;
+    }
+    let mut unresolved = BTreeMap::<(u32, u32), usize>::new();
+    for import in &graph.unresolved {
+        *unresolved.entry((import.module, import.target_module)).or_default() += 1;
+    }
+    for (&(from, to), count) in &unresolved {
+        if !graph.modules.iter().any(|m| m.id == to) {
+            writeln!(w, "    m{} [label=\"? ({})\", style=dashed];", to, to)?;
+        }
+        writeln!(
+            w,
+            "    m{} -> m{} [label=\"{} unresolved\", style=dashed, color=red];",
+            from, to, count
+        )?;
+    }
+    writeln!(w, "}}")?;
+    Ok(())
+}
+
+/// Parses a `section:offset` entry point.
+fn parse_entry(s: &str) -> Result<(u8, u32)> {
+    let (section, offset) =
+        s.split_once(':').with_context(|| format!("Expected section:offset, got '{}'", s))?;
+    let section =
+        section.trim().parse::<u8>().with_context(|| format!("Invalid section '{}'", s))?;
+    Ok((section, parse_hex(offset)?))
+}
+
+fn edit(args: EditArgs) -> Result<()> {
+    let mut data = fs::read(&args.rel_file)
+        .with_context(|| format!("Failed to read '{}'", args.rel_file.display()))?;
+    let original = process_rel_header(&mut Cursor::new(&data))?;
+    let mut header = original.clone();
+
+    if let Some(module_id) = args.module_id {
+        header.module_id = module_id;
+    }
+    for (name, value, field) in [
+        ("align", &args.align, &mut header.align),
+        ("bss_align", &args.bss_align, &mut header.bss_align),
+        ("fix_size", &args.fix_size, &mut header.fix_size),
+    ] {
+        let Some(value) = value else { continue };
+        let Some(field) = field else {
+            bail!("Version {} RELs have no {} field", original.version, name);
+        };
+        *field = parse_hex(value)?;
+    }
+    for (value, section, offset) in [
+        (&args.prolog, &mut header.prolog_section, &mut header.prolog_offset),
+        (&args.epilog, &mut header.epilog_section, &mut header.epilog_offset),
+        (&args.unresolved, &mut header.unresolved_section, &mut header.unresolved_offset),
+    ] {
+        if let Some(value) = value {
+            (*section, *offset) = parse_entry(value)?;
+        }
+    }
+    let sections = process_rel_sections(&mut Cursor::new(&data), &header)?;
+    for (name, section) in [
+        ("_prolog", header.prolog_section),
+        ("_epilog", header.epilog_section),
+        ("_unresolved", header.unresolved_section),
+    ] {
+        if section != 0 {
+            ensure!(
+                sections.get(section as usize).is_some_and(|s| s.offset() != 0 && s.exec()),
+                "{} section {} is not an executable section",
+                name,
+                section
+            );
+        }
+    }
+
+    rewrite_rel_header(&mut data, &header)?;
+    for violation in verify_rel(&mut Cursor::new(&data), None)? {
+        log::warn!("{:#X}: {}", violation.offset, violation.message);
+    }
+
+    let out_path = args.out_file.as_ref().unwrap_or(&args.rel_file);
+    log::info!("Writing {}", out_path.display());
+    fs::write(out_path, data)
+        .with_context(|| format!("Failed to write '{}'", out_path.display()))?;
+    Ok(())
+}
+
+fn verify(args: VerifyArgs) -> Result<()> {
+    let load_address = args.address.as_deref().map(parse_hex).transpose()?;
+    let mut errors = 0usize;
+    for result in FileIterator::new(&args.rel_files)? {
+        let (path, entry) = result?;
+        let violations = verify_rel(&mut entry.as_reader(), load_address)
+            .with_context(|| format!("Failed to verify '{}'", path.display()))?;
+        if violations.is_empty() {
+            println!("{}: OK", path.display());
+            continue;
+        }
+        for violation in &violations {
+            let severity = match violation.severity {
+                RelSeverity::Warning => "warning",
+                RelSeverity::Error => {
+                    errors += 1;
+                    "error"
+                }
+            };
+            println!(
+                "{}:{:#X}: {}: {}",
+                path.display(),
+                violation.offset,
+                severity,
+                violation.message
+            );
+        }
+    }
+    ensure!(errors == 0, "REL verification failed with {} error(s)", errors);
+    Ok(())
+}
+
 fn info(args: InfoArgs) -> Result<()> {
     let file = map_file(args.rel_file)?;
     let (header, mut module_obj) = process_rel(&mut file.as_reader(), "")?;
@@ -583,49 +1254,712 @@ fn merge(args: MergeArgs) -> Result<()> {
     Ok(())
 }
 
+/// Start of the flat memory image.
+const MEM1_BASE: u32 = 0x80000000;
+/// `__OSModuleList` head and tail pointers in low memory.
+const OS_MODULE_LIST: u32 = 0x800030C8;
+
+/// A REL placed in memory by [`link`].
+struct LinkedModule {
+    name: String,
+    header: RelHeader,
+    obj: ObjInfo,
+    /// REL file contents, updated in place as OSLink would.
+    data: Vec<u8>,
+    /// Load address.
+    base: u32,
+    /// Size of the REL in memory. (`fixSize` with OSLinkFixed)
+    size: u32,
+    /// BSS address.
+    bss: u32,
+}
+
+fn parse_hex(s: &str) -> Result<u32> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    u32::from_str_radix(s, 16).with_context(|| format!("Invalid address '{}'", s))
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: u32) -> u32 {
+    u32::from_be_bytes(*array_ref!(data, offset as usize, 4))
+}
+
+#[inline]
+fn write_u32(data: &mut [u8], offset: u32, value: u32) {
+    *array_ref_mut!(data, offset as usize, 4) = value.to_be_bytes();
+}
+
+fn link(args: LinkArgs) -> Result<()> {
+    log::info!("Loading {}", args.dol_file.display());
+    let mut obj = {
+        let file = map_file(&args.dol_file)?;
+        let name = args.dol_file.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+        process_dol(file.as_slice(), name.as_ref())?
+    };
+    let addresses = args.address.iter().map(|s| parse_hex(s)).collect::<Result<Vec<_>>>()?;
+    let bss_addresses = args.bss.iter().map(|s| parse_hex(s)).collect::<Result<Vec<_>>>()?;
+
+    // Place modules
+    let mut modules = Vec::<LinkedModule>::new();
+    let mut next_address = None;
+    for (idx, result) in FileIterator::new(&args.rel_files)?.enumerate() {
+        let (path, entry) = result?;
+        log::info!("Loading {}", path.display());
+        let name = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+        let (header, module_obj) = process_rel(&mut entry.as_reader(), name.as_ref())?;
+        ensure!(
+            !modules.iter().any(|m| m.header.module_id == header.module_id),
+            "Duplicate module ID {}",
+            header.module_id
+        );
+        let data = entry.as_slice().to_vec();
+
+        let base = match (addresses.get(idx), next_address) {
+            (Some(&address), _) => address,
+            (None, Some(address)) => address,
+            (None, None) => bail!("Missing load address for {}", path.display()),
+        };
+        let size = if args.fixed {
+            let Some(fix_size) = header.fix_size else {
+                bail!("OSLinkFixed requires a version 3 REL: {}", path.display())
+            };
+            ensure!(
+                fix_size as usize <= data.len(),
+                "fix_size {:#X} beyond end of file ({:#X}): {}",
+                fix_size,
+                data.len(),
+                path.display()
+            );
+            fix_size
+        } else {
+            data.len() as u32
+        };
+        let bss_align = header.bss_align.unwrap_or(32).max(1);
+        let bss = match bss_addresses.get(idx) {
+            Some(&address) => address,
+            None => align_up(base + size, bss_align),
+        };
+        if let Some(align) = header.align.filter(|&a| a > 1) {
+            ensure!(base % align == 0, "Load address {:#010X} not aligned to {:#X}", base, align);
+        }
+        ensure!(bss % bss_align == 0, "BSS address {:#010X} not aligned to {:#X}", bss, bss_align);
+        log::info!(
+            "Module {} ({}) at {:#010X}, BSS at {:#010X}",
+            header.module_id,
+            name,
+            base,
+            bss
+        );
+        next_address = Some(align_up((base + size).max(bss + header.bss_size), 32));
+        modules.push(LinkedModule {
+            name: name.to_string(),
+            header,
+            obj: module_obj,
+            data,
+            base,
+            size,
+            bss,
+        });
+    }
+
+    let section_map = link_modules(&mut modules, args.fixed)?;
+
+    // Write flat memory image
+    if let Some(image_path) = &args.image {
+        let mut end = OS_MODULE_LIST + 8;
+        for (_, section) in obj.sections.iter() {
+            end = end.max((section.address + section.size) as u32);
+        }
+        for module in &modules {
+            end = end.max(module.base + module.size).max(module.bss + module.header.bss_size);
+        }
+        ensure!(end > MEM1_BASE, "Nothing to write below {:#010X}", MEM1_BASE);
+        let mut image = vec![0u8; (end - MEM1_BASE) as usize];
+        for (_, section) in obj.sections.iter() {
+            let start = section.address as u32;
+            ensure!(start >= MEM1_BASE, "Section {} below {:#010X}", section.name, MEM1_BASE);
+            let offset = (start - MEM1_BASE) as usize;
+            image[offset..offset + section.data.len()].copy_from_slice(&section.data);
+        }
+        for module in &modules {
+            ensure!(module.base >= MEM1_BASE, "Module {} below {:#010X}", module.name, MEM1_BASE);
+            let offset = (module.base - MEM1_BASE) as usize;
+            let size = module.size as usize;
+            image[offset..offset + size].copy_from_slice(&module.data[..size]);
+        }
+        for module in &modules {
+            ensure!(
+                module.bss >= MEM1_BASE,
+                "Module {} BSS below {:#010X}",
+                module.name,
+                MEM1_BASE
+            );
+            let offset = (module.bss - MEM1_BASE) as usize;
+            image[offset..offset + module.header.bss_size as usize].fill(0);
+        }
+        if let (Some(first), Some(last)) = (modules.first(), modules.last()) {
+            write_u32(&mut image, OS_MODULE_LIST - MEM1_BASE, first.base);
+            write_u32(&mut image, OS_MODULE_LIST + 4 - MEM1_BASE, last.base);
+        }
+        log::info!("Writing {}", image_path.display());
+        fs::write(image_path, image)?;
+    }
+
+    // Add REL sections to the DOL
+    for module in &modules {
+        let module_id = module.header.module_id;
+        for (mod_section_index, mod_section) in module.obj.sections.iter() {
+            let address = section_map
+                .get(&module_id)
+                .and_then(|sections| sections.get(&(mod_section.elf_index as u32)))
+                .copied()
+                .with_context(|| {
+                    format!("Section {} is empty or missing in {}", mod_section.name, module.name)
+                })?;
+            let data = if mod_section.kind == ObjSectionKind::Bss {
+                vec![]
+            } else {
+                let start = mod_section.file_offset as usize;
+                module.data[start..start + mod_section.size as usize].to_vec()
+            };
+            let section_idx = obj.sections.push(ObjSection {
+                name: format!("{}:{}", mod_section.name, module_id),
+                kind: mod_section.kind,
+                address: address as u64,
+                size: mod_section.size,
+                data,
+                align: mod_section.align,
+                elf_index: mod_section.elf_index,
+                relocations: Default::default(),
+                original_address: mod_section.original_address,
+                file_offset: mod_section.file_offset,
+                section_known: mod_section.section_known,
+                splits: Default::default(),
+            });
+            for (_, mod_symbol) in module.obj.symbols.for_section(mod_section_index) {
+                obj.symbols.add_direct(ObjSymbol {
+                    address: mod_symbol.address + address as u64,
+                    section: Some(section_idx),
+                    ..mod_symbol.clone()
+                })?;
+            }
+        }
+    }
+
+    log::info!("Writing {}", args.out_file.display());
+    fs::write(&args.out_file, write_elf(&obj)?)?;
+    Ok(())
+}
+
+/// Resolves section addresses, applies relocations between the placed modules and updates
+/// their headers and module list links as OSLink does. Returns the section addresses of
+/// each module.
+fn link_modules(
+    modules: &mut [LinkedModule],
+    fixed: bool,
+) -> Result<BTreeMap<u32, BTreeMap<u32, u32>>> {
+    // Resolve section addresses
+    let mut section_map: BTreeMap<u32, BTreeMap<u32, u32>> = BTreeMap::new();
+    for module in modules.iter() {
+        let sections = process_rel_sections(&mut Cursor::new(&module.data), &module.header)?;
+        // BSS sections are placed back to back
+        let mut bss = module.bss;
+        for (idx, section) in sections.iter().enumerate() {
+            let address = match section.offset() {
+                0 if section.size() != 0 => {
+                    bss += section.size();
+                    bss - section.size()
+                }
+                0 => continue,
+                offset => module.base + offset,
+            };
+            section_map.nested_insert(module.header.module_id, idx as u32, address)?;
+        }
+    }
+
+    // Apply relocations
+    let mut unresolved = 0usize;
+    for module in modules.iter_mut() {
+        let module_id = module.header.module_id;
+        let sections = process_rel_sections(&mut Cursor::new(&module.data), &module.header)?;
+        for rel_reloc in &module.obj.unresolved_relocations {
+            let target_address = if rel_reloc.module_id == 0 {
+                rel_reloc.addend
+            } else if let Some(target_sections) = section_map.get(&rel_reloc.module_id) {
+                let section_address =
+                    target_sections.get(&(rel_reloc.target_section as u32)).with_context(|| {
+                        format!(
+                            "Relocation against unknown section {} in module {}",
+                            rel_reloc.target_section, rel_reloc.module_id
+                        )
+                    })?;
+                section_address + rel_reloc.addend
+            } else {
+                // Module not loaded; OSLink leaves these for a later link
+                unresolved += 1;
+                continue;
+            };
+            let source = sections
+                .get(rel_reloc.section as usize)
+                .with_context(|| format!("Relocation in unknown section {}", rel_reloc.section))?;
+            ensure!(source.offset() != 0, "Relocation in BSS section {}", rel_reloc.section);
+            let offset = source.offset() + rel_reloc.address;
+            patch_relocation(
+                &mut module.data,
+                offset as usize,
+                rel_reloc.kind,
+                module.base + offset,
+                target_address,
+            )
+            .with_context(|| {
+                format!(
+                    "Failed to apply {:?} relocation at {:#010X} in {}",
+                    rel_reloc.kind,
+                    module.base + offset,
+                    module.name
+                )
+            })?;
+        }
+
+        // Update header as OSLink does
+        let base = module.base;
+        let data = &mut module.data;
+        let header = &module.header;
+        let mut bss = module.bss;
+        for (idx, section) in sections.iter().enumerate() {
+            let entry = header.section_info_offset + idx as u32 * 8;
+            if section.offset() != 0 {
+                let offset = read_u32(data, entry) + base;
+                write_u32(data, entry, offset);
+            } else if section.size() != 0 {
+                write_u32(data, entry, bss);
+                bss += section.size();
+                data[0x33] = idx as u8; // bss_section
+            }
+        }
+        let section_address = |section: u8| -> Result<u32> {
+            section_map
+                .get(&module_id)
+                .and_then(|sections| sections.get(&(section as u32)))
+                .copied()
+                .with_context(|| {
+                    format!("Section {} is empty or missing in {}", section, module.name)
+                })
+        };
+        if header.prolog_section != 0 {
+            write_u32(data, 0x34, section_address(header.prolog_section)? + header.prolog_offset);
+        }
+        if header.epilog_section != 0 {
+            write_u32(data, 0x38, section_address(header.epilog_section)? + header.epilog_offset);
+        }
+        if header.unresolved_section != 0 {
+            write_u32(
+                data,
+                0x3C,
+                section_address(header.unresolved_section)? + header.unresolved_offset,
+            );
+        }
+        let mut imp_size = header.imp_size;
+        for imp_idx in 0..header.imp_size / 8 {
+            let imp = header.imp_offset + imp_idx * 8;
+            let imp_module_id = read_u32(data, imp);
+            if fixed && (imp_module_id == 0 || imp_module_id == module_id) {
+                // OSLinkFixed discards the imports that have been fully applied
+                imp_size = imp_size.min(imp_idx * 8);
+            }
+            let offset = read_u32(data, imp + 4) + base;
+            write_u32(data, imp + 4, offset);
+        }
+        write_u32(data, 0x10, header.section_info_offset + base);
+        write_u32(data, 0x24, header.rel_offset + base);
+        write_u32(data, 0x28, header.imp_offset + base);
+        write_u32(data, 0x2C, imp_size);
+    }
+    if unresolved > 0 {
+        log::warn!("Skipped {} relocation(s) against modules that were not provided", unresolved);
+    }
+
+    // Module list links
+    let bases = modules.iter().map(|m| m.base).collect::<Vec<_>>();
+    for (idx, module) in modules.iter_mut().enumerate() {
+        write_u32(&mut module.data, 0x4, bases.get(idx + 1).copied().unwrap_or(0));
+        write_u32(&mut module.data, 0x8, if idx > 0 { bases[idx - 1] } else { 0 });
+    }
+    Ok(section_map)
+}
+
 fn link_relocations(obj: &mut ObjInfo) -> Result<()> {
     for (_, section) in obj.sections.iter_mut() {
         for (source_address, reloc) in section.relocations.iter() {
+            if reloc.kind == ObjRelocKind::PpcEmbSda21 {
+                // Unused in RELs
+                continue;
+            }
             let target_address =
                 (obj.symbols[reloc.target_symbol].address as i64 + reloc.addend) as u32;
-            let ins_ref =
-                array_ref_mut!(section.data, (source_address as u64 - section.address) as usize, 4);
-            let mut ins = u32::from_be_bytes(*ins_ref);
-            match reloc.kind {
-                ObjRelocKind::Absolute => {
-                    ins = target_address;
-                }
-                ObjRelocKind::PpcAddr16Hi => {
-                    ins = (ins & 0xffff0000) | ((target_address >> 16) & 0xffff);
-                }
-                ObjRelocKind::PpcAddr16Ha => {
-                    ins = (ins & 0xffff0000) | (((target_address + 0x8000) >> 16) & 0xffff);
-                }
-                ObjRelocKind::PpcAddr16Lo => {
-                    ins = (ins & 0xffff0000) | (target_address & 0xffff);
-                }
-                ObjRelocKind::PpcRel24 => {
-                    let diff = target_address as i32 - source_address as i32;
-                    ensure!(
-                        (-0x2000000..0x2000000).contains(&diff),
-                        "R_PPC_REL24 relocation out of range"
-                    );
-                    ins = (ins & !0x3fffffc) | (diff as u32 & 0x3fffffc);
-                }
-                ObjRelocKind::PpcRel14 => {
-                    let diff = target_address as i32 - source_address as i32;
-                    ensure!(
-                        (-0x2000..0x2000).contains(&diff),
-                        "R_PPC_REL14 relocation out of range"
-                    );
-                    ins = (ins & !0xfffc) | (diff as u32 & 0xfffc);
-                }
-                ObjRelocKind::PpcEmbSda21 => {
-                    // Unused in RELs
-                }
-            };
-            *ins_ref = ins.to_be_bytes();
+            patch_relocation(
+                &mut section.data,
+                (source_address as u64 - section.address) as usize,
+                reloc.kind,
+                source_address,
+                target_address,
+            )?;
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use object::write::Object as WriteObject;
+
+    use super::*;
+    use crate::{
+        obj::{ObjArchitecture, ObjKind, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind},
+        util::rel::tests::write_test_rel,
+    };
+
+    fn linked_module(module_id: u32, version: u32, base: u32) -> LinkedModule {
+        let data = write_test_rel(module_id, version);
+        let (header, obj) = process_rel(&mut Cursor::new(&data), "test").unwrap();
+        let size = data.len() as u32;
+        LinkedModule {
+            name: format!("module{}", module_id),
+            header,
+            obj,
+            data,
+            base,
+            size,
+            bss: align_up(base + size, 32),
+        }
+    }
+
+    /// Returns the file offset of each section. (0 for BSS and empty sections)
+    fn section_offsets(module: &LinkedModule) -> Vec<u32> {
+        process_rel_sections(&mut Cursor::new(&module.data), &module.header)
+            .unwrap()
+            .iter()
+            .map(|s| s.offset())
+            .collect()
+    }
+
+    #[test]
+    fn test_link_modules() {
+        let mut modules = vec![linked_module(5, 2, 0x80500000), linked_module(3, 2, 0x80600000)];
+        let offsets = modules.iter().map(section_offsets).collect::<Vec<_>>();
+        let original = modules.iter().map(|m| m.data.clone()).collect::<Vec<_>>();
+        let section_map = link_modules(&mut modules, false).unwrap();
+
+        let module = &modules[0];
+        let (base, data, header) = (module.base, &module.data, &module.header);
+        let text = base + offsets[0][1];
+        assert_eq!(section_map[&5][&1], text);
+        assert_eq!(section_map[&5][&3], module.bss);
+        assert_eq!(section_map[&3][&1], 0x80600000 + offsets[1][1]);
+
+        // bl 0x80003000
+        let source = text + 0xC;
+        let expected = 0x48000001 | (0x80003000u32.wrapping_sub(source) & 0x3FFFFFC);
+        assert_eq!(read_u32(data, offsets[0][1] + 0xC), expected);
+        // Pointers to _epilog and into module 3
+        assert_eq!(read_u32(data, offsets[0][2]), text + 4);
+        assert_eq!(read_u32(data, offsets[0][2] + 4), section_map[&3][&1] + 0x10);
+
+        // Module list links
+        assert_eq!(read_u32(data, 0x4), 0x80600000);
+        assert_eq!(read_u32(data, 0x8), 0);
+        assert_eq!(read_u32(&modules[1].data, 0x8), 0x80500000);
+
+        // Offsets become addresses
+        assert_eq!(read_u32(data, 0x10), header.section_info_offset + base);
+        assert_eq!(read_u32(data, 0x24), header.rel_offset + base);
+        assert_eq!(read_u32(data, 0x28), header.imp_offset + base);
+        assert_eq!(read_u32(data, 0x2C), header.imp_size);
+        let text_entry = header.section_info_offset + 8;
+        assert_eq!(read_u32(data, text_entry), read_u32(&original[0], text_entry) + base);
+        assert_eq!(read_u32(data, header.section_info_offset + 3 * 8), module.bss);
+        assert_eq!(data[0x33], 3); // bss_section
+        assert_eq!(read_u32(data, 0x34), text);
+        assert_eq!(read_u32(data, 0x38), text + 4);
+        assert_eq!(read_u32(data, 0x3C), text + 8);
+        for imp_idx in 0..header.imp_size / 8 {
+            let imp = header.imp_offset + imp_idx * 8;
+            assert_eq!(read_u32(data, imp + 4), read_u32(&original[0], imp + 4) + base);
+        }
+    }
+
+    #[test]
+    fn test_link_modules_fixed() {
+        let mut modules = vec![linked_module(5, 3, 0x80500000), linked_module(3, 3, 0x80600000)];
+        link_modules(&mut modules, true).unwrap();
+        // Imports are ordered [3, 5, 0]; only the import from module 3 is kept
+        let (data, header) = (&modules[0].data, &modules[0].header);
+        let imports = (0..header.imp_size / 8)
+            .map(|i| read_u32(data, header.imp_offset + i * 8))
+            .collect::<Vec<_>>();
+        assert_eq!(imports, [3, 5, 0]);
+        assert_eq!(read_u32(data, 0x2C), 8);
+        // Module 3 only imports itself and the DOL
+        assert_eq!(read_u32(&modules[1].data, 0x2C), 0);
+    }
+
+    #[test]
+    fn test_link_modules_missing_prolog_section() {
+        let mut modules = vec![linked_module(5, 2, 0x80500000)];
+        modules[0].header.prolog_section = 4;
+        let error = link_modules(&mut modules, false).unwrap_err();
+        assert!(error.to_string().contains("Section 4 is empty or missing"), "{}", error);
+    }
+
+    /// Builds a relocatable ELF with the given sections. (name, kind, size)
+    fn section_elf(sections: &[(&str, SectionKind, u64)]) -> Vec<u8> {
+        let mut obj =
+            WriteObject::new(object::BinaryFormat::Elf, Architecture::PowerPc, Endianness::Big);
+        for &(name, kind, size) in sections {
+            let id = obj.add_section(vec![], name.as_bytes().to_vec(), kind);
+            if kind == SectionKind::UninitializedData {
+                obj.append_section_bss(id, size, 4);
+            } else {
+                obj.append_section_data(id, &vec![0; size as usize], 4);
+            }
+        }
+        obj.write().unwrap()
+    }
+
+    /// Maps the original REL section indices by ELF section name.
+    fn match_names(
+        elf: &[u8],
+        rel_sections: &[RelSectionHeader],
+        names: Option<&[&str]>,
+    ) -> Result<BTreeMap<String, usize>> {
+        let obj = load_obj(elf)?;
+        let defs = names.map(|names| {
+            names
+                .iter()
+                .map(|name| SectionDef { name: name.to_string(), kind: None, align: None })
+                .collect::<Vec<_>>()
+        });
+        let map = match_sections(&obj, rel_sections, defs.as_deref())?;
+        Ok(map
+            .into_iter()
+            .map(|(elf_index, rel_index)| {
+                let section = obj.section_by_index(SectionIndex(elf_index)).unwrap();
+                (section.name().unwrap().to_string(), rel_index)
+            })
+            .collect())
+    }
+
+    fn expected(entries: &[(&str, usize)]) -> BTreeMap<String, usize> {
+        entries.iter().map(|&(name, index)| (name.to_string(), index)).collect()
+    }
+
+    #[test]
+    fn test_match_sections_empty() {
+        let elf = section_elf(&[
+            (".text", SectionKind::Text, 0x10),
+            (".ctors", SectionKind::Data, 0),
+            (".data", SectionKind::Data, 8),
+            (".bss", SectionKind::UninitializedData, 0x10),
+        ]);
+        // The original REL has no .ctors, so the empty section is dropped
+        let rel_sections = [
+            RelSectionHeader::new(0, 0, false),
+            RelSectionHeader::new(0x100, 0x10, true),
+            RelSectionHeader::new(0x110, 8, false),
+            RelSectionHeader::new(0, 0x10, false),
+        ];
+        let map = match_names(&elf, &rel_sections, None).unwrap();
+        assert_eq!(map, expected(&[(".text", 1), (".data", 2), (".bss", 3)]));
+
+        // With an empty slot, it's placed there
+        let rel_sections = [
+            RelSectionHeader::new(0, 0, false),
+            RelSectionHeader::new(0x100, 0x10, true),
+            RelSectionHeader::new(0, 0, false),
+            RelSectionHeader::new(0x110, 8, false),
+            RelSectionHeader::new(0, 0x10, false),
+        ];
+        let map = match_names(&elf, &rel_sections, None).unwrap();
+        assert_eq!(map, expected(&[(".text", 1), (".ctors", 2), (".data", 3), (".bss", 4)]));
+    }
+
+    #[test]
+    fn test_match_sections_by_name() {
+        let elf = section_elf(&[
+            (".text", SectionKind::Text, 0x10),
+            (".rodata", SectionKind::ReadOnlyData, 8),
+            (".data", SectionKind::Data, 8),
+        ]);
+        let rel_sections = [
+            RelSectionHeader::new(0, 0, false),
+            RelSectionHeader::new(0x100, 0x10, true),
+            RelSectionHeader::new(0x110, 8, false),
+            RelSectionHeader::new(0x118, 8, false),
+        ];
+        let names = [".text", ".data", ".rodata"];
+        let map = match_names(&elf, &rel_sections, Some(&names)).unwrap();
+        assert_eq!(map, expected(&[(".text", 1), (".data", 2), (".rodata", 3)]));
+    }
+
+    #[test]
+    fn test_match_sections_by_size() {
+        let elf = section_elf(&[
+            (".text", SectionKind::Text, 0x10),
+            (".rodata", SectionKind::ReadOnlyData, 8),
+            (".data", SectionKind::Data, 0x18),
+        ]);
+        let rel_sections = [
+            RelSectionHeader::new(0, 0, false),
+            RelSectionHeader::new(0x100, 0x10, true),
+            RelSectionHeader::new(0x110, 0x18, false),
+            RelSectionHeader::new(0x128, 8, false),
+        ];
+        let map = match_names(&elf, &rel_sections, None).unwrap();
+        assert_eq!(map, expected(&[(".text", 1), (".data", 2), (".rodata", 3)]));
+    }
+
+    #[test]
+    fn test_match_sections_unmatched() {
+        let elf =
+            section_elf(&[(".text", SectionKind::Text, 0x10), (".data", SectionKind::Data, 8)]);
+        let rel_sections =
+            [RelSectionHeader::new(0, 0, false), RelSectionHeader::new(0x100, 0x10, true)];
+        let error = match_names(&elf, &rel_sections, None).unwrap_err();
+        assert!(
+            error.to_string().contains("Failed to find matching REL section for .data"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_build_str_table() {
+        let names = [(1, "a.rel".to_string()), (2, "mod/b.rel".to_string())];
+        let (data, entries) = build_str_table(&names).unwrap();
+        assert_eq!(data, b"a.rel\0mod/b.rel\0");
+        assert_eq!(entries, BTreeMap::from([(1, (0, 5)), (2, (6, 9))]));
+        for (module_id, (offset, size)) in entries {
+            let name = &names[module_id as usize - 1].1;
+            assert_eq!(&data[offset as usize..(offset + size) as usize], name.as_bytes());
+        }
+        assert!(build_str_table(&[(1, "a\0.rel".to_string())]).is_err());
+    }
+
+    fn graph_obj(
+        module_id: u32,
+        sections: &[(&str, ObjSectionKind, u64)],
+        symbols: Vec<ObjSymbol>,
+        relocations: Vec<RelReloc>,
+    ) -> ObjInfo {
+        let kind = if module_id == 0 { ObjKind::Executable } else { ObjKind::Relocatable };
+        let base = if module_id == 0 { 0x80003000 } else { 0 };
+        let sections = sections
+            .iter()
+            .enumerate()
+            .map(|(i, &(name, kind, size))| ObjSection {
+                name: name.to_string(),
+                kind,
+                address: base,
+                size,
+                data: vec![0; size as usize],
+                align: 4,
+                elf_index: i + 1,
+                relocations: Default::default(),
+                original_address: 0,
+                file_offset: 0,
+                section_known: true,
+                splits: Default::default(),
+            })
+            .collect();
+        let mut obj = ObjInfo::new(
+            kind,
+            ObjArchitecture::PowerPc,
+            format!("module{}", module_id),
+            symbols,
+            sections,
+        );
+        obj.module_id = module_id;
+        obj.unresolved_relocations = relocations;
+        obj
+    }
+
+    fn graph_symbol(name: &str, address: u64, flags: ObjSymbolFlags) -> ObjSymbol {
+        ObjSymbol {
+            name: name.to_string(),
+            address,
+            section: Some(0),
+            size: 8,
+            size_known: true,
+            flags: ObjSymbolFlagSet(flags.into()),
+            kind: ObjSymbolKind::Function,
+            ..Default::default()
+        }
+    }
+
+    fn graph_reloc(section: u8, address: u32, module_id: u32, addend: u32) -> RelReloc {
+        RelReloc {
+            kind: ObjRelocKind::PpcRel24,
+            section,
+            address,
+            module_id,
+            target_section: if module_id == 0 { 0 } else { 1 },
+            addend,
+            original_section: section,
+            original_target_section: 1,
+        }
+    }
+
+    #[test]
+    fn test_build_import_graph() {
+        let text = (".text", ObjSectionKind::Code, 0x20);
+        let objs = [
+            graph_obj(0, &[(".text", ObjSectionKind::Code, 0x100)], vec![], vec![]),
+            graph_obj(
+                1,
+                &[text, (".data", ObjSectionKind::Data, 0x10)],
+                vec![graph_symbol("func1", 0, ObjSymbolFlags::Global)],
+                vec![
+                    // Local symbol in module 2
+                    graph_reloc(1, 4, 2, 0),
+                    // Module that wasn't loaded
+                    RelReloc { kind: ObjRelocKind::Absolute, ..graph_reloc(2, 0, 9, 0) },
+                ],
+            ),
+            graph_obj(
+                2,
+                &[text],
+                vec![
+                    graph_symbol("local2", 0, ObjSymbolFlags::Local),
+                    graph_symbol("func2", 0x10, ObjSymbolFlags::Global),
+                ],
+                vec![graph_reloc(1, 0x14, 1, 0), graph_reloc(1, 0x18, 0, 0x80003010)],
+            ),
+        ];
+        let graph = build_import_graph(&objs).unwrap();
+
+        let edges = graph
+            .edges
+            .iter()
+            .map(|e| {
+                (e.from, e.to, e.count, e.sections[0].from.as_str(), e.sections[0].to.as_str())
+            })
+            .collect_vec();
+        assert_eq!(edges, [
+            (1, 2, 1, ".text", ".text"),
+            (2, 0, 1, ".text", ".text"),
+            (2, 1, 1, ".text", ".text"),
+        ]);
+        assert_eq!(graph.cycles, [vec![1, 2]]);
+
+        assert_eq!(graph.unresolved.len(), 1);
+        let import = &graph.unresolved[0];
+        assert_eq!((import.module, import.section.as_str()), (1, ".data"));
+        assert_eq!((import.target_module, import.reason.as_str()), (9, "module not found"));
+
+        assert_eq!(graph.non_exported.len(), 1);
+        let import = &graph.non_exported[0];
+        assert_eq!((import.module, import.address), (1, 4));
+        assert_eq!(import.symbol.as_deref(), Some("local2"));
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    cmp::{min, Ordering},
+    cmp::Ordering,
     collections::{btree_map, BTreeMap},
     io::Write,
 };
@@ -13,7 +13,10 @@ use crate::{
         ObjDataKind, ObjInfo, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
         ObjSymbolKind,
     },
-    util::nested::NestedVec,
+    util::{
+        debug_info::{DataField, DataLayouts},
+        nested::NestedVec,
+    },
 };
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -29,7 +32,9 @@ struct SymbolEntry {
     kind: SymbolEntryKind,
 }
 
-pub fn write_asm<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+/// Writes an object as assembly. When data layouts are provided, variables are written
+/// field by field, annotated with their member names and types.
+pub fn write_asm<W>(w: &mut W, obj: &ObjInfo, data_layouts: Option<&DataLayouts>) -> Result<()>
 where W: Write + ?Sized {
     writeln!(w, ".include \"macros.inc\"")?;
     if !obj.name.is_empty() {
@@ -47,7 +52,32 @@ where W: Write + ?Sized {
     let mut symbols: Vec<ObjSymbol> = obj.symbols.iter().cloned().collect();
     let mut section_entries: Vec<BTreeMap<u32, Vec<SymbolEntry>>> = vec![];
     let mut section_relocations: Vec<BTreeMap<u32, ObjReloc>> = vec![];
+    let mut section_fields: Vec<BTreeMap<u32, &DataField>> = vec![];
     for (section_idx, section) in obj.sections.iter() {
+        // Build typed data fields
+        let mut fields = BTreeMap::<u32, &DataField>::new();
+        if let (Some(layouts), ObjSectionKind::Data | ObjSectionKind::ReadOnlyData) =
+            (data_layouts, section.kind)
+        {
+            for (_, symbol) in obj.symbols.for_section(section_idx) {
+                // Split objects have section-relative symbols, layouts use the original address
+                let address = (section.original_address + symbol.address) as u32;
+                let Some(layout) =
+                    layouts.get(&address).filter(|layout| layout.name == symbol.name)
+                else {
+                    continue;
+                };
+                if layout.fields.last().map(|f| (f.offset + f.size) as u64) != Some(symbol.size) {
+                    log::debug!("Data layout for {} doesn't match symbol size", symbol.name);
+                    continue;
+                }
+                for field in &layout.fields {
+                    fields.insert(symbol.address as u32 + field.offset, field);
+                }
+            }
+        }
+        section_fields.push(fields);
+
         // Build symbol start/end entries
         let mut entries = BTreeMap::<u32, Vec<SymbolEntry>>::new();
         for (symbol_index, symbol) in obj.symbols.for_section(section_idx) {
@@ -183,6 +213,7 @@ where W: Write + ?Sized {
     for (section_index, section) in obj.sections.iter() {
         let entries = &section_entries[section_index];
         let relocations = &section_relocations[section_index];
+        let fields = &section_fields[section_index];
 
         let mut current_address = section.address as u32;
         let section_end = (section.address + section.size) as u32;
@@ -202,6 +233,7 @@ where W: Write + ?Sized {
                         &symbols,
                         entries,
                         relocations,
+                        fields,
                         section,
                         current_address,
                         section_end,
@@ -415,6 +447,7 @@ fn write_data<W>(
     symbols: &[ObjSymbol],
     entries: &BTreeMap<u32, Vec<SymbolEntry>>,
     relocations: &BTreeMap<u32, ObjReloc>,
+    fields: &BTreeMap<u32, &DataField>,
     section: &ObjSection,
     start: u32,
     end: u32,
@@ -425,12 +458,14 @@ where
 {
     let mut entry_iter = entries.range(start..end);
     let mut reloc_iter = relocations.range(start..end);
+    let mut field_iter = fields.range(start..end);
 
     let mut current_address = start;
     let mut current_symbol_kind = ObjSymbolKind::Unknown;
     let mut current_data_kind = ObjDataKind::Unknown;
     let mut entry = entry_iter.next();
     let mut reloc = reloc_iter.next();
+    let mut field = field_iter.next();
     let mut begin = true;
     loop {
         if current_address == end {
@@ -460,6 +495,20 @@ where
         }
         begin = false;
 
+        // Fields may be skipped over by relocations
+        while let Some((&field_addr, f)) = field {
+            if field_addr > current_address {
+                break;
+            }
+            if field_addr == current_address {
+                if !f.label.is_empty() {
+                    writeln!(w, "\t# {}: {}", f.label, f.type_name)?;
+                }
+                current_data_kind = field_data_kind(f, section, field_addr);
+            }
+            field = field_iter.next();
+        }
+
         let symbol_kind = if current_symbol_kind == ObjSymbolKind::Unknown {
             match section.kind {
                 ObjSectionKind::Code => ObjSymbolKind::Function,
@@ -487,11 +536,11 @@ where
             }
         }
 
-        let until = match (entry, reloc) {
-            (Some((sym_addr, _)), Some((reloc_addr, _))) => min(*reloc_addr, *sym_addr),
-            (Some((addr, _)), None) | (None, Some((addr, _))) => *addr,
-            (None, None) => end,
-        };
+        let until = [entry.map(|(&a, _)| a), reloc.map(|(&a, _)| a), field.map(|(&a, _)| a)]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(end);
         ensure!(
             until > current_address,
             "Invalid address range: {}..{}\n\tNext entry: {:?}\n\tNext reloc: {:?}",
@@ -521,6 +570,19 @@ where
     Ok(())
 }
 
+/// Character arrays are only written as strings when they hold a single null-terminated string.
+fn field_data_kind(field: &DataField, section: &ObjSection, address: u32) -> ObjDataKind {
+    if field.kind != ObjDataKind::String {
+        return field.kind;
+    }
+    match section.data_range(address, address + field.size) {
+        Ok(data) if data.iter().position(|&b| b == 0) == Some(data.len() - 1) => {
+            ObjDataKind::String
+        }
+        _ => ObjDataKind::Byte,
+    }
+}
+
 fn find_symbol_kind(
     current: ObjSymbolKind,
     symbols: &[ObjSymbol],
@@ -921,3 +983,98 @@ where W: Write + ?Sized {
 fn is_illegal_instruction(code: u32) -> bool {
     matches!(code, 0x43000000 /* bc 24, lt, 0x0 */ | 0xB8030000 /* lmw r0, 0(r3) */)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        obj::{ObjArchitecture, ObjKind, ObjRelocations, ObjSplit, ObjSplits, ObjUnit},
+        util::{debug_info::DataLayout, split::split_obj},
+    };
+
+    #[test]
+    fn test_write_asm_split_data_layout() {
+        let mut splits = ObjSplits::default();
+        for (address, end, unit) in
+            [(0x80005000, 0x80005010, "a.c"), (0x80005010, 0x80005020, "b.c")]
+        {
+            splits.push(address, ObjSplit {
+                unit: unit.to_string(),
+                end,
+                align: None,
+                common: false,
+                autogenerated: false,
+                skip: false,
+                rename: None,
+            });
+        }
+        let mut data = vec![0u8; 0x20];
+        data[0x10..0x14].copy_from_slice(&7u32.to_be_bytes());
+        data[0x14..0x18].copy_from_slice(&1.5f32.to_be_bytes());
+        let section = ObjSection {
+            name: ".data".to_string(),
+            kind: ObjSectionKind::Data,
+            address: 0x80005000,
+            size: 0x20,
+            data,
+            align: 8,
+            elf_index: 1,
+            relocations: ObjRelocations::default(),
+            original_address: 0,
+            file_offset: 0x100,
+            section_known: true,
+            splits,
+        };
+        let symbols = [("a_data", 0x80005000), ("b_data", 0x80005010)]
+            .iter()
+            .map(|&(name, address)| ObjSymbol {
+                name: name.to_string(),
+                address,
+                section: Some(0),
+                size: 0x10,
+                size_known: true,
+                kind: ObjSymbolKind::Object,
+                ..Default::default()
+            })
+            .collect();
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main.dol".to_string(),
+            symbols,
+            vec![section],
+        );
+        obj.link_order = ["a.c", "b.c"]
+            .iter()
+            .map(|name| ObjUnit {
+                name: name.to_string(),
+                autogenerated: false,
+                comment_version: None,
+            })
+            .collect();
+
+        let field = |offset, size, kind, label: &str, type_name: &str| DataField {
+            offset,
+            size,
+            kind,
+            label: label.to_string(),
+            type_name: type_name.to_string(),
+        };
+        let mut layouts = DataLayouts::new();
+        layouts.insert(0x80005010, DataLayout {
+            name: "b_data".to_string(),
+            fields: vec![
+                field(0, 4, ObjDataKind::Byte4, ".count", "int"),
+                field(4, 4, ObjDataKind::Float, ".scale", "float"),
+                field(8, 8, ObjDataKind::Byte, "", ""),
+            ],
+        });
+
+        let objects = split_obj(&obj).unwrap();
+        let mut out = Vec::new();
+        write_asm(&mut out, &objects[1], Some(&layouts)).unwrap();
+        let asm = String::from_utf8(out).unwrap();
+        let expected = "\t# .count: int\n\t.4byte 0x00000007\n\t# .scale: float\n\t.float 1.5\n";
+        assert!(asm.contains(expected), "{}", asm);
+    }
+}
@@ -1,4 +1,18 @@
-use crate::obj::{ObjSection, ObjSectionKind, ObjSymbol};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    obj::{
+        ObjDataKind, ObjInfo, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
+        ObjSymbolKind, SymbolIndex,
+    },
+    util::align_up,
+};
 
 const PROLOGUE: &str = r#"
 #ifndef ATTRIBUTE_ALIGN
@@ -13,31 +27,609 @@ const PROLOGUE: &str = r#"
 
 "#;
 
+/// Primitive element types for typed extraction.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScalarType {
+    U8,
+    S8,
+    U16,
+    S16,
+    U32,
+    S32,
+    U64,
+    S64,
+    #[serde(alias = "float")]
+    F32,
+    #[serde(alias = "double")]
+    F64,
+    /// Null-terminated string. Within a structure, requires a `count`.
+    String,
+}
+
+/// Describes the layout of extracted data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum DataType {
+    Scalar(ScalarType),
+    Struct {
+        #[serde(rename = "struct")]
+        fields: Vec<StructField>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StructField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub data_type: DataType,
+    /// If specified, the field is an array with the given length.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+}
+
+impl ScalarType {
+    fn c_type(self) -> &'static str {
+        match self {
+            ScalarType::U8 => "unsigned char",
+            ScalarType::S8 => "signed char",
+            ScalarType::U16 => "unsigned short",
+            ScalarType::S16 => "short",
+            ScalarType::U32 => "unsigned int",
+            ScalarType::S32 => "int",
+            ScalarType::U64 => "unsigned long long",
+            ScalarType::S64 => "long long",
+            ScalarType::F32 => "float",
+            ScalarType::F64 => "double",
+            ScalarType::String => "char",
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::U8 | ScalarType::S8 | ScalarType::String => 1,
+            ScalarType::U16 | ScalarType::S16 => 2,
+            ScalarType::U32 | ScalarType::S32 | ScalarType::F32 => 4,
+            ScalarType::U64 | ScalarType::S64 | ScalarType::F64 => 8,
+        }
+    }
+
+    fn initializer(self, data: &[u8]) -> Result<String> {
+        Ok(match self {
+            ScalarType::U8 => format!("{:#04X}", data[0]),
+            ScalarType::S8 => format!("{}", data[0] as i8),
+            ScalarType::U16 => format!("{:#06X}", u16::from_be_bytes(data.try_into()?)),
+            ScalarType::S16 => format!("{}", i16::from_be_bytes(data.try_into()?)),
+            ScalarType::U32 => format!("{:#010X}", u32::from_be_bytes(data.try_into()?)),
+            ScalarType::S32 => format!("{}", i32::from_be_bytes(data.try_into()?)),
+            ScalarType::U64 => format!("{:#018X}ULL", u64::from_be_bytes(data.try_into()?)),
+            ScalarType::S64 => format!("{}LL", i64::from_be_bytes(data.try_into()?)),
+            ScalarType::F32 => {
+                let value = f32::from_be_bytes(data.try_into()?);
+                ensure!(value.is_finite(), "Non-finite float {}", value);
+                format!("{:?}f", value)
+            }
+            ScalarType::F64 => {
+                let value = f64::from_be_bytes(data.try_into()?);
+                ensure!(value.is_finite(), "Non-finite double {}", value);
+                format!("{:?}", value)
+            }
+            ScalarType::String => c_char_array(data),
+        })
+    }
+}
+
+impl DataType {
+    /// Size and alignment, following C layout rules.
+    fn layout(&self) -> (usize, usize) {
+        match self {
+            DataType::Scalar(t) => (t.size(), t.size()),
+            DataType::Struct { fields } => {
+                let mut size = 0;
+                let mut align = 1;
+                for field in fields {
+                    let (field_size, field_align) = field.data_type.layout();
+                    size = align_up(size as u32, field_align as u32) as usize;
+                    size += field_size * field.count.unwrap_or(1) as usize;
+                    align = align.max(field_align);
+                }
+                (align_up(size as u32, align as u32) as usize, align)
+            }
+        }
+    }
+
+    fn c_type(&self, indent: usize) -> String {
+        match self {
+            DataType::Scalar(t) => t.c_type().to_string(),
+            DataType::Struct { fields } => {
+                let mut out = "struct {\n".to_string();
+                for field in fields {
+                    let ty = field.data_type.c_type(indent + 4);
+                    write!(out, "{:indent$}{} {}", "", ty, field.name, indent = indent + 4)
+                        .unwrap();
+                    if let Some(count) = field.count {
+                        write!(out, "[{}]", count).unwrap();
+                    }
+                    out.push_str(";\n");
+                }
+                write!(out, "{:indent$}}}", "", indent = indent).unwrap();
+                out
+            }
+        }
+    }
+
+    fn initializer(&self, data: &[u8]) -> Result<String> {
+        let fields = match self {
+            DataType::Scalar(t) => return t.initializer(data),
+            DataType::Struct { fields } => fields,
+        };
+        let mut elements = Vec::with_capacity(fields.len());
+        let mut offset = 0;
+        for field in fields {
+            let (size, align) = field.data_type.layout();
+            offset = align_up(offset as u32, align as u32) as usize;
+            match (field.count, &field.data_type) {
+                (Some(count), DataType::Scalar(ScalarType::String)) => {
+                    let data = &data[offset..offset + count as usize];
+                    elements.push(ScalarType::String.initializer(data)?);
+                    offset += count as usize;
+                }
+                (Some(count), t) => {
+                    let mut inner = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        inner.push(t.initializer(&data[offset..offset + size])?);
+                        offset += size;
+                    }
+                    elements.push(format!("{{ {} }}", inner.join(", ")));
+                }
+                (None, DataType::Scalar(ScalarType::String)) => {
+                    bail!("String field {} requires a count", field.name)
+                }
+                (None, t) => {
+                    elements.push(t.initializer(&data[offset..offset + size])?);
+                    offset += size;
+                }
+            }
+        }
+        Ok(format!("{{ {} }}", elements.join(", ")))
+    }
+}
+
+/// Options for [`bin2c`].
+#[derive(Debug, Default)]
+pub struct Bin2COptions<'a> {
+    /// Layout of the data. If not specified, a byte array is written.
+    pub data_type: Option<&'a DataType>,
+    /// Element type name, overriding the one derived from `data_type`.
+    /// Useful for structures defined in another header.
+    pub type_name: Option<&'a str>,
+    /// Overrides whether the array is `const`. Defaults to `true` for read-only sections.
+    pub is_const: Option<bool>,
+    /// Overrides whether the array is `static`. Defaults to `true` for local symbols.
+    pub is_static: Option<bool>,
+}
+
 /// Converts a binary blob into a C array.
-pub fn bin2c(symbol: &ObjSymbol, section: &ObjSection, data: &[u8]) -> String {
+pub fn bin2c(
+    symbol: &ObjSymbol,
+    section: &ObjSection,
+    data: &[u8],
+    options: &Bin2COptions,
+) -> Result<String> {
     let mut output = String::new();
     output.push_str(PROLOGUE);
     output.push_str(&format!(
         "// {} (size: {:#X}, address: {:#X}, section: {})\n",
         symbol.name, symbol.size, symbol.address, section.name
     ));
-    if symbol.flags.is_local() {
+    if options.is_static.unwrap_or(symbol.flags.is_local()) {
         output.push_str("static ");
     }
-    if section.kind == ObjSectionKind::ReadOnlyData {
+    if options.is_const.unwrap_or(section.kind == ObjSectionKind::ReadOnlyData) {
         output.push_str("const ");
     }
-    output.push_str("unsigned char ");
-    output.push_str(symbol.demangled_name.as_deref().unwrap_or(symbol.name.as_str()));
-    output.push_str(&format!("[] ATTRIBUTE_ALIGN({}) = {{", symbol.align.unwrap_or(4)));
-    for (i, byte) in data.iter().enumerate() {
-        if i % 16 == 0 {
+    let name = symbol.demangled_name.as_deref().unwrap_or(symbol.name.as_str());
+    let align = symbol.align.unwrap_or(4);
+    let data_type = options.data_type.unwrap_or(&DataType::Scalar(ScalarType::U8));
+    if let DataType::Scalar(ScalarType::String) = data_type {
+        ensure!(data.last() == Some(&0), "String data for {} is not null-terminated", symbol.name);
+        output.push_str(options.type_name.unwrap_or("char"));
+        output.push_str(&format!(
+            " {}[{}] ATTRIBUTE_ALIGN({}) = {};\n",
+            name,
+            data.len(),
+            align,
+            c_string_literal(&data[..data.len() - 1])
+        ));
+        return Ok(output);
+    }
+
+    let (size, _) = data_type.layout();
+    ensure!(
+        size != 0 && data.len() % size == 0,
+        "Data size {:#X} for {} is not a multiple of the element size {:#X}",
+        data.len(),
+        symbol.name,
+        size
+    );
+    match options.type_name {
+        Some(type_name) => output.push_str(type_name),
+        None => output.push_str(&data_type.c_type(0)),
+    }
+    output.push_str(&format!(" {}[] ATTRIBUTE_ALIGN({}) = {{", name, align));
+    let per_line = match data_type {
+        DataType::Scalar(t) => 16 / t.size(),
+        DataType::Struct { .. } => 1,
+    };
+    for (i, element) in data.chunks_exact(size).enumerate() {
+        if i % per_line == 0 {
             output.push_str("\n    ");
         } else {
             output.push(' ');
         }
-        output.push_str(&format!("0x{:02X},", byte));
+        let initializer = data_type
+            .initializer(element)
+            .with_context(|| format!("In {} element {}", symbol.name, i))?;
+        output.push_str(&initializer);
+        output.push(',');
     }
     output.push_str("\n};\n");
-    output
+    Ok(output)
+}
+
+/// C type and initializer elements for a data symbol.
+struct CData {
+    ty: &'static str,
+    /// Array length, or `None` for scalars
+    count: Option<usize>,
+    /// Initializer elements, or a string literal
+    init: Option<CInit>,
+}
+
+enum CInit {
+    Elements(Vec<String>),
+    String(String),
+}
+
+pub fn is_c_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn c_name(symbol: &ObjSymbol) -> String {
+    if is_c_identifier(&symbol.name) {
+        return symbol.name.clone();
+    }
+    let name = symbol
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    log::warn!("Symbol {} is not a valid C identifier, renamed to {}", symbol.name, name);
+    name
+}
+
+fn c_string_literal(data: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in data {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            // Avoid trigraphs
+            b'?' => out.push_str("\\?"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            b' '..=b'~' => out.push(b as char),
+            _ => write!(out, "\\{:03o}", b).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Initializer for a `char[N]`. A string literal can only express zeroes after the
+/// terminator, so any other trailing data is written as bytes.
+fn c_char_array(data: &[u8]) -> String {
+    let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    if data[len..].iter().all(|&b| b == 0) {
+        return c_string_literal(&data[..len]);
+    }
+    let bytes = data.iter().map(|b| format!("{:#04X}", b)).collect::<Vec<_>>();
+    format!("{{ {} }}", bytes.join(", "))
+}
+
+fn c_reference(obj: &ObjInfo, names: &BTreeMap<SymbolIndex, String>, reloc: &ObjReloc) -> String {
+    let name = &names[&reloc.target_symbol];
+    let target = &obj.symbols[reloc.target_symbol];
+    match reloc.addend {
+        0 if target.kind == ObjSymbolKind::Function => format!("(void*){}", name),
+        0 => format!("&{}", name),
+        addend if addend > 0 => format!("(char*)&{} + {:#X}", name, addend),
+        addend => format!("(char*)&{} - {:#X}", name, -addend),
+    }
+}
+
+fn c_elements<const N: usize>(data: &[u8], f: impl Fn([u8; N]) -> String) -> Vec<String> {
+    data.chunks_exact(N).map(|c| f(c.try_into().unwrap())).collect()
+}
+
+fn c_data(
+    obj: &ObjInfo,
+    names: &BTreeMap<SymbolIndex, String>,
+    symbol: &ObjSymbol,
+    section: &ObjSection,
+) -> Result<CData> {
+    let size = symbol.size as usize;
+    let aligned = |n: usize| size % n == 0 && symbol.address % n as u64 == 0;
+    let array = |ty: &'static str, elem: usize, init: Option<Vec<String>>| CData {
+        ty,
+        count: (size != elem).then_some(size / elem),
+        init: init.map(CInit::Elements),
+    };
+    if section.kind == ObjSectionKind::Bss {
+        return Ok(match symbol.data_kind {
+            ObjDataKind::Float if aligned(4) => array("float", 4, None),
+            ObjDataKind::Double if aligned(8) => array("double", 8, None),
+            ObjDataKind::Byte2 if aligned(2) => array("unsigned short", 2, None),
+            ObjDataKind::Byte8 if aligned(8) => array("unsigned long long", 8, None),
+            ObjDataKind::Byte4 | ObjDataKind::Unknown if aligned(4) => {
+                array("unsigned int", 4, None)
+            }
+            _ => array("unsigned char", 1, None),
+        });
+    }
+
+    let data = section.symbol_data(symbol)?;
+    let start = symbol.address as u32;
+    let relocations =
+        section.relocations.range(start..start + symbol.size as u32).collect::<Vec<_>>();
+    if !relocations.is_empty() {
+        // Pointer table
+        ensure!(aligned(4), "Symbol {} with relocations is not word aligned", symbol.name);
+        let mut elements = vec![];
+        for (offset, word) in data.chunks_exact(4).enumerate() {
+            let address = start + offset as u32 * 4;
+            match relocations.iter().find(|(addr, _)| *addr == address) {
+                Some((_, reloc)) => {
+                    ensure!(
+                        reloc.kind == ObjRelocKind::Absolute,
+                        "Unsupported relocation {:?} in {} @ {:#010X}",
+                        reloc.kind,
+                        symbol.name,
+                        address
+                    );
+                    elements.push(c_reference(obj, names, reloc));
+                }
+                None => match u32::from_be_bytes(word.try_into().unwrap()) {
+                    0 => elements.push("NULL".to_string()),
+                    value => elements.push(format!("(void*){:#X}", value)),
+                },
+            }
+        }
+        ensure!(
+            relocations.iter().all(|(addr, _)| (addr - start) % 4 == 0),
+            "Unaligned relocation in {}",
+            symbol.name
+        );
+        return Ok(array("void*", 4, Some(elements)));
+    }
+
+    let is_string = data.last() == Some(&0);
+    Ok(match symbol.data_kind {
+        ObjDataKind::String | ObjDataKind::StringTable if is_string => CData {
+            ty: "char",
+            count: Some(size),
+            init: Some(CInit::String(c_string_literal(&data[..size - 1]))),
+        },
+        ObjDataKind::String16 | ObjDataKind::String16Table if aligned(2) => array(
+            "unsigned short",
+            2,
+            Some(c_elements(data, |b| format!("{:#06X}", u16::from_be_bytes(b)))),
+        ),
+        ObjDataKind::Float
+            if aligned(4)
+                && data
+                    .chunks_exact(4)
+                    .all(|c| f32::from_be_bytes(c.try_into().unwrap()).is_finite()) =>
+        {
+            array("float", 4, Some(c_elements(data, |b| format!("{:?}f", f32::from_be_bytes(b)))))
+        }
+        ObjDataKind::Double
+            if aligned(8)
+                && data
+                    .chunks_exact(8)
+                    .all(|c| f64::from_be_bytes(c.try_into().unwrap()).is_finite()) =>
+        {
+            array("double", 8, Some(c_elements(data, |b| format!("{:?}", f64::from_be_bytes(b)))))
+        }
+        ObjDataKind::Byte2 if aligned(2) => array(
+            "unsigned short",
+            2,
+            Some(c_elements(data, |b| format!("{:#06X}", u16::from_be_bytes(b)))),
+        ),
+        ObjDataKind::Byte8 if aligned(8) => array(
+            "unsigned long long",
+            8,
+            Some(c_elements(data, |b| format!("{:#018X}ULL", u64::from_be_bytes(b)))),
+        ),
+        ObjDataKind::Byte4 | ObjDataKind::Unknown | ObjDataKind::Float | ObjDataKind::Double
+            if aligned(4) =>
+        {
+            array(
+                "unsigned int",
+                4,
+                Some(c_elements(data, |b| format!("{:#010X}", u32::from_be_bytes(b)))),
+            )
+        }
+        _ => {
+            array("unsigned char", 1, Some(c_elements(data, |b: [u8; 1]| format!("{:#04X}", b[0]))))
+        }
+    })
+}
+
+/// Converts the data symbols of a split object into C definitions.
+///
+/// Relocations are written as pointer initializers, with `extern` declarations for
+/// referenced symbols. Code sections are skipped.
+pub fn obj2c(obj: &ObjInfo) -> Result<String> {
+    // Collect defined symbols and relocation targets
+    let mut defined = Vec::<(SymbolIndex, usize)>::new();
+    let mut referenced = BTreeSet::<SymbolIndex>::new();
+    for (section_index, section) in obj.sections.iter() {
+        if section.kind == ObjSectionKind::Code {
+            continue;
+        }
+        let mut end = 0;
+        for (symbol_index, symbol) in obj.symbols.for_section(section_index) {
+            if symbol.kind == ObjSymbolKind::Section || symbol.size == 0 || symbol.name.is_empty() {
+                continue;
+            }
+            if symbol.address < end {
+                log::warn!("Skipping overlapping symbol {}", symbol.name);
+                continue;
+            }
+            end = symbol.address + symbol.size;
+            defined.push((symbol_index, section_index));
+        }
+        for (_, reloc) in section.relocations.iter() {
+            referenced.insert(reloc.target_symbol);
+        }
+    }
+    let names = defined
+        .iter()
+        .map(|&(idx, _)| idx)
+        .chain(referenced.iter().copied())
+        .map(|idx| (idx, c_name(&obj.symbols[idx])))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut definitions = Vec::with_capacity(defined.len());
+    for &(symbol_index, section_index) in &defined {
+        let symbol = &obj.symbols[symbol_index];
+        let section = &obj.sections[section_index];
+        definitions.push((symbol_index, section, c_data(obj, &names, symbol, section)?));
+    }
+
+    let mut out = String::new();
+    out.push_str(PROLOGUE);
+    if !obj.name.is_empty() {
+        writeln!(out, "// {}", obj.name)?;
+    }
+    if definitions.iter().any(|(_, _, d)| d.ty == "void*") {
+        out.push_str("\n#ifndef NULL\n#define NULL ((void*)0)\n#endif\n");
+    }
+
+    // Declarations for referenced symbols
+    let mut declarations = String::new();
+    for &symbol_index in &referenced {
+        let symbol = &obj.symbols[symbol_index];
+        let name = &names[&symbol_index];
+        if let Some((_, section, data)) =
+            definitions.iter().find(|(idx, _, _)| *idx == symbol_index)
+        {
+            // Forward declaration
+            declarations.push_str(if symbol.flags.is_local() { "static " } else { "extern " });
+            if section.kind == ObjSectionKind::ReadOnlyData {
+                declarations.push_str("const ");
+            }
+            write!(declarations, "{} {}", data.ty, name)?;
+            if let Some(count) = data.count {
+                write!(declarations, "[{}]", count)?;
+            }
+            declarations.push_str(";\n");
+        } else if symbol.kind == ObjSymbolKind::Function {
+            writeln!(declarations, "extern void {}();", name)?;
+        } else {
+            writeln!(declarations, "extern unsigned char {}[];", name)?;
+        }
+    }
+    if !declarations.is_empty() {
+        out.push('\n');
+        out.push_str(&declarations);
+    }
+
+    for (symbol_index, section, data) in &definitions {
+        let symbol = &obj.symbols[*symbol_index];
+        writeln!(
+            out,
+            "\n// {} (size: {:#X}, address: {:#X}, section: {})",
+            symbol.name, symbol.size, symbol.address, section.name
+        )?;
+        if symbol.flags.is_local() {
+            out.push_str("static ");
+        }
+        if section.kind == ObjSectionKind::ReadOnlyData {
+            out.push_str("const ");
+        }
+        write!(out, "{} {}", data.ty, names[symbol_index])?;
+        if let Some(count) = data.count {
+            write!(out, "[{}]", count)?;
+        }
+        if let Some(align) = symbol.align {
+            write!(out, " ATTRIBUTE_ALIGN({})", align)?;
+        }
+        match &data.init {
+            None => out.push_str(";\n"),
+            Some(CInit::String(s)) => writeln!(out, " = {};", s)?,
+            Some(CInit::Elements(elements)) if data.count.is_none() => {
+                writeln!(out, " = {};", elements[0])?
+            }
+            Some(CInit::Elements(elements)) => {
+                let per_line = match data.ty {
+                    "void*" => 1,
+                    "double" | "unsigned long long" => 2,
+                    "unsigned short" => 8,
+                    "unsigned char" => 16,
+                    _ => 4,
+                };
+                out.push_str(" = {");
+                for (i, element) in elements.iter().enumerate() {
+                    if i % per_line == 0 {
+                        out.push_str("\n    ");
+                    } else {
+                        out.push(' ');
+                    }
+                    write!(out, "{},", element)?;
+                }
+                out.push_str("\n};\n");
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn char_field(count: u32) -> DataType {
+        DataType::Struct {
+            fields: vec![
+                StructField {
+                    name: "name".to_string(),
+                    data_type: DataType::Scalar(ScalarType::String),
+                    count: Some(count),
+                },
+                StructField {
+                    name: "id".to_string(),
+                    data_type: DataType::Scalar(ScalarType::U16),
+                    count: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_char_array_initializer() {
+        let t = char_field(6);
+        assert_eq!(t.initializer(b"abc\0\0\0\x12\x34").unwrap(), r#"{ "abc", 0x1234 }"#);
+        // Unterminated, filling the whole field
+        assert_eq!(t.initializer(b"abcdef\x12\x34").unwrap(), r#"{ "abcdef", 0x1234 }"#);
+        // Data after the terminator
+        assert_eq!(
+            t.initializer(b"ab\0cd\0\x12\x34").unwrap(),
+            "{ { 0x61, 0x62, 0x00, 0x63, 0x64, 0x00 }, 0x1234 }"
+        );
+    }
 }
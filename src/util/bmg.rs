@@ -0,0 +1,281 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::Serialize;
+
+pub const BMG_MAGIC: [u8; 8] = *b"MESGbmg1";
+
+/// Text encoding of a BMG file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BmgEncoding {
+    Cp1252,
+    Utf16,
+    ShiftJis,
+    Utf8,
+}
+
+/// A decoded BMG file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Bmg {
+    pub encoding: BmgEncoding,
+    /// INF1 file ID.
+    pub file_id: u16,
+    pub messages: Vec<BmgMessage>,
+}
+
+/// A single BMG message.
+///
+/// Control sequences are written to `text` as `{hex}`, containing the bytes following the
+/// escape and size bytes. Characters that could not be decoded are written as U+FFFD, in which
+/// case the raw string bytes are included in `raw`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BmgMessage {
+    /// Message ID from the MID1 section, if present.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    /// Additional INF1 entry bytes (hex).
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub attributes: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+#[inline]
+fn be16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| anyhow!("Offset {:#X} out of bounds", offset))
+}
+
+#[inline]
+fn be32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| anyhow!("Offset {:#X} out of bounds", offset))
+}
+
+fn hex_string(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 2);
+    for b in data {
+        write!(out, "{:02X}", b).unwrap();
+    }
+    out
+}
+
+/// CP1252 characters for 0x80..0xA0. Undefined entries map to U+FFFD.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{FFFD}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{FFFD}', 'Ž',
+    '\u{FFFD}', '\u{FFFD}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{FFFD}',
+    'ž', 'Ÿ',
+];
+
+/// Decodes a single character, returning the character and its length in bytes.
+/// Shift-JIS decoding is limited to ASCII and half-width katakana.
+fn decode_char(data: &[u8], encoding: BmgEncoding) -> (char, usize) {
+    match encoding {
+        BmgEncoding::Cp1252 => match data[0] {
+            b @ 0x80..=0x9F => (CP1252_HIGH[(b - 0x80) as usize], 1),
+            b => (b as char, 1),
+        },
+        BmgEncoding::ShiftJis => match data[0] {
+            b @ 0x00..=0x7F => (b as char, 1),
+            b @ 0xA1..=0xDF => (char::from_u32(0xFF61 + (b - 0xA1) as u32).unwrap(), 1),
+            0x81..=0x9F | 0xE0..=0xFC if data.len() >= 2 => ('\u{FFFD}', 2),
+            _ => ('\u{FFFD}', 1),
+        },
+        BmgEncoding::Utf8 => {
+            let len = match data[0] {
+                0x00..=0x7F => 1,
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF7 => 4,
+                _ => return ('\u{FFFD}', 1),
+            };
+            match data.get(..len).and_then(|b| std::str::from_utf8(b).ok()) {
+                Some(s) => (s.chars().next().unwrap(), len),
+                None => ('\u{FFFD}', 1),
+            }
+        }
+        BmgEncoding::Utf16 => {
+            let unit = u16::from_be_bytes([data[0], data[1]]);
+            if (0xD800..0xDC00).contains(&unit) && data.len() >= 4 {
+                let low = u16::from_be_bytes([data[2], data[3]]);
+                if let Some(Ok(c)) = char::decode_utf16([unit, low]).next() {
+                    return (c, 4);
+                }
+            }
+            match char::decode_utf16([unit]).next() {
+                Some(Ok(c)) => (c, 2),
+                _ => ('\u{FFFD}', 2),
+            }
+        }
+    }
+}
+
+/// Decodes a null-terminated message starting at `offset` in the string pool.
+/// Returns the text, whether decoding was lossy, and the offset of the terminator.
+fn decode_message(
+    pool: &[u8],
+    offset: usize,
+    encoding: BmgEncoding,
+) -> Result<(String, bool, usize)> {
+    let unit = if encoding == BmgEncoding::Utf16 { 2 } else { 1 };
+    let mut text = String::new();
+    let mut lossy = false;
+    let mut pos = offset;
+    loop {
+        let rest = pool
+            .get(pos..)
+            .filter(|r| r.len() >= unit)
+            .ok_or_else(|| anyhow!("Unterminated message at {:#X}", offset))?;
+        let value = if unit == 2 { u16::from_be_bytes([rest[0], rest[1]]) } else { rest[0] as u16 };
+        match value {
+            0 => break,
+            0x1A => {
+                let size = *rest
+                    .get(unit)
+                    .ok_or_else(|| anyhow!("Truncated escape sequence at {:#X}", pos))?
+                    as usize;
+                ensure!(size > unit, "Invalid escape sequence size {} at {:#X}", size, pos);
+                let data = rest
+                    .get(unit + 1..size)
+                    .ok_or_else(|| anyhow!("Truncated escape sequence at {:#X}", pos))?;
+                write!(text, "{{{}}}", hex_string(data))?;
+                pos += size;
+            }
+            _ => {
+                let (c, len) = decode_char(rest, encoding);
+                lossy |= c == '\u{FFFD}';
+                text.push(c);
+                pos += len;
+            }
+        }
+    }
+    Ok((text, lossy, pos))
+}
+
+/// Decodes a BMG file.
+pub fn read_bmg(data: &[u8]) -> Result<Bmg> {
+    ensure!(data.len() >= 0x20 && data[..8] == BMG_MAGIC, "Invalid BMG magic");
+    let section_count = be32(data, 0xC)?;
+    let encoding = match data[0x10] {
+        0 | 1 => BmgEncoding::Cp1252,
+        2 => BmgEncoding::Utf16,
+        3 => BmgEncoding::ShiftJis,
+        4 => BmgEncoding::Utf8,
+        v => bail!("Unknown BMG encoding {}", v),
+    };
+
+    let mut inf1 = None;
+    let mut dat1 = None;
+    let mut mid1 = None;
+    let mut offset = 0x20usize;
+    for _ in 0..section_count {
+        let magic = data
+            .get(offset..offset + 4)
+            .ok_or_else(|| anyhow!("Section header at {:#X} out of bounds", offset))?;
+        let size = be32(data, offset + 4)? as usize;
+        ensure!(size >= 8, "Invalid BMG section size {:#X} at {:#X}", size, offset);
+        let section = data
+            .get(offset..offset + size)
+            .ok_or_else(|| anyhow!("Section at {:#X} out of bounds", offset))?;
+        match magic {
+            b"INF1" => inf1 = Some(section),
+            b"DAT1" => dat1 = Some(&section[8..]),
+            b"MID1" => mid1 = Some(section),
+            _ => {}
+        }
+        offset += size;
+    }
+    let inf1 = inf1.ok_or_else(|| anyhow!("Missing INF1 section"))?;
+    let dat1 = dat1.ok_or_else(|| anyhow!("Missing DAT1 section"))?;
+
+    let count = be16(inf1, 0x8)? as usize;
+    let entry_size = be16(inf1, 0xA)? as usize;
+    ensure!(entry_size >= 4, "Invalid INF1 entry size {}", entry_size);
+    let file_id = be16(inf1, 0xC)?;
+    let ids = match mid1 {
+        Some(mid1) => {
+            let id_count = be16(mid1, 0x8)? as usize;
+            ensure!(id_count == count, "MID1 count {} != INF1 count {}", id_count, count);
+            Some((0..count).map(|i| be32(mid1, 0x10 + i * 4)).collect::<Result<Vec<_>>>()?)
+        }
+        None => None,
+    };
+
+    let mut messages = Vec::with_capacity(count);
+    for i in 0..count {
+        let entry_offset = 0x10 + i * entry_size;
+        let entry = inf1
+            .get(entry_offset..entry_offset + entry_size)
+            .ok_or_else(|| anyhow!("INF1 entry {} out of bounds", i))?;
+        let string_offset = be32(entry, 0)? as usize;
+        let (text, lossy, end) = decode_message(dat1, string_offset, encoding)?;
+        let raw = lossy.then(|| hex_string(&dat1[string_offset..end]));
+        messages.push(BmgMessage {
+            id: ids.as_ref().map(|ids| ids[i]),
+            attributes: hex_string(&entry[4..]),
+            text,
+            raw,
+        });
+    }
+    Ok(Bmg { encoding, file_id, messages })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(magic: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let size = (body.len() + 8 + 0x1F) & !0x1F;
+        let mut out = Vec::with_capacity(size);
+        out.extend_from_slice(magic);
+        out.extend_from_slice(&(size as u32).to_be_bytes());
+        out.extend_from_slice(body);
+        out.resize(size, 0);
+        out
+    }
+
+    #[test]
+    fn test_read_bmg() {
+        let mut inf1 = vec![];
+        inf1.extend_from_slice(&2u16.to_be_bytes()); // count
+        inf1.extend_from_slice(&8u16.to_be_bytes()); // entry size
+        inf1.extend_from_slice(&5u16.to_be_bytes()); // file ID
+        inf1.extend_from_slice(&[0, 0]);
+        inf1.extend_from_slice(&[0, 0, 0, 1, 0x12, 0x34, 0x56, 0x78]);
+        inf1.extend_from_slice(&[0, 0, 0, 11, 0, 0, 0, 0]);
+        let dat1 = b"\0Hi\x1A\x06\x00\x01\x02\x03!\0\x80\x81\0";
+        let mut mid1 = vec![];
+        mid1.extend_from_slice(&2u16.to_be_bytes());
+        mid1.extend_from_slice(&[0x10, 0, 0, 0, 0, 0]);
+        mid1.extend_from_slice(&100u32.to_be_bytes());
+        mid1.extend_from_slice(&200u32.to_be_bytes());
+
+        let mut data = vec![0u8; 0x20];
+        data[..8].copy_from_slice(&BMG_MAGIC);
+        data[0xC..0x10].copy_from_slice(&3u32.to_be_bytes());
+        data[0x10] = 1; // CP1252
+        data.extend(section(b"INF1", &inf1));
+        data.extend(section(b"DAT1", dat1));
+        data.extend(section(b"MID1", &mid1));
+        let size = data.len() as u32;
+        data[8..0xC].copy_from_slice(&size.to_be_bytes());
+
+        let bmg = read_bmg(&data).unwrap();
+        assert_eq!(bmg.encoding, BmgEncoding::Cp1252);
+        assert_eq!(bmg.file_id, 5);
+        assert_eq!(bmg.messages.len(), 2);
+        let message = &bmg.messages[0];
+        assert_eq!(message.id, Some(100));
+        assert_eq!(message.attributes, "12345678");
+        assert_eq!(message.text, "Hi{00010203}!");
+        assert_eq!(message.raw, None);
+        let message = &bmg.messages[1];
+        assert_eq!(message.id, Some(200));
+        assert_eq!(message.text, "€\u{FFFD}");
+        assert_eq!(message.raw.as_deref(), Some("8081"));
+    }
+}
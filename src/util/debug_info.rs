@@ -0,0 +1,798 @@
+use std::{
+    collections::{hash_map, BTreeMap, HashMap},
+    io::Cursor,
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, ensure, Result};
+use cwdemangle::{demangle, DemangleOptions};
+use object::{elf, Object, ObjectSection, ObjectSymbol, RelocationKind, RelocationTarget, Section};
+
+use crate::{
+    obj::{
+        ObjDataKind, ObjInfo, ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
+    },
+    util::{
+        dwarf::{
+            process_root_tag, read_aranges_section, read_debug_section, read_line_section,
+            read_sfnames_section, read_srcinfo_section, type_string, ud_type, AttributeKind,
+            FundType, Modifier, TagKind, TagMap, TagType, Type, TypeKind, TypedefMap,
+            UserDefinedType,
+        },
+        dwarf2::read_debug_info,
+        file::map_file,
+        reader::Endian,
+    },
+};
+
+/// Debug information read from a single object.
+pub struct DebugObject {
+    pub name: String,
+    pub tags: TagMap,
+    pub lines: Option<LineInfo>,
+}
+
+/// Reads DWARF 1.1 `.debug` or DWARF 2+ `.debug_info`, whichever is present.
+pub fn read_debug_tags(obj_file: &object::File<'_>) -> Result<Option<TagMap>> {
+    if let Some(debug_section) = obj_file.section_by_name(".debug") {
+        let data = relocated_section_data(obj_file, debug_section)?;
+        let mut reader = Cursor::new(&*data);
+        return read_debug_section(&mut reader).map(Some);
+    }
+    let Some(info_section) = obj_file.section_by_name(".debug_info") else {
+        return Ok(None);
+    };
+    let info = relocated_section_data(obj_file, info_section)?;
+    let abbrev = match obj_file.section_by_name(".debug_abbrev") {
+        Some(section) => relocated_section_data(obj_file, section)?,
+        None => bail!("Failed to locate .debug_abbrev section"),
+    };
+    let str = match obj_file.section_by_name(".debug_str") {
+        Some(section) => relocated_section_data(obj_file, section)?,
+        None => vec![],
+    };
+    let e = if obj_file.is_little_endian() { Endian::Little } else { Endian::Big };
+    read_debug_info(&info, &abbrev, &str, e).map(Some)
+}
+
+fn relocated_section_data(obj_file: &object::File<'_>, section: Section) -> Result<Vec<u8>> {
+    let mut data = section.uncompressed_data()?.into_owned();
+    let name = section.name()?;
+
+    // Apply relocations to data
+    for (addr, reloc) in section.relocations() {
+        match reloc.kind() {
+            RelocationKind::Absolute | RelocationKind::Elf(elf::R_PPC_UADDR32) => {
+                let target = match reloc.target() {
+                    RelocationTarget::Symbol(symbol_idx) => {
+                        let symbol = obj_file.symbol_by_index(symbol_idx)?;
+                        (symbol.address() as i64 + reloc.addend()) as u32
+                    }
+                    _ => bail!("Invalid {} relocation target", name),
+                };
+                let bytes = if obj_file.is_little_endian() {
+                    target.to_le_bytes()
+                } else {
+                    target.to_be_bytes()
+                };
+                data[addr as usize..addr as usize + 4].copy_from_slice(&bytes);
+            }
+            RelocationKind::Elf(elf::R_PPC_NONE) => {}
+            _ => bail!("Unhandled {} relocation type {:?}", name, reloc.kind()),
+        }
+    }
+    Ok(data)
+}
+
+#[derive(Debug, Clone)]
+pub struct LineRow {
+    pub address: u32,
+    /// Index into [`LineInfo::files`]
+    pub file: usize,
+    pub line: u32,
+    pub column: Option<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionLines {
+    pub name: String,
+    pub start_address: u32,
+    pub end_address: u32,
+    pub rows: Vec<LineRow>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnitLines {
+    /// Key of the compile unit tag
+    pub key: u32,
+    pub name: String,
+    /// All rows of the unit's line table, sorted by address
+    pub rows: Vec<LineRow>,
+    pub functions: Vec<FunctionLines>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SourceLocation<'a> {
+    pub file: &'a str,
+    pub line: u32,
+    pub column: Option<u16>,
+}
+
+impl std::fmt::Display for SourceLocation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Per-function source file and line tables, read from DWARF 1.1 `.line`.
+#[derive(Debug, Clone, Default)]
+pub struct LineInfo {
+    pub files: Vec<String>,
+    pub units: Vec<UnitLines>,
+    /// Start address -> (end address, unit index)
+    ranges: BTreeMap<u32, (u32, usize)>,
+}
+
+impl LineInfo {
+    pub fn file(&self, index: usize) -> &str { &self.files[index] }
+
+    /// Looks up the source location for an address.
+    pub fn lookup(&self, address: u32) -> Option<SourceLocation> {
+        let (_, &(end, unit)) = self.ranges.range(..=address).next_back()?;
+        if address >= end {
+            return None;
+        }
+        let rows = &self.units[unit].rows;
+        let idx = rows.partition_point(|row| row.address <= address).checked_sub(1)?;
+        let row = &rows[idx];
+        Some(SourceLocation { file: self.file(row.file), line: row.line, column: row.column })
+    }
+
+    fn file_index(&mut self, name: &str) -> usize {
+        match self.files.iter().position(|f| f == name) {
+            Some(idx) => idx,
+            None => {
+                self.files.push(name.to_string());
+                self.files.len() - 1
+            }
+        }
+    }
+}
+
+/// Reads DWARF 1.1 line information (`.line`, with source file names from
+/// `.debug_srcinfo`/`.debug_sfnames` and ranges from `.debug_aranges`, if present).
+pub fn read_debug_lines(obj_file: &object::File<'_>, tags: &TagMap) -> Result<Option<LineInfo>> {
+    let Some(line_section) = obj_file.section_by_name(".line") else {
+        return Ok(None);
+    };
+    let data = relocated_section_data(obj_file, line_section)?;
+    let tables = read_line_section(&mut Cursor::new(&*data))?;
+
+    // Source file switches by .line entry offset
+    let mut switches = BTreeMap::<u32, String>::new();
+    if let (Some(srcinfo), Some(sfnames)) =
+        (obj_file.section_by_name(".debug_srcinfo"), obj_file.section_by_name(".debug_sfnames"))
+    {
+        let names = read_sfnames_section(&relocated_section_data(obj_file, sfnames)?)?;
+        match read_srcinfo_section(&relocated_section_data(obj_file, srcinfo)?) {
+            Ok(infos) => {
+                for (entry_offset, name_offset) in infos.into_iter().flat_map(|i| i.switches) {
+                    if let Some(name) = names.get(&name_offset) {
+                        switches.insert(entry_offset, name.clone());
+                    }
+                }
+            }
+            Err(e) => log::warn!("Failed to read .debug_srcinfo: {:?}", e),
+        }
+    }
+
+    // Address ranges by compile unit
+    let mut unit_ranges = BTreeMap::<u32, Vec<(u32, u32)>>::new();
+    if let Some(aranges) = obj_file.section_by_name(".debug_aranges") {
+        let data = relocated_section_data(obj_file, aranges)?;
+        for entry in read_aranges_section(&mut Cursor::new(&*data))? {
+            unit_ranges.entry(entry.debug_offset).or_default().extend(entry.ranges);
+        }
+    }
+
+    let mut info = LineInfo::default();
+    let Some((_, mut tag)) = tags.first_key_value() else {
+        return Ok(Some(info));
+    };
+    while tag.kind == TagKind::CompileUnit {
+        let unit_name = tag.string_attribute(AttributeKind::Name).cloned().unwrap_or_default();
+        let table = tag.data4_attribute(AttributeKind::StmtList).and_then(|o| tables.get(&o));
+        if let Some(table) = table {
+            // Resolve the source file of each entry
+            let mut file = info.file_index(&unit_name);
+            let mut rows = Vec::with_capacity(table.entries.len());
+            for (entry, offset) in table.entries.iter().zip(&table.entry_offsets) {
+                if let Some(name) = switches.get(offset) {
+                    file = info.file_index(name);
+                }
+                // Line 0 marks the end of the table
+                if entry.line != 0 {
+                    rows.push(LineRow {
+                        address: entry.address,
+                        file,
+                        line: entry.line,
+                        column: entry.column,
+                    });
+                }
+            }
+            rows.sort_by_key(|row| row.address);
+
+            let unit_index = info.units.len();
+            let mut functions = Vec::new();
+            for child in tag.children(tags) {
+                let Ok(TagType::UserDefined(UserDefinedType::Subroutine(s))) =
+                    process_root_tag(tags, child)
+                else {
+                    continue;
+                };
+                let (Some(start_address), Some(end_address)) = (s.start_address, s.end_address)
+                else {
+                    continue;
+                };
+                let function_rows = rows
+                    .iter()
+                    .filter(|row| row.address >= start_address && row.address < end_address)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if function_rows.is_empty() {
+                    continue;
+                }
+                let name = s
+                    .mangled_name
+                    .or(s.name)
+                    .unwrap_or_else(|| format!("fn_{:08X}", start_address));
+                functions.push(FunctionLines {
+                    name,
+                    start_address,
+                    end_address,
+                    rows: function_rows,
+                });
+            }
+            // Prefer unit ranges from .debug_aranges, falling back to function ranges
+            match unit_ranges.get(&tag.key) {
+                Some(ranges) => {
+                    for &(address, length) in ranges {
+                        info.ranges.insert(address, (address.wrapping_add(length), unit_index));
+                    }
+                }
+                None => {
+                    for function in &functions {
+                        info.ranges
+                            .insert(function.start_address, (function.end_address, unit_index));
+                    }
+                }
+            }
+            info.units.push(UnitLines { key: tag.key, name: unit_name, rows, functions });
+        }
+        match tag.next_sibling(tags) {
+            Some(next) => tag = next,
+            None => break,
+        }
+    }
+    Ok(Some(info))
+}
+
+/// Reads the DWARF tags from a linked debug ELF.
+pub fn read_debug_elf<P>(path: P) -> Result<TagMap>
+where P: AsRef<Path> {
+    let file = map_file(&path)?;
+    let obj_file = object::read::File::parse(file.as_slice())?;
+    read_debug_tags(&obj_file)?
+        .ok_or_else(|| anyhow!("No debug info found in {}", path.as_ref().display()))
+}
+
+/// Reads the line information from a linked debug ELF, if present.
+pub fn read_debug_elf_lines<P>(path: P) -> Result<Option<LineInfo>>
+where P: AsRef<Path> {
+    let file = map_file(&path)?;
+    let obj_file = object::read::File::parse(file.as_slice())?;
+    let Some(tags) = read_debug_tags(&obj_file)? else {
+        return Ok(None);
+    };
+    read_debug_lines(&obj_file, &tags)
+}
+
+struct DebugSymbol {
+    name: String,
+    address: u32,
+    size: u32,
+    kind: ObjSymbolKind,
+    local: bool,
+    data_type: Option<Type>,
+}
+
+struct DebugUnit {
+    name: String,
+    symbols: Vec<DebugSymbol>,
+}
+
+/// Converts a source file path from debug info into a project-relative path.
+/// Everything up to the last `src` or `include` directory is stripped, or the
+/// file name is used if neither is present.
+pub fn source_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = match path.split_once(':') {
+        Some((drive, rest)) if drive.len() == 1 => rest,
+        _ => path.as_str(),
+    };
+    let root = ["/src/", "/include/"]
+        .iter()
+        .filter_map(|dir| path.rfind(dir).map(|idx| idx + dir.len()))
+        .max();
+    match root {
+        Some(idx) => path[idx..].to_string(),
+        None => path.rsplit_once('/').map(|(_, name)| name).unwrap_or(path).to_string(),
+    }
+}
+
+fn collect_units(tags: &TagMap) -> Result<Vec<DebugUnit>> {
+    let mut units = Vec::<DebugUnit>::new();
+    let Some((_, mut tag)) = tags.first_key_value() else {
+        return Ok(units);
+    };
+    loop {
+        if tag.kind != TagKind::CompileUnit {
+            log::warn!("Expected CompileUnit, got {:?}", tag.kind);
+            break;
+        }
+        let unit = tag
+            .string_attribute(AttributeKind::Name)
+            .ok_or_else(|| anyhow!("CompileUnit without name {:?}", tag))?;
+        let mut symbols = vec![];
+        for child in tag.children(tags) {
+            let tag_type = match process_root_tag(tags, child) {
+                Ok(tag_type) => tag_type,
+                Err(e) => {
+                    log::debug!("Failed to process tag {} (unit {}): {}", child.key, unit, e);
+                    continue;
+                }
+            };
+            match tag_type {
+                TagType::Variable(v) => {
+                    let (Some(address), Some(name)) = (v.address, v.mangled_name.or(v.name)) else {
+                        continue;
+                    };
+                    let size = v.kind.size(tags).unwrap_or_default();
+                    symbols.push(DebugSymbol {
+                        name,
+                        address,
+                        size,
+                        kind: ObjSymbolKind::Object,
+                        local: v.local,
+                        data_type: Some(v.kind),
+                    });
+                }
+                TagType::UserDefined(UserDefinedType::Subroutine(s)) => {
+                    let (Some(start), Some(end)) = (s.start_address, s.end_address) else {
+                        continue;
+                    };
+                    // Member functions can't be named without their mangled name
+                    let name = match (s.mangled_name, s.name) {
+                        (Some(name), _) => name,
+                        (None, Some(name)) if s.member_of.is_none() => name,
+                        _ => continue,
+                    };
+                    symbols.push(DebugSymbol {
+                        name,
+                        address: start,
+                        size: end.saturating_sub(start),
+                        kind: ObjSymbolKind::Function,
+                        local: s.local,
+                        data_type: None,
+                    });
+                }
+                _ => {}
+            }
+        }
+        units.push(DebugUnit { name: source_path(unit), symbols });
+        match tag.next_sibling(tags) {
+            Some(next) => tag = next,
+            None => break,
+        }
+    }
+    Ok(units)
+}
+
+fn fund_data_kind(ft: FundType) -> ObjDataKind {
+    match ft {
+        FundType::Char | FundType::SignedChar | FundType::UnsignedChar | FundType::Boolean => {
+            ObjDataKind::Byte
+        }
+        FundType::WideChar | FundType::Short | FundType::SignedShort | FundType::UnsignedShort => {
+            ObjDataKind::Byte2
+        }
+        FundType::Integer
+        | FundType::SignedInteger
+        | FundType::UnsignedInteger
+        | FundType::Long
+        | FundType::SignedLong
+        | FundType::UnsignedLong
+        | FundType::Pointer => ObjDataKind::Byte4,
+        FundType::LongLong | FundType::SignedLongLong | FundType::UnsignedLongLong => {
+            ObjDataKind::Byte8
+        }
+        FundType::Float => ObjDataKind::Float,
+        FundType::DblPrecFloat => ObjDataKind::Double,
+        _ => ObjDataKind::Unknown,
+    }
+}
+
+/// Derives the data kind of a variable from its type.
+/// Character arrays are only treated as strings when the data is a single
+/// null-terminated string.
+fn type_data_kind(tags: &TagMap, t: &Type, data: Option<&[u8]>) -> ObjDataKind {
+    if t.modifiers.iter().any(|m| matches!(m, Modifier::PointerTo | Modifier::ReferenceTo)) {
+        return ObjDataKind::Byte4;
+    }
+    let key = match t.kind {
+        TypeKind::Fundamental(ft) => return fund_data_kind(ft),
+        TypeKind::UserDefined(key) => key,
+    };
+    let Some(ud) = tags.get(&key).and_then(|tag| ud_type(tags, tag).ok()) else {
+        return ObjDataKind::Unknown;
+    };
+    match ud {
+        UserDefinedType::Array(array) => {
+            let kind = type_data_kind(tags, &array.element_type, None);
+            let is_char = array.element_type.modifiers.is_empty()
+                && matches!(
+                    array.element_type.kind,
+                    TypeKind::Fundamental(
+                        FundType::Char | FundType::SignedChar | FundType::UnsignedChar
+                    )
+                );
+            let is_string = matches!(
+                data,
+                Some(data) if data.last() == Some(&0)
+                    && data.iter().position(|&b| b == 0) == Some(data.len() - 1)
+            );
+            if is_char && array.dimensions.len() == 1 && is_string {
+                ObjDataKind::String
+            } else {
+                kind
+            }
+        }
+        UserDefinedType::Enumeration(e) => match e.byte_size {
+            1 => ObjDataKind::Byte,
+            2 => ObjDataKind::Byte2,
+            4 => ObjDataKind::Byte4,
+            _ => ObjDataKind::Unknown,
+        },
+        UserDefinedType::PtrToMember(_) => ObjDataKind::Byte4,
+        _ => ObjDataKind::Unknown,
+    }
+}
+
+/// A typed field within a variable's data.
+#[derive(Debug, Clone)]
+pub struct DataField {
+    /// Offset from the start of the variable
+    pub offset: u32,
+    pub size: u32,
+    pub kind: ObjDataKind,
+    /// Member path, e.g. `[2].pos.x`. Empty for padding.
+    pub label: String,
+    pub type_name: String,
+}
+
+/// The field layout of a variable.
+#[derive(Debug, Clone)]
+pub struct DataLayout {
+    /// Symbol name, to verify the variable at an address
+    pub name: String,
+    pub fields: Vec<DataField>,
+}
+
+/// Data layouts of variables, keyed by address. (Local variables with the same name
+/// may be defined by multiple compile units)
+pub type DataLayouts = HashMap<u32, DataLayout>;
+
+/// Upper bound on fields per variable, larger arrays of structures are left untyped.
+const MAX_DATA_FIELDS: usize = 0x4000;
+
+fn push_data_field(
+    tags: &TagMap,
+    t: &Type,
+    offset: u32,
+    size: u32,
+    kind: ObjDataKind,
+    label: &str,
+    out: &mut Vec<DataField>,
+) {
+    if size == 0 {
+        return;
+    }
+    let type_name = type_string(tags, &TypedefMap::new(), t, false)
+        .map(|ts| format!("{}{}", ts.prefix, ts.suffix))
+        .unwrap_or_else(|_| "?".to_string());
+    out.push(DataField { offset, size, kind, label: label.to_string(), type_name });
+}
+
+/// Flattens a type into its scalar fields. Arrays of scalars are kept as a single field.
+fn flatten_type(
+    tags: &TagMap,
+    t: &Type,
+    offset: u32,
+    label: &str,
+    out: &mut Vec<DataField>,
+) -> Result<()> {
+    ensure!(out.len() <= MAX_DATA_FIELDS, "Too many fields");
+    let size = t.size(tags)?;
+    let key = match t.kind {
+        TypeKind::UserDefined(key)
+            if !t
+                .modifiers
+                .iter()
+                .any(|m| matches!(m, Modifier::PointerTo | Modifier::ReferenceTo)) =>
+        {
+            key
+        }
+        _ => {
+            push_data_field(tags, t, offset, size, type_data_kind(tags, t, None), label, out);
+            return Ok(());
+        }
+    };
+    let tag =
+        tags.get(&key).ok_or_else(|| anyhow!("Failed to locate user defined type {}", key))?;
+    match ud_type(tags, tag)? {
+        UserDefinedType::Structure(s) => {
+            for base in &s.bases {
+                flatten_type(tags, &base.base_type, offset + base.offset, label, out)?;
+            }
+            for member in &s.members {
+                let member_label = format!("{}.{}", label, member.name);
+                if member.bit.is_some() {
+                    // Bitfields share their storage unit, only emit it once
+                    let member_offset = offset + member.offset;
+                    if out.last().is_some_and(|f| f.offset == member_offset) {
+                        continue;
+                    }
+                    let size = member.byte_size.unwrap_or(member.kind.size(tags)?);
+                    let kind = type_data_kind(tags, &member.kind, None);
+                    push_data_field(
+                        tags,
+                        &member.kind,
+                        member_offset,
+                        size,
+                        kind,
+                        &member_label,
+                        out,
+                    );
+                    continue;
+                }
+                flatten_type(tags, &member.kind, offset + member.offset, &member_label, out)?;
+            }
+        }
+        UserDefinedType::Array(array) => {
+            let element_size = array.element_type.size(tags)?;
+            let count = array
+                .dimensions
+                .iter()
+                .map(|d| d.size.map(|s| s.get()).unwrap_or(0))
+                .product::<u32>();
+            let mut element_fields = vec![];
+            flatten_type(tags, &array.element_type, 0, "", &mut element_fields)?;
+            if element_fields.len() <= 1 || element_size == 0 {
+                // Array of scalars
+                let is_char = array.element_type.modifiers.is_empty()
+                    && matches!(
+                        array.element_type.kind,
+                        TypeKind::Fundamental(
+                            FundType::Char | FundType::SignedChar | FundType::UnsignedChar
+                        )
+                    );
+                let kind = if is_char && array.dimensions.len() == 1 {
+                    ObjDataKind::String
+                } else {
+                    type_data_kind(tags, &array.element_type, None)
+                };
+                push_data_field(tags, t, offset, size, kind, label, out);
+                return Ok(());
+            }
+            ensure!(
+                out.len() + element_fields.len() * count as usize <= MAX_DATA_FIELDS,
+                "Too many fields"
+            );
+            for index in 0..count {
+                // Index into each dimension, outermost first
+                let mut indices = String::new();
+                let mut remain = index;
+                let mut stride = count;
+                for dim in &array.dimensions {
+                    stride /= dim.size.map(|s| s.get()).unwrap_or(1);
+                    indices.push_str(&format!("[{}]", remain / stride));
+                    remain %= stride;
+                }
+                let element_offset = offset + index * element_size;
+                for field in &element_fields {
+                    out.push(DataField {
+                        offset: element_offset + field.offset,
+                        label: format!("{}{}{}", label, indices, field.label),
+                        ..field.clone()
+                    });
+                }
+            }
+        }
+        UserDefinedType::Enumeration(_) | UserDefinedType::PtrToMember(_) => {
+            push_data_field(tags, t, offset, size, type_data_kind(tags, t, None), label, out);
+        }
+        UserDefinedType::Union(_) => {
+            push_data_field(tags, t, offset, size, ObjDataKind::Unknown, label, out);
+        }
+        UserDefinedType::Subroutine(_) => {}
+    }
+    Ok(())
+}
+
+/// Collects the field layout of every variable with a structure or array type,
+/// for writing typed data.
+pub fn debug_data_layouts(tags: &TagMap) -> Result<DataLayouts> {
+    let mut layouts = DataLayouts::new();
+    for unit in collect_units(tags)? {
+        for symbol in unit.symbols {
+            let Some(t) = &symbol.data_type else {
+                continue;
+            };
+            let is_aggregate = matches!(t.kind, TypeKind::UserDefined(key)
+            if t.modifiers.is_empty()
+                && tags.get(&key).and_then(|tag| ud_type(tags, tag).ok()).is_some_and(|ud| {
+                    matches!(ud, UserDefinedType::Structure(_) | UserDefinedType::Array(_))
+                }));
+            if !is_aggregate {
+                continue;
+            }
+            let mut fields = vec![];
+            if let Err(e) = flatten_type(tags, t, 0, "", &mut fields) {
+                log::debug!("Skipping data layout for {}: {}", symbol.name, e);
+                continue;
+            }
+            fields.sort_by_key(|f| f.offset);
+
+            // Fill gaps with padding
+            let mut padded = Vec::with_capacity(fields.len());
+            let mut end = 0;
+            for field in fields {
+                if field.offset < end {
+                    continue;
+                }
+                if field.offset > end {
+                    padded.push(DataField {
+                        offset: end,
+                        size: field.offset - end,
+                        kind: ObjDataKind::Byte,
+                        label: String::new(),
+                        type_name: String::new(),
+                    });
+                }
+                end = field.offset + field.size;
+                padded.push(field);
+            }
+            if symbol.size > end {
+                padded.push(DataField {
+                    offset: end,
+                    size: symbol.size - end,
+                    kind: ObjDataKind::Byte,
+                    label: String::new(),
+                    type_name: String::new(),
+                });
+            }
+            match layouts.entry(symbol.address) {
+                hash_map::Entry::Occupied(e) => {
+                    log::debug!(
+                        "Skipping data layout for {}: {} is already defined at {:#010X}",
+                        symbol.name,
+                        e.get().name,
+                        symbol.address
+                    );
+                }
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(DataLayout { name: symbol.name, fields: padded });
+                }
+            }
+        }
+    }
+    Ok(layouts)
+}
+
+/// Applies function and variable names, sizes, scopes and data kinds from debug info.
+pub fn apply_debug_symbols(obj: &mut ObjInfo, tags: &TagMap) -> Result<()> {
+    let mut count = 0usize;
+    for unit in collect_units(tags)? {
+        for symbol in unit.symbols {
+            let Ok((section_index, section)) = obj.sections.at_address(symbol.address) else {
+                log::warn!(
+                    "Debug symbol {} @ {:#010X} is outside of any section",
+                    symbol.name,
+                    symbol.address
+                );
+                continue;
+            };
+            let data_kind = match &symbol.data_type {
+                Some(t) => {
+                    let data = section
+                        .data_range(symbol.address, symbol.address + symbol.size)
+                        .ok()
+                        .filter(|data| !data.is_empty());
+                    type_data_kind(tags, t, data)
+                }
+                None => ObjDataKind::Unknown,
+            };
+            let flags = if symbol.local { ObjSymbolFlags::Local } else { ObjSymbolFlags::Global };
+            obj.add_symbol(
+                ObjSymbol {
+                    demangled_name: demangle(&symbol.name, &DemangleOptions::default()),
+                    name: symbol.name,
+                    address: symbol.address as u64,
+                    section: Some(section_index),
+                    size: symbol.size as u64,
+                    size_known: symbol.size != 0,
+                    flags: ObjSymbolFlagSet(flags.into()),
+                    kind: symbol.kind,
+                    data_kind,
+                    ..Default::default()
+                },
+                true,
+            )?;
+            count += 1;
+        }
+    }
+    log::info!("Applied {} symbols from debug info", count);
+    Ok(())
+}
+
+/// Adds splits for each compile unit, covering the symbols it defines in each section.
+/// Ranges overlapping existing splits are skipped.
+pub fn apply_debug_splits(obj: &mut ObjInfo, tags: &TagMap) -> Result<()> {
+    let common_bss_start = obj.sections.common_bss_start();
+    let mut count = 0usize;
+    for unit in collect_units(tags)? {
+        let mut ranges = BTreeMap::<usize, (u32, u32)>::new();
+        for symbol in &unit.symbols {
+            let Ok((section_index, _)) = obj.sections.at_address(symbol.address) else {
+                continue;
+            };
+            let end = symbol.address + symbol.size.max(1);
+            let range = ranges.entry(section_index).or_insert((symbol.address, end));
+            range.0 = range.0.min(symbol.address);
+            range.1 = range.1.max(end);
+        }
+        for (section_index, (start, end)) in ranges {
+            let section = &mut obj.sections[section_index];
+            let end = end.min((section.address + section.size) as u32);
+            if section.splits.for_address(start).is_some()
+                || section.splits.for_range(start..end).next().is_some()
+            {
+                log::debug!(
+                    "Skipping split for {} in {} ({:#010X}-{:#010X}), overlaps existing split",
+                    unit.name,
+                    section.name,
+                    start,
+                    end
+                );
+                continue;
+            }
+            let common = matches!(common_bss_start, Some((idx, addr)) if idx == section_index && start >= addr);
+            section.splits.push(start, ObjSplit {
+                unit: unit.name.clone(),
+                end,
+                align: None,
+                common,
+                autogenerated: false,
+                skip: false,
+                rename: None,
+            });
+            count += 1;
+        }
+    }
+    log::info!("Applied {} splits from debug info", count);
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::{
     collections::BTreeMap,
     io,
-    io::{Cursor, Read, Seek},
+    io::{Cursor, Read, Seek, Write},
 };
 
 use anyhow::{anyhow, bail, ensure, Result};
@@ -15,12 +15,13 @@ use crate::{
     },
     util::{
         alf::{AlfFile, AlfSymbol, ALF_MAGIC},
-        reader::{skip_bytes, Endian, FromReader},
+        align_up,
+        reader::{skip_bytes, Endian, FromReader, ToWriter},
     },
 };
 
-const MAX_TEXT_SECTIONS: usize = 7;
-const MAX_DATA_SECTIONS: usize = 11;
+pub const MAX_TEXT_SECTIONS: usize = 7;
+pub const MAX_DATA_SECTIONS: usize = 11;
 const MAX_ROM_COPY_INFO_SIZE: usize = (MAX_TEXT_SECTIONS + MAX_DATA_SECTIONS + 1) * 3 * 4; // num sections * 3 entries * u32
 const MAX_BSS_INIT_INFO_SIZE: usize = (MAX_DATA_SECTIONS + 1) * 2 * 4; // num sections * 2 entries * u32
 const ETI_INIT_INFO_SIZE: usize = 16; // eti_start, eti_end, code_start, code_size
@@ -130,7 +131,7 @@ impl FromReader for DolFile {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DolHeader {
     pub text_offs: [u32; MAX_TEXT_SECTIONS],
     pub data_offs: [u32; MAX_DATA_SECTIONS],
@@ -166,6 +167,121 @@ impl FromReader for DolHeader {
     }
 }
 
+impl ToWriter for DolHeader {
+    fn to_writer<W>(&self, writer: &mut W, e: Endian) -> io::Result<()>
+    where W: Write + ?Sized {
+        for values in [
+            &self.text_offs[..],
+            &self.data_offs,
+            &self.text_addrs,
+            &self.data_addrs,
+            &self.text_sizes,
+            &self.data_sizes,
+        ] {
+            for value in values {
+                value.to_writer(writer, e)?;
+            }
+        }
+        self.bss_addr.to_writer(writer, e)?;
+        self.bss_size.to_writer(writer, e)?;
+        self.entry_point.to_writer(writer, e)?;
+        [0u8; 0x1C].to_writer(writer, e)?; // padding
+        Ok(())
+    }
+
+    fn write_size(&self) -> usize { Self::STATIC_SIZE }
+}
+
+impl DolHeader {
+    /// Returns the index of the first unused text or data section slot.
+    pub fn free_slot(&self, kind: DolSectionKind) -> Option<usize> {
+        match kind {
+            DolSectionKind::Text => self.text_sizes.iter().position(|&s| s == 0),
+            DolSectionKind::Data => self.data_sizes.iter().position(|&s| s == 0),
+            DolSectionKind::Bss => None,
+        }
+    }
+}
+
+/// A text or data section to be written by [`write_dol`].
+#[derive(Debug, Clone)]
+pub struct DolSectionWrite<'a> {
+    /// Section name, used for logging.
+    pub name: &'a str,
+    pub kind: DolSectionKind,
+    pub address: u32,
+    pub data: &'a [u8],
+    /// File offset. Defaults to the end of the file, aligned to 32 bytes.
+    pub offset: Option<u32>,
+    /// Section size. Defaults to the data size, aligned to 32 bytes.
+    pub size: Option<u32>,
+}
+
+/// Appends sections to a DOL file in `out`, filling the free slots in `header` in order,
+/// then writes `header` to the start of `out`. Empty sections still take a slot.
+pub fn write_dol(
+    out: &mut Vec<u8>,
+    header: &mut DolHeader,
+    sections: &[DolSectionWrite],
+) -> Result<()> {
+    if out.len() < DolHeader::STATIC_SIZE {
+        out.resize(DolHeader::STATIC_SIZE, 0);
+    }
+    // Slots written here may have a size of 0, so continue from the last one used
+    let mut next_text_slot = 0;
+    let mut next_data_slot = 0;
+    for section in sections {
+        let (offs, addrs, sizes, next_slot) = match section.kind {
+            DolSectionKind::Text => (
+                &mut header.text_offs[..],
+                &mut header.text_addrs[..],
+                &mut header.text_sizes[..],
+                &mut next_text_slot,
+            ),
+            DolSectionKind::Data => (
+                &mut header.data_offs[..],
+                &mut header.data_addrs[..],
+                &mut header.data_sizes[..],
+                &mut next_data_slot,
+            ),
+            DolSectionKind::Bss => bail!("Unexpected BSS section '{}'", section.name),
+        };
+        let Some(slot) = (*next_slot..sizes.len()).find(|&i| sizes[i] == 0) else {
+            bail!(
+                "Too many {} sections (while processing '{}')",
+                if section.kind == DolSectionKind::Text { "text" } else { "data" },
+                section.name
+            );
+        };
+        let offset = section.offset.unwrap_or_else(|| align_up(out.len() as u32, 32));
+        let size = section.size.unwrap_or_else(|| align_up(section.data.len() as u32, 32));
+        ensure!(
+            offset as usize >= out.len(),
+            "Section '{}' offset {:#X} overlaps previous data (ends at {:#X})",
+            section.name,
+            offset,
+            out.len()
+        );
+        ensure!(
+            section.data.len() <= size as usize,
+            "Section '{}' data size {:#X} exceeds section size {:#X}",
+            section.name,
+            section.data.len(),
+            size
+        );
+        *next_slot = slot + 1;
+        offs[slot] = offset;
+        addrs[slot] = section.address;
+        sizes[slot] = size;
+        out.resize(offset as usize, 0);
+        out.extend_from_slice(section.data);
+        out.resize((offset + size) as usize, 0);
+    }
+    let header_bytes = header.to_bytes(Endian::Big)?;
+    out[..header_bytes.len()].copy_from_slice(&header_bytes);
+    Ok(())
+}
+
 impl DolLike for DolFile {
     fn sections(&self) -> &[DolSection] { &self.sections }
 
@@ -761,3 +877,45 @@ fn validate_eti_init_info(
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dol_header_round_trip() {
+        let mut header = DolHeader {
+            bss_addr: 0x80400000,
+            bss_size: 0x12340,
+            entry_point: 0x80003154,
+            ..Default::default()
+        };
+        for i in 0..3 {
+            header.text_offs[i] = 0x100 + i as u32 * 0x1000;
+            header.text_addrs[i] = 0x80003100 + i as u32 * 0x1000;
+            header.text_sizes[i] = 0x1000;
+        }
+        for i in 0..5 {
+            header.data_offs[i] = 0x4000 + i as u32 * 0x20;
+            header.data_addrs[i] = 0x80010000 + i as u32 * 0x20;
+            header.data_sizes[i] = 0x20;
+        }
+        let bytes = header.to_bytes(Endian::Big).unwrap();
+        assert_eq!(bytes.len(), DolHeader::STATIC_SIZE);
+        assert_eq!(&bytes[0xD8..0xE4], &[0x80, 0x40, 0, 0, 0, 1, 0x23, 0x40, 0x80, 0, 0x31, 0x54]);
+        assert!(bytes[0xE4..].iter().all(|&b| b == 0));
+
+        let read = DolHeader::from_reader(&mut Cursor::new(&bytes), Endian::Big).unwrap();
+        assert_eq!(read.to_bytes(Endian::Big).unwrap(), bytes);
+        assert_eq!(read.text_offs, header.text_offs);
+        assert_eq!(read.data_addrs, header.data_addrs);
+        assert_eq!(read.text_sizes, header.text_sizes);
+        assert_eq!(
+            (read.bss_addr, read.bss_size, read.entry_point),
+            (0x80400000, 0x12340, 0x80003154)
+        );
+        assert_eq!(read.free_slot(DolSectionKind::Text), Some(3));
+        assert_eq!(read.free_slot(DolSectionKind::Data), Some(5));
+        assert_eq!(read.free_slot(DolSectionKind::Bss), None);
+    }
+}
@@ -431,8 +431,15 @@ where R: BufRead + Seek + ?Sized {
     Ok(tags)
 }
 
-#[allow(unused)]
-pub fn read_aranges_section<R>(reader: &mut R) -> Result<()>
+#[derive(Debug, Clone)]
+pub struct ArangeEntry {
+    /// Offset of the compile unit in `.debug`
+    pub debug_offset: u32,
+    /// Address and length pairs
+    pub ranges: Vec<(u32, u32)>,
+}
+
+pub fn read_aranges_section<R>(reader: &mut R) -> Result<Vec<ArangeEntry>>
 where R: BufRead + Seek + ?Sized {
     let len = {
         let old_pos = reader.stream_position()?;
@@ -441,7 +448,7 @@ where R: BufRead + Seek + ?Sized {
         len
     };
 
-    // let mut tags = BTreeMap::new();
+    let mut entries = Vec::new();
     loop {
         let position = reader.stream_position()?;
         if position >= len {
@@ -451,14 +458,123 @@ where R: BufRead + Seek + ?Sized {
         let size = u32::from_reader(reader, Endian::Big)?;
         let version = u8::from_reader(reader, Endian::Big)?;
         ensure!(version == 1, "Expected version 1, got {version}");
-        let _debug_offs = u32::from_reader(reader, Endian::Big)?;
-        let _debug_size = u32::from_reader(reader, Endian::Big)?;
+        let debug_offset = u32::from_reader(reader, Endian::Big)?;
+        u32::from_reader(reader, Endian::Big)?; // Size of the unit in .debug
+        let mut ranges = Vec::new();
         while reader.stream_position()? < position + size as u64 {
-            let _address = u32::from_reader(reader, Endian::Big)?;
-            let _length = u32::from_reader(reader, Endian::Big)?;
+            let address = u32::from_reader(reader, Endian::Big)?;
+            let length = u32::from_reader(reader, Endian::Big)?;
+            if length > 0 {
+                ranges.push((address, length));
+            }
         }
+        entries.push(ArangeEntry { debug_offset, ranges });
     }
-    Ok(())
+    Ok(entries)
+}
+
+#[derive(Debug, Clone)]
+pub struct LineEntry {
+    pub address: u32,
+    pub line: u32,
+    pub column: Option<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineTable {
+    /// Offset of each entry in `.line`, used by `.debug_srcinfo`
+    pub entry_offsets: Vec<u32>,
+    pub entries: Vec<LineEntry>,
+}
+
+/// Line table map, keyed by offset in `.line`
+pub type LineTableMap = BTreeMap<u32, LineTable>;
+
+const LINE_NO_POSITION: u16 = 0xFFFF;
+
+pub fn read_line_section<R>(reader: &mut R) -> Result<LineTableMap>
+where R: BufRead + Seek + ?Sized {
+    let len = {
+        let old_pos = reader.stream_position()?;
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(old_pos))?;
+        len
+    };
+
+    let mut tables = BTreeMap::new();
+    loop {
+        let position = reader.stream_position()?;
+        if position >= len {
+            break;
+        }
+
+        let size = u32::from_reader(reader, Endian::Big)?;
+        ensure!(size >= 8, "Invalid line table size {:#X} at {:#X}", size, position);
+        let base_address = u32::from_reader(reader, Endian::Big)?;
+        let mut entry_offsets = Vec::new();
+        let mut entries = Vec::new();
+        while reader.stream_position()? + 10 <= position + size as u64 {
+            entry_offsets.push(reader.stream_position()? as u32);
+            let line = u32::from_reader(reader, Endian::Big)?;
+            let column = u16::from_reader(reader, Endian::Big)?;
+            let delta = u32::from_reader(reader, Endian::Big)?;
+            entries.push(LineEntry {
+                address: base_address.wrapping_add(delta),
+                line,
+                column: (column != LINE_NO_POSITION).then_some(column),
+            });
+        }
+        reader.seek(SeekFrom::Start(position + size as u64))?;
+        tables.insert(position as u32, LineTable { entry_offsets, entries });
+    }
+    Ok(tables)
+}
+
+/// Reads source file names from `.debug_sfnames`, keyed by offset.
+pub fn read_sfnames_section(data: &[u8]) -> Result<BTreeMap<u32, String>> {
+    let mut names = BTreeMap::new();
+    let mut reader = Cursor::new(data);
+    while reader.position() < data.len() as u64 {
+        let position = reader.position() as u32;
+        let name = read_string(&mut reader)?;
+        if !name.is_empty() {
+            names.insert(position, name);
+        }
+    }
+    Ok(names)
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceInfo {
+    /// Offset of the line entry in `.line` and the file name in `.debug_sfnames`
+    /// at which the source file changes
+    pub switches: Vec<(u32, u32)>,
+}
+
+/// Reads `.debug_srcinfo`, which maps line table entries to the source file names in
+/// `.debug_sfnames`. Each unit starts with the offsets of its line table and file names,
+/// followed by pairs of relative offsets, terminated by `0xFFFFFFFF`.
+pub fn read_srcinfo_section(data: &[u8]) -> Result<Vec<SourceInfo>> {
+    let mut infos = Vec::new();
+    let mut reader = Cursor::new(data);
+    while reader.position() + 8 <= data.len() as u64 {
+        let line_offset = u32::from_reader(&mut reader, Endian::Big)?;
+        let sfnames_offset = u32::from_reader(&mut reader, Endian::Big)?;
+        let mut switches = Vec::new();
+        loop {
+            let entry_offset = u32::from_reader(&mut reader, Endian::Big)?;
+            if entry_offset == u32::MAX {
+                break;
+            }
+            let name_offset = u32::from_reader(&mut reader, Endian::Big)?;
+            switches.push((
+                line_offset.wrapping_add(entry_offset),
+                sfnames_offset.wrapping_add(name_offset),
+            ));
+        }
+        infos.push(SourceInfo { switches });
+    }
+    Ok(infos)
 }
 
 fn read_tag<R>(reader: &mut R) -> Result<Tag>
@@ -613,14 +729,14 @@ pub struct UnionType {
 pub struct SubroutineParameter {
     pub name: Option<String>,
     pub kind: Type,
-    pub location: Option<String>,
+    pub location: Option<VariableLocation>,
 }
 
 #[derive(Debug, Clone)]
 pub struct SubroutineVariable {
     pub name: Option<String>,
     pub kind: Type,
-    pub location: Option<String>,
+    pub location: Option<VariableLocation>,
 }
 
 #[derive(Debug, Clone)]
@@ -661,6 +777,8 @@ pub struct SubroutineType {
     pub labels: Vec<SubroutineLabel>,
     pub blocks: Vec<SubroutineBlock>,
     pub inlines: Vec<SubroutineInline>,
+    pub start_address: Option<u32>,
+    pub end_address: Option<u32>,
 }
 
 #[derive(Debug, Clone)]
@@ -1277,6 +1395,154 @@ fn subroutine_block_string(
     Ok(out)
 }
 
+struct FrameVariable {
+    decl: String,
+    location: VariableLocation,
+    size: u32,
+    parameter: bool,
+    start_address: u32,
+    end_address: u32,
+}
+
+fn collect_frame_variables(
+    tags: &TagMap,
+    typedefs: &TypedefMap,
+    variables: &[SubroutineVariable],
+    blocks: &[SubroutineBlock],
+    range: (u32, u32),
+    out: &mut Vec<FrameVariable>,
+) -> Result<()> {
+    for variable in variables {
+        let Some(location) = variable.location else {
+            continue;
+        };
+        let ts = type_string(tags, typedefs, &variable.kind, true)?;
+        out.push(FrameVariable {
+            decl: format!(
+                "{} {}{};",
+                ts.prefix,
+                variable.name.as_deref().unwrap_or_default(),
+                ts.suffix
+            ),
+            location,
+            size: variable.kind.size(tags).unwrap_or_default(),
+            parameter: false,
+            start_address: range.0,
+            end_address: range.1,
+        });
+    }
+    for block in blocks {
+        collect_frame_variables(
+            tags,
+            typedefs,
+            &block.variables,
+            &block.blocks,
+            (block.start_address, block.end_address),
+            out,
+        )?;
+    }
+    Ok(())
+}
+
+/// Describes the stack layout and register allocation of a subroutine's parameters and
+/// local variables, grouped by storage.
+pub fn subroutine_frame_string(
+    tags: &TagMap,
+    typedefs: &TypedefMap,
+    t: &SubroutineType,
+) -> Result<String> {
+    let (Some(start_address), Some(end_address)) = (t.start_address, t.end_address) else {
+        bail!("Subroutine without address range");
+    };
+    let mut variables = Vec::new();
+    for parameter in &t.parameters {
+        let Some(location) = parameter.location else {
+            continue;
+        };
+        let ts = type_string(tags, typedefs, &parameter.kind, true)?;
+        variables.push(FrameVariable {
+            decl: format!(
+                "{} {}{};",
+                ts.prefix,
+                parameter.name.as_deref().unwrap_or_default(),
+                ts.suffix
+            ),
+            location,
+            size: parameter.kind.size(tags).unwrap_or_default(),
+            parameter: true,
+            start_address,
+            end_address,
+        });
+    }
+    collect_frame_variables(
+        tags,
+        typedefs,
+        &t.variables,
+        &t.blocks,
+        (start_address, end_address),
+        &mut variables,
+    )?;
+
+    let mut name = String::new();
+    if let Some(member_of) = t.member_of {
+        if let Some(base_name) =
+            tags.get(&member_of).and_then(|tag| tag.string_attribute(AttributeKind::Name))
+        {
+            write!(name, "{}::", base_name)?;
+        }
+    }
+    name.push_str(t.name.as_deref().unwrap_or("[anonymous]"));
+    let mut out = String::new();
+    writeln!(out, "// Frame for {} ({:#010X} - {:#010X})", name, start_address, end_address)?;
+
+    let mut stack = Vec::new();
+    let mut gprs = Vec::new();
+    let mut fprs = Vec::new();
+    let mut other = Vec::new();
+    for variable in &variables {
+        match variable.location {
+            VariableLocation::Offset { register: 1, .. } => stack.push(variable),
+            VariableLocation::Register(reg) | VariableLocation::BaseRegister(reg) if reg < 32 => {
+                gprs.push(variable)
+            }
+            VariableLocation::Register(reg) | VariableLocation::BaseRegister(reg) if reg < 64 => {
+                fprs.push(variable)
+            }
+            _ => other.push(variable),
+        }
+    }
+    stack.sort_by_key(|v| match v.location {
+        VariableLocation::Offset { offset, .. } => offset,
+        _ => 0,
+    });
+    for group in [&mut gprs, &mut fprs] {
+        group.sort_by_key(|v| match v.location {
+            VariableLocation::Register(reg) | VariableLocation::BaseRegister(reg) => reg,
+            _ => 0,
+        });
+    }
+    for (label, group) in [("Stack", stack), ("GPRs", gprs), ("FPRs", fprs), ("Other", other)] {
+        if group.is_empty() {
+            continue;
+        }
+        writeln!(out, "//   {}:", label)?;
+        for variable in group {
+            let location = variable.location.to_string();
+            writeln!(
+                out,
+                "//     {:<8} size {:#06X}  {:#010X} - {:#010X}  {}{}",
+                location,
+                variable.size,
+                variable.start_address,
+                variable.end_address,
+                if variable.parameter { "(param) " } else { "" },
+                variable.decl
+            )?;
+        }
+    }
+    Ok(out)
+}
+
 pub fn struct_def_string(
     tags: &TagMap,
     typedefs: &TypedefMap,
@@ -1414,23 +1680,48 @@ pub const fn register_name(reg: u32) -> &'static str {
     }
 }
 
-pub fn process_variable_location(block: &[u8]) -> Result<String> {
-    if block.len() == 5
-        && (block[0] == LocationOp::Register as u8 || block[0] == LocationOp::BaseRegister as u8)
-    {
-        Ok(register_name(u32::from_be_bytes(*array_ref!(block, 1, 4))).to_string())
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VariableLocation {
+    /// Value held in a register
+    Register(u32),
+    /// Value at the address held in a register
+    BaseRegister(u32),
+    /// Value at an offset from a register, usually the stack pointer
+    Offset { register: u32, offset: u32 },
+    /// Value at an absolute address
+    Address(u32),
+}
+
+impl Display for VariableLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            VariableLocation::Register(reg) | VariableLocation::BaseRegister(reg) => {
+                f.write_str(register_name(reg))
+            }
+            VariableLocation::Offset { register, offset } => {
+                write!(f, "{}+{:#X}", register_name(register), offset)
+            }
+            VariableLocation::Address(address) => write!(f, "@ {:#010X}", address),
+        }
+    }
+}
+
+pub fn process_variable_location(block: &[u8]) -> Result<VariableLocation> {
+    if block.len() == 5 && block[0] == LocationOp::Register as u8 {
+        Ok(VariableLocation::Register(u32::from_be_bytes(*array_ref!(block, 1, 4))))
+    } else if block.len() == 5 && block[0] == LocationOp::BaseRegister as u8 {
+        Ok(VariableLocation::BaseRegister(u32::from_be_bytes(*array_ref!(block, 1, 4))))
     } else if block.len() == 5 && block[0] == LocationOp::Address as u8 {
-        Ok(format!("@ {:#010X}", u32::from_be_bytes(*array_ref!(block, 1, 4))))
+        Ok(VariableLocation::Address(u32::from_be_bytes(*array_ref!(block, 1, 4))))
     } else if block.len() == 11
         && block[0] == LocationOp::BaseRegister as u8
         && block[5] == LocationOp::Const as u8
         && block[10] == LocationOp::Add as u8
     {
-        Ok(format!(
-            "{}+{:#X}",
-            register_name(u32::from_be_bytes(*array_ref!(block, 1, 4))),
-            u32::from_be_bytes(*array_ref!(block, 6, 4))
-        ))
+        Ok(VariableLocation::Offset {
+            register: u32::from_be_bytes(*array_ref!(block, 1, 4)),
+            offset: u32::from_be_bytes(*array_ref!(block, 6, 4)),
+        })
     } else {
         Err(anyhow!("Unhandled location data {:?}, expected variable loc", block))
     }
@@ -1775,6 +2066,8 @@ fn process_subroutine_tag(tags: &TagMap, tag: &Tag) -> Result<SubroutineType> {
     let mut references = Vec::new();
     let mut member_of = None;
     let mut inline = false;
+    let mut start_address = None;
+    let mut end_address = None;
     for attr in &tag.attributes {
         match (attr.kind, &attr.value) {
             (AttributeKind::Sibling, _) => {}
@@ -1788,9 +2081,8 @@ fn process_subroutine_tag(tags: &TagMap, tag: &Tag) -> Result<SubroutineType> {
                 _,
             ) => return_type = Some(process_type(attr)?),
             (AttributeKind::Prototyped, _) => prototyped = true,
-            (AttributeKind::LowPc, _) | (AttributeKind::HighPc, _) => {
-                // TODO?
-            }
+            (AttributeKind::LowPc, &AttributeValue::Address(addr)) => start_address = Some(addr),
+            (AttributeKind::HighPc, &AttributeValue::Address(addr)) => end_address = Some(addr),
             (AttributeKind::MwGlobalRef, &AttributeValue::Reference(key)) => {
                 references.push(key);
             }
@@ -1881,6 +2173,8 @@ fn process_subroutine_tag(tags: &TagMap, tag: &Tag) -> Result<SubroutineType> {
         labels,
         blocks,
         inlines,
+        start_address,
+        end_address,
     })
 }
 
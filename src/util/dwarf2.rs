@@ -0,0 +1,1156 @@
+//! DWARF 2+ frontend. Reads `.debug_info`/`.debug_abbrev` and lowers the DIE tree into
+//! DWARF 1.1 tags, so that the rest of [`crate::util::dwarf`] can process both versions.
+
+use std::{
+    collections::{hash_map, HashMap},
+    io::Cursor,
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::util::{
+    dwarf::{
+        Attribute, AttributeKind, AttributeValue, FundType, LocationOp, Modifier, SubscriptFormat,
+        Tag, TagKind, TagMap,
+    },
+    reader::{Endian, FromReader},
+};
+
+// DW_TAG_*
+const DW_TAG_ARRAY_TYPE: u16 = 0x01;
+const DW_TAG_CLASS_TYPE: u16 = 0x02;
+const DW_TAG_ENUMERATION_TYPE: u16 = 0x04;
+const DW_TAG_FORMAL_PARAMETER: u16 = 0x05;
+const DW_TAG_LABEL: u16 = 0x0a;
+const DW_TAG_LEXICAL_BLOCK: u16 = 0x0b;
+const DW_TAG_MEMBER: u16 = 0x0d;
+const DW_TAG_POINTER_TYPE: u16 = 0x0f;
+const DW_TAG_REFERENCE_TYPE: u16 = 0x10;
+const DW_TAG_COMPILE_UNIT: u16 = 0x11;
+const DW_TAG_STRUCTURE_TYPE: u16 = 0x13;
+const DW_TAG_SUBROUTINE_TYPE: u16 = 0x15;
+const DW_TAG_TYPEDEF: u16 = 0x16;
+const DW_TAG_UNION_TYPE: u16 = 0x17;
+const DW_TAG_UNSPECIFIED_PARAMETERS: u16 = 0x18;
+const DW_TAG_INHERITANCE: u16 = 0x1c;
+const DW_TAG_INLINED_SUBROUTINE: u16 = 0x1d;
+const DW_TAG_PTR_TO_MEMBER_TYPE: u16 = 0x1f;
+const DW_TAG_SUBRANGE_TYPE: u16 = 0x21;
+const DW_TAG_BASE_TYPE: u16 = 0x24;
+const DW_TAG_CONST_TYPE: u16 = 0x26;
+const DW_TAG_ENUMERATOR: u16 = 0x28;
+const DW_TAG_SUBPROGRAM: u16 = 0x2e;
+const DW_TAG_VARIABLE: u16 = 0x34;
+const DW_TAG_VOLATILE_TYPE: u16 = 0x35;
+const DW_TAG_RESTRICT_TYPE: u16 = 0x37;
+const DW_TAG_NAMESPACE: u16 = 0x39;
+const DW_TAG_UNSPECIFIED_TYPE: u16 = 0x3b;
+const DW_TAG_RVALUE_REFERENCE_TYPE: u16 = 0x42;
+
+// DW_AT_*
+const DW_AT_LOCATION: u16 = 0x02;
+const DW_AT_NAME: u16 = 0x03;
+const DW_AT_BYTE_SIZE: u16 = 0x0b;
+const DW_AT_BIT_OFFSET: u16 = 0x0c;
+const DW_AT_BIT_SIZE: u16 = 0x0d;
+const DW_AT_LOW_PC: u16 = 0x11;
+const DW_AT_HIGH_PC: u16 = 0x12;
+const DW_AT_LANGUAGE: u16 = 0x13;
+const DW_AT_COMP_DIR: u16 = 0x1b;
+const DW_AT_CONST_VALUE: u16 = 0x1c;
+const DW_AT_CONTAINING_TYPE: u16 = 0x1d;
+const DW_AT_INLINE: u16 = 0x20;
+const DW_AT_PRODUCER: u16 = 0x25;
+const DW_AT_PROTOTYPED: u16 = 0x27;
+const DW_AT_LOWER_BOUND: u16 = 0x22;
+const DW_AT_UPPER_BOUND: u16 = 0x2f;
+const DW_AT_ABSTRACT_ORIGIN: u16 = 0x31;
+const DW_AT_ACCESSIBILITY: u16 = 0x32;
+const DW_AT_COUNT: u16 = 0x37;
+const DW_AT_DATA_MEMBER_LOCATION: u16 = 0x38;
+const DW_AT_ENCODING: u16 = 0x3e;
+const DW_AT_EXTERNAL: u16 = 0x3f;
+const DW_AT_FRAME_BASE: u16 = 0x40;
+const DW_AT_SPECIFICATION: u16 = 0x47;
+const DW_AT_TYPE: u16 = 0x49;
+const DW_AT_VIRTUALITY: u16 = 0x4c;
+const DW_AT_DATA_BIT_OFFSET: u16 = 0x6b;
+const DW_AT_LINKAGE_NAME: u16 = 0x6e;
+const DW_AT_MIPS_LINKAGE_NAME: u16 = 0x2007;
+
+// DW_FORM_*
+const DW_FORM_ADDR: u16 = 0x01;
+const DW_FORM_BLOCK2: u16 = 0x03;
+const DW_FORM_BLOCK4: u16 = 0x04;
+const DW_FORM_DATA2: u16 = 0x05;
+const DW_FORM_DATA4: u16 = 0x06;
+const DW_FORM_DATA8: u16 = 0x07;
+const DW_FORM_STRING: u16 = 0x08;
+const DW_FORM_BLOCK: u16 = 0x09;
+const DW_FORM_BLOCK1: u16 = 0x0a;
+const DW_FORM_DATA1: u16 = 0x0b;
+const DW_FORM_FLAG: u16 = 0x0c;
+const DW_FORM_SDATA: u16 = 0x0d;
+const DW_FORM_STRP: u16 = 0x0e;
+const DW_FORM_UDATA: u16 = 0x0f;
+const DW_FORM_REF_ADDR: u16 = 0x10;
+const DW_FORM_REF1: u16 = 0x11;
+const DW_FORM_REF2: u16 = 0x12;
+const DW_FORM_REF4: u16 = 0x13;
+const DW_FORM_REF8: u16 = 0x14;
+const DW_FORM_REF_UDATA: u16 = 0x15;
+const DW_FORM_INDIRECT: u16 = 0x16;
+const DW_FORM_SEC_OFFSET: u16 = 0x17;
+const DW_FORM_EXPRLOC: u16 = 0x18;
+const DW_FORM_FLAG_PRESENT: u16 = 0x19;
+const DW_FORM_REF_SIG8: u16 = 0x20;
+
+// DW_ATE_*
+const DW_ATE_ADDRESS: u64 = 0x1;
+const DW_ATE_BOOLEAN: u64 = 0x2;
+const DW_ATE_FLOAT: u64 = 0x4;
+const DW_ATE_SIGNED: u64 = 0x5;
+const DW_ATE_SIGNED_CHAR: u64 = 0x6;
+const DW_ATE_UNSIGNED: u64 = 0x7;
+const DW_ATE_UNSIGNED_CHAR: u64 = 0x8;
+
+// DW_OP_*
+const DW_OP_ADDR: u8 = 0x03;
+const DW_OP_CONST1U: u8 = 0x08;
+const DW_OP_CONST2U: u8 = 0x0a;
+const DW_OP_CONST4U: u8 = 0x0c;
+const DW_OP_CONSTU: u8 = 0x10;
+const DW_OP_PLUS: u8 = 0x22;
+const DW_OP_PLUS_UCONST: u8 = 0x23;
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_REG31: u8 = 0x6f;
+const DW_OP_BREG0: u8 = 0x70;
+const DW_OP_BREG31: u8 = 0x8f;
+const DW_OP_REGX: u8 = 0x90;
+const DW_OP_FBREG: u8 = 0x91;
+const DW_OP_BREGX: u8 = 0x92;
+
+#[derive(Debug, Clone)]
+enum Value {
+    Address(u64),
+    Block(Vec<u8>),
+    Data(u64),
+    SData(i64),
+    String(String),
+    Reference(u32),
+    Flag(bool),
+    /// Offset into another section (line tables, location lists)
+    SecOffset,
+}
+
+#[derive(Debug, Clone)]
+struct AbbrevAttribute {
+    name: u16,
+    form: u16,
+}
+
+#[derive(Debug, Clone)]
+struct Abbrev {
+    tag: u16,
+    has_children: bool,
+    attributes: Vec<AbbrevAttribute>,
+}
+
+#[derive(Debug, Clone)]
+struct Die {
+    offset: u32,
+    tag: u16,
+    attributes: Vec<(u16, Value)>,
+    children: Vec<usize>,
+    parent: Option<usize>,
+    /// Offset of the null entry terminating the children
+    end: Option<u32>,
+}
+
+impl Die {
+    fn attribute(&self, name: u16) -> Option<&Value> {
+        self.attributes.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    fn string(&self, name: u16) -> Option<&str> {
+        match self.attribute(name) {
+            Some(Value::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn udata(&self, name: u16) -> Option<u64> {
+        match self.attribute(name) {
+            Some(&Value::Data(v)) => Some(v),
+            Some(&Value::SData(v)) => Some(v as u64),
+            Some(&Value::Flag(v)) => Some(v as u64),
+            _ => None,
+        }
+    }
+
+    fn sdata(&self, name: u16) -> Option<i64> {
+        match self.attribute(name) {
+            Some(&Value::Data(v)) => Some(v as i64),
+            Some(&Value::SData(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn reference(&self, name: u16) -> Option<u32> {
+        match self.attribute(name) {
+            Some(&Value::Reference(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    fn block(&self, name: u16) -> Option<&[u8]> {
+        match self.attribute(name) {
+            Some(Value::Block(b)) => Some(b),
+            _ => None,
+        }
+    }
+
+    fn flag(&self, name: u16) -> bool {
+        match self.attribute(name) {
+            Some(&Value::Flag(v)) => v,
+            Some(&Value::Data(v)) => v != 0,
+            _ => false,
+        }
+    }
+
+    fn address(&self, name: u16) -> Option<u32> {
+        match self.attribute(name) {
+            Some(&Value::Address(v)) => Some(v as u32),
+            _ => None,
+        }
+    }
+}
+
+fn read_uleb128(reader: &mut Cursor<&[u8]>) -> Result<u64> {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = u8::from_reader(reader, Endian::Big)?;
+        if shift < 64 {
+            result |= ((byte & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+}
+
+fn read_sleb128(reader: &mut Cursor<&[u8]>) -> Result<i64> {
+    let mut result = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = u8::from_reader(reader, Endian::Big)?;
+        if shift < 64 {
+            result |= ((byte & 0x7f) as i64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                result |= -1i64 << shift;
+            }
+            return Ok(result);
+        }
+    }
+}
+
+fn read_cstring(reader: &mut Cursor<&[u8]>) -> Result<String> {
+    let data = *reader.get_ref();
+    let start = reader.position() as usize;
+    let len = data[start..]
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| anyhow!("Unterminated string at {:#X}", start))?;
+    reader.set_position((start + len + 1) as u64);
+    Ok(String::from_utf8_lossy(&data[start..start + len]).into_owned())
+}
+
+fn read_block(reader: &mut Cursor<&[u8]>, len: usize) -> Result<Vec<u8>> {
+    let data = *reader.get_ref();
+    let start = reader.position() as usize;
+    ensure!(start + len <= data.len(), "Block out of bounds at {:#X}", start);
+    reader.set_position((start + len) as u64);
+    Ok(data[start..start + len].to_vec())
+}
+
+fn read_abbrevs(data: &[u8], offset: u64) -> Result<HashMap<u64, Abbrev>> {
+    let mut reader = Cursor::new(data);
+    reader.set_position(offset);
+    let mut abbrevs = HashMap::new();
+    loop {
+        let code = read_uleb128(&mut reader)?;
+        if code == 0 {
+            break;
+        }
+        let tag = read_uleb128(&mut reader)? as u16;
+        let has_children = u8::from_reader(&mut reader, Endian::Big)? != 0;
+        let mut attributes = vec![];
+        loop {
+            let name = read_uleb128(&mut reader)? as u16;
+            let form = read_uleb128(&mut reader)? as u16;
+            if name == 0 && form == 0 {
+                break;
+            }
+            attributes.push(AbbrevAttribute { name, form });
+        }
+        abbrevs.insert(code, Abbrev { tag, has_children, attributes });
+    }
+    Ok(abbrevs)
+}
+
+struct UnitHeader {
+    offset: u32,
+    version: u16,
+    address_size: u8,
+    e: Endian,
+}
+
+fn read_value(
+    reader: &mut Cursor<&[u8]>,
+    unit: &UnitHeader,
+    form: u16,
+    debug_str: &[u8],
+) -> Result<Value> {
+    let e = unit.e;
+    Ok(match form {
+        DW_FORM_ADDR => match unit.address_size {
+            4 => Value::Address(u32::from_reader(reader, e)? as u64),
+            8 => Value::Address(u64::from_reader(reader, e)?),
+            size => bail!("Unsupported address size {}", size),
+        },
+        DW_FORM_BLOCK1 => {
+            let len = u8::from_reader(reader, e)? as usize;
+            Value::Block(read_block(reader, len)?)
+        }
+        DW_FORM_BLOCK2 => {
+            let len = u16::from_reader(reader, e)? as usize;
+            Value::Block(read_block(reader, len)?)
+        }
+        DW_FORM_BLOCK4 => {
+            let len = u32::from_reader(reader, e)? as usize;
+            Value::Block(read_block(reader, len)?)
+        }
+        DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+            let len = read_uleb128(reader)? as usize;
+            Value::Block(read_block(reader, len)?)
+        }
+        DW_FORM_DATA1 => Value::Data(u8::from_reader(reader, e)? as u64),
+        DW_FORM_DATA2 => Value::Data(u16::from_reader(reader, e)? as u64),
+        DW_FORM_DATA4 => Value::Data(u32::from_reader(reader, e)? as u64),
+        DW_FORM_DATA8 => Value::Data(u64::from_reader(reader, e)?),
+        DW_FORM_SDATA => Value::SData(read_sleb128(reader)?),
+        DW_FORM_UDATA => Value::Data(read_uleb128(reader)?),
+        DW_FORM_STRING => Value::String(read_cstring(reader)?),
+        DW_FORM_STRP => {
+            let offset = u32::from_reader(reader, e)? as u64;
+            ensure!(offset < debug_str.len() as u64, "Invalid .debug_str offset {:#X}", offset);
+            let mut str_reader = Cursor::new(debug_str);
+            str_reader.set_position(offset);
+            Value::String(read_cstring(&mut str_reader)?)
+        }
+        DW_FORM_FLAG => Value::Flag(u8::from_reader(reader, e)? != 0),
+        DW_FORM_FLAG_PRESENT => Value::Flag(true),
+        DW_FORM_REF_ADDR => {
+            // DWARF 2 uses the address size, later versions the offset size
+            if unit.version == 2 && unit.address_size == 8 {
+                Value::Reference(u64::from_reader(reader, e)? as u32)
+            } else {
+                Value::Reference(u32::from_reader(reader, e)?)
+            }
+        }
+        DW_FORM_REF1 => Value::Reference(unit.offset + u8::from_reader(reader, e)? as u32),
+        DW_FORM_REF2 => Value::Reference(unit.offset + u16::from_reader(reader, e)? as u32),
+        DW_FORM_REF4 => Value::Reference(unit.offset + u32::from_reader(reader, e)?),
+        DW_FORM_REF8 => Value::Reference(unit.offset + u64::from_reader(reader, e)? as u32),
+        DW_FORM_REF_UDATA => Value::Reference(unit.offset + read_uleb128(reader)? as u32),
+        DW_FORM_SEC_OFFSET => {
+            u32::from_reader(reader, e)?;
+            Value::SecOffset
+        }
+        DW_FORM_REF_SIG8 => Value::Data(u64::from_reader(reader, e)?),
+        DW_FORM_INDIRECT => {
+            let form = read_uleb128(reader)? as u16;
+            read_value(reader, unit, form, debug_str)?
+        }
+        form => bail!("Unsupported DWARF form {:#X}", form),
+    })
+}
+
+/// Reads all DIEs from `.debug_info`, returning the DIEs and the indices of the unit DIEs.
+fn read_dies(
+    debug_info: &[u8],
+    debug_abbrev: &[u8],
+    debug_str: &[u8],
+    e: Endian,
+) -> Result<(Vec<Die>, Vec<usize>)> {
+    let mut dies = Vec::<Die>::new();
+    let mut roots = vec![];
+    let mut abbrev_cache = HashMap::<u64, HashMap<u64, Abbrev>>::new();
+    let mut reader = Cursor::new(debug_info);
+    while reader.position() < debug_info.len() as u64 {
+        let unit_offset = reader.position() as u32;
+        let unit_length = u32::from_reader(&mut reader, e)?;
+        ensure!(unit_length != 0xFFFFFFFF, "64-bit DWARF is not supported");
+        let unit_end = reader.position() + unit_length as u64;
+        let version = u16::from_reader(&mut reader, e)?;
+        ensure!((2..=4).contains(&version), "Unsupported DWARF version {}", version);
+        let abbrev_offset = u32::from_reader(&mut reader, e)? as u64;
+        let address_size = u8::from_reader(&mut reader, e)?;
+        let unit = UnitHeader { offset: unit_offset, version, address_size, e };
+        let abbrevs = match abbrev_cache.entry(abbrev_offset) {
+            hash_map::Entry::Occupied(e) => e.into_mut(),
+            hash_map::Entry::Vacant(e) => e.insert(read_abbrevs(debug_abbrev, abbrev_offset)?),
+        };
+
+        let mut stack = Vec::<usize>::new();
+        while reader.position() < unit_end {
+            let offset = reader.position() as u32;
+            let code = read_uleb128(&mut reader)?;
+            if code == 0 {
+                if let Some(parent) = stack.pop() {
+                    dies[parent].end = Some(offset);
+                }
+                continue;
+            }
+            let abbrev = abbrevs
+                .get(&code)
+                .ok_or_else(|| anyhow!("Unknown abbreviation {} at {:#X}", code, offset))?;
+            let mut attributes = Vec::with_capacity(abbrev.attributes.len());
+            for attr in &abbrev.attributes {
+                let value = read_value(&mut reader, &unit, attr.form, debug_str)
+                    .with_context(|| format!("Failed to read attribute of DIE at {:#X}", offset))?;
+                attributes.push((attr.name, value));
+            }
+            let index = dies.len();
+            let parent = stack.last().cloned();
+            dies.push(Die {
+                offset,
+                tag: abbrev.tag,
+                attributes,
+                children: vec![],
+                parent,
+                end: None,
+            });
+            match parent {
+                Some(parent) => dies[parent].children.push(index),
+                None => roots.push(index),
+            }
+            if abbrev.has_children {
+                stack.push(index);
+            }
+        }
+        reader.set_position(unit_end);
+    }
+    Ok((dies, roots))
+}
+
+/// Reads DWARF 2+ debug information and lowers it into DWARF 1.1 tags.
+///
+/// Tag keys are the DIE offsets shifted left by one, leaving room for synthesized tags.
+pub fn read_debug_info(
+    debug_info: &[u8],
+    debug_abbrev: &[u8],
+    debug_str: &[u8],
+    e: Endian,
+) -> Result<TagMap> {
+    let (dies, roots) = read_dies(debug_info, debug_abbrev, debug_str, e)?;
+    let index = dies.iter().enumerate().map(|(i, die)| (die.offset, i)).collect();
+    let mut lowering = Lowering { dies: &dies, index, e, tags: TagMap::new(), lists: vec![] };
+    for root in roots {
+        lowering.lower(root, None, 1)?;
+    }
+    lowering.finish()
+}
+
+#[inline]
+fn key(offset: u32) -> u32 { offset << 1 }
+
+struct Lowering<'a> {
+    dies: &'a [Die],
+    index: HashMap<u32, usize>,
+    e: Endian,
+    tags: TagMap,
+    /// Tags with children, along with the key of their terminating padding tag
+    lists: Vec<(u32, u32)>,
+}
+
+/// Child tag kinds accepted by the DWARF 1.1 processors for each parent kind.
+fn is_allowed_child(parent: Option<TagKind>, child: TagKind) -> bool {
+    let is_type = matches!(
+        child,
+        TagKind::StructureType
+            | TagKind::ArrayType
+            | TagKind::EnumerationType
+            | TagKind::UnionType
+            | TagKind::ClassType
+            | TagKind::SubroutineType
+            | TagKind::PtrToMemberType
+    );
+    match parent {
+        None => child == TagKind::CompileUnit,
+        Some(TagKind::CompileUnit) => {
+            is_type
+                || matches!(
+                    child,
+                    TagKind::Typedef
+                        | TagKind::GlobalVariable
+                        | TagKind::LocalVariable
+                        | TagKind::GlobalSubroutine
+                        | TagKind::Subroutine
+                )
+        }
+        Some(TagKind::StructureType | TagKind::ClassType) => {
+            is_type
+                || matches!(
+                    child,
+                    TagKind::Inheritance
+                        | TagKind::Member
+                        | TagKind::Typedef
+                        | TagKind::GlobalSubroutine
+                        | TagKind::Subroutine
+                )
+        }
+        Some(TagKind::UnionType) => is_type || child == TagKind::Member,
+        Some(TagKind::GlobalSubroutine | TagKind::Subroutine | TagKind::SubroutineType) => {
+            is_type
+                || matches!(
+                    child,
+                    TagKind::FormalParameter
+                        | TagKind::UnspecifiedParameters
+                        | TagKind::LocalVariable
+                        | TagKind::GlobalVariable
+                        | TagKind::Label
+                        | TagKind::LexicalBlock
+                        | TagKind::InlinedSubroutine
+                )
+        }
+        Some(TagKind::LexicalBlock) => {
+            is_type
+                || matches!(
+                    child,
+                    TagKind::LocalVariable | TagKind::GlobalVariable | TagKind::LexicalBlock
+                )
+        }
+        _ => false,
+    }
+}
+
+fn attr(kind: AttributeKind, value: AttributeValue) -> Attribute { Attribute { kind, value } }
+
+fn flag_attr(kind: AttributeKind) -> Attribute { attr(kind, AttributeValue::String(String::new())) }
+
+fn offset_block(offset: u32) -> Vec<u8> {
+    let mut block = vec![LocationOp::Const as u8];
+    block.extend(offset.to_be_bytes());
+    block.push(LocationOp::Add as u8);
+    block
+}
+
+/// Encodes a type attribute as it appears in DWARF 1.1 array subscript data.
+fn encode_type_attribute(attr: &Attribute) -> Result<Vec<u8>> {
+    let mut out = u16::from(attr.kind).to_be_bytes().to_vec();
+    match &attr.value {
+        AttributeValue::Data2(v) => out.extend(v.to_be_bytes()),
+        AttributeValue::Reference(v) => out.extend(v.to_be_bytes()),
+        AttributeValue::Block(b) => {
+            out.extend((b.len() as u16).to_be_bytes());
+            out.extend(b);
+        }
+        _ => bail!("Unexpected type attribute {:?}", attr),
+    }
+    Ok(out)
+}
+
+fn base_fund_type(die: &Die) -> Result<FundType> {
+    let name = die.string(DW_AT_NAME).unwrap_or_default();
+    let size = die.udata(DW_AT_BYTE_SIZE).unwrap_or_default();
+    Ok(match name {
+        "char" => FundType::Char,
+        "signed char" => FundType::SignedChar,
+        "unsigned char" => FundType::UnsignedChar,
+        "short" | "short int" => FundType::Short,
+        "signed short" | "short signed int" => FundType::SignedShort,
+        "unsigned short" | "short unsigned int" => FundType::UnsignedShort,
+        "int" => FundType::Integer,
+        "signed int" => FundType::SignedInteger,
+        "unsigned int" => FundType::UnsignedInteger,
+        "long" | "long int" => FundType::Long,
+        "signed long" | "long signed int" => FundType::SignedLong,
+        "unsigned long" | "long unsigned int" => FundType::UnsignedLong,
+        "long long" | "long long int" => FundType::LongLong,
+        "signed long long" | "long long signed int" => FundType::SignedLongLong,
+        "unsigned long long" | "long long unsigned int" => FundType::UnsignedLongLong,
+        "float" => FundType::Float,
+        "double" => FundType::DblPrecFloat,
+        "long double" if size == 8 => FundType::DblPrecFloat,
+        "long double" => FundType::ExtPrecFloat,
+        "bool" | "_Bool" => FundType::Boolean,
+        "wchar_t" => FundType::WideChar,
+        _ => match (die.udata(DW_AT_ENCODING).unwrap_or_default(), size) {
+            (DW_ATE_BOOLEAN, _) => FundType::Boolean,
+            (DW_ATE_ADDRESS, _) => FundType::Pointer,
+            (DW_ATE_FLOAT, 4) => FundType::Float,
+            (DW_ATE_FLOAT, 8) => FundType::DblPrecFloat,
+            (DW_ATE_SIGNED_CHAR, 1) | (DW_ATE_SIGNED, 1) => FundType::SignedChar,
+            (DW_ATE_UNSIGNED_CHAR, 1) | (DW_ATE_UNSIGNED, 1) => FundType::UnsignedChar,
+            (DW_ATE_SIGNED, 2) => FundType::Short,
+            (DW_ATE_UNSIGNED, 2) => FundType::UnsignedShort,
+            (DW_ATE_SIGNED, 4) => FundType::Integer,
+            (DW_ATE_UNSIGNED, 4) => FundType::UnsignedInteger,
+            (DW_ATE_SIGNED, 8) => FundType::LongLong,
+            (DW_ATE_UNSIGNED, 8) => FundType::UnsignedLongLong,
+            (encoding, size) => bail!(
+                "Unhandled base type '{}' at {:#X} (encoding {}, size {})",
+                name,
+                die.offset,
+                encoding,
+                size
+            ),
+        },
+    })
+}
+
+/// Evaluates a member location expression to a constant offset.
+fn member_offset(value: &Value, e: Endian) -> Option<u32> {
+    match value {
+        &Value::Data(v) => Some(v as u32),
+        Value::Block(block) => {
+            let mut reader = Cursor::new(block.as_slice());
+            let op = u8::from_reader(&mut reader, e).ok()?;
+            let value = match op {
+                DW_OP_PLUS_UCONST => return read_uleb128(&mut reader).ok().map(|v| v as u32),
+                DW_OP_CONSTU => read_uleb128(&mut reader).ok()?,
+                DW_OP_CONST1U => u8::from_reader(&mut reader, e).ok()? as u64,
+                DW_OP_CONST2U => u16::from_reader(&mut reader, e).ok()? as u64,
+                DW_OP_CONST4U => u32::from_reader(&mut reader, e).ok()? as u64,
+                _ => return None,
+            };
+            (u8::from_reader(&mut reader, e).ok()? == DW_OP_PLUS).then_some(value as u32)
+        }
+        _ => None,
+    }
+}
+
+/// Converts a DWARF 2 location expression into an equivalent DWARF 1.1 expression.
+fn lower_location(block: &[u8], frame_base: u32, e: Endian) -> Option<Vec<u8>> {
+    let register_offset = |reg: u32, offset: i64| {
+        let mut out = vec![LocationOp::BaseRegister as u8];
+        out.extend(reg.to_be_bytes());
+        if offset != 0 {
+            out.push(LocationOp::Const as u8);
+            out.extend((offset as u32).to_be_bytes());
+            out.push(LocationOp::Add as u8);
+        }
+        out
+    };
+    let mut reader = Cursor::new(block);
+    let op = u8::from_reader(&mut reader, e).ok()?;
+    let out = match op {
+        DW_OP_ADDR if block.len() == 5 => {
+            let mut out = vec![LocationOp::Address as u8];
+            out.extend(u32::from_reader(&mut reader, e).ok()?.to_be_bytes());
+            out
+        }
+        DW_OP_REG0..=DW_OP_REG31 => {
+            let mut out = vec![LocationOp::Register as u8];
+            out.extend(((op - DW_OP_REG0) as u32).to_be_bytes());
+            out
+        }
+        DW_OP_REGX => {
+            let mut out = vec![LocationOp::Register as u8];
+            out.extend((read_uleb128(&mut reader).ok()? as u32).to_be_bytes());
+            out
+        }
+        DW_OP_BREG0..=DW_OP_BREG31 => {
+            register_offset((op - DW_OP_BREG0) as u32, read_sleb128(&mut reader).ok()?)
+        }
+        DW_OP_BREGX => {
+            let reg = read_uleb128(&mut reader).ok()? as u32;
+            register_offset(reg, read_sleb128(&mut reader).ok()?)
+        }
+        DW_OP_FBREG => register_offset(frame_base, read_sleb128(&mut reader).ok()?),
+        _ => return None,
+    };
+    // Only single operation expressions are supported
+    (reader.position() == block.len() as u64).then_some(out)
+}
+
+impl Lowering<'_> {
+    fn die(&self, offset: u32) -> Result<&Die> {
+        self.index
+            .get(&offset)
+            .map(|&i| &self.dies[i])
+            .ok_or_else(|| anyhow!("Failed to locate DIE at {:#X}", offset))
+    }
+
+    /// Resolves a DIE attribute, following DW_AT_specification and DW_AT_abstract_origin.
+    fn inherited<'a>(&'a self, die: &'a Die, name: u16) -> Option<&'a Die> {
+        let mut current = die;
+        for _ in 0..8 {
+            if current.attribute(name).is_some() {
+                return Some(current);
+            }
+            let origin = current
+                .reference(DW_AT_SPECIFICATION)
+                .or_else(|| current.reference(DW_AT_ABSTRACT_ORIGIN))?;
+            current = self.die(origin).ok()?;
+        }
+        None
+    }
+
+    fn inherited_string<'a>(&'a self, die: &'a Die, name: u16) -> Option<&'a str> {
+        self.inherited(die, name).and_then(|d| d.string(name))
+    }
+
+    /// Lowers a DW_AT_type chain into a DWARF 1.1 type attribute, collecting modifiers.
+    fn type_attribute(&self, type_ref: Option<u32>) -> Result<Attribute> {
+        let mut modifiers = Vec::<u8>::new();
+        let mut current = type_ref;
+        let mut fund_type = None;
+        let mut ud_ref = None;
+        for _ in 0..64 {
+            let Some(offset) = current else {
+                fund_type = Some(FundType::Void);
+                break;
+            };
+            let die = self.die(offset)?;
+            match die.tag {
+                DW_TAG_POINTER_TYPE => modifiers.push(Modifier::PointerTo as u8),
+                DW_TAG_REFERENCE_TYPE | DW_TAG_RVALUE_REFERENCE_TYPE => {
+                    modifiers.push(Modifier::ReferenceTo as u8)
+                }
+                DW_TAG_CONST_TYPE => modifiers.push(Modifier::Const as u8),
+                DW_TAG_VOLATILE_TYPE => modifiers.push(Modifier::Volatile as u8),
+                DW_TAG_RESTRICT_TYPE | DW_TAG_TYPEDEF => {}
+                DW_TAG_BASE_TYPE => {
+                    fund_type = Some(base_fund_type(die)?);
+                    break;
+                }
+                DW_TAG_UNSPECIFIED_TYPE => {
+                    fund_type = Some(FundType::Void);
+                    break;
+                }
+                DW_TAG_STRUCTURE_TYPE
+                | DW_TAG_CLASS_TYPE
+                | DW_TAG_UNION_TYPE
+                | DW_TAG_ENUMERATION_TYPE
+                | DW_TAG_ARRAY_TYPE
+                | DW_TAG_SUBROUTINE_TYPE
+                | DW_TAG_PTR_TO_MEMBER_TYPE => {
+                    ud_ref = Some(key(offset));
+                    break;
+                }
+                tag => bail!("Unhandled type DIE {:#X} at {:#X}", tag, offset),
+            }
+            current = die.reference(DW_AT_TYPE);
+        }
+        Ok(match (fund_type, ud_ref) {
+            (Some(ft), _) if modifiers.is_empty() => {
+                attr(AttributeKind::FundType, AttributeValue::Data2(ft.into()))
+            }
+            (Some(ft), _) => {
+                modifiers.extend(u16::from(ft).to_be_bytes());
+                attr(AttributeKind::ModFundType, AttributeValue::Block(modifiers))
+            }
+            (None, Some(r)) if modifiers.is_empty() => {
+                attr(AttributeKind::UserDefType, AttributeValue::Reference(r))
+            }
+            (None, Some(r)) => {
+                modifiers.extend(r.to_be_bytes());
+                attr(AttributeKind::ModUDType, AttributeValue::Block(modifiers))
+            }
+            (None, None) => bail!("Type chain too deep at {:?}", type_ref),
+        })
+    }
+
+    fn die_type(&self, die: &Die) -> Result<Attribute> {
+        let type_ref = self.inherited(die, DW_AT_TYPE).and_then(|d| d.reference(DW_AT_TYPE));
+        self.type_attribute(type_ref)
+    }
+
+    fn push_name(&self, die: &Die, attributes: &mut Vec<Attribute>) {
+        if let Some(name) = self.inherited_string(die, DW_AT_NAME) {
+            attributes.push(attr(AttributeKind::Name, AttributeValue::String(name.to_string())));
+        }
+    }
+
+    fn push_mangled_name(&self, die: &Die, attributes: &mut Vec<Attribute>) {
+        let mangled = self
+            .inherited_string(die, DW_AT_LINKAGE_NAME)
+            .or_else(|| self.inherited_string(die, DW_AT_MIPS_LINKAGE_NAME));
+        if let Some(mangled) = mangled {
+            attributes
+                .push(attr(AttributeKind::MwMangled, AttributeValue::String(mangled.to_string())));
+        }
+    }
+
+    fn push_pc_range(&self, die: &Die, attributes: &mut Vec<Attribute>) -> bool {
+        let Some(low_pc) = die.address(DW_AT_LOW_PC) else {
+            return false;
+        };
+        let high_pc = match die.attribute(DW_AT_HIGH_PC) {
+            Some(&Value::Address(addr)) => addr as u32,
+            // DWARF 4: offset from low_pc
+            Some(&Value::Data(size)) => low_pc + size as u32,
+            _ => return false,
+        };
+        attributes.push(attr(AttributeKind::LowPc, AttributeValue::Address(low_pc)));
+        attributes.push(attr(AttributeKind::HighPc, AttributeValue::Address(high_pc)));
+        true
+    }
+
+    fn push_visibility(&self, die: &Die, parent: Option<TagKind>, attributes: &mut Vec<Attribute>) {
+        let accessibility = die.udata(DW_AT_ACCESSIBILITY).unwrap_or(match parent {
+            Some(TagKind::ClassType) => 3,
+            _ => 1,
+        });
+        attributes.push(flag_attr(match accessibility {
+            2 => AttributeKind::Protected,
+            3 => AttributeKind::Private,
+            _ => AttributeKind::Public,
+        }));
+    }
+
+    /// Lowers a DIE and its children. Returns whether any tags were emitted.
+    fn lower(&mut self, index: usize, parent: Option<TagKind>, frame_base: u32) -> Result<bool> {
+        let dies = self.dies;
+        let die = &dies[index];
+        let mut attributes = vec![];
+        let mut frame_base = frame_base;
+        let kind = match die.tag {
+            DW_TAG_COMPILE_UNIT => {
+                self.push_name(die, &mut attributes);
+                if let Some(producer) = die.string(DW_AT_PRODUCER) {
+                    attributes.push(attr(
+                        AttributeKind::Producer,
+                        AttributeValue::String(producer.to_string()),
+                    ));
+                }
+                if let Some(comp_dir) = die.string(DW_AT_COMP_DIR) {
+                    attributes.push(attr(
+                        AttributeKind::CompDir,
+                        AttributeValue::String(comp_dir.to_string()),
+                    ));
+                }
+                if let Some(language) = die.udata(DW_AT_LANGUAGE) {
+                    attributes.push(attr(
+                        AttributeKind::Language,
+                        AttributeValue::Data4(language as u32),
+                    ));
+                }
+                self.push_pc_range(die, &mut attributes);
+                TagKind::CompileUnit
+            }
+            DW_TAG_NAMESPACE => {
+                // No namespaces in DWARF 1.1, flatten into the parent
+                let mut emitted = false;
+                for &child in &die.children {
+                    emitted |= self.lower(child, parent, frame_base)?;
+                }
+                return Ok(emitted);
+            }
+            DW_TAG_STRUCTURE_TYPE | DW_TAG_CLASS_TYPE | DW_TAG_UNION_TYPE => {
+                self.push_name(die, &mut attributes);
+                if let Some(size) = die.udata(DW_AT_BYTE_SIZE) {
+                    attributes
+                        .push(attr(AttributeKind::ByteSize, AttributeValue::Data4(size as u32)));
+                } else if die.tag == DW_TAG_UNION_TYPE {
+                    attributes.push(attr(AttributeKind::ByteSize, AttributeValue::Data4(0)));
+                }
+                match die.tag {
+                    DW_TAG_STRUCTURE_TYPE => TagKind::StructureType,
+                    DW_TAG_CLASS_TYPE => TagKind::ClassType,
+                    _ => TagKind::UnionType,
+                }
+            }
+            DW_TAG_MEMBER => {
+                // Anonymous members still need a name
+                attributes.push(attr(
+                    AttributeKind::Name,
+                    AttributeValue::String(die.string(DW_AT_NAME).unwrap_or_default().to_string()),
+                ));
+                attributes.push(self.die_type(die)?);
+                let byte_size = die.udata(DW_AT_BYTE_SIZE).map(|v| v as u32);
+                let bit_size = die.udata(DW_AT_BIT_SIZE).map(|v| v as u32);
+                let mut offset = die
+                    .attribute(DW_AT_DATA_MEMBER_LOCATION)
+                    .and_then(|v| member_offset(v, self.e));
+                let mut bit_offset = die.udata(DW_AT_BIT_OFFSET).map(|v| v as u16);
+                if let (Some(_), Some(data_bit_offset)) =
+                    (bit_size, die.udata(DW_AT_DATA_BIT_OFFSET))
+                {
+                    // DWARF 4: bit offset from the start of the structure (big endian)
+                    let unit_bits = byte_size.unwrap_or(4) * 8;
+                    let unit_offset = data_bit_offset as u32 / unit_bits * unit_bits;
+                    offset = Some(unit_offset / 8);
+                    bit_offset = Some((data_bit_offset as u32 - unit_offset) as u16);
+                }
+                attributes.push(attr(
+                    AttributeKind::Location,
+                    AttributeValue::Block(offset_block(offset.unwrap_or(0))),
+                ));
+                if let (Some(bit_size), Some(bit_offset)) = (bit_size, bit_offset) {
+                    attributes.push(attr(
+                        AttributeKind::ByteSize,
+                        AttributeValue::Data4(byte_size.unwrap_or(4)),
+                    ));
+                    attributes.push(attr(AttributeKind::BitSize, AttributeValue::Data4(bit_size)));
+                    attributes
+                        .push(attr(AttributeKind::BitOffset, AttributeValue::Data2(bit_offset)));
+                }
+                self.push_visibility(die, parent, &mut attributes);
+                TagKind::Member
+            }
+            DW_TAG_INHERITANCE => {
+                attributes.push(self.die_type(die)?);
+                let offset = die
+                    .attribute(DW_AT_DATA_MEMBER_LOCATION)
+                    .and_then(|v| member_offset(v, self.e));
+                attributes.push(attr(
+                    AttributeKind::Location,
+                    AttributeValue::Block(offset_block(offset.unwrap_or(0))),
+                ));
+                self.push_visibility(die, parent, &mut attributes);
+                if die.udata(DW_AT_VIRTUALITY).unwrap_or(0) != 0 {
+                    attributes.push(flag_attr(AttributeKind::Virtual));
+                }
+                TagKind::Inheritance
+            }
+            DW_TAG_ENUMERATION_TYPE => {
+                self.push_name(die, &mut attributes);
+                let byte_size = die.udata(DW_AT_BYTE_SIZE).unwrap_or(4) as u32;
+                attributes.push(attr(AttributeKind::ByteSize, AttributeValue::Data4(byte_size)));
+                let mut elements = vec![];
+                for &child in &die.children {
+                    let child = &dies[child];
+                    if child.tag != DW_TAG_ENUMERATOR {
+                        continue;
+                    }
+                    let value = child.sdata(DW_AT_CONST_VALUE).unwrap_or_default() as i32;
+                    elements.extend(value.to_be_bytes());
+                    elements.extend(child.string(DW_AT_NAME).unwrap_or_default().as_bytes());
+                    elements.push(0);
+                }
+                attributes.push(attr(AttributeKind::ElementList, AttributeValue::Block(elements)));
+                self.emit(die, TagKind::EnumerationType, attributes);
+                return Ok(true);
+            }
+            DW_TAG_ARRAY_TYPE => {
+                let mut subscr_data = vec![];
+                for &child in &die.children {
+                    let child = &dies[child];
+                    if child.tag != DW_TAG_SUBRANGE_TYPE {
+                        continue;
+                    }
+                    let lower_bound = child.udata(DW_AT_LOWER_BOUND).unwrap_or(0);
+                    let high_bound = if let Some(upper_bound) = child.udata(DW_AT_UPPER_BOUND) {
+                        upper_bound.wrapping_sub(lower_bound) as u32
+                    } else if let Some(count) = child.udata(DW_AT_COUNT) {
+                        (count as u32).wrapping_sub(1)
+                    } else {
+                        // Unbounded
+                        u32::MAX
+                    };
+                    subscr_data.push(SubscriptFormat::FundTypeConstConst as u8);
+                    subscr_data.extend(u16::from(FundType::Long).to_be_bytes());
+                    subscr_data.extend(0u32.to_be_bytes());
+                    subscr_data.extend(high_bound.to_be_bytes());
+                }
+                subscr_data.push(SubscriptFormat::ElementType as u8);
+                subscr_data.extend(encode_type_attribute(&self.die_type(die)?)?);
+                attributes
+                    .push(attr(AttributeKind::SubscrData, AttributeValue::Block(subscr_data)));
+                self.emit(die, TagKind::ArrayType, attributes);
+                return Ok(true);
+            }
+            DW_TAG_SUBPROGRAM => {
+                self.push_name(die, &mut attributes);
+                self.push_mangled_name(die, &mut attributes);
+                attributes.push(self.die_type(die)?);
+                if self.inherited(die, DW_AT_PROTOTYPED).is_some_and(|d| d.flag(DW_AT_PROTOTYPED)) {
+                    attributes.push(flag_attr(AttributeKind::Prototyped));
+                }
+                if self.inherited(die, DW_AT_INLINE).is_some_and(|d| d.flag(DW_AT_INLINE)) {
+                    attributes.push(flag_attr(AttributeKind::Inline));
+                }
+                // Out-of-line method definitions refer to the declaration in the class
+                if let Some(spec) = die.reference(DW_AT_SPECIFICATION) {
+                    if let Some(class) = self.die(spec)?.parent.map(|i| &dies[i]) {
+                        if matches!(class.tag, DW_TAG_STRUCTURE_TYPE | DW_TAG_CLASS_TYPE) {
+                            attributes.push(attr(
+                                AttributeKind::Member,
+                                AttributeValue::Reference(key(class.offset)),
+                            ));
+                        }
+                    }
+                }
+                self.push_pc_range(die, &mut attributes);
+                if let Some(block) = die.block(DW_AT_FRAME_BASE) {
+                    frame_base = match block {
+                        [op @ DW_OP_REG0..=DW_OP_REG31] => (op - DW_OP_REG0) as u32,
+                        [op @ DW_OP_BREG0..=DW_OP_BREG31, 0] => (op - DW_OP_BREG0) as u32,
+                        _ => frame_base,
+                    };
+                }
+                let external =
+                    self.inherited(die, DW_AT_EXTERNAL).is_some_and(|d| d.flag(DW_AT_EXTERNAL));
+                if external {
+                    TagKind::GlobalSubroutine
+                } else {
+                    TagKind::Subroutine
+                }
+            }
+            DW_TAG_SUBROUTINE_TYPE => {
+                attributes.push(self.die_type(die)?);
+                if die.flag(DW_AT_PROTOTYPED) {
+                    attributes.push(flag_attr(AttributeKind::Prototyped));
+                }
+                TagKind::SubroutineType
+            }
+            DW_TAG_FORMAL_PARAMETER => {
+                self.push_name(die, &mut attributes);
+                attributes.push(self.die_type(die)?);
+                if let Some(location) =
+                    die.block(DW_AT_LOCATION).and_then(|b| lower_location(b, frame_base, self.e))
+                {
+                    attributes.push(attr(AttributeKind::Location, AttributeValue::Block(location)));
+                }
+                TagKind::FormalParameter
+            }
+            DW_TAG_UNSPECIFIED_PARAMETERS => {
+                // Must be the last child in DWARF 1.1, so it's emitted right before the terminator
+                if let Some(end) = die.parent.and_then(|p| dies[p].end) {
+                    self.tags.insert(key(end) - 1, Tag {
+                        key: key(end) - 1,
+                        kind: TagKind::UnspecifiedParameters,
+                        attributes: vec![],
+                    });
+                    return Ok(true);
+                }
+                return Ok(false);
+            }
+            DW_TAG_VARIABLE => {
+                self.push_name(die, &mut attributes);
+                self.push_mangled_name(die, &mut attributes);
+                attributes.push(self.die_type(die)?);
+                let location =
+                    die.block(DW_AT_LOCATION).and_then(|b| lower_location(b, frame_base, self.e));
+                let in_subroutine = matches!(
+                    parent,
+                    Some(TagKind::GlobalSubroutine | TagKind::Subroutine | TagKind::LexicalBlock)
+                );
+                if in_subroutine {
+                    if let Some(location) = location {
+                        attributes
+                            .push(attr(AttributeKind::Location, AttributeValue::Block(location)));
+                    }
+                    TagKind::LocalVariable
+                } else {
+                    if let Some(location) =
+                        location.filter(|l| l.first() == Some(&(LocationOp::Address as u8)))
+                    {
+                        attributes
+                            .push(attr(AttributeKind::Location, AttributeValue::Block(location)));
+                    }
+                    let external =
+                        self.inherited(die, DW_AT_EXTERNAL).is_some_and(|d| d.flag(DW_AT_EXTERNAL));
+                    if external {
+                        TagKind::GlobalVariable
+                    } else {
+                        TagKind::LocalVariable
+                    }
+                }
+            }
+            DW_TAG_LEXICAL_BLOCK => {
+                self.push_name(die, &mut attributes);
+                if !self.push_pc_range(die, &mut attributes) {
+                    // Non-contiguous block, flatten into the parent
+                    let mut emitted = false;
+                    for &child in &die.children {
+                        emitted |= self.lower(child, parent, frame_base)?;
+                    }
+                    return Ok(emitted);
+                }
+                TagKind::LexicalBlock
+            }
+            DW_TAG_LABEL => {
+                let Some(low_pc) = die.address(DW_AT_LOW_PC) else {
+                    return Ok(false);
+                };
+                self.push_name(die, &mut attributes);
+                attributes.push(attr(AttributeKind::LowPc, AttributeValue::Address(low_pc)));
+                TagKind::Label
+            }
+            DW_TAG_INLINED_SUBROUTINE => {
+                let Some(origin) = die.reference(DW_AT_ABSTRACT_ORIGIN) else {
+                    return Ok(false);
+                };
+                attributes.push(attr(
+                    AttributeKind::Specification,
+                    AttributeValue::Reference(key(origin)),
+                ));
+                if !self.push_pc_range(die, &mut attributes) {
+                    return Ok(false);
+                }
+                self.emit(die, TagKind::InlinedSubroutine, attributes);
+                return Ok(true);
+            }
+            DW_TAG_TYPEDEF => {
+                self.push_name(die, &mut attributes);
+                attributes.push(self.die_type(die)?);
+                TagKind::Typedef
+            }
+            DW_TAG_PTR_TO_MEMBER_TYPE => {
+                attributes.push(self.die_type(die)?);
+                let containing_type = die
+                    .reference(DW_AT_CONTAINING_TYPE)
+                    .ok_or_else(|| anyhow!("PtrToMemberType without containing type"))?;
+                attributes.push(attr(
+                    AttributeKind::ContainingType,
+                    AttributeValue::Reference(key(containing_type)),
+                ));
+                TagKind::PtrToMemberType
+            }
+            // Lowered into type attributes, or unsupported
+            _ => return Ok(false),
+        };
+        if !is_allowed_child(parent, kind) {
+            return Ok(false);
+        }
+
+        self.emit(die, kind, attributes);
+        let mut has_children = false;
+        for &child in &die.children {
+            has_children |= self.lower(child, Some(kind), frame_base)?;
+        }
+        if has_children {
+            let end =
+                key(die.end.ok_or_else(|| anyhow!("DIE at {:#X} without end", die.offset))?);
+            self.tags.insert(end, Tag { key: end, kind: TagKind::Padding, attributes: vec![] });
+            self.lists.push((key(die.offset), end));
+        }
+        Ok(true)
+    }
+
+    fn emit(&mut self, die: &Die, kind: TagKind, attributes: Vec<Attribute>) {
+        let key = key(die.offset);
+        self.tags.insert(key, Tag { key, kind, attributes });
+    }
+
+    /// Links tags with children to their next sibling. Like DWARF 1.1, the last tag
+    /// links past the end, since [`Tag::children`] would otherwise find no children.
+    fn finish(mut self) -> Result<TagMap> {
+        for (key, end) in std::mem::take(&mut self.lists) {
+            let sibling = self.tags.range(end + 1..).next().map_or(end + 1, |(&k, _)| k);
+            let tag = self.tags.get_mut(&key).unwrap();
+            tag.attributes
+                .insert(0, attr(AttributeKind::Sibling, AttributeValue::Reference(sibling)));
+        }
+        Ok(self.tags)
+    }
+}
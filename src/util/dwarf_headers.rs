@@ -0,0 +1,755 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+    hash::{Hash, Hasher},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::util::{
+    debug_info::{source_path, DebugObject},
+    dwarf::{
+        process_root_tag, should_skip_tag, tag_type_string, type_string, ud_type, AttributeKind,
+        Modifier, StructureKind, StructureMember, SubroutineType, TagKind, TagMap, TagType, Type,
+        TypeKind, TypedefMap, UserDefinedType, VariableTag,
+    },
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum DeclKind {
+    Struct,
+    Class,
+    Union,
+    Enum,
+    Typedef,
+    Function,
+    Variable,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Dep {
+    decl: usize,
+    /// Whether the complete definition is required, rather than a forward declaration
+    complete: bool,
+}
+
+struct Decl {
+    kind: DeclKind,
+    name: String,
+    text: String,
+    deps: Vec<Dep>,
+    units: BTreeSet<usize>,
+    /// Other declarations with the same name, but a different layout
+    conflicts: Vec<usize>,
+}
+
+struct Unit {
+    path: String,
+    /// Headers referenced by the unit's line table
+    files: BTreeSet<String>,
+}
+
+/// Header file path for a source file.
+fn header_path(source: &str) -> String {
+    match source.rsplit_once('.') {
+        Some((stem, ext)) if !ext.contains('/') => format!("{}.h", stem),
+        _ => format!("{}.h", source),
+    }
+}
+
+fn include_guard(path: &str) -> String {
+    let mut guard = path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect::<String>();
+    if guard.starts_with(|c: char| c.is_ascii_digit()) {
+        guard.insert(0, '_');
+    }
+    guard
+}
+
+fn decl_info(tag_type: &TagType) -> Option<(DeclKind, String)> {
+    match tag_type {
+        TagType::Typedef(t) => Some((DeclKind::Typedef, t.name.clone())),
+        TagType::Variable(v) => match (&v.name, v.address) {
+            (Some(name), Some(_)) if !v.local => Some((DeclKind::Variable, name.clone())),
+            _ => None,
+        },
+        TagType::UserDefined(ud) => match ud {
+            UserDefinedType::Structure(s) => Some((
+                match s.kind {
+                    StructureKind::Struct => DeclKind::Struct,
+                    StructureKind::Class => DeclKind::Class,
+                },
+                s.name.clone()?,
+            )),
+            UserDefinedType::Union(u) => Some((DeclKind::Union, u.name.clone()?)),
+            UserDefinedType::Enumeration(e) => Some((DeclKind::Enum, e.name.clone()?)),
+            UserDefinedType::Subroutine(s)
+                if !s.local && s.member_of.is_none() && s.start_address.is_some() =>
+            {
+                Some((DeclKind::Function, s.name.clone()?))
+            }
+            _ => None,
+        },
+    }
+}
+
+fn prototype_string(tags: &TagMap, typedefs: &TypedefMap, t: &SubroutineType) -> Result<String> {
+    let name = t.name.as_ref().ok_or_else(|| anyhow!("Subroutine without name"))?;
+    let rt = type_string(tags, typedefs, &t.return_type, true)?;
+    let mut parameters = String::new();
+    for (idx, parameter) in t.parameters.iter().enumerate() {
+        if idx > 0 {
+            parameters.push_str(", ");
+        }
+        let ts = type_string(tags, typedefs, &parameter.kind, true)?;
+        match &parameter.name {
+            Some(name) => write!(parameters, "{} {}{}", ts.prefix, name, ts.suffix)?,
+            None => write!(parameters, "{}{}", ts.prefix, ts.suffix)?,
+        }
+    }
+    if t.var_args {
+        parameters.push_str(if t.parameters.is_empty() { "..." } else { ", ..." });
+    } else if t.parameters.is_empty() && t.prototyped {
+        parameters.push_str("void");
+    }
+    Ok(format!("{} {}({}){};", rt.prefix, name, parameters, rt.suffix))
+}
+
+fn extern_string(tags: &TagMap, typedefs: &TypedefMap, v: &VariableTag) -> Result<String> {
+    let name = v.name.as_ref().ok_or_else(|| anyhow!("Variable without name"))?;
+    let ts = type_string(tags, typedefs, &v.kind, true)?;
+    Ok(format!("extern {} {}{};", ts.prefix, name, ts.suffix))
+}
+
+/// Anonymous types are hashed inline, so this only guards against malformed input.
+const MAX_HASH_DEPTH: usize = 16;
+
+/// Hashes a type by layout. Named records and enumerations are hashed by name, since
+/// their layout is hashed with their own declaration.
+fn hash_type<H: Hasher>(tags: &TagMap, t: &Type, depth: usize, state: &mut H) -> Result<()> {
+    for &modifier in &t.modifiers {
+        u8::from(modifier).hash(state);
+    }
+    match t.kind {
+        TypeKind::Fundamental(ft) => (0u8, u16::from(ft)).hash(state),
+        TypeKind::UserDefined(key) => {
+            1u8.hash(state);
+            let tag = tags
+                .get(&key)
+                .ok_or_else(|| anyhow!("Failed to locate user defined type {}", key))?;
+            hash_ud_type(tags, &ud_type(tags, tag)?, false, depth + 1, state)?;
+        }
+    }
+    Ok(())
+}
+
+fn hash_members<H: Hasher>(
+    tags: &TagMap,
+    members: &[StructureMember],
+    depth: usize,
+    state: &mut H,
+) -> Result<()> {
+    members.len().hash(state);
+    for member in members {
+        (&member.name, member.offset).hash(state);
+        member.bit.as_ref().map(|b| (b.bit_size, b.bit_offset)).hash(state);
+        hash_type(tags, &member.kind, depth, state)?;
+    }
+    Ok(())
+}
+
+/// Hashes a user defined type. The layout of named types is only included for definitions.
+fn hash_ud_type<H: Hasher>(
+    tags: &TagMap,
+    ud: &UserDefinedType,
+    definition: bool,
+    depth: usize,
+    state: &mut H,
+) -> Result<()> {
+    if depth > MAX_HASH_DEPTH {
+        return Ok(());
+    }
+    match ud {
+        UserDefinedType::Structure(s) => {
+            (0u8, s.kind == StructureKind::Class, &s.name).hash(state);
+            if definition || s.name.is_none() {
+                s.byte_size.hash(state);
+                for base in &s.bases {
+                    (base.offset, base.virtual_base).hash(state);
+                    hash_type(tags, &base.base_type, depth, state)?;
+                }
+                hash_members(tags, &s.members, depth, state)?;
+            }
+        }
+        UserDefinedType::Union(u) => {
+            (1u8, &u.name).hash(state);
+            if definition || u.name.is_none() {
+                u.byte_size.hash(state);
+                hash_members(tags, &u.members, depth, state)?;
+            }
+        }
+        UserDefinedType::Enumeration(e) => {
+            (2u8, &e.name).hash(state);
+            if definition || e.name.is_none() {
+                e.byte_size.hash(state);
+                for member in &e.members {
+                    (&member.name, member.value).hash(state);
+                }
+            }
+        }
+        UserDefinedType::Array(a) => {
+            3u8.hash(state);
+            for dimension in &a.dimensions {
+                dimension.size.map(|s| s.get()).hash(state);
+            }
+            hash_type(tags, &a.element_type, depth, state)?;
+        }
+        UserDefinedType::Subroutine(f) => {
+            (4u8, definition.then_some(&f.name), f.var_args, f.prototyped).hash(state);
+            hash_type(tags, &f.return_type, depth, state)?;
+            f.parameters.len().hash(state);
+            for parameter in &f.parameters {
+                hash_type(tags, &parameter.kind, depth, state)?;
+            }
+        }
+        UserDefinedType::PtrToMember(p) => {
+            5u8.hash(state);
+            hash_type(tags, &p.kind, depth, state)?;
+            let tag = tags
+                .get(&p.containing_type)
+                .ok_or_else(|| anyhow!("Failed to locate containing type {}", p.containing_type))?;
+            hash_ud_type(tags, &ud_type(tags, tag)?, false, depth + 1, state)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hashes a declaration by layout, independent of tag keys and of how types are printed
+/// (e.g. whether a typedef name is available in the unit).
+fn structural_hash(tags: &TagMap, tag_type: &TagType) -> Result<u64> {
+    let mut state = DefaultHasher::new();
+    match tag_type {
+        TagType::Typedef(t) => {
+            (0u8, &t.name).hash(&mut state);
+            hash_type(tags, &t.kind, 0, &mut state)?;
+        }
+        TagType::Variable(v) => {
+            (1u8, &v.name).hash(&mut state);
+            hash_type(tags, &v.kind, 0, &mut state)?;
+        }
+        TagType::UserDefined(ud) => {
+            2u8.hash(&mut state);
+            hash_ud_type(tags, ud, true, 0, &mut state)?;
+        }
+    }
+    Ok(state.finish())
+}
+
+/// Chooses the header for a declaration shared by multiple units, among the headers
+/// referenced by all of them. A header named after the declaration is preferred, then
+/// the most specific header (referenced by the fewest units overall). Ambiguous
+/// declarations are placed in `common.h`.
+fn shared_header(decl: &Decl, units: &[Unit], header_use: &HashMap<&str, usize>) -> Option<String> {
+    let mut iter = decl.units.iter().map(|&u| &units[u].files);
+    let first = iter.next().cloned().unwrap_or_default();
+    let common = iter.fold(first, |acc, files| acc.intersection(files).cloned().collect());
+    let named = common.iter().find(|path| {
+        let stem = path.rsplit('/').next().unwrap_or(path).trim_end_matches(".h");
+        stem.eq_ignore_ascii_case(&decl.name)
+    });
+    if let Some(path) = named {
+        return Some(path.clone());
+    }
+    let uses = |path: &String| header_use.get(path.as_str()).copied().unwrap_or(0);
+    let min_uses = common.iter().map(uses).min()?;
+    let mut candidates = common.iter().filter(|&path| uses(path) == min_uses);
+    match (candidates.next(), candidates.next()) {
+        (Some(path), None) => Some(path.clone()),
+        _ => {
+            log::debug!("Ambiguous header for {}: {:?}", decl.name, common);
+            None
+        }
+    }
+}
+
+/// Collects the type tags referenced by a type, as they would be printed.
+fn type_deps(
+    tags: &TagMap,
+    typedefs: &TypedefMap,
+    t: &Type,
+    complete: bool,
+    out: &mut Vec<(u32, bool)>,
+) -> Result<()> {
+    let complete = complete
+        && !t.modifiers.iter().any(|m| matches!(m, Modifier::PointerTo | Modifier::ReferenceTo));
+    let TypeKind::UserDefined(key) = t.kind else {
+        return Ok(());
+    };
+    if let Some(&td_key) = typedefs.get(&key).and_then(|v| v.first()) {
+        // Typedefs can't be forward declared
+        out.push((td_key, true));
+        return Ok(());
+    }
+    let tag =
+        tags.get(&key).ok_or_else(|| anyhow!("Failed to locate user defined type {}", key))?;
+    match ud_type(tags, tag)? {
+        UserDefinedType::Structure(s) if s.name.is_some() => out.push((key, complete)),
+        UserDefinedType::Union(u) if u.name.is_some() => out.push((key, complete)),
+        // Enums can't be forward declared in C
+        UserDefinedType::Enumeration(e) if e.name.is_some() => out.push((key, true)),
+        UserDefinedType::Enumeration(_) => {}
+        UserDefinedType::Structure(s) => {
+            // Anonymous, defined inline
+            for base in &s.bases {
+                type_deps(tags, typedefs, &base.base_type, complete, out)?;
+            }
+            for member in &s.members {
+                type_deps(tags, typedefs, &member.kind, complete, out)?;
+            }
+        }
+        UserDefinedType::Union(u) => {
+            for member in &u.members {
+                type_deps(tags, typedefs, &member.kind, complete, out)?;
+            }
+        }
+        UserDefinedType::Array(a) => type_deps(tags, typedefs, &a.element_type, complete, out)?,
+        UserDefinedType::Subroutine(f) => {
+            type_deps(tags, typedefs, &f.return_type, false, out)?;
+            for parameter in &f.parameters {
+                type_deps(tags, typedefs, &parameter.kind, false, out)?;
+            }
+        }
+        UserDefinedType::PtrToMember(p) => {
+            type_deps(tags, typedefs, &p.kind, false, out)?;
+            out.push((p.containing_type, false));
+        }
+    }
+    Ok(())
+}
+
+fn decl_deps(tags: &TagMap, typedefs: &TypedefMap, tag_type: &TagType) -> Result<Vec<(u32, bool)>> {
+    let mut out = vec![];
+    match tag_type {
+        TagType::Typedef(t) => {
+            // `typedef struct Foo Foo;` only requires a declaration of `struct Foo`
+            let is_record = match t.kind.kind {
+                TypeKind::UserDefined(key) if t.kind.modifiers.is_empty() => {
+                    tags.get(&key).and_then(|tag| ud_type(tags, tag).ok()).is_some_and(|ud| {
+                        matches!(&ud, UserDefinedType::Structure(s) if s.name.is_some())
+                            || matches!(&ud, UserDefinedType::Union(u) if u.name.is_some())
+                    })
+                }
+                _ => false,
+            };
+            match t.kind.kind {
+                TypeKind::UserDefined(key) if is_record && !typedefs.contains_key(&key) => {
+                    out.push((key, false))
+                }
+                _ => type_deps(tags, typedefs, &t.kind, true, &mut out)?,
+            }
+        }
+        TagType::Variable(v) => type_deps(tags, typedefs, &v.kind, false, &mut out)?,
+        TagType::UserDefined(UserDefinedType::Structure(s)) => {
+            for base in &s.bases {
+                type_deps(tags, typedefs, &base.base_type, true, &mut out)?;
+            }
+            for member in &s.members {
+                type_deps(tags, typedefs, &member.kind, true, &mut out)?;
+            }
+        }
+        TagType::UserDefined(UserDefinedType::Union(u)) => {
+            for member in &u.members {
+                type_deps(tags, typedefs, &member.kind, true, &mut out)?;
+            }
+        }
+        TagType::UserDefined(UserDefinedType::Subroutine(f)) => {
+            type_deps(tags, typedefs, &f.return_type, false, &mut out)?;
+            for parameter in &f.parameters {
+                type_deps(tags, typedefs, &parameter.kind, false, &mut out)?;
+            }
+        }
+        TagType::UserDefined(_) => {}
+    }
+    Ok(out)
+}
+
+/// Generates header files for all compile units, keyed by path.
+///
+/// Declarations are deduplicated across compile units by a structural hash of their layout.
+/// Each is assigned to the header of the unit that defines it, or if shared by multiple
+/// units, to a header that appears in all of their line tables (see [`shared_header`]).
+pub fn generate_headers(objects: &[DebugObject]) -> Result<BTreeMap<String, String>> {
+    let mut units = Vec::<Unit>::new();
+    let mut decls = Vec::<Decl>::new();
+    let mut by_hash = HashMap::<u64, usize>::new();
+
+    for object in objects {
+        log::debug!("Processing '{}'", object.name);
+        let tags = &object.tags;
+        let Some((_, mut tag)) = tags.first_key_value() else {
+            continue;
+        };
+        while tag.kind == TagKind::CompileUnit {
+            let name = tag.string_attribute(AttributeKind::Name).cloned().unwrap_or_default();
+            let path = source_path(&name);
+            let mut files = BTreeSet::new();
+            if let Some(unit_lines) =
+                object.lines.as_ref().and_then(|l| l.units.iter().find(|u| u.key == tag.key))
+            {
+                let lines = object.lines.as_ref().unwrap();
+                for row in &unit_lines.rows {
+                    let file = source_path(lines.file(row.file));
+                    if file != path && file.ends_with(".h") {
+                        files.insert(file);
+                    }
+                }
+            }
+            let unit_index = units.len();
+            units.push(Unit { path, files });
+
+            let mut typedefs = TypedefMap::new();
+            let mut key_to_decl = HashMap::<u32, usize>::new();
+            let mut pending = Vec::<(usize, Vec<(u32, bool)>)>::new();
+            for child in tag.children(tags) {
+                let tag_type = match process_root_tag(tags, child) {
+                    Ok(tag_type) => tag_type,
+                    Err(e) => {
+                        log::debug!("Failed to process tag {} (unit {}): {}", child.key, name, e);
+                        continue;
+                    }
+                };
+                if should_skip_tag(&tag_type) {
+                    continue;
+                }
+                let Some((kind, decl_name)) = decl_info(&tag_type) else {
+                    continue;
+                };
+                let text = match &tag_type {
+                    TagType::UserDefined(UserDefinedType::Subroutine(s)) => {
+                        prototype_string(tags, &typedefs, s)
+                    }
+                    TagType::Variable(v) => extern_string(tags, &typedefs, v),
+                    _ => tag_type_string(tags, &typedefs, &tag_type),
+                };
+                let text = match text {
+                    Ok(text) => text,
+                    Err(e) => {
+                        log::warn!("Failed to emit tag {} (unit {}): {}", child.key, name, e);
+                        continue;
+                    }
+                };
+                let hash = match structural_hash(tags, &tag_type) {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        log::warn!("Failed to hash tag {} (unit {}): {}", child.key, name, e);
+                        continue;
+                    }
+                };
+                let decl_index = match by_hash.get(&hash) {
+                    Some(&idx) => idx,
+                    None => {
+                        let idx = decls.len();
+                        pending.push((idx, decl_deps(tags, &typedefs, &tag_type)?));
+                        decls.push(Decl {
+                            kind,
+                            name: decl_name,
+                            text,
+                            deps: vec![],
+                            units: BTreeSet::new(),
+                            conflicts: vec![],
+                        });
+                        by_hash.insert(hash, idx);
+                        idx
+                    }
+                };
+                decls[decl_index].units.insert(unit_index);
+                key_to_decl.insert(child.key, decl_index);
+
+                if let TagType::Typedef(t) = &tag_type {
+                    if let TypeKind::UserDefined(key) = t.kind.kind {
+                        if t.kind.modifiers.is_empty() {
+                            typedefs.entry(key).or_default().push(child.key);
+                        }
+                    }
+                }
+            }
+
+            // Resolve dependencies within the unit
+            for (decl_index, raw_deps) in pending {
+                let mut deps = Vec::<Dep>::new();
+                for (key, complete) in raw_deps {
+                    let Some(&decl) = key_to_decl.get(&key) else {
+                        continue;
+                    };
+                    if decl == decl_index {
+                        continue;
+                    }
+                    match deps.iter_mut().find(|d| d.decl == decl) {
+                        Some(dep) => dep.complete |= complete,
+                        None => deps.push(Dep { decl, complete }),
+                    }
+                }
+                decls[decl_index].deps = deps;
+            }
+
+            match tag.next_sibling(tags) {
+                Some(next) => tag = next,
+                None => break,
+            }
+        }
+    }
+
+    // Assign declarations to headers
+    let mut header_use = HashMap::<&str, usize>::new();
+    for unit in &units {
+        for file in &unit.files {
+            *header_use.entry(file.as_str()).or_default() += 1;
+        }
+    }
+    let decl_headers = decls
+        .iter()
+        .map(|decl| {
+            if decl.units.len() == 1 {
+                return header_path(&units[*decl.units.first().unwrap()].path);
+            }
+            shared_header(decl, &units, &header_use).unwrap_or_else(|| "common.h".to_string())
+        })
+        .collect::<Vec<_>>();
+
+    // Report declarations with the same name, but a different layout
+    let mut by_name = BTreeMap::<(&str, bool), Vec<usize>>::new();
+    for (idx, decl) in decls.iter().enumerate() {
+        let is_type = !matches!(decl.kind, DeclKind::Function | DeclKind::Variable);
+        by_name.entry((decl.name.as_str(), is_type)).or_default().push(idx);
+    }
+    let mut conflicts = Vec::<(usize, Vec<usize>)>::new();
+    for ((name, _), indices) in by_name.into_iter().filter(|(_, v)| v.len() > 1) {
+        log::warn!("{} conflicting definitions of {}:", indices.len(), name);
+        for &idx in &indices {
+            let unit_paths = decls[idx].units.iter().map(|&u| units[u].path.as_str());
+            log::warn!(
+                "  in {} (units {})",
+                decl_headers[idx],
+                unit_paths.collect::<Vec<_>>().join(", ")
+            );
+            conflicts.push((idx, indices.iter().copied().filter(|&i| i != idx).collect()));
+        }
+    }
+    for (idx, others) in conflicts {
+        decls[idx].conflicts = others;
+    }
+    let mut headers = BTreeMap::<&str, Vec<usize>>::new();
+    for (idx, header) in decl_headers.iter().enumerate() {
+        headers.entry(header.as_str()).or_default().push(idx);
+    }
+
+    let mut out = BTreeMap::new();
+    for (path, members) in headers {
+        // Order by complete dependencies within the header
+        let mut order = Vec::with_capacity(members.len());
+        let mut state = HashMap::<usize, bool>::new(); // false = visiting, true = done
+        fn visit(
+            idx: usize,
+            header: &str,
+            decls: &[Decl],
+            decl_headers: &[String],
+            state: &mut HashMap<usize, bool>,
+            order: &mut Vec<usize>,
+        ) {
+            if state.contains_key(&idx) {
+                return;
+            }
+            state.insert(idx, false);
+            for dep in &decls[idx].deps {
+                if dep.complete && decl_headers[dep.decl] == header {
+                    visit(dep.decl, header, decls, decl_headers, state, order);
+                }
+            }
+            state.insert(idx, true);
+            order.push(idx);
+        }
+        for &idx in &members {
+            visit(idx, path, &decls, &decl_headers, &mut state, &mut order);
+        }
+
+        let mut includes = BTreeSet::new();
+        let mut forward = Vec::<usize>::new();
+        let mut emitted = BTreeSet::new();
+        for &idx in &order {
+            for dep in &decls[idx].deps {
+                let dep_header = decl_headers[dep.decl].as_str();
+                let is_record = matches!(
+                    decls[dep.decl].kind,
+                    DeclKind::Struct | DeclKind::Class | DeclKind::Union
+                );
+                if dep_header != path && (dep.complete || !is_record) {
+                    includes.insert(dep_header);
+                } else if !dep.complete
+                    && is_record
+                    && (dep_header != path || !emitted.contains(&dep.decl))
+                    && !forward.contains(&dep.decl)
+                {
+                    forward.push(dep.decl);
+                }
+            }
+            emitted.insert(idx);
+        }
+
+        let guard = include_guard(path);
+        let mut header = String::new();
+        writeln!(header, "#ifndef {}", guard)?;
+        writeln!(header, "#define {}", guard)?;
+        if !includes.is_empty() {
+            header.push('\n');
+            for include in &includes {
+                writeln!(header, "#include \"{}\"", include)?;
+            }
+        }
+        if !forward.is_empty() {
+            header.push('\n');
+            for &idx in &forward {
+                let decl = &decls[idx];
+                let keyword = match decl.kind {
+                    DeclKind::Class => "class",
+                    DeclKind::Union => "union",
+                    _ => "struct",
+                };
+                writeln!(header, "{} {};", keyword, decl.name)?;
+            }
+        }
+        for &idx in &order {
+            header.push('\n');
+            let decl = &decls[idx];
+            for &other in &decl.conflicts {
+                let unit_paths = decls[other].units.iter().map(|&u| units[u].path.as_str());
+                writeln!(
+                    header,
+                    "// WARNING: Conflicts with the definition in {} (units {})",
+                    decl_headers[other],
+                    unit_paths.collect::<Vec<_>>().join(", ")
+                )?;
+            }
+            writeln!(header, "{}", decl.text)?;
+        }
+        writeln!(header, "\n#endif // {}", guard)?;
+        out.insert(path.to_string(), header);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{
+        debug_info::{LineInfo, LineRow, UnitLines},
+        dwarf::{Attribute, AttributeValue, FundType, LocationOp, Tag},
+    };
+
+    fn attr(kind: AttributeKind, value: AttributeValue) -> Attribute { Attribute { kind, value } }
+
+    fn name(name: &str) -> Attribute {
+        attr(AttributeKind::Name, AttributeValue::String(name.to_string()))
+    }
+
+    fn member(
+        name_str: &str,
+        fund_type: FundType,
+        offset: u32,
+    ) -> (usize, TagKind, Vec<Attribute>) {
+        let mut block = vec![LocationOp::Const as u8];
+        block.extend(offset.to_be_bytes());
+        block.push(LocationOp::Add as u8);
+        (2, TagKind::Member, vec![
+            name(name_str),
+            attr(AttributeKind::FundType, AttributeValue::Data2(fund_type.into())),
+            attr(AttributeKind::Location, AttributeValue::Block(block)),
+        ])
+    }
+
+    fn structure(name_str: &str, size: u32) -> (usize, TagKind, Vec<Attribute>) {
+        (1, TagKind::StructureType, vec![
+            name(name_str),
+            attr(AttributeKind::ByteSize, AttributeValue::Data4(size)),
+        ])
+    }
+
+    /// Builds a single compile unit from tags given as (depth, kind, attributes), with a
+    /// line table referencing the given headers.
+    fn unit(
+        unit_name: &str,
+        headers: &[&str],
+        entries: Vec<(usize, TagKind, Vec<Attribute>)>,
+    ) -> DebugObject {
+        let mut entries = entries;
+        entries.insert(0, (0, TagKind::CompileUnit, vec![name(unit_name)]));
+        let end = entries.len() as u32 * 0x10;
+        let mut tags = TagMap::new();
+        for (i, (depth, kind, mut attributes)) in entries.iter().cloned().enumerate() {
+            let key = i as u32 * 0x10;
+            if entries.get(i + 1).is_some_and(|(next, _, _)| *next > depth) {
+                let sibling = entries[i + 1..]
+                    .iter()
+                    .position(|(next, _, _)| *next <= depth)
+                    .map_or(end, |pos| (i + 1 + pos) as u32 * 0x10);
+                attributes
+                    .insert(0, attr(AttributeKind::Sibling, AttributeValue::Reference(sibling)));
+            }
+            tags.insert(key, Tag { key, kind, attributes });
+        }
+        tags.insert(end, Tag { key: end, kind: TagKind::Padding, attributes: vec![] });
+
+        let mut lines = LineInfo::default();
+        lines.files = headers.iter().map(|h| h.to_string()).collect();
+        lines.units.push(UnitLines {
+            key: 0,
+            name: unit_name.to_string(),
+            rows: (0..headers.len())
+                .map(|file| LineRow { address: file as u32, file, line: 1, column: None })
+                .collect(),
+            functions: vec![],
+        });
+        DebugObject { name: unit_name.to_string(), tags, lines: Some(lines) }
+    }
+
+    #[test]
+    fn test_generate_headers_dedupe_and_conflicts() {
+        let point = || {
+            vec![
+                structure("Point", 8),
+                member("x", FundType::Integer, 0),
+                member("y", FundType::Integer, 4),
+            ]
+        };
+        let mut a = point();
+        a.extend([structure("Foo", 4), member("a", FundType::Integer, 0)]);
+        let mut b = point();
+        b.extend([
+            structure("Foo", 4),
+            member("a", FundType::Short, 0),
+            member("b", FundType::Short, 2),
+        ]);
+        let objects = [
+            unit("C:\\game\\src\\a.c", &["C:\\game\\include\\point.h", "/game/include/types.h"], a),
+            unit("C:\\game\\src\\b.c", &["C:\\game\\include\\point.h", "/game/include/types.h"], b),
+        ];
+        let headers = generate_headers(&objects).unwrap();
+
+        // Identical layouts are shared, in the header named after the type
+        let shared = headers.values().filter(|h| h.contains("struct Point {")).collect::<Vec<_>>();
+        assert_eq!(shared.len(), 1);
+        assert!(headers["point.h"].contains("struct Point {"));
+
+        // Conflicting layouts stay with their units, and are marked
+        assert!(headers["a.h"].contains("int a;"));
+        assert!(
+            headers["a.h"].contains("// WARNING: Conflicts with the definition in b.h (units b.c)")
+        );
+        assert!(headers["b.h"].contains("short b;"));
+        assert!(
+            headers["b.h"].contains("// WARNING: Conflicts with the definition in a.h (units a.c)")
+        );
+        assert!(!headers.contains_key("common.h"));
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::util::{
+    debug_info::DebugObject,
+    dwarf::{
+        process_root_tag, AttributeKind, FundType, Modifier, StructureKind, StructureMember,
+        TagKind, TagMap, TagType, Type, TypeKind, UserDefinedType, Visibility,
+    },
+};
+
+/// Version of the type database schema. Incremented on breaking changes.
+pub const TYPE_DATABASE_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct TypeDatabase {
+    pub version: u32,
+    pub units: Vec<UnitTypes>,
+}
+
+#[derive(Serialize)]
+pub struct UnitTypes {
+    pub name: String,
+    /// Object (or archive member) containing the compile unit
+    pub object: String,
+    pub types: Vec<TypeDef>,
+    pub variables: Vec<VariableDef>,
+}
+
+/// Reference to a type. Exactly one of `fundamental` or `id` is set.
+#[derive(Serialize)]
+pub struct TypeRef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fundamental: Option<&'static str>,
+    /// ID of a [`TypeDef`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    /// Outermost first, i.e. `const char *` is `["pointer", "const"]`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u32>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypeDef {
+    Struct(RecordDef),
+    Class(RecordDef),
+    Union(RecordDef),
+    Enum(EnumDef),
+    Array(ArrayDef),
+    Function(FunctionDef),
+    PtrToMember(PtrToMemberDef),
+    Typedef(TypedefDef),
+}
+
+#[derive(Serialize)]
+pub struct RecordDef {
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub size: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bases: Vec<BaseDef>,
+    pub members: Vec<MemberDef>,
+    /// Offset of the vtable pointer, if the record declares one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vtable_offset: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct BaseDef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: TypeRef,
+    pub offset: u32,
+    pub visibility: &'static str,
+    #[serde(rename = "virtual")]
+    pub virtual_base: bool,
+}
+
+#[derive(Serialize)]
+pub struct MemberDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: TypeRef,
+    pub offset: u32,
+    pub size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitfield: Option<BitfieldDef>,
+    pub visibility: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct BitfieldDef {
+    pub bit_size: u32,
+    pub bit_offset: u16,
+}
+
+#[derive(Serialize)]
+pub struct EnumDef {
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub size: u32,
+    pub members: Vec<EnumMemberDef>,
+}
+
+#[derive(Serialize)]
+pub struct EnumMemberDef {
+    pub name: String,
+    pub value: i32,
+}
+
+#[derive(Serialize)]
+pub struct ArrayDef {
+    pub id: u32,
+    pub element_type: TypeRef,
+    /// Element count per dimension, outermost first. `null` if unbounded
+    pub dimensions: Vec<Option<u32>>,
+    pub size: u32,
+}
+
+#[derive(Serialize)]
+pub struct FunctionDef {
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mangled_name: Option<String>,
+    pub return_type: TypeRef,
+    pub parameters: Vec<ParameterDef>,
+    pub var_args: bool,
+    pub prototyped: bool,
+    /// ID of the containing record, for member functions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member_of: Option<u32>,
+    pub inline: bool,
+    pub local: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_address: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_address: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct ParameterDef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: TypeRef,
+}
+
+#[derive(Serialize)]
+pub struct PtrToMemberDef {
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub kind: TypeRef,
+    pub containing_type: u32,
+}
+
+#[derive(Serialize)]
+pub struct TypedefDef {
+    pub id: u32,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: TypeRef,
+}
+
+#[derive(Serialize)]
+pub struct VariableDef {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mangled_name: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: TypeRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<u32>,
+    pub local: bool,
+}
+
+/// Assigns database-wide IDs to tags, which are only unique per object.
+#[derive(Default)]
+struct TypeIds {
+    ids: HashMap<(usize, u32), u32>,
+}
+
+impl TypeIds {
+    fn get(&mut self, object: usize, key: u32) -> u32 {
+        let next = self.ids.len() as u32;
+        *self.ids.entry((object, key)).or_insert(next)
+    }
+}
+
+struct Converter<'a> {
+    tags: &'a TagMap,
+    object: usize,
+    ids: &'a mut TypeIds,
+}
+
+fn fund_type_name(ft: FundType) -> &'static str { ft.name().unwrap_or("unknown") }
+
+fn modifier_name(modifier: Modifier) -> &'static str {
+    match modifier {
+        Modifier::PointerTo => "pointer",
+        Modifier::ReferenceTo => "reference",
+        Modifier::Const => "const",
+        Modifier::Volatile => "volatile",
+    }
+}
+
+fn visibility_name(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Private => "private",
+        Visibility::Protected => "protected",
+        Visibility::Public => "public",
+    }
+}
+
+/// MWCC names the vtable pointer `__vt`, GCC uses `_vptr.Name` or `_vptr$Name`.
+fn is_vtable_member(member: &StructureMember) -> bool {
+    member.name == "__vt" || member.name.starts_with("_vptr")
+}
+
+impl Converter<'_> {
+    fn type_ref(&mut self, t: &Type) -> TypeRef {
+        let (fundamental, id) = match t.kind {
+            TypeKind::Fundamental(ft) => (Some(fund_type_name(ft)), None),
+            TypeKind::UserDefined(key) => (None, Some(self.ids.get(self.object, key))),
+        };
+        TypeRef {
+            fundamental,
+            id,
+            modifiers: t.modifiers.iter().map(|&m| modifier_name(m)).collect(),
+            size: t.size(self.tags).ok(),
+        }
+    }
+
+    fn members(&mut self, members: &[StructureMember]) -> Vec<MemberDef> {
+        members
+            .iter()
+            .map(|member| MemberDef {
+                name: member.name.clone(),
+                kind: self.type_ref(&member.kind),
+                offset: member.offset,
+                size: member
+                    .byte_size
+                    .or_else(|| member.kind.size(self.tags).ok())
+                    .unwrap_or_default(),
+                bitfield: member
+                    .bit
+                    .as_ref()
+                    .map(|b| BitfieldDef { bit_size: b.bit_size, bit_offset: b.bit_offset }),
+                visibility: visibility_name(member.visibility),
+            })
+            .collect()
+    }
+
+    fn type_def(&mut self, key: u32, ud: &UserDefinedType) -> TypeDef {
+        let id = self.ids.get(self.object, key);
+        let size = ud.size(self.tags).unwrap_or_default();
+        match ud {
+            UserDefinedType::Structure(t) => {
+                let def = RecordDef {
+                    id,
+                    name: t.name.clone(),
+                    size,
+                    bases: t
+                        .bases
+                        .iter()
+                        .map(|base| BaseDef {
+                            name: base.name.clone(),
+                            kind: self.type_ref(&base.base_type),
+                            offset: base.offset,
+                            visibility: visibility_name(base.visibility),
+                            virtual_base: base.virtual_base,
+                        })
+                        .collect(),
+                    members: self.members(&t.members),
+                    vtable_offset: t.members.iter().find(|m| is_vtable_member(m)).map(|m| m.offset),
+                };
+                match t.kind {
+                    StructureKind::Struct => TypeDef::Struct(def),
+                    StructureKind::Class => TypeDef::Class(def),
+                }
+            }
+            UserDefinedType::Union(t) => TypeDef::Union(RecordDef {
+                id,
+                name: t.name.clone(),
+                size,
+                bases: vec![],
+                members: self.members(&t.members),
+                vtable_offset: None,
+            }),
+            UserDefinedType::Enumeration(t) => TypeDef::Enum(EnumDef {
+                id,
+                name: t.name.clone(),
+                size,
+                members: t
+                    .members
+                    .iter()
+                    .map(|m| EnumMemberDef { name: m.name.clone(), value: m.value })
+                    .collect(),
+            }),
+            UserDefinedType::Array(t) => TypeDef::Array(ArrayDef {
+                id,
+                element_type: self.type_ref(&t.element_type),
+                dimensions: t.dimensions.iter().map(|d| d.size.map(|s| s.get())).collect(),
+                size,
+            }),
+            UserDefinedType::Subroutine(t) => TypeDef::Function(FunctionDef {
+                id,
+                name: t.name.clone(),
+                mangled_name: t.mangled_name.clone(),
+                return_type: self.type_ref(&t.return_type),
+                parameters: t
+                    .parameters
+                    .iter()
+                    .map(|p| ParameterDef { name: p.name.clone(), kind: self.type_ref(&p.kind) })
+                    .collect(),
+                var_args: t.var_args,
+                prototyped: t.prototyped,
+                member_of: t.member_of.map(|key| self.ids.get(self.object, key)),
+                inline: t.inline,
+                local: t.local,
+                start_address: t.start_address,
+                end_address: t.end_address,
+            }),
+            UserDefinedType::PtrToMember(t) => TypeDef::PtrToMember(PtrToMemberDef {
+                id,
+                kind: self.type_ref(&t.kind),
+                containing_type: self.ids.get(self.object, t.containing_type),
+            }),
+        }
+    }
+}
+
+/// Builds a type database from all compile units.
+///
+/// Type IDs are unique across the database and stable for the same input.
+pub fn build_type_database(objects: &[DebugObject]) -> Result<TypeDatabase> {
+    let mut ids = TypeIds::default();
+    let mut units = vec![];
+    for (object_index, object) in objects.iter().enumerate() {
+        let tags = &object.tags;
+        let Some((_, mut tag)) = tags.first_key_value() else {
+            continue;
+        };
+        while tag.kind == TagKind::CompileUnit {
+            let name = tag.string_attribute(AttributeKind::Name).cloned().unwrap_or_default();
+            let mut converter = Converter { tags, object: object_index, ids: &mut ids };
+            let mut unit =
+                UnitTypes { name, object: object.name.clone(), types: vec![], variables: vec![] };
+            for child in tag.children(tags) {
+                let tag_type = match process_root_tag(tags, child) {
+                    Ok(tag_type) => tag_type,
+                    Err(e) => {
+                        log::warn!(
+                            "Failed to process tag {} (unit {}): {}",
+                            child.key,
+                            unit.name,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                match tag_type {
+                    TagType::Typedef(t) => {
+                        let id = converter.ids.get(object_index, child.key);
+                        let kind = converter.type_ref(&t.kind);
+                        unit.types.push(TypeDef::Typedef(TypedefDef { id, name: t.name, kind }));
+                    }
+                    TagType::Variable(v) => {
+                        let Some(name) = v.name else {
+                            continue;
+                        };
+                        unit.variables.push(VariableDef {
+                            name,
+                            mangled_name: v.mangled_name,
+                            kind: converter.type_ref(&v.kind),
+                            address: v.address,
+                            local: v.local,
+                        });
+                    }
+                    TagType::UserDefined(ud) => {
+                        unit.types.push(converter.type_def(child.key, &ud));
+                    }
+                }
+            }
+            units.push(unit);
+
+            match tag.next_sibling(tags) {
+                Some(next) => tag = next,
+                None => break,
+            }
+        }
+    }
+    Ok(TypeDatabase { version: TYPE_DATABASE_VERSION, units })
+}
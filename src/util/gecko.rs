@@ -0,0 +1,203 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::{obj::ObjRelocKind, util::rel::patch_relocation};
+
+/// `nop`
+const NOP: u32 = 0x60000000;
+/// `mtctr r12`
+const MTCTR_R12: u32 = 0x7D8903A6;
+/// `bctr`
+const BCTR: u32 = 0x4E800420;
+
+/// Gecko code list, as lines of two words.
+#[derive(Debug, Clone, Default)]
+pub struct GeckoCodes {
+    pub lines: Vec<(u32, u32)>,
+}
+
+/// Encodes the code type and address for codes using the default base address (0x80000000).
+fn code_address(code_type: u32, address: u32) -> Result<u32> {
+    ensure!(
+        (0x80000000..0x82000000).contains(&address),
+        "Address {:#010X} out of range for Gecko codes",
+        address
+    );
+    Ok((code_type << 24) | (address & 0x1FFFFFF))
+}
+
+fn push_words(lines: &mut Vec<(u32, u32)>, words: &[u32]) {
+    for pair in words.chunks(2) {
+        lines.push((pair[0], pair.get(1).copied().unwrap_or(0)));
+    }
+}
+
+impl GeckoCodes {
+    /// 04: 32-bit write.
+    pub fn write_u32(&mut self, address: u32, value: u32) -> Result<()> {
+        ensure!(address % 4 == 0, "Unaligned 32-bit write at {:#010X}", address);
+        self.lines.push((code_address(0x04, address)?, value));
+        Ok(())
+    }
+
+    /// 06: string write.
+    pub fn write_bytes(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.lines.push((code_address(0x06, address)?, data.len() as u32));
+        let words = data
+            .chunks(4)
+            .map(|c| {
+                let mut word = [0u8; 4];
+                word[..c.len()].copy_from_slice(c);
+                u32::from_be_bytes(word)
+            })
+            .collect::<Vec<_>>();
+        push_words(&mut self.lines, &words);
+        Ok(())
+    }
+
+    /// C2: insert ASM. The instruction at `address` is replaced by `code`, followed by a
+    /// branch back (written by the code handler in place of the final zero word).
+    ///
+    /// The code handler copies the code elsewhere, so relative branches out of the code are
+    /// redirected to stubs appended to it, which branch to the target via `ctr` (clobbering
+    /// `r12`). `branches` lists these as (offset, kind, target address).
+    pub fn insert_asm(
+        &mut self,
+        address: u32,
+        code: &[u8],
+        branches: &[(u32, ObjRelocKind, u32)],
+    ) -> Result<()> {
+        ensure!(code.len() % 4 == 0, "Code size {:#X} is not a multiple of 4", code.len());
+        let mut code = code.to_vec();
+        if !branches.is_empty() {
+            // Skip over the stubs when falling through
+            let targets = branches.iter().map(|&(_, _, t)| t).collect::<BTreeSet<_>>();
+            let stubs_offset = code.len() as u32 + 4;
+            let end_offset = stubs_offset + targets.len() as u32 * 16;
+            code.extend_from_slice(&(0x48000000 | (end_offset - code.len() as u32)).to_be_bytes());
+            let mut stub_offsets = BTreeMap::new();
+            for (i, &target) in targets.iter().enumerate() {
+                stub_offsets.insert(target, stubs_offset + i as u32 * 16);
+                // lis r12, target@h; ori r12, r12, target@l; mtctr r12; bctr
+                for ins in
+                    [0x3D800000 | (target >> 16), 0x618C0000 | (target & 0xFFFF), MTCTR_R12, BCTR]
+                {
+                    code.extend_from_slice(&ins.to_be_bytes());
+                }
+            }
+            for &(offset, kind, target) in branches {
+                ensure!(
+                    matches!(kind, ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14),
+                    "Unexpected {:?} branch at offset {:#X}",
+                    kind,
+                    offset
+                );
+                patch_relocation(&mut code, offset as usize, kind, offset, stub_offsets[&target])
+                    .with_context(|| format!("Failed to redirect branch at offset {:#X}", offset))?;
+            }
+        }
+        let mut words = code
+            .chunks(4)
+            .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+            .collect::<Vec<_>>();
+        if words.len() % 2 == 0 {
+            words.push(NOP);
+        }
+        words.push(0);
+        self.lines.push((code_address(0xC2, address)?, (words.len() / 2) as u32));
+        push_words(&mut self.lines, &words);
+        Ok(())
+    }
+
+    /// Converts the codes to unencrypted Action Replay codes.
+    /// 32-bit writes are kept as-is, and string writes are split into 32-bit and 8-bit writes.
+    /// Insert ASM (C2) codes have no Action Replay equivalent.
+    pub fn to_action_replay(&self) -> Result<GeckoCodes> {
+        let mut lines = vec![];
+        let mut iter = self.lines.iter();
+        while let Some(&(code, value)) = iter.next() {
+            let address = 0x80000000 | (code & 0x1FFFFFF);
+            match code >> 24 {
+                0x04 => lines.push((code, value)),
+                0x06 => {
+                    let data = iter
+                        .by_ref()
+                        .take((value as usize + 7) / 8)
+                        .flat_map(|&(a, b)| [a.to_be_bytes(), b.to_be_bytes()])
+                        .flatten()
+                        .take(value as usize)
+                        .collect::<Vec<_>>();
+                    let mut offset = 0;
+                    while offset < data.len() {
+                        let ar_address = address + offset as u32;
+                        if ar_address % 4 == 0 && offset + 4 <= data.len() {
+                            let word = u32::from_be_bytes(data[offset..offset + 4].try_into()?);
+                            lines.push((code_address(0x04, ar_address)?, word));
+                            offset += 4;
+                        } else {
+                            lines.push((code_address(0x00, ar_address)?, data[offset] as u32));
+                            offset += 1;
+                        }
+                    }
+                }
+                0xC2 => {
+                    bail!("Insert ASM code at {:#010X} has no Action Replay equivalent", address)
+                }
+                code_type => bail!("Unexpected code type {:02X}", code_type),
+            }
+        }
+        Ok(GeckoCodes { lines })
+    }
+}
+
+impl fmt::Display for GeckoCodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (a, b) in &self.lines {
+            writeln!(f, "{:08X} {:08X}", a, b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_asm_stubs() {
+        // bl 0x80001000; li r3, 0; bl 0x80001000
+        let code = [0x48000001u32, 0x38600000, 0x48000001].map(u32::to_be_bytes).concat();
+        let branches =
+            [(0, ObjRelocKind::PpcRel24, 0x80001000), (8, ObjRelocKind::PpcRel24, 0x80001000)];
+        let mut codes = GeckoCodes::default();
+        codes.insert_asm(0x80003000, &code, &branches).unwrap();
+        assert_eq!(codes.lines, vec![
+            (0xC2003000, 5),
+            (0x48000011, 0x38600000), // bl stub
+            (0x48000009, 0x48000014), // bl stub; b end
+            (0x3D808000, 0x618C1000), // lis r12, 0x8000; ori r12, r12, 0x1000
+            (MTCTR_R12, BCTR),
+            (NOP, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_action_replay() {
+        let mut codes = GeckoCodes::default();
+        codes.write_u32(0x80003000, 0x4E800020).unwrap();
+        codes.write_bytes(0x80003102, &[1, 2, 3, 4, 5, 6, 7]).unwrap();
+        assert_eq!(codes.to_action_replay().unwrap().lines, vec![
+            (0x04003000, 0x4E800020),
+            (0x00003102, 1),
+            (0x00003103, 2),
+            (0x04003104, 0x03040506),
+            (0x00003108, 7),
+        ]);
+        codes.insert_asm(0x80003000, &NOP.to_be_bytes(), &[]).unwrap();
+        assert!(codes.to_action_replay().is_err());
+    }
+}
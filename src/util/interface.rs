@@ -0,0 +1,353 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    analysis::cfa::SectionAddress,
+    obj::{
+        ObjArchitecture, ObjInfo, ObjKind, ObjSection, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet,
+        ObjSymbolFlags, ObjSymbolKind, SymbolIndex,
+    },
+    util::{align_up, bin2c::is_c_identifier},
+};
+
+/// `blr`
+const STUB_INSTRUCTION: [u8; 4] = 0x4E800020u32.to_be_bytes();
+
+/// Whether the symbol is referenced as code. Labels created for relocation targets
+/// have no kind, so fall back to the kind of the containing section.
+fn is_function(obj: &ObjInfo, symbol: &ObjSymbol) -> bool {
+    match symbol.kind {
+        ObjSymbolKind::Function => true,
+        ObjSymbolKind::Object => false,
+        _ => symbol.section.is_some_and(|idx| obj.sections[idx].kind == ObjSectionKind::Code),
+    }
+}
+
+/// Finds all symbols in this module referenced from other modules.
+pub fn interface_symbols(obj: &ObjInfo, modules: &[&ObjInfo]) -> Result<Vec<SymbolIndex>> {
+    let mut symbols = BTreeSet::new();
+    for rel_reloc in modules
+        .iter()
+        .filter(|o| o.module_id != obj.module_id)
+        .flat_map(|o| o.unresolved_relocations.iter())
+        .filter(|r| r.module_id == obj.module_id)
+    {
+        let Some((target_section_index, _)) =
+            obj.sections.get_elf_index(rel_reloc.target_section as usize)
+        else {
+            continue;
+        };
+        match obj.symbols.for_relocation(
+            SectionAddress::new(target_section_index, rel_reloc.addend),
+            rel_reloc.kind,
+        )? {
+            Some((symbol_index, _)) => {
+                symbols.insert(symbol_index);
+            }
+            None => log::warn!(
+                "Module {} has no symbol for reference to section {} at {:#010X}",
+                obj.name,
+                rel_reloc.target_section,
+                rel_reloc.addend
+            ),
+        }
+    }
+    Ok(symbols.into_iter().collect())
+}
+
+/// Creates an object defining the given module symbols as weak stubs.
+/// Functions are defined as `blr` in `.text`, and data as zero-filled space in `.bss`.
+pub fn interface_obj(obj: &ObjInfo, symbols: &[SymbolIndex]) -> Result<ObjInfo> {
+    let mut out_obj = ObjInfo::new(
+        ObjKind::Relocatable,
+        ObjArchitecture::PowerPc,
+        format!("{}_interface", obj.name),
+        vec![],
+        vec![],
+    );
+    let mut text = ObjSection {
+        name: ".text".to_string(),
+        kind: ObjSectionKind::Code,
+        address: 0,
+        size: 0,
+        data: vec![],
+        align: 4,
+        elf_index: 1,
+        relocations: Default::default(),
+        original_address: 0,
+        file_offset: 0,
+        section_known: true,
+        splits: Default::default(),
+    };
+    let mut bss = ObjSection {
+        name: ".bss".to_string(),
+        kind: ObjSectionKind::Bss,
+        elf_index: 2,
+        ..text.clone()
+    };
+
+    let mut out_symbols = Vec::with_capacity(symbols.len());
+    for &symbol_index in symbols {
+        let symbol = &obj.symbols[symbol_index];
+        let (section_index, section, size, align) = if is_function(obj, symbol) {
+            (0, &mut text, STUB_INSTRUCTION.len() as u32, 4)
+        } else {
+            let size = if symbol.size_known && symbol.size > 0 { symbol.size as u32 } else { 4 };
+            (1, &mut bss, size, symbol.align.unwrap_or(4))
+        };
+        let address = align_up(section.size as u32, align);
+        if section.kind == ObjSectionKind::Code {
+            section.data.resize(address as usize, 0);
+            section.data.extend_from_slice(&STUB_INSTRUCTION);
+        }
+        section.size = (address + size) as u64;
+        section.align = section.align.max(align as u64);
+        out_symbols.push(ObjSymbol {
+            name: symbol.name.clone(),
+            demangled_name: symbol.demangled_name.clone(),
+            address: address as u64,
+            section: Some(section_index),
+            size: size as u64,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::Weak),
+            kind: if section_index == 0 { ObjSymbolKind::Function } else { ObjSymbolKind::Object },
+            align: symbol.align,
+            data_kind: symbol.data_kind,
+            ..Default::default()
+        });
+    }
+
+    out_obj.sections.push(text);
+    out_obj.sections.push(bss);
+    for symbol in out_symbols {
+        out_obj.symbols.add_direct(symbol).with_context(|| {
+            format!("Failed to add interface symbol for module {}", obj.module_id)
+        })?;
+    }
+    Ok(out_obj)
+}
+
+/// Generates a C header declaring the given module symbols.
+/// Symbols that aren't valid C identifiers (e.g. mangled C++ names) are listed in comments.
+pub fn interface_header(obj: &ObjInfo, symbols: &[SymbolIndex]) -> Result<String> {
+    let guard = format!("{}_INTERFACE_H", obj.name)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect::<String>();
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Symbols referenced by other modules from {} (module ID {})",
+        obj.name, obj.module_id
+    )?;
+    writeln!(out, "// Generated by decomp-toolkit. Do not edit.")?;
+    writeln!(out, "#ifndef {}", guard)?;
+    writeln!(out, "#define {}\n", guard)?;
+    writeln!(out, "#ifdef __cplusplus\nextern \"C\" {{\n#endif\n")?;
+    for &symbol_index in symbols {
+        let symbol = &obj.symbols[symbol_index];
+        let declaration = if is_function(obj, symbol) {
+            format!("extern void {}();", symbol.name)
+        } else if symbol.size_known && symbol.size > 0 {
+            format!("extern unsigned char {}[{:#X}];", symbol.name, symbol.size)
+        } else {
+            format!("extern unsigned char {}[];", symbol.name)
+        };
+        if is_c_identifier(&symbol.name) {
+            writeln!(out, "{}", declaration)?;
+        } else if let Some(demangled) = &symbol.demangled_name {
+            writeln!(out, "// {} ({})", demangled, symbol.name)?;
+        } else {
+            writeln!(out, "// {}", declaration)?;
+        }
+    }
+    writeln!(out, "\n#ifdef __cplusplus\n}}\n#endif\n")?;
+    writeln!(out, "#endif // {}", guard)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{obj::ObjRelocKind, util::rel::RelReloc};
+
+    fn section(name: &str, kind: ObjSectionKind, elf_index: usize, data: Vec<u8>) -> ObjSection {
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address: 0,
+            size: data.len() as u64,
+            data,
+            align: 4,
+            elf_index,
+            relocations: Default::default(),
+            original_address: 0,
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    fn symbol(
+        name: &str,
+        section: usize,
+        address: u64,
+        size: u64,
+        kind: ObjSymbolKind,
+    ) -> ObjSymbol {
+        ObjSymbol {
+            name: name.to_string(),
+            address,
+            section: Some(section),
+            size,
+            size_known: size > 0,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn reloc(kind: ObjRelocKind, module_id: u32, target_section: u8, addend: u32) -> RelReloc {
+        RelReloc {
+            kind,
+            section: 1,
+            address: 0,
+            module_id,
+            target_section,
+            addend,
+            original_section: 1,
+            original_target_section: target_section,
+        }
+    }
+
+    /// Module 1 exports symbols referenced by module 2.
+    fn modules() -> (ObjInfo, ObjInfo) {
+        let symbols = vec![
+            symbol("fn_a", 0, 0, 4, ObjSymbolKind::Function),
+            ObjSymbol {
+                demangled_name: Some("Foo<int>::Foo()".to_string()),
+                ..symbol("__ct__8Foo<int>Fv", 0, 4, 4, ObjSymbolKind::Function)
+            },
+            // Label created for a relocation target
+            symbol("helper", 0, 8, 0, ObjSymbolKind::Unknown),
+            ObjSymbol { align: Some(8), ..symbol("data_a", 1, 0, 8, ObjSymbolKind::Object) },
+            symbol("unused", 1, 8, 4, ObjSymbolKind::Object),
+            symbol("table", 1, 0x10, 0, ObjSymbolKind::Unknown),
+            symbol("@stringBase0", 1, 0x14, 4, ObjSymbolKind::Object),
+        ];
+        let sections = vec![
+            section(".text", ObjSectionKind::Code, 1, vec![0; 0xC]),
+            section(".data", ObjSectionKind::Data, 2, vec![0; 0x18]),
+        ];
+        let mut module = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "a".to_string(),
+            symbols,
+            sections,
+        );
+        module.module_id = 1;
+        // Self-references aren't part of the interface
+        module.unresolved_relocations = vec![reloc(ObjRelocKind::Absolute, 1, 2, 8)];
+
+        let mut other = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "b".to_string(),
+            vec![],
+            vec![section(".text", ObjSectionKind::Code, 1, vec![0; 0x20])],
+        );
+        other.module_id = 2;
+        other.unresolved_relocations = vec![
+            reloc(ObjRelocKind::PpcRel24, 1, 1, 0),
+            reloc(ObjRelocKind::PpcRel24, 1, 1, 4),
+            reloc(ObjRelocKind::Absolute, 1, 1, 8),
+            reloc(ObjRelocKind::PpcAddr16Ha, 1, 2, 0),
+            // Inside data_a
+            reloc(ObjRelocKind::Absolute, 1, 2, 4),
+            reloc(ObjRelocKind::Absolute, 1, 2, 0x10),
+            reloc(ObjRelocKind::Absolute, 1, 2, 0x14),
+            // Missing section
+            reloc(ObjRelocKind::Absolute, 1, 5, 0),
+            // DOL
+            reloc(ObjRelocKind::PpcRel24, 0, 0, 0x80003100),
+        ];
+        (module, other)
+    }
+
+    #[test]
+    fn test_interface_symbols() {
+        let (module, other) = modules();
+        let symbols = interface_symbols(&module, &[&module, &other]).unwrap();
+        let names = symbols.iter().map(|&i| module.symbols[i].name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, [
+            "fn_a",
+            "__ct__8Foo<int>Fv",
+            "helper",
+            "data_a",
+            "table",
+            "@stringBase0"
+        ]);
+        assert!(interface_symbols(&other, &[&module, &other]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_interface_obj() {
+        let (module, other) = modules();
+        let symbols = interface_symbols(&module, &[&module, &other]).unwrap();
+        let obj = interface_obj(&module, &symbols).unwrap();
+        assert_eq!(obj.name, "a_interface");
+
+        let text = &obj.sections[0];
+        assert_eq!((text.name.as_str(), text.kind), (".text", ObjSectionKind::Code));
+        assert_eq!(text.data, STUB_INSTRUCTION.repeat(3));
+        let bss = &obj.sections[1];
+        assert_eq!((bss.name.as_str(), bss.kind), (".bss", ObjSectionKind::Bss));
+        assert_eq!((bss.size, bss.align), (0x10, 8));
+        assert!(bss.data.is_empty());
+
+        let expected = [
+            ("fn_a", 0, 0, 4, ObjSymbolKind::Function),
+            ("__ct__8Foo<int>Fv", 0, 4, 4, ObjSymbolKind::Function),
+            ("helper", 0, 8, 4, ObjSymbolKind::Function),
+            ("data_a", 1, 0, 8, ObjSymbolKind::Object),
+            ("table", 1, 8, 4, ObjSymbolKind::Object),
+            ("@stringBase0", 1, 0xC, 4, ObjSymbolKind::Object),
+        ];
+        assert_eq!(obj.symbols.count(), expected.len());
+        for (symbol, (name, section, address, size, kind)) in obj.symbols.iter().zip(expected) {
+            assert_eq!(symbol.name, name);
+            assert_eq!(
+                (symbol.section, symbol.address, symbol.size, symbol.kind),
+                (Some(section), address, size, kind),
+                "{}",
+                name
+            );
+            assert!(symbol.size_known && symbol.flags.is_global() && symbol.flags.is_weak());
+        }
+    }
+
+    #[test]
+    fn test_interface_header() {
+        let (module, other) = modules();
+        let symbols = interface_symbols(&module, &[&module, &other]).unwrap();
+        let header = interface_header(&module, &symbols).unwrap();
+        let declarations = header
+            .lines()
+            .skip_while(|l| !l.starts_with("extern \"C\""))
+            .skip(3)
+            .take_while(|l| !l.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(declarations, [
+            "extern void fn_a();",
+            "// Foo<int>::Foo() (__ct__8Foo<int>Fv)",
+            "extern void helper();",
+            "extern unsigned char data_a[0x8];",
+            "extern unsigned char table[];",
+            "// extern unsigned char @stringBase0[0x4];",
+        ]);
+        assert!(header.contains("#ifndef A_INTERFACE_H\n#define A_INTERFACE_H\n"));
+        assert!(header.ends_with("#endif // A_INTERFACE_H\n"));
+    }
+}
@@ -1,35 +1,21 @@
-use std::path::PathBuf;
+use std::{collections::HashSet, fmt::Write, path::PathBuf};
 
 use anyhow::{bail, Result};
 use itertools::Itertools;
 use path_slash::PathBufExt;
 
 use crate::{
-    obj::{ObjInfo, ObjKind},
+    obj::{ObjInfo, ObjKind, ObjSectionKind, ObjSymbolKind},
     util::align_up,
 };
 
 const LCF_TEMPLATE: &str = include_str!("../../assets/ldscript.lcf");
 const LCF_PARTIAL_TEMPLATE: &str = include_str!("../../assets/ldscript_partial.lcf");
 
-pub fn generate_ldscript(
-    obj: &ObjInfo,
-    template: Option<&str>,
-    force_active: &[String],
-) -> Result<String> {
-    if obj.kind == ObjKind::Relocatable {
-        return generate_ldscript_partial(obj, template, force_active);
-    }
-
-    let origin = obj.sections.iter().map(|(_, s)| s.address).min().unwrap();
-    let stack_size = match (obj.stack_address, obj.stack_end) {
-        (Some(stack_address), Some(stack_end)) => stack_address - stack_end,
-        _ => 65535, // default
-    };
-
-    // Guess section alignment
+/// Guess section alignment from the gaps between sections.
+fn guess_section_alignments(obj: &ObjInfo, origin: u32) -> Result<Vec<u32>> {
     let mut alignments = Vec::with_capacity(obj.sections.count());
-    let mut last_section_end = origin as u32;
+    let mut last_section_end = origin;
     for (_, section) in obj.sections.iter() {
         let section_start = section.address as u32;
         let mut align = 0x20;
@@ -47,7 +33,36 @@ pub fn generate_ldscript(
         last_section_end = section_start + section.size as u32;
         alignments.push(align);
     }
+    Ok(alignments)
+}
+
+fn force_active_symbols(obj: &ObjInfo, force_active: &[String]) -> Vec<String> {
+    let mut force_active = force_active.to_vec();
+    for symbol in obj.symbols.iter() {
+        if symbol.flags.is_force_active() && symbol.flags.is_global() && !symbol.flags.is_no_write()
+        {
+            force_active.push(symbol.name.clone());
+        }
+    }
+    force_active
+}
+
+pub fn generate_ldscript(
+    obj: &ObjInfo,
+    template: Option<&str>,
+    force_active: &[String],
+) -> Result<String> {
+    if obj.kind == ObjKind::Relocatable {
+        return generate_ldscript_partial(obj, template, force_active);
+    }
+
+    let origin = obj.sections.iter().map(|(_, s)| s.address).min().unwrap();
+    let stack_size = match (obj.stack_address, obj.stack_end) {
+        (Some(stack_address), Some(stack_end)) => stack_address - stack_end,
+        _ => 65535, // default
+    };
 
+    let alignments = guess_section_alignments(obj, origin as u32)?;
     let section_defs = obj
         .sections
         .iter()
@@ -61,13 +76,7 @@ pub fn generate_ldscript(
         force_files.push(obj_path.file_name().unwrap().to_str().unwrap().to_string());
     }
 
-    let mut force_active = force_active.to_vec();
-    for symbol in obj.symbols.iter() {
-        if symbol.flags.is_force_active() && symbol.flags.is_global() && !symbol.flags.is_no_write()
-        {
-            force_active.push(symbol.name.clone());
-        }
-    }
+    let force_active = force_active_symbols(obj, force_active);
 
     // Hack to handle missing .sbss2 section... what's the proper way?
     let last_section_name = obj.sections.iter().next_back().unwrap().1.name.clone();
@@ -96,13 +105,7 @@ pub fn generate_ldscript_partial(
         force_files.push(obj_path.file_name().unwrap().to_str().unwrap().to_string());
     }
 
-    let mut force_active = force_active.to_vec();
-    for symbol in obj.symbols.iter() {
-        if symbol.flags.is_force_active() && symbol.flags.is_global() && !symbol.flags.is_no_write()
-        {
-            force_active.push(symbol.name.clone());
-        }
-    }
+    let force_active = force_active_symbols(obj, force_active);
 
     let out = template
         .unwrap_or(LCF_PARTIAL_TEMPLATE)
@@ -110,6 +113,191 @@ pub fn generate_ldscript_partial(
     Ok(out)
 }
 
+/// Generates a GNU ld linker script with the same layout and link order as `generate_ldscript`.
+/// Object files are matched by their path relative to the split `obj` directory.
+pub fn generate_ldscript_gnu(obj: &ObjInfo, force_active: &[String]) -> Result<String> {
+    let force_active = force_active_symbols(obj, force_active);
+
+    // Units containing force active symbols are kept in their entirety
+    let mut keep_units = HashSet::new();
+    for name in &force_active {
+        for (_, symbol) in obj.symbols.for_name(name) {
+            let Some(section_index) = symbol.section else {
+                continue;
+            };
+            if let Some((_, split)) =
+                obj.sections[section_index].splits.for_address(symbol.address as u32)
+            {
+                keep_units.insert(split.unit.as_str());
+            }
+        }
+    }
+
+    let executable = obj.kind == ObjKind::Executable;
+    let (origin, alignments) = if executable {
+        let origin = obj.sections.iter().map(|(_, s)| s.address).min().unwrap() as u32;
+        (origin, guess_section_alignments(obj, origin)?)
+    } else {
+        (0, vec![])
+    };
+
+    let mut out = String::new();
+    if executable {
+        let entry = obj.entry.and_then(|entry| {
+            let (section_index, _) = obj.sections.at_address(entry as u32).ok()?;
+            obj.symbols
+                .at_section_address(section_index, entry as u32)
+                .find(|(_, s)| s.kind == ObjSymbolKind::Function)
+                .map(|(_, s)| s.name.as_str())
+        });
+        if let Some(entry) = entry {
+            writeln!(out, "ENTRY({})", entry)?;
+        }
+    }
+    if !force_active.is_empty() {
+        writeln!(out, "EXTERN({})", force_active.join(" "))?;
+    }
+    writeln!(out, "\nSECTIONS\n{{")?;
+    if executable {
+        writeln!(out, "    . = {:#X};", origin)?;
+    }
+    for (section_index, section) in obj.sections.iter() {
+        if executable {
+            writeln!(
+                out,
+                "\n    {} ALIGN({:#X}) :\n    {{",
+                section.name, alignments[section_index]
+            )?;
+        } else {
+            writeln!(out, "\n    {} :\n    {{", section.name)?;
+        }
+        let keep_section =
+            matches!(section.name.as_str(), ".init" | ".ctors" | ".dtors" | "extab" | "extabindex");
+        let mut written = HashSet::new();
+        for (_, split) in section.splits.iter() {
+            if split.skip || split.common {
+                continue;
+            }
+            let input_name = split.rename.as_deref().unwrap_or(&section.name);
+            if !written.insert((split.unit.as_str(), input_name)) {
+                continue;
+            }
+            let pattern =
+                format!("*/{}({})", obj_path_for_unit(&split.unit).to_slash_lossy(), input_name);
+            if keep_section || keep_units.contains(split.unit.as_str()) {
+                writeln!(out, "        KEEP({})", pattern)?;
+            } else {
+                writeln!(out, "        {}", pattern)?;
+            }
+        }
+        if section.name == ".bss" {
+            writeln!(out, "        *(COMMON)")?;
+        }
+        if executable {
+            write_linker_tables(&mut out, obj, section_index)?;
+        }
+        writeln!(out, "    }}")?;
+    }
+
+    if executable {
+        writeln!(out)?;
+        for (_, section) in obj.sections.iter() {
+            let name = section.name.as_str();
+            let (start, end) = section_symbols(name);
+            writeln!(out, "    {} = ADDR({});", start, name)?;
+            writeln!(out, "    {}_rom = LOADADDR({});", start, name)?;
+            writeln!(out, "    {} = ADDR({}) + SIZEOF({});", end, name, name)?;
+        }
+        if obj.sections.iter().any(|(_, s)| s.name == ".sdata") {
+            writeln!(out, "    _SDA_BASE_ = ADDR(.sdata) + 0x8000;")?;
+        }
+        if obj.sections.iter().any(|(_, s)| s.name == ".sdata2") {
+            writeln!(out, "    _SDA2_BASE_ = ADDR(.sdata2) + 0x8000;")?;
+        }
+
+        let stack_size = match (obj.stack_address, obj.stack_end) {
+            (Some(stack_address), Some(stack_end)) => stack_address - stack_end,
+            _ => 65535, // default
+        };
+        let last_section_name = &obj.sections.iter().next_back().unwrap().1.name;
+        writeln!(out, "\n    _stack_end = ADDR({0}) + SIZEOF({0});", last_section_name)?;
+        writeln!(out, "    _stack_addr = (_stack_end + {:#X} + 0x7) & ~0x7;", stack_size)?;
+        writeln!(out, "    _db_stack_addr = (_stack_addr + 0x2000);")?;
+        writeln!(out, "    _db_stack_end = _stack_addr;")?;
+        writeln!(out, "    __ArenaLo = (_db_stack_addr + 0x1f) & ~0x1f;")?;
+        writeln!(out, "    __ArenaHi = {:#X};", obj.arena_hi.unwrap_or(0x81700000))?;
+    }
+    writeln!(out, "}}")?;
+    Ok(out)
+}
+
+/// Returns the names of the start and end symbols defined for a section (`_f_text`, `_e_text`).
+fn section_symbols(name: &str) -> (String, String) {
+    match name.strip_prefix('.') {
+        Some(trimmed) => (format!("_f_{}", trimmed), format!("_e_{}", trimmed)),
+        None => (format!("_f{}", name), format!("_e{}", name)),
+    }
+}
+
+/// Emits the runtime initialization tables that mwld generates, if the original
+/// object placed them in this section.
+///
+/// Like mwld, every section has an entry, including the one containing the table.
+/// Its size can't be taken from `SIZEOF` while it's being laid out, so the section's
+/// start and end symbols (defined after `SECTIONS`) are used instead.
+fn write_linker_tables(out: &mut String, obj: &ObjInfo, section_index: usize) -> Result<()> {
+    let in_section =
+        |name: &str| obj.symbols.for_name(name).any(|(_, s)| s.section == Some(section_index));
+    let size = |idx: usize, name: &str| {
+        if idx == section_index {
+            let (start, end) = section_symbols(name);
+            format!("{} - {}", end, start)
+        } else {
+            format!("SIZEOF({})", name)
+        }
+    };
+    if in_section("_rom_copy_info") {
+        writeln!(out, "        . = ALIGN(4);\n        _rom_copy_info = .;")?;
+        for (idx, section) in obj.sections.iter().filter(|(_, s)| s.kind != ObjSectionKind::Bss) {
+            writeln!(
+                out,
+                "        LONG(LOADADDR({0})); LONG(ADDR({0})); LONG({1});",
+                section.name,
+                size(idx, &section.name)
+            )?;
+        }
+        writeln!(out, "        LONG(0); LONG(0); LONG(0);")?;
+    }
+    if in_section("_bss_init_info") {
+        writeln!(out, "        . = ALIGN(4);\n        _bss_init_info = .;")?;
+        for (idx, section) in obj.sections.iter().filter(|(_, s)| s.kind == ObjSectionKind::Bss) {
+            writeln!(
+                out,
+                "        LONG(ADDR({})); LONG({});",
+                section.name,
+                size(idx, &section.name)
+            )?;
+        }
+        writeln!(out, "        LONG(0); LONG(0);")?;
+    }
+    if in_section("_eti_init_info") {
+        writeln!(out, "        . = ALIGN(4);\n        _eti_init_info = .;")?;
+        let find_section = |name: &str| obj.sections.iter().find(|(_, s)| s.name == name);
+        if let (Some((extab_idx, _)), Some((text_idx, _))) =
+            (find_section("extabindex"), find_section(".text"))
+        {
+            writeln!(
+                out,
+                "        LONG(ADDR(extabindex)); LONG(ADDR(extabindex) + {});",
+                size(extab_idx, "extabindex")
+            )?;
+            writeln!(out, "        LONG(ADDR(.text)); LONG({});", size(text_idx, ".text"))?;
+        }
+        writeln!(out, "        LONG(0); LONG(0); LONG(0); LONG(0);")?;
+    }
+    Ok(())
+}
+
 pub fn obj_path_for_unit(unit: &str) -> PathBuf {
     PathBuf::from_slash(unit).with_extension("").with_extension("o")
 }
@@ -117,3 +305,71 @@ pub fn obj_path_for_unit(unit: &str) -> PathBuf {
 pub fn asm_path_for_unit(unit: &str) -> PathBuf {
     PathBuf::from_slash(unit).with_extension("").with_extension("s")
 }
+
+pub fn c_path_for_unit(unit: &str) -> PathBuf {
+    PathBuf::from_slash(unit).with_extension("").with_extension("c")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{ObjArchitecture, ObjRelocations, ObjSection, ObjSplits, ObjSymbol};
+
+    fn section(name: &str, kind: ObjSectionKind, address: u64, size: u64) -> ObjSection {
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address,
+            size,
+            data: vec![],
+            align: 0x20,
+            elf_index: 0,
+            relocations: ObjRelocations::default(),
+            original_address: 0,
+            file_offset: 0,
+            section_known: true,
+            splits: ObjSplits::default(),
+        }
+    }
+
+    #[test]
+    fn test_linker_tables_include_containing_section() {
+        let sections = vec![
+            section(".init", ObjSectionKind::Code, 0x80003100, 0x100),
+            section(".text", ObjSectionKind::Code, 0x80003200, 0x100),
+            section(".bss", ObjSectionKind::Bss, 0x80003300, 0x100),
+        ];
+        let symbols = ["_rom_copy_info", "_bss_init_info"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ObjSymbol {
+                name: name.to_string(),
+                address: 0x80003180 + i as u64 * 0x20,
+                section: Some(0),
+                kind: ObjSymbolKind::Object,
+                ..Default::default()
+            })
+            .collect();
+        let obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main.dol".to_string(),
+            symbols,
+            sections,
+        );
+        let script = generate_ldscript_gnu(&obj, &[]).unwrap();
+        let expected = [
+            "        _rom_copy_info = .;",
+            "        LONG(LOADADDR(.init)); LONG(ADDR(.init)); LONG(_e_init - _f_init);",
+            "        LONG(LOADADDR(.text)); LONG(ADDR(.text)); LONG(SIZEOF(.text));",
+            "        LONG(0); LONG(0); LONG(0);",
+            "        . = ALIGN(4);",
+            "        _bss_init_info = .;",
+            "        LONG(ADDR(.bss)); LONG(SIZEOF(.bss));",
+            "        LONG(0); LONG(0);",
+        ]
+        .join("\n");
+        assert!(script.contains(&expected), "{}", script);
+        assert!(script.contains("    _e_init = ADDR(.init) + SIZEOF(.init);"));
+    }
+}
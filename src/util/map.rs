@@ -1,14 +1,15 @@
 #![allow(dead_code)]
 #![allow(unused_mut)]
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, HashMap, HashSet},
     hash::Hash,
-    io::BufRead,
+    io::{BufRead, Write},
     mem::{replace, take},
     path::Path,
 };
 
-use anyhow::{anyhow, bail, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 use cwdemangle::{demangle, DemangleOptions};
 use flagset::FlagSet;
 use itertools::Itertools;
@@ -18,8 +19,8 @@ use regex::{Captures, Regex};
 
 use crate::{
     obj::{
-        ObjInfo, ObjKind, ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
-        ObjUnit,
+        ObjInfo, ObjKind, ObjSectionKind, ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags,
+        ObjSymbolKind, ObjUnit, SymbolIndex,
     },
     util::{file::map_file, nested::NestedVec},
 };
@@ -75,7 +76,6 @@ macro_rules! static_regex {
 }
 
 // Link map
-static_regex!(LINK_MAP_START, "^Link map of (?P<entry>.*)$");
 static_regex!(
     LINK_MAP_ENTRY,
     "^\\s*(?P<depth>\\d+)] (?P<sym>.*) \\((?P<type>.*),(?P<vis>.*)\\) found in (?P<tu>.*)$"
@@ -90,25 +90,142 @@ static_regex!(
 );
 static_regex!(LINK_MAP_EXTERN_SYMBOL, "^\\s*>>> SYMBOL NOT FOUND: (.*)$");
 
-// Section layout
-static_regex!(SECTION_LAYOUT_START, "^(?P<section>.*) section layout$");
-static_regex!(
-    SECTION_LAYOUT_SYMBOL,
-    "^\\s*(?P<rom_addr>[0-9A-Fa-f]+|UNUSED)\\s+(?P<size>[0-9A-Fa-f]+)\\s+(?P<addr>[0-9A-Fa-f]{8}|\\.{8})(?:\\s+(?P<offset>[0-9A-Fa-f]{8}|\\.{8}))?\\s+(?P<align>\\d+)?\\s*(?P<sym>.*?)(?:\\s+\\(entry of (?P<entry_of>.*?)\\))?\\s+(?P<tu>.*)$"
-);
-static_regex!(
-    SECTION_LAYOUT_HEADER,
-    "^(\\s*Starting\\s+Virtual\\s*(File\\s*)?|\\s*address\\s+Size\\s+address\\s*(offset\\s*)?|\\s*-----------------------(----------)?\\s*)$"
-);
+/// Top-level blocks of a CodeWarrior map file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum MapHeading {
+    LinkMap {
+        entry: String,
+    },
+    SectionLayout {
+        section: String,
+    },
+    MemoryMap,
+    LinkerGeneratedSymbols,
+    /// Blocks we don't use, e.g. `Mixed Mode Islands` and `Branch Islands` (Wii)
+    Ignored,
+}
+
+fn parse_heading(line: &str) -> Option<MapHeading> {
+    let line = line.trim();
+    if let Some(entry) = line.strip_prefix("Link map of ") {
+        Some(MapHeading::LinkMap { entry: entry.to_string() })
+    } else if let Some(section) = line.strip_suffix(" section layout") {
+        Some(MapHeading::SectionLayout { section: section.to_string() })
+    } else if line == "Memory map:" {
+        Some(MapHeading::MemoryMap)
+    } else if line == "Linker generated symbols:" {
+        Some(MapHeading::LinkerGeneratedSymbols)
+    } else if matches!(
+        line,
+        "Mixed Mode Islands"
+            | "Branch Islands"
+            | "Linktime size-decreasing optimizations"
+            | "Linktime size-increasing optimizations"
+    ) {
+        Some(MapHeading::Ignored)
+    } else {
+        None
+    }
+}
+
+/// Column layout of section layout entries.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+enum SectionLayoutFormat {
+    /// No header seen, infer from each entry
+    #[default]
+    Unknown,
+    /// GC 1.x: `Starting address, Size, Virtual address`
+    NoFileOffset,
+    /// GC 2.x, 3.0 and Wii: `Starting address, Size, Virtual address, File offset`
+    FileOffset,
+}
+
+#[derive(Debug)]
+struct SectionLayoutEntry<'a> {
+    unused: bool,
+    size: u32,
+    address: Option<u32>,
+    file_offset: Option<u32>,
+    align: Option<u32>,
+    name: &'a str,
+    entry_of: Option<&'a str>,
+    unit: &'a str,
+}
+
+/// Splits off the next whitespace-delimited token, keeping the remainder untrimmed.
+fn split_token(s: &str) -> (&str, &str) {
+    let s = s.trim_start_matches(' ');
+    match s.find(char::is_whitespace) {
+        Some(idx) => (&s[..idx], &s[idx..]),
+        None => (s, ""),
+    }
+}
+
+#[inline]
+fn is_hex_column(s: &str) -> bool { !s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit()) }
+
+/// Parses a hex column, where `........` means the value is not present.
+fn parse_hex_column(s: &str) -> Result<Option<u32>> {
+    if !s.is_empty() && s.chars().all(|c| c == '.') {
+        Ok(None)
+    } else if is_hex_column(s) {
+        Ok(Some(u32::from_str_radix(s, 16)?))
+    } else {
+        bail!("Invalid hex column '{s}'")
+    }
+}
+
+fn parse_section_layout_entry(
+    line: &str,
+    format: SectionLayoutFormat,
+) -> Result<SectionLayoutEntry> {
+    let (rom_address, rest) = split_token(line);
+    let unused = rom_address == "UNUSED";
+    if !unused {
+        parse_hex_column(rom_address)?;
+    }
+    let (size, rest) = split_token(rest);
+    let size = parse_hex_column(size)?.ok_or_else(|| anyhow!("Missing size"))?;
+    let (address, mut rest) = split_token(rest);
+    let address = parse_hex_column(address)?;
 
-// Memory map
-static_regex!(MEMORY_MAP_START, "^\\s*Memory map:\\s*$");
-static_regex!(MEMORY_MAP_HEADER, "^(\\s*Starting Size\\s+File\\s*|\\s*address\\s+Offset\\s*)$");
-static_regex!(MEMORY_MAP_ENTRY, "^\\s*(?P<section>\\S+)\\s+(?P<addr>[0-9A-Fa-f]+|\\.{0,8})\\s+(?P<size>[0-9A-Fa-f]+|\\.{1,8})\\s+(?P<offset>[0-9A-Fa-f]+|\\.{1,8})\\s*$");
+    let (token, remain) = split_token(rest);
+    // UNUSED entries omit the file offset column, even when the header includes it
+    let has_file_offset = format != SectionLayoutFormat::NoFileOffset
+        && token.len() == 8
+        && (is_hex_column(token) || token.chars().all(|c| c == '.'))
+        && !split_token(remain).0.is_empty();
+    let mut file_offset = None;
+    if has_file_offset {
+        file_offset = parse_hex_column(token)?;
+        rest = remain;
+    }
+
+    let mut align = None;
+    let (token, remain) = split_token(rest);
+    if !token.is_empty() && token.chars().all(|c| c.is_ascii_digit()) && !remain.trim().is_empty() {
+        align = Some(token.parse()?);
+        rest = remain;
+    }
 
-// Linker generated symbols
-static_regex!(LINKER_SYMBOLS_START, "^\\s*Linker generated symbols:\\s*$");
-static_regex!(LINKER_SYMBOL_ENTRY, "^\\s*(?P<name>\\S+)\\s+(?P<addr>[0-9A-Fa-f]+|\\.{0,8})\\s*$");
+    // The unit is usually separated from the symbol name by a tab,
+    // but some linker versions only use spaces.
+    let rest = rest.trim();
+    let (name, unit) = if let Some((name, unit)) = rest.split_once('\t') {
+        (name.trim(), unit.trim())
+    } else if let Some(idx) = rest.find(" (entry of ") {
+        let end = rest[idx..].find(')').map(|i| idx + i + 1).unwrap_or(rest.len());
+        (&rest[..end], rest[end..].trim())
+    } else {
+        let (name, unit) = split_token(rest);
+        (name, unit.trim())
+    };
+    let (name, entry_of) = match name.split_once(" (entry of ") {
+        Some((name, entry_of)) => (name.trim(), Some(entry_of.trim_end_matches(')'))),
+        None => (name, None),
+    };
+    Ok(SectionLayoutEntry { unused, size, address, file_offset, align, name, entry_of, unit })
+}
 
 #[derive(Debug)]
 pub struct SectionInfo {
@@ -158,6 +275,7 @@ struct SectionLayoutState {
     symbols: BTreeMap<u32, Vec<SymbolEntry>>,
     has_link_map: bool,
     last_address: u32,
+    format: SectionLayoutFormat,
 }
 
 enum ProcessMapState {
@@ -166,6 +284,7 @@ enum ProcessMapState {
     SectionLayout(SectionLayoutState),
     MemoryMap,
     LinkerGeneratedSymbols,
+    Ignored,
 }
 
 struct StateMachine {
@@ -175,83 +294,66 @@ struct StateMachine {
 }
 
 impl StateMachine {
-    fn process_line(&mut self, line: String) -> Result<()> {
+    fn process_line(&mut self, line: &str) -> Result<()> {
         if line.trim().is_empty() {
             return Ok(());
         }
-        match &mut self.state {
-            ProcessMapState::None => {
-                if let Some(captures) = LINK_MAP_START.captures(&line) {
-                    self.result.entry_point = captures["entry"].to_string();
-                    self.switch_state(ProcessMapState::LinkMap(Default::default()))?;
-                } else if let Some(captures) = SECTION_LAYOUT_START.captures(&line) {
-                    self.switch_state(ProcessMapState::SectionLayout(SectionLayoutState {
-                        current_section: captures["section"].to_string(),
+        if let Some(heading) = parse_heading(line) {
+            let new_state = match heading {
+                MapHeading::LinkMap { entry } => {
+                    self.result.entry_point = entry;
+                    ProcessMapState::LinkMap(Default::default())
+                }
+                MapHeading::SectionLayout { section } => {
+                    ProcessMapState::SectionLayout(SectionLayoutState {
+                        current_section: section,
                         has_link_map: self.has_link_map,
                         ..Default::default()
-                    }))?;
-                } else if MEMORY_MAP_START.is_match(&line) {
-                    self.switch_state(ProcessMapState::MemoryMap)?;
-                } else if LINKER_SYMBOLS_START.is_match(&line) {
-                    self.switch_state(ProcessMapState::LinkerGeneratedSymbols)?;
-                } else {
-                    bail!("Unexpected line while processing map: '{line}'");
+                    })
                 }
-            }
+                MapHeading::MemoryMap => ProcessMapState::MemoryMap,
+                MapHeading::LinkerGeneratedSymbols => ProcessMapState::LinkerGeneratedSymbols,
+                MapHeading::Ignored => ProcessMapState::Ignored,
+            };
+            return self.switch_state(new_state);
+        }
+        match &mut self.state {
+            ProcessMapState::None => bail!("Unexpected line while processing map: '{line}'"),
             ProcessMapState::LinkMap(ref mut state) => {
-                if let Some(captures) = LINK_MAP_ENTRY.captures(&line) {
+                if let Some(captures) = LINK_MAP_ENTRY.captures(line) {
                     StateMachine::process_link_map_entry(captures, state, &mut self.result)?;
-                } else if let Some(captures) = LINK_MAP_ENTRY_GENERATED.captures(&line) {
+                } else if let Some(captures) = LINK_MAP_ENTRY_GENERATED.captures(line) {
                     StateMachine::process_link_map_generated(captures, state, &mut self.result)?;
-                } else if LINK_MAP_ENTRY_DUPLICATE.is_match(&line)
-                    || LINK_MAP_EXTERN_SYMBOL.is_match(&line)
+                } else if LINK_MAP_ENTRY_DUPLICATE.is_match(line)
+                    || LINK_MAP_EXTERN_SYMBOL.is_match(line)
                 {
                     // Ignore
-                } else if let Some(captures) = SECTION_LAYOUT_START.captures(&line) {
-                    self.switch_state(ProcessMapState::SectionLayout(SectionLayoutState {
-                        current_section: captures["section"].to_string(),
-                        has_link_map: self.has_link_map,
-                        ..Default::default()
-                    }))?;
-                } else if MEMORY_MAP_START.is_match(&line) {
-                    self.switch_state(ProcessMapState::MemoryMap)?;
-                } else if LINKER_SYMBOLS_START.is_match(&line) {
-                    self.switch_state(ProcessMapState::LinkerGeneratedSymbols)?;
                 } else {
                     bail!("Unexpected line while processing map: '{line}'");
                 }
             }
             ProcessMapState::SectionLayout(ref mut state) => {
-                if let Some(captures) = SECTION_LAYOUT_SYMBOL.captures(&line) {
-                    StateMachine::section_layout_entry(captures, state, &self.result)?;
-                } else if let Some(captures) = SECTION_LAYOUT_START.captures(&line) {
-                    self.switch_state(ProcessMapState::SectionLayout(SectionLayoutState {
-                        current_section: captures["section"].to_string(),
-                        has_link_map: self.has_link_map,
-                        ..Default::default()
-                    }))?;
-                } else if SECTION_LAYOUT_HEADER.is_match(&line) {
-                    // Ignore
-                } else if MEMORY_MAP_START.is_match(&line) {
-                    self.switch_state(ProcessMapState::MemoryMap)?;
-                } else if LINKER_SYMBOLS_START.is_match(&line) {
-                    self.switch_state(ProcessMapState::LinkerGeneratedSymbols)?;
+                let trimmed = line.trim();
+                if trimmed.starts_with("Starting") {
+                    state.format = if trimmed.contains("File") {
+                        SectionLayoutFormat::FileOffset
+                    } else {
+                        SectionLayoutFormat::NoFileOffset
+                    };
+                } else if trimmed.starts_with("address") || trimmed.chars().all(|c| c == '-') {
+                    // Header
                 } else {
-                    bail!("Unexpected line while processing map: '{line}'");
+                    let entry = parse_section_layout_entry(line, state.format)?;
+                    StateMachine::section_layout_entry(entry, state, &mut self.result)?;
                 }
             }
             ProcessMapState::MemoryMap => {
-                if let Some(captures) = MEMORY_MAP_ENTRY.captures(&line) {
-                    StateMachine::memory_map_entry(captures, &mut self.result)?;
-                } else if LINKER_SYMBOLS_START.is_match(&line) {
-                    self.switch_state(ProcessMapState::LinkerGeneratedSymbols)?;
-                }
+                StateMachine::memory_map_entry(line, &mut self.result)?;
             }
             ProcessMapState::LinkerGeneratedSymbols => {
-                if let Some(captures) = LINKER_SYMBOL_ENTRY.captures(&line) {
-                    StateMachine::linker_symbol_entry(captures, &mut self.result)?;
-                }
+                StateMachine::linker_symbol_entry(line, &mut self.result)?;
             }
+            ProcessMapState::Ignored => {}
         }
         Ok(())
     }
@@ -549,16 +651,16 @@ impl StateMachine {
     }
 
     fn section_layout_entry(
-        captures: Captures,
+        entry: SectionLayoutEntry,
         state: &mut SectionLayoutState,
-        result: &MapInfo,
+        result: &mut MapInfo,
     ) -> Result<()> {
-        let sym_name = captures["sym"].trim();
+        let sym_name = entry.name;
         if sym_name == "*fill*" {
             return Ok(());
         }
 
-        let tu = captures["tu"].trim().to_string();
+        let tu = entry.unit.to_string();
         if tu == "*fill*" || tu == "Linker Generated Symbol File" {
             return Ok(());
         }
@@ -567,17 +669,29 @@ impl StateMachine {
             Some((_, name)) => name != &tu,
         };
 
-        let (address, unused) = if captures["rom_addr"].trim() == "UNUSED" {
+        let (address, unused) = if entry.unused {
             // Addresses for unused symbols that _start_ a TU
             // are corrected in end_section_layout
             (state.last_address, true)
         } else {
-            let address = u32::from_str_radix(captures["addr"].trim(), 16)?;
+            let address = entry.address.ok_or_else(|| anyhow!("Missing address for {sym_name}"))?;
             state.last_address = address;
             (address, false)
         };
-        let size = u32::from_str_radix(captures["size"].trim(), 16)?;
-        let align = captures.name("align").and_then(|m| m.as_str().trim().parse::<u32>().ok());
+        let size = entry.size;
+        let align = entry.align;
+
+        // Common symbols may be grouped under a `.comm` entry, which marks the start of common BSS
+        if sym_name == ".comm" {
+            if !unused {
+                if result.common_bss_start.is_none() {
+                    log::info!("Found common BSS start @ {:#010X} ({})", address, tu);
+                    result.common_bss_start = Some(address);
+                }
+                state.units.push((address, tu));
+            }
+            return Ok(());
+        }
 
         if is_new_tu || sym_name == state.current_section {
             if !unused {
@@ -639,16 +753,20 @@ impl StateMachine {
         Ok(())
     }
 
-    fn memory_map_entry(captures: Captures, entries: &mut MapInfo) -> Result<()> {
-        let section = &captures["section"];
-        let addr_str = &captures["addr"];
-        if addr_str.is_empty() {
-            // Stripped from DOL
+    fn memory_map_entry(line: &str, entries: &mut MapInfo) -> Result<()> {
+        let tokens = line.split_whitespace().collect_vec();
+        // Non-loaded sections have no address column. Wii maps have additional
+        // ROM / S-Record / binary file columns, which are ignored.
+        let [section, addr_str, size, offset, ..] = tokens[..] else {
+            return Ok(());
+        };
+        if section == "Starting" || section == "address" || !is_hex_column(addr_str) {
+            // Header, or stripped from DOL
             return Ok(());
         }
         let address = u32::from_str_radix(addr_str, 16)?;
-        let size = u32::from_str_radix(&captures["size"], 16)?;
-        let file_offset = u32::from_str_radix(&captures["offset"], 16)?;
+        let size = u32::from_str_radix(size, 16)?;
+        let file_offset = u32::from_str_radix(offset, 16)?;
         // log::info!("Memory map entry: {section} {address:#010X} {size:#010X} {file_offset:#010X}");
         entries.sections.push(SectionInfo {
             name: section.to_string(),
@@ -659,9 +777,15 @@ impl StateMachine {
         Ok(())
     }
 
-    fn linker_symbol_entry(captures: Captures, result: &mut MapInfo) -> Result<()> {
-        let name = &captures["name"];
-        let address = u32::from_str_radix(&captures["addr"], 16)?;
+    fn linker_symbol_entry(line: &str, result: &mut MapInfo) -> Result<()> {
+        let tokens = line.split_whitespace().collect_vec();
+        let [name, addr_str] = tokens[..] else {
+            return Ok(());
+        };
+        if !is_hex_column(addr_str) {
+            return Ok(());
+        }
+        let address = u32::from_str_radix(addr_str, 16)?;
         if address == 0 {
             return Ok(());
         }
@@ -699,9 +823,11 @@ where
         result: MapInfo { common_bss_start, mw_comment_version, ..Default::default() },
         has_link_map: false,
     };
-    for result in reader.lines() {
+    for (line_number, result) in reader.lines().enumerate() {
         match result {
-            Ok(line) => sm.process_line(line)?,
+            Ok(line) => sm
+                .process_line(&line)
+                .with_context(|| format!("Failed to process map line {}", line_number + 1))?,
             Err(e) => return Err(Error::from(e)),
         }
     }
@@ -850,3 +976,506 @@ fn add_symbol(obj: &mut ObjInfo, symbol_entry: &SymbolEntry, section: Option<usi
     )?;
     Ok(())
 }
+
+/// Converts a unit name back to the CodeWarrior `library.a object.o` form.
+fn map_unit_name(unit: &str) -> Cow<str> {
+    match unit.split_once('/') {
+        Some((lib, obj)) if lib.ends_with(".a") => Cow::Owned(format!("{} {}", lib, obj)),
+        _ => Cow::Borrowed(unit),
+    }
+}
+
+fn map_symbol_unit<'a>(obj: &'a ObjInfo, symbol: &ObjSymbol) -> Option<Cow<'a, str>> {
+    let section_index = symbol.section?;
+    match obj.sections[section_index].splits.for_address(symbol.address as u32) {
+        Some((_, split)) => Some(map_unit_name(&split.unit)),
+        None => Some(Cow::Borrowed(&obj.name)),
+    }
+}
+
+#[inline]
+fn map_symbol_kind(symbol: &ObjSymbol) -> &'static str {
+    match symbol.kind {
+        ObjSymbolKind::Function => "func",
+        ObjSymbolKind::Object => "object",
+        ObjSymbolKind::Section => "section",
+        ObjSymbolKind::Unknown => "notype",
+    }
+}
+
+#[inline]
+fn map_symbol_visibility(symbol: &ObjSymbol) -> &'static str {
+    if symbol.flags.is_local() {
+        "local"
+    } else if symbol.flags.is_weak() {
+        "weak"
+    } else {
+        "global"
+    }
+}
+
+/// Whether a symbol should be listed in the map. Skips section symbols and unsized labels.
+#[inline]
+fn is_map_symbol(symbol: &ObjSymbol) -> bool {
+    match symbol.kind {
+        ObjSymbolKind::Section => false,
+        ObjSymbolKind::Unknown => symbol.size > 0,
+        _ => true,
+    }
+}
+
+/// Collects the symbols referenced by relocations within a symbol, in order of first use.
+fn symbol_references(obj: &ObjInfo, symbol_index: SymbolIndex) -> Vec<SymbolIndex> {
+    let symbol = &obj.symbols[symbol_index];
+    let Some(section_index) = symbol.section else {
+        return vec![];
+    };
+    let start = symbol.address as u32;
+    let end = start + symbol.size as u32;
+    let mut out = Vec::<SymbolIndex>::new();
+    for (_, reloc) in obj.sections[section_index].relocations.range(start..end) {
+        if reloc.target_symbol != symbol_index
+            && reloc.module.is_none()
+            && !out.contains(&reloc.target_symbol)
+        {
+            out.push(reloc.target_symbol);
+        }
+    }
+    out
+}
+
+fn write_link_map_entry<W>(
+    w: &mut W,
+    obj: &ObjInfo,
+    symbol_index: SymbolIndex,
+    depth: usize,
+) -> Result<()>
+where
+    W: Write + ?Sized,
+{
+    let symbol = &obj.symbols[symbol_index];
+    match map_symbol_unit(obj, symbol) {
+        Some(unit) => writeln!(
+            w,
+            "{:width$}{}] {} ({},{}) found in {}",
+            "",
+            depth,
+            symbol.name,
+            map_symbol_kind(symbol),
+            map_symbol_visibility(symbol),
+            unit,
+            width = depth + 1
+        )?,
+        None => writeln!(
+            w,
+            "{:width$}{}] {} found as linker generated symbol",
+            "",
+            depth,
+            symbol.name,
+            width = depth + 1
+        )?,
+    }
+    Ok(())
+}
+
+fn write_link_map<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    let entry = if obj.kind == ObjKind::Executable {
+        obj.entry.and_then(|entry| {
+            let (section_index, _) = obj.sections.at_address(entry as u32).ok()?;
+            obj.symbols
+                .at_section_address(section_index, entry as u32)
+                .find(|(_, s)| s.kind == ObjSymbolKind::Function)
+        })
+    } else {
+        obj.symbols.by_name("_prolog").ok().flatten()
+    };
+    let Some((entry_index, entry_symbol)) = entry else {
+        log::warn!("No entry point symbol found, skipping link map tree");
+        return Ok(());
+    };
+    writeln!(w, "Link map of {}", entry_symbol.name)?;
+
+    // Entry point first, then any force active symbols
+    let roots = std::iter::once(entry_index).chain(
+        obj.symbols
+            .iter_ordered()
+            .filter(|(_, s)| s.flags.is_force_active() && is_map_symbol(s))
+            .map(|(idx, _)| idx),
+    );
+    let mut visited = HashSet::<SymbolIndex>::new();
+    let mut stack = Vec::<(usize, std::vec::IntoIter<SymbolIndex>)>::new();
+    for root in roots {
+        if !visited.insert(root) {
+            continue;
+        }
+        write_link_map_entry(w, obj, root, 1)?;
+        stack.push((1, symbol_references(obj, root).into_iter()));
+        while let Some((depth, iter)) = stack.last_mut() {
+            let depth = *depth + 1;
+            match iter.next() {
+                Some(target) => {
+                    write_link_map_entry(w, obj, target, depth)?;
+                    // Only the first occurrence of a symbol lists its references
+                    if visited.insert(target) {
+                        stack.push((depth, symbol_references(obj, target).into_iter()));
+                    }
+                }
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+    }
+    writeln!(w)?;
+    Ok(())
+}
+
+fn write_section_layout<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    for (section_index, section) in obj.sections.iter() {
+        if section.size == 0 {
+            continue;
+        }
+        writeln!(w, "\n{} section layout", section.name)?;
+        writeln!(w, "  Starting        Virtual  File")?;
+        writeln!(w, "  address  Size   address  offset")?;
+        writeln!(w, "  ---------------------------------")?;
+
+        let section_start = section.address as u32;
+        let section_end = (section.address + section.size) as u32;
+        let file_offset = |address: u32| section.file_offset as u32 + (address - section_start);
+
+        // Determine unit ranges. Anything before the first split is attributed to the object.
+        let mut ranges = Vec::<(u32, u32, Cow<str>, Option<&ObjSplit>)>::new();
+        let mut iter = section.splits.iter().peekable();
+        let first_split = iter.peek().map(|&(addr, _)| addr).unwrap_or(section_end);
+        if first_split > section_start {
+            ranges.push((section_start, first_split, Cow::Borrowed(obj.name.as_str()), None));
+        }
+        while let Some((addr, split)) = iter.next() {
+            let end = if split.end != 0 {
+                split.end
+            } else {
+                iter.peek().map(|&(addr, _)| addr).unwrap_or(section_end)
+            };
+            ranges.push((addr, end, map_unit_name(&split.unit), Some(split)));
+        }
+
+        for (start, end, unit, split) in ranges {
+            let align = match split {
+                Some(split) => split.alignment(obj, section_index, section, start),
+                None => section.align as u32,
+            };
+            writeln!(
+                w,
+                "  {:08x} {:06x} {:08x} {:08x} {:2} {} \t{}",
+                start - section_start,
+                end.saturating_sub(start),
+                start,
+                file_offset(start),
+                align,
+                section.name,
+                unit
+            )?;
+            let default_align = if section.kind == ObjSectionKind::Code { 4 } else { 1 };
+            for (_, symbol) in obj.symbols.for_section_range(section_index, start..end) {
+                if !is_map_symbol(symbol) {
+                    continue;
+                }
+                if symbol.flags.is_stripped() {
+                    writeln!(
+                        w,
+                        "  UNUSED   {:06x} ........ {} {}",
+                        symbol.size, symbol.name, unit
+                    )?;
+                    continue;
+                }
+                let address = symbol.address as u32;
+                writeln!(
+                    w,
+                    "  {:08x} {:06x} {:08x} {:08x} {:2} {} \t{}",
+                    address - section_start,
+                    symbol.size,
+                    address,
+                    file_offset(address),
+                    symbol.align.unwrap_or(default_align),
+                    symbol.name,
+                    unit
+                )?;
+            }
+        }
+    }
+    writeln!(w)?;
+    Ok(())
+}
+
+fn write_memory_map<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    writeln!(w, "\nMemory map:")?;
+    writeln!(w, "                   Starting Size     File")?;
+    writeln!(w, "                   address           Offset")?;
+    for (_, section) in obj.sections.iter() {
+        writeln!(
+            w,
+            "{:>18}  {:08x} {:08x} {:08x}",
+            section.name, section.address, section.size, section.file_offset
+        )?;
+    }
+    Ok(())
+}
+
+fn write_linker_symbols<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    writeln!(w, "\nLinker generated symbols:")?;
+    let symbols = [
+        ("_stack_addr", obj.stack_address),
+        ("_stack_end", obj.stack_end),
+        ("_db_stack_addr", obj.db_stack_addr),
+        ("__ArenaLo", obj.arena_lo),
+        ("__ArenaHi", obj.arena_hi),
+        ("_SDA_BASE_", obj.sda_base),
+        ("_SDA2_BASE_", obj.sda2_base),
+    ];
+    for (name, address) in symbols {
+        if let Some(address) = address {
+            writeln!(w, "{:>25} {:08x}", name, address)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a CodeWarrior-style link map for the given object.
+/// The link map tree is rebuilt from relocations, starting at the entry point.
+pub fn write_map<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    write_link_map(w, obj)?;
+    write_section_layout(w, obj)?;
+    write_memory_map(w, obj)?;
+    write_linker_symbols(w, obj)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(map: &str) -> MapInfo { process_map(&mut map.as_bytes(), None, None).unwrap() }
+
+    fn find_symbol<'a>(info: &'a MapInfo, section: &str, name: &str) -> Option<&'a SymbolEntry> {
+        info.section_symbols.get(section)?.values().flatten().find(|e| e.name == name)
+    }
+
+    fn symbol<'a>(info: &'a MapInfo, section: &str, name: &str) -> &'a SymbolEntry {
+        find_symbol(info, section, name)
+            .unwrap_or_else(|| panic!("Symbol {name} not found in {section}"))
+    }
+
+    fn sections(info: &MapInfo) -> Vec<(&str, u32, u32, u32)> {
+        info.sections.iter().map(|s| (s.name.as_str(), s.address, s.size, s.file_offset)).collect()
+    }
+
+    fn symbol_ref(name: &str, unit: &str) -> SymbolRef {
+        SymbolRef { name: name.to_string(), unit: Some(unit.to_string()) }
+    }
+
+    #[test]
+    fn test_parse_heading() {
+        assert_eq!(
+            parse_heading("Link map of __start"),
+            Some(MapHeading::LinkMap { entry: "__start".to_string() })
+        );
+        assert_eq!(
+            parse_heading(".sdata2 section layout"),
+            Some(MapHeading::SectionLayout { section: ".sdata2".to_string() })
+        );
+        assert_eq!(parse_heading("  Memory map:"), Some(MapHeading::MemoryMap));
+        assert_eq!(
+            parse_heading("Linker generated symbols:"),
+            Some(MapHeading::LinkerGeneratedSymbols)
+        );
+        assert_eq!(parse_heading("Branch Islands"), Some(MapHeading::Ignored));
+        assert_eq!(parse_heading("  00000000 000010 80003100  4 main \tmain.o"), None);
+    }
+
+    #[test]
+    fn test_parse_section_layout_entry() {
+        let entry = parse_section_layout_entry(
+            "  00000020 000010 80003240  4 helper \tmain.o ",
+            SectionLayoutFormat::NoFileOffset,
+        )
+        .unwrap();
+        assert!(!entry.unused);
+        assert_eq!(entry.size, 0x10);
+        assert_eq!(entry.address, Some(0x80003240));
+        assert_eq!(entry.file_offset, None);
+        assert_eq!(entry.align, Some(4));
+        assert_eq!(entry.name, "helper");
+        assert_eq!(entry.unit, "main.o");
+
+        let entry = parse_section_layout_entry(
+            "  00000020 000010 80003240 00000240  4 OSReport \tos.a OSError.o ",
+            SectionLayoutFormat::FileOffset,
+        )
+        .unwrap();
+        assert_eq!(entry.file_offset, Some(0x240));
+        assert_eq!(entry.align, Some(4));
+        assert_eq!(entry.name, "OSReport");
+        assert_eq!(entry.unit, "os.a OSError.o");
+
+        let entry = parse_section_layout_entry(
+            "  00000080 000000 80003180 00000180    __init_hardware (entry of __start) \tcrt0.o ",
+            SectionLayoutFormat::FileOffset,
+        )
+        .unwrap();
+        assert_eq!(entry.align, None);
+        assert_eq!(entry.name, "__init_hardware");
+        assert_eq!(entry.entry_of, Some("__start"));
+        assert_eq!(entry.unit, "crt0.o");
+
+        // UNUSED entries, with and without the file offset column
+        for line in [
+            "  UNUSED   000018 ........ unused_func main.o ",
+            "  UNUSED   000018 ........ ........    unused_func main.o ",
+        ] {
+            let entry = parse_section_layout_entry(line, SectionLayoutFormat::FileOffset).unwrap();
+            assert!(entry.unused);
+            assert_eq!(entry.size, 0x18);
+            assert_eq!(entry.address, None);
+            assert_eq!(entry.file_offset, None);
+            assert_eq!(entry.name, "unused_func");
+            assert_eq!(entry.unit, "main.o");
+        }
+    }
+
+    #[test]
+    fn test_process_map_gc_1x() {
+        let info = parse(include_str!("../../tests/fixtures/map/gc_1.x.map"));
+        assert_eq!(info.entry_point, "__start");
+        assert_eq!(sections(&info), [
+            (".init", 0x80003100, 0x110, 0x100),
+            (".text", 0x80003220, 0x30, 0x220),
+            (".data", 0x80003260, 0x8, 0x260),
+        ]);
+
+        let start = symbol(&info, ".init", "__start");
+        assert_eq!((start.address, start.size, start.align), (0x80003100, 0x100, Some(4)));
+        assert_eq!(start.kind, SymbolKind::Function);
+        assert_eq!(start.visibility, SymbolVisibility::Global);
+        let helper = symbol(&info, ".text", "helper");
+        assert_eq!((helper.address, helper.size), (0x80003240, 0x10));
+        assert_eq!(helper.visibility, SymbolVisibility::Local);
+        assert_eq!(helper.unit.as_deref(), Some("main.o"));
+        let data = symbol(&info, ".data", "gData");
+        assert_eq!((data.address, data.size), (0x80003260, 4));
+        assert_eq!(data.kind, SymbolKind::Object);
+
+        // UNUSED entries are dropped
+        assert!(find_symbol(&info, ".text", "unused_func").is_none());
+
+        assert_eq!(info.section_units[".text"], [(0x80003220, "main.o".to_string())]);
+        let references =
+            info.entry_references.get_vec(&symbol_ref("__start", "__start.o")).unwrap();
+        assert_eq!(references, &[
+            symbol_ref("__init_registers", "__start.o"),
+            symbol_ref("main", "main.o")
+        ]);
+    }
+
+    #[test]
+    fn test_process_map_gc_2x() {
+        let info = parse(include_str!("../../tests/fixtures/map/gc_2.x.map"));
+        assert_eq!(sections(&info), [
+            (".init", 0x80003100, 0x120, 0x100),
+            (".text", 0x80003220, 0x30, 0x220),
+            (".data", 0x80003260, 0x10, 0x260),
+            (".bss", 0x80003280, 0x8, 0x270),
+        ]);
+
+        let entry = symbol(&info, ".init", "__init_hardware");
+        assert_eq!((entry.address, entry.size), (0x80003180, 0));
+        assert_eq!(entry.kind, SymbolKind::Function);
+        assert_eq!(entry.visibility, SymbolVisibility::Global);
+        let report = symbol(&info, ".text", "OSReport");
+        assert_eq!(report.address, 0x80003240);
+        assert_eq!(report.visibility, SymbolVisibility::Weak);
+        assert_eq!(report.unit.as_deref(), Some("os.a OSError.o"));
+        let anon = symbol(&info, ".data", "@12");
+        assert_eq!(anon.visibility, SymbolVisibility::Local);
+        assert_eq!(anon.kind, SymbolKind::Object);
+        assert!(find_symbol(&info, ".text", "unused_func").is_none());
+
+        // Symbols missing from the link map are assumed local
+        let counter = symbol(&info, ".bss", "sCounter");
+        assert_eq!(counter.visibility, SymbolVisibility::Local);
+        assert_eq!(counter.kind, SymbolKind::Object);
+
+        assert_eq!(info.section_units[".text"], [
+            (0x80003220, "main.o".to_string()),
+            (0x80003240, "os.a OSError.o".to_string())
+        ]);
+        let generated = SymbolRef { name: "_stack_addr".to_string(), unit: None };
+        assert_eq!(info.link_map_symbols[&generated].address, 0x80400000);
+        let generated = SymbolRef { name: "_stack_end".to_string(), unit: None };
+        assert_eq!(info.link_map_symbols[&generated].address, 0x803F0000);
+        let generated = SymbolRef { name: "_db_stack_addr".to_string(), unit: None };
+        assert!(!info.link_map_symbols.contains_key(&generated));
+    }
+
+    #[test]
+    fn test_process_map_gc_30_no_link_map() {
+        let info = parse(include_str!("../../tests/fixtures/map/gc_3.0.map"));
+        assert_eq!(info.entry_point, "");
+        assert!(info.link_map_symbols.is_empty());
+        assert_eq!(sections(&info), [
+            (".init", 0x80003100, 0x100, 0x100),
+            (".text", 0x80003200, 0x60, 0x200),
+            (".rodata", 0x80003260, 0x10, 0x260),
+        ]);
+
+        // Visibility is guessed from the symbol names
+        assert_eq!(symbol(&info, ".init", "__start").visibility, SymbolVisibility::Global);
+        assert_eq!(symbol(&info, ".rodata", "gTable").visibility, SymbolVisibility::Global);
+        assert_eq!(symbol(&info, ".rodata", "...rodata.0").visibility, SymbolVisibility::Local);
+        assert_eq!(symbol(&info, ".text", "__sinit_a_cpp").visibility, SymbolVisibility::Local);
+        let inits = info.section_symbols[".text"]
+            .values()
+            .flatten()
+            .filter(|e| e.name == "init")
+            .map(|e| (e.address, e.unit.as_deref(), e.visibility, e.kind))
+            .collect_vec();
+        assert_eq!(inits, [
+            (0x80003200, Some("a.o"), SymbolVisibility::Local, SymbolKind::Function),
+            (0x80003240, Some("b.o"), SymbolVisibility::Local, SymbolKind::Function),
+        ]);
+        assert!(find_symbol(&info, ".text", "unused").is_none());
+    }
+
+    #[test]
+    fn test_process_map_wii() {
+        let info = parse(include_str!("../../tests/fixtures/map/wii.map"));
+        assert_eq!(info.entry_point, "__start");
+        assert_eq!(sections(&info), [
+            (".init", 0x80004000, 0x200, 0x100),
+            (".text", 0x80004200, 0x100, 0x300),
+            (".bss", 0x80004300, 0x18, 0x400),
+        ]);
+
+        // `.comm` marks the start of common BSS
+        assert_eq!(info.common_bss_start, Some(0x80004310));
+        assert!(find_symbol(&info, ".bss", ".comm").is_none());
+        let shared = symbol(&info, ".bss", "gShared");
+        assert_eq!((shared.address, shared.size), (0x80004310, 4));
+        assert_eq!(shared.visibility, SymbolVisibility::Global);
+        assert_eq!(symbol(&info, ".bss", "gOther").address, 0x80004314);
+        assert_eq!(symbol(&info, ".bss", "sState").visibility, SymbolVisibility::Local);
+        assert!(find_symbol(&info, ".bss", "gUnused").is_none());
+
+        assert_eq!(info.section_units[".bss"], [
+            (0x80004300, "main.o".to_string()),
+            (0x80004310, "main.o".to_string())
+        ]);
+        let generated = SymbolRef { name: "_stack_addr".to_string(), unit: None };
+        assert_eq!(info.link_map_symbols[&generated].address, 0x80500000);
+    }
+}
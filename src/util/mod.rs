@@ -3,23 +3,33 @@ use std::{borrow::Cow, ops::Deref};
 pub mod alf;
 pub mod asm;
 pub mod bin2c;
+pub mod bmg;
 pub mod comment;
 pub mod config;
+pub mod debug_info;
 pub mod dep;
 pub mod dol;
 pub mod dwarf;
+pub mod dwarf2;
+pub mod dwarf_headers;
+pub mod dwarf_types;
 pub mod elf;
 pub mod file;
+pub mod gecko;
+pub mod interface;
 pub mod lcf;
 pub mod map;
 pub mod nested;
+pub mod patch;
 pub mod rarc;
 pub mod reader;
 pub mod rel;
 pub mod rso;
 pub mod signatures;
 pub mod split;
+pub mod symbol_map;
 pub mod take_seek;
+pub mod texture;
 pub mod yaz0;
 
 #[inline]
@@ -0,0 +1,365 @@
+use std::collections::{hash_map, HashMap};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    obj::{ObjInfo, ObjRelocKind, ObjSectionKind, ObjSymbol},
+    util::{align_up, rel::patch_relocation},
+};
+
+/// Relocatable objects linked at a fixed address.
+#[derive(Debug, Clone)]
+pub struct LinkedPatch {
+    /// Load address.
+    pub address: u32,
+    /// Size of the code at the start of `data`, aligned to 32 bytes.
+    /// Data and BSS sections follow.
+    pub text_size: u32,
+    /// Linked section data. BSS is included as zeroes.
+    pub data: Vec<u8>,
+    /// Addresses of global symbols defined by the objects.
+    pub symbols: HashMap<String, u32>,
+    /// Applied relocations.
+    pub relocations: Vec<LinkedReloc>,
+}
+
+/// A relocation applied by [`link_patch`].
+#[derive(Debug, Clone)]
+pub struct LinkedReloc {
+    pub address: u32,
+    pub kind: ObjRelocKind,
+    pub target: u32,
+}
+
+impl LinkedPatch {
+    /// Resolves a symbol against the linked objects, falling back to the base object.
+    pub fn resolve(&self, base: &ObjInfo, name: &str) -> Result<u32> {
+        if let Some(&address) = self.symbols.get(name) {
+            return Ok(address);
+        }
+        match base.symbols.by_name(name)? {
+            Some((_, symbol)) => Ok(symbol.address as u32),
+            None => bail!("Failed to resolve symbol '{}'", name),
+        }
+    }
+}
+
+const SECTION_ORDER: [ObjSectionKind; 4] =
+    [ObjSectionKind::Code, ObjSectionKind::Data, ObjSectionKind::ReadOnlyData, ObjSectionKind::Bss];
+
+#[inline]
+fn is_defined(symbol: &ObjSymbol) -> bool { symbol.section.is_some() || symbol.flags.is_common() }
+
+/// Links relocatable objects at `address`, resolving undefined symbols against `base`
+/// (the project's DOL with its symbols applied).
+///
+/// Code sections are placed first, followed by data, read-only data, BSS and common symbols.
+pub fn link_patch(objects: &[ObjInfo], base: &ObjInfo, address: u32) -> Result<LinkedPatch> {
+    // Lay out sections
+    let mut section_addresses =
+        objects.iter().map(|obj| vec![0u32; obj.sections.count()]).collect::<Vec<_>>();
+    let mut data = Vec::<u8>::new();
+    let mut text_size = 0;
+    for kind in SECTION_ORDER {
+        for (obj_index, obj) in objects.iter().enumerate() {
+            for (section_index, section) in obj.sections.iter().filter(|(_, s)| s.kind == kind) {
+                let offset = align_up(data.len() as u32, section.align.max(1) as u32);
+                data.resize(offset as usize, 0);
+                if kind == ObjSectionKind::Bss {
+                    data.resize((offset as u64 + section.size) as usize, 0);
+                } else {
+                    data.extend_from_slice(&section.data);
+                }
+                section_addresses[obj_index][section_index] = address + offset;
+            }
+        }
+        if kind == ObjSectionKind::Code {
+            text_size = align_up(data.len() as u32, 32);
+            data.resize(text_size as usize, 0);
+        }
+    }
+
+    // Define symbols, allocating common symbols at the end
+    let mut symbols = HashMap::<String, u32>::new();
+    let mut commons = HashMap::<String, u32>::new();
+    for (obj_index, obj) in objects.iter().enumerate() {
+        for symbol in obj.symbols.iter().filter(|s| s.flags.is_global()) {
+            let symbol_address = if let Some(section_index) = symbol.section {
+                section_addresses[obj_index][section_index] + symbol.address as u32
+            } else if symbol.flags.is_common() {
+                if let Some(&address) = commons.get(&symbol.name) {
+                    address
+                } else {
+                    // For common symbols, the address is the alignment
+                    let offset = align_up(data.len() as u32, symbol.address.max(1) as u32);
+                    data.resize((offset as u64 + symbol.size) as usize, 0);
+                    commons.insert(symbol.name.clone(), address + offset);
+                    address + offset
+                }
+            } else {
+                continue;
+            };
+            match symbols.entry(symbol.name.clone()) {
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(symbol_address);
+                }
+                hash_map::Entry::Occupied(e) => {
+                    if !symbol.flags.is_weak() && !symbol.flags.is_common() {
+                        if *e.get() != symbol_address {
+                            log::debug!("Symbol {} redefined in {}", symbol.name, obj.name);
+                        }
+                        *e.into_mut() = symbol_address;
+                    }
+                }
+            }
+        }
+    }
+    let mut patch = LinkedPatch { address, text_size, data, symbols, relocations: vec![] };
+
+    // Apply relocations
+    for (obj_index, obj) in objects.iter().enumerate() {
+        for (section_index, section) in obj.sections.iter() {
+            let section_address = section_addresses[obj_index][section_index];
+            for (reloc_address, reloc) in section.relocations.iter() {
+                let target_symbol = &obj.symbols[reloc.target_symbol];
+                let target_address = match target_symbol.section {
+                    // Local symbols (and section symbols) are resolved within the object
+                    Some(target_section) if target_symbol.flags.is_local() => {
+                        section_addresses[obj_index][target_section] + target_symbol.address as u32
+                    }
+                    // Undefined weak symbols resolve to 0
+                    None if target_symbol.flags.is_weak() && !is_defined(target_symbol) => {
+                        patch.resolve(base, &target_symbol.name).unwrap_or(0)
+                    }
+                    _ => patch.resolve(base, &target_symbol.name)?,
+                };
+                let target_address = (target_address as i64 + reloc.addend) as u32;
+                let source_address = section_address + reloc_address;
+                let offset = (source_address - address) as usize;
+                if reloc.kind == ObjRelocKind::PpcEmbSda21 {
+                    bail!(
+                        "Unsupported SDA21 relocation to {} in {} (compile without small data)",
+                        target_symbol.name,
+                        obj.name
+                    );
+                }
+                patch_relocation(
+                    &mut patch.data,
+                    offset,
+                    reloc.kind,
+                    source_address,
+                    target_address,
+                )
+                .with_context(|| {
+                    format!(
+                        "While applying relocation to {} at {:#010X} in {}",
+                        target_symbol.name, source_address, obj.name
+                    )
+                })?;
+                patch.relocations.push(LinkedReloc {
+                    address: source_address,
+                    kind: reloc.kind,
+                    target: target_address,
+                });
+            }
+        }
+    }
+    Ok(patch)
+}
+
+/// Encodes `b target` (or `bl target`) at `address`.
+pub fn branch_instruction(address: u32, target: u32, link: bool) -> Result<u32> {
+    let mut ins = [0u8; 4];
+    ins[0] = 0x48;
+    ins[3] = link as u8;
+    patch_relocation(&mut ins, 0, ObjRelocKind::PpcRel24, address, target)
+        .map_err(|_| anyhow!("Branch from {:#010X} to {:#010X} out of range", address, target))?;
+    Ok(u32::from_be_bytes(ins))
+}
+
+#[cfg(test)]
+mod tests {
+    use flagset::FlagSet;
+
+    use super::*;
+    use crate::obj::{
+        ObjArchitecture, ObjKind, ObjReloc, ObjRelocations, ObjSection, ObjSymbolFlagSet,
+        ObjSymbolFlags,
+    };
+
+    const ADDRESS: u32 = 0x80400000;
+    const DOL_FN: u32 = 0x80003100;
+
+    fn section(
+        name: &str,
+        kind: ObjSectionKind,
+        align: u64,
+        data: Vec<u8>,
+        size: u64,
+    ) -> ObjSection {
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address: 0,
+            size,
+            data,
+            align,
+            elf_index: 0,
+            relocations: Default::default(),
+            original_address: 0,
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    fn symbol(
+        name: &str,
+        section: Option<usize>,
+        address: u64,
+        flags: impl Into<FlagSet<ObjSymbolFlags>>,
+    ) -> ObjSymbol {
+        ObjSymbol {
+            name: name.to_string(),
+            address,
+            section,
+            flags: ObjSymbolFlagSet(flags.into()),
+            ..Default::default()
+        }
+    }
+
+    fn reloc(kind: ObjRelocKind, target_symbol: usize, addend: i64) -> ObjReloc {
+        ObjReloc { kind, target_symbol, addend, module: None }
+    }
+
+    fn object(name: &str, symbols: Vec<ObjSymbol>, sections: Vec<ObjSection>) -> ObjInfo {
+        ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            name.to_string(),
+            symbols,
+            sections,
+        )
+    }
+
+    fn words(data: &[u8]) -> Vec<u32> {
+        data.chunks_exact(4).map(|c| u32::from_be_bytes(c.try_into().unwrap())).collect()
+    }
+
+    /// `a.o` references its own local `helper`, `b.o`'s global `helper`, a weak symbol
+    /// overridden by `b.o`, the DOL, an undefined weak symbol and a shared common symbol.
+    fn objects() -> Vec<ObjInfo> {
+        let global = ObjSymbolFlags::Global;
+        let common = ObjSymbolFlags::Global | ObjSymbolFlags::Common;
+        let weak = ObjSymbolFlags::Global | ObjSymbolFlags::Weak;
+        let mut a_text = section(
+            ".text",
+            ObjSectionKind::Code,
+            4,
+            [0x4E800020u32, 0x48000001].map(u32::to_be_bytes).concat(),
+            8,
+        );
+        a_text.relocations =
+            ObjRelocations::new(vec![(4, reloc(ObjRelocKind::PpcRel24, 3, 0))]).unwrap();
+        let mut a_data = section(".data", ObjSectionKind::Data, 8, vec![0; 0x18], 0x18);
+        a_data.relocations = ObjRelocations::new(vec![
+            (0, reloc(ObjRelocKind::Absolute, 0, 0)),
+            (4, reloc(ObjRelocKind::Absolute, 3, 0)),
+            (8, reloc(ObjRelocKind::Absolute, 2, 0)),
+            (0xC, reloc(ObjRelocKind::Absolute, 4, 0)),
+            (0x10, reloc(ObjRelocKind::Absolute, 5, 0)),
+            (0x14, reloc(ObjRelocKind::Absolute, 6, 4)),
+        ])
+        .unwrap();
+        let a = object(
+            "a.o",
+            vec![
+                symbol("helper", Some(0), 4, ObjSymbolFlags::Local),
+                symbol("func_a", Some(0), 0, global),
+                symbol("weak_fn", Some(0), 4, weak),
+                symbol("func_b", None, 0, global),
+                symbol("dol_fn", None, 0, global),
+                symbol("missing_weak", None, 0, weak),
+                // For common symbols, the address is the alignment
+                ObjSymbol { size: 0x10, ..symbol("common_buf", None, 16, common) },
+            ],
+            vec![a_text, a_data, section(".bss", ObjSectionKind::Bss, 8, vec![], 8)],
+        );
+        let b = object(
+            "b.o",
+            vec![
+                symbol("helper", Some(0), 0, global),
+                symbol("weak_fn", Some(0), 4, global),
+                symbol("func_b", Some(0), 0, global),
+                symbol("rodata_b", Some(1), 0, global),
+                ObjSymbol { size: 0x10, ..symbol("common_buf", None, 16, common) },
+            ],
+            vec![
+                section(
+                    ".text",
+                    ObjSectionKind::Code,
+                    4,
+                    [0x4E800020u32; 2].map(u32::to_be_bytes).concat(),
+                    8,
+                ),
+                section(".rodata", ObjSectionKind::ReadOnlyData, 4, vec![1, 2, 3, 4], 4),
+            ],
+        );
+        vec![a, b]
+    }
+
+    fn base() -> ObjInfo {
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main.dol".to_string(),
+            vec![symbol("dol_fn", None, DOL_FN as u64, ObjSymbolFlags::Global)],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn test_link_patch_layout() {
+        let patch = link_patch(&objects(), &base(), ADDRESS).unwrap();
+        // Code from both objects, padded to 32 bytes
+        assert_eq!(patch.text_size, 0x20);
+        // a.o .data (8-byte aligned), b.o .rodata, a.o .bss, then common_buf (16-byte aligned)
+        assert_eq!(patch.data.len(), 0x60);
+        assert_eq!(&patch.data[0x38..0x3C], &[1, 2, 3, 4]);
+        assert!(patch.data[0x40..].iter().all(|&b| b == 0));
+        assert_eq!(patch.symbols["func_a"], ADDRESS);
+        assert_eq!(patch.symbols["func_b"], ADDRESS + 8);
+        assert_eq!(patch.symbols["rodata_b"], ADDRESS + 0x38);
+        // Allocated once for both objects
+        assert_eq!(patch.symbols["common_buf"], ADDRESS + 0x50);
+    }
+
+    #[test]
+    fn test_link_patch_resolution() {
+        let patch = link_patch(&objects(), &base(), ADDRESS).unwrap();
+        // bl func_b
+        assert_eq!(words(&patch.data[4..8]), [0x48000005]);
+        assert_eq!(words(&patch.data[0x20..0x38]), [
+            // Local helper in a.o, not b.o's global helper
+            ADDRESS + 4,
+            ADDRESS + 8,
+            // b.o's definition overrides the weak one in a.o
+            ADDRESS + 0xC,
+            DOL_FN,
+            // Undefined weak symbol
+            0,
+            ADDRESS + 0x54,
+        ]);
+        assert_eq!(patch.symbols["helper"], ADDRESS + 8);
+        assert_eq!(patch.symbols["weak_fn"], ADDRESS + 0xC);
+        assert!(!patch.symbols.contains_key("missing_weak"));
+        assert_eq!(patch.relocations.len(), 7);
+        assert_eq!(patch.resolve(&base(), "dol_fn").unwrap(), DOL_FN);
+        assert!(patch.resolve(&base(), "missing").is_err());
+
+        // Undefined global symbols are an error
+        let mut objects = objects();
+        objects.truncate(1);
+        assert!(link_patch(&objects, &base(), ADDRESS).is_err());
+    }
+}
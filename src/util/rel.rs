@@ -1,7 +1,8 @@
 use std::{
     cmp::Ordering,
+    collections::BTreeMap,
     io,
-    io::{Read, Seek, SeekFrom, Write},
+    io::{Cursor, Read, Seek, SeekFrom, Write},
 };
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
@@ -10,7 +11,7 @@ use object::{elf, Object, ObjectSection, ObjectSymbol};
 use tracing::warn;
 
 use crate::{
-    array_ref_mut,
+    array_ref, array_ref_mut,
     obj::{
         ObjArchitecture, ObjInfo, ObjKind, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
         ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
@@ -35,7 +36,7 @@ pub const R_DOLPHIN_END: u32 = 203;
 #[allow(unused)]
 pub const R_DOLPHIN_MRKREF: u32 = 204;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RelHeader {
     /// Arbitrary identification number.
     /// Must be unique amongst all RELs used by a game.
@@ -295,7 +296,7 @@ impl ToWriter for RelSectionHeader {
 }
 
 impl RelSectionHeader {
-    fn new(offset: u32, size: u32, exec: bool) -> Self {
+    pub fn new(offset: u32, size: u32, exec: bool) -> Self {
         Self { offset_and_flags: offset | (exec as u32), size }
     }
 
@@ -600,6 +601,526 @@ where R: Read + Seek + ?Sized {
     Ok(())
 }
 
+/// Rewrites the header of a REL in place. If the module ID changed, self imports are
+/// updated to match, and version 1 and 2 imports are re-sorted as [`write_rel`] would.
+pub fn rewrite_rel_header(data: &mut [u8], header: &RelHeader) -> Result<()> {
+    let original = process_rel_header(&mut Cursor::new(&*data))?;
+    ensure!(
+        header.version == original.version,
+        "Changing the REL version ({} -> {}) is not supported",
+        original.version,
+        header.version
+    );
+    let mut header = header.clone();
+    if header.module_id != original.module_id {
+        ensure!(header.module_id != 0, "Module ID 0 is reserved for the DOL");
+        ensure!(
+            header.imp_offset as usize + header.imp_size as usize <= data.len(),
+            "Import table out of bounds"
+        );
+        let imports = (header.imp_offset..header.imp_offset + header.imp_size)
+            .step_by(RelImport::STATIC_SIZE)
+            .collect::<Vec<_>>();
+        let import_module =
+            |data: &[u8], imp: u32| u32::from_be_bytes(*array_ref!(data, imp as usize, 4));
+        ensure!(
+            !imports.iter().any(|&imp| import_module(data, imp) == header.module_id),
+            "Module ID {} is already imported by this module",
+            header.module_id
+        );
+        for &imp in &imports {
+            if import_module(data, imp) == original.module_id {
+                *array_ref_mut!(data, imp as usize, 4) = header.module_id.to_be_bytes();
+            }
+        }
+        if header.version < 3 {
+            sort_rel_imports(data, &mut header)?;
+        }
+    }
+    let header_bytes = header.to_bytes(Endian::Big)?;
+    data[..header_bytes.len()].copy_from_slice(&header_bytes);
+
+    // Round-trip the header to ensure nothing else changed
+    let written = process_rel_header(&mut Cursor::new(&*data))?;
+    ensure!(written == header, "Header round-trip mismatch: {:?} != {:?}", written, header);
+    Ok(())
+}
+
+/// Sorts the imports of a version 1 or 2 REL by module ID, moving their relocation
+/// lists to match. Updates `rel_offset` in the header.
+fn sort_rel_imports(data: &mut [u8], header: &mut RelHeader) -> Result<()> {
+    let mut reader = Cursor::new(&*data);
+    reader.seek(SeekFrom::Start(header.imp_offset as u64))?;
+    let imports = (0..header.imp_size / RelImport::STATIC_SIZE as u32)
+        .map(|_| RelImport::from_reader(&mut reader, Endian::Big))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut lists = Vec::with_capacity(imports.len());
+    for import in &imports {
+        reader.seek(SeekFrom::Start(import.offset as u64))?;
+        while RelRelocRaw::from_reader(&mut reader, Endian::Big)?.kind as u32 != R_DOLPHIN_END {}
+        lists.push((import.offset, reader.stream_position()? as u32));
+    }
+    let (Some(start), Some(end)) =
+        (lists.iter().map(|&(s, _)| s).min(), lists.iter().map(|&(_, e)| e).max())
+    else {
+        return Ok(());
+    };
+    ensure!(
+        lists.iter().map(|&(s, e)| e - s).sum::<u32>() == end - start,
+        "Relocation lists are not contiguous"
+    );
+
+    let mut order = (0..imports.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| imports[i].module_id);
+    let mut relocations = Vec::with_capacity((end - start) as usize);
+    let mut sorted = Vec::with_capacity(imports.len());
+    for i in order {
+        let offset = start + relocations.len() as u32;
+        sorted.push(RelImport { module_id: imports[i].module_id, offset });
+        let (list_start, list_end) = lists[i];
+        relocations.extend_from_slice(&data[list_start as usize..list_end as usize]);
+    }
+    data[start as usize..end as usize].copy_from_slice(&relocations);
+    let mut writer = &mut data[header.imp_offset as usize..];
+    for import in &sorted {
+        import.to_writer(&mut writer, Endian::Big)?;
+    }
+    header.rel_offset = start;
+    Ok(())
+}
+
+/// Severity of a [`RelViolation`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RelSeverity {
+    /// May work depending on how the module is loaded.
+    Warning,
+    /// Rejected or mislinked by `OSLink`.
+    Error,
+}
+
+/// A REL loader constraint violation.
+#[derive(Debug, Clone)]
+pub struct RelViolation {
+    pub severity: RelSeverity,
+    /// File offset of the offending data.
+    pub offset: u32,
+    pub message: String,
+}
+
+/// Checks a REL against the constraints of the runtime loader (`OSLink` / `OSLinkFixed`).
+/// If `load_address` is specified, branches to the DOL are checked for range.
+pub fn verify_rel<R>(reader: &mut R, load_address: Option<u32>) -> Result<Vec<RelViolation>>
+where R: Read + Seek + ?Sized {
+    use RelSeverity::{Error, Warning};
+
+    let file_len = reader.seek(SeekFrom::End(0))? as u32;
+    reader.seek(SeekFrom::Start(0))?;
+    let header = process_rel_header(reader)?;
+    let header_size = header.write_size() as u32;
+    let mut violations = Vec::new();
+    let mut report = |severity: RelSeverity, offset: u32, message: String| {
+        violations.push(RelViolation { severity, offset, message });
+    };
+    // Header
+    if header.version == 0 {
+        report(Error, 0x1C, "Unsupported REL version 0".to_string());
+    }
+    for (offset, name, align) in
+        [(0x40, "align", header.align), (0x44, "bss_align", header.bss_align)]
+    {
+        let Some(align) = align else { continue };
+        if !align.is_power_of_two() {
+            report(Error, offset, format!("{} {:#X} is not a power of two", name, align));
+        } else if align > 32 {
+            report(
+                Warning,
+                offset,
+                format!("{} {:#X} exceeds the 32-byte alignment of OSAlloc", name, align),
+            );
+        }
+    }
+    let table_end = header.section_info_offset as u64
+        + header.num_sections as u64 * RelSectionHeader::STATIC_SIZE as u64;
+    if header.num_sections == 0 || table_end > file_len as u64 {
+        report(
+            Error,
+            0x10,
+            format!(
+                "Section table ({} sections at {:#X}) out of bounds",
+                header.num_sections, header.section_info_offset
+            ),
+        );
+        return Ok(violations);
+    }
+    if header.section_info_offset < header_size {
+        report(Error, 0x10, "Section table overlaps header".to_string());
+    }
+
+    // Sections
+    // OSLink checks the module and BSS alignment from version 2. Version 1 modules are
+    // assumed to be 32-byte aligned, with 8-byte aligned BSS.
+    let guaranteed =
+        |align: Option<u32>, default: u32| align.filter(|a| a.is_power_of_two()).unwrap_or(default);
+    let (align, bss_align) = (guaranteed(header.align, 32), guaranteed(header.bss_align, 8));
+    let sections = process_rel_sections(reader, &header)?;
+    let mut ranges = vec![(0u32, header_size, "header".to_string())];
+    ranges.push((header.section_info_offset, table_end as u32, "section table".to_string()));
+    let mut total_bss_size = 0;
+    let mut data_end = table_end as u32;
+    for (idx, section) in sections.iter().enumerate() {
+        let entry = header.section_info_offset + idx as u32 * RelSectionHeader::STATIC_SIZE as u32;
+        if idx == 0 && (section.offset() != 0 || section.size() != 0) {
+            report(Error, entry, "Section 0 must be empty".to_string());
+        }
+        if section.offset() == 0 {
+            if section.exec() {
+                report(Error, entry, format!("BSS section {} marked executable", idx));
+            }
+            // BSS sections are placed back to back
+            if section.size() != 0 && total_bss_size % bss_align != 0 {
+                report(
+                    Warning,
+                    entry,
+                    format!(
+                        "BSS section {} at BSS offset {:#X} not aligned to {:#X} (bss_align)",
+                        idx, total_bss_size, bss_align
+                    ),
+                );
+            }
+            total_bss_size += section.size();
+            continue;
+        }
+        // Padding before a section reveals the alignment it was written with
+        if section.offset() > data_end {
+            let padded_align =
+                (1..=12).map(|b| 1u32 << b).find(|&a| align_up(data_end, a) == section.offset());
+            if let Some(padded_align) = padded_align.filter(|&a| a > align) {
+                report(
+                    Warning,
+                    entry,
+                    format!(
+                        "Section {} aligned to {:#X}, but the module is only {:#X}-aligned",
+                        idx, padded_align, align
+                    ),
+                );
+            }
+        }
+        let end = section.offset() as u64 + section.size() as u64;
+        if end > file_len as u64 {
+            report(
+                Error,
+                entry,
+                format!("Section {} data ({:#X}..{:#X}) out of bounds", idx, section.offset(), end),
+            );
+            continue;
+        }
+        if section.exec() && section.offset() % 4 != 0 {
+            report(Error, entry, format!("Executable section {} not 4-byte aligned", idx));
+        }
+        ranges.push((section.offset(), end as u32, format!("section {}", idx)));
+        data_end = data_end.max(end as u32);
+    }
+    if total_bss_size != header.bss_size {
+        report(
+            Error,
+            0x20,
+            format!(
+                "BSS size {:#X} != total BSS section size {:#X}",
+                header.bss_size, total_bss_size
+            ),
+        );
+    }
+
+    // Entry points
+    for (offset, name, section, entry_offset) in [
+        (0x30, "_prolog", header.prolog_section, header.prolog_offset),
+        (0x31, "_epilog", header.epilog_section, header.epilog_offset),
+        (0x32, "_unresolved", header.unresolved_section, header.unresolved_offset),
+    ] {
+        if section == 0 {
+            if entry_offset != 0 {
+                report(
+                    Warning,
+                    offset,
+                    format!("{} offset {:#X} without section", name, entry_offset),
+                );
+            }
+            continue;
+        }
+        let Some(rel_section) = sections.get(section as usize) else {
+            report(Error, offset, format!("{} section {} out of range", name, section));
+            continue;
+        };
+        if rel_section.offset() == 0 || !rel_section.exec() {
+            report(Error, offset, format!("{} section {} is not executable", name, section));
+        } else if entry_offset >= rel_section.size() || entry_offset % 4 != 0 {
+            report(
+                Error,
+                offset,
+                format!("{} offset {:#X} invalid for section {}", name, entry_offset, section),
+            );
+        }
+    }
+
+    // Imports
+    if header.imp_size % RelImport::STATIC_SIZE as u32 != 0
+        || header.imp_offset as u64 + header.imp_size as u64 > file_len as u64
+    {
+        report(
+            Error,
+            0x28,
+            format!(
+                "Import table ({:#X} bytes at {:#X}) invalid",
+                header.imp_size, header.imp_offset
+            ),
+        );
+        return Ok(violations);
+    }
+    if header.imp_size > 0 {
+        ranges.push((
+            header.imp_offset,
+            header.imp_offset + header.imp_size,
+            "import table".to_string(),
+        ));
+    }
+    let mut imports = Vec::new();
+    reader.seek(SeekFrom::Start(header.imp_offset as u64))?;
+    for _ in 0..header.imp_size / RelImport::STATIC_SIZE as u32 {
+        imports
+            .push((reader.stream_position()? as u32, RelImport::from_reader(reader, Endian::Big)?));
+    }
+    if let Some((_, first)) = imports.first() {
+        if first.offset != header.rel_offset {
+            report(
+                Error,
+                0x24,
+                format!(
+                    "rel_offset {:#X} != first import offset {:#X}",
+                    header.rel_offset, first.offset
+                ),
+            );
+        }
+    }
+    let is_fixed_import = |module_id: u32| module_id == 0 || module_id == header.module_id;
+    let mut seen_fixed = false;
+    for (i, &(entry, import)) in imports.iter().enumerate() {
+        if imports[..i].iter().any(|(_, other)| other.module_id == import.module_id) {
+            report(Error, entry, format!("Duplicate import of module {}", import.module_id));
+        }
+        if header.version >= 3 {
+            if is_fixed_import(import.module_id) {
+                seen_fixed = true;
+            } else if seen_fixed {
+                report(
+                    Error,
+                    entry,
+                    format!(
+                        "Import of module {} follows DOL/self imports (dropped by OSLinkFixed)",
+                        import.module_id
+                    ),
+                );
+            }
+        }
+    }
+
+    // Relocations
+    let mut reloc_end = header.rel_offset;
+    for &(entry, import) in &imports {
+        if import.offset >= file_len {
+            report(
+                Error,
+                entry,
+                format!("Relocations for module {} out of bounds", import.module_id),
+            );
+            continue;
+        }
+        reader.seek(SeekFrom::Start(import.offset as u64))?;
+        let mut address = 0u32;
+        let mut section: Option<&RelSectionHeader> = None;
+        let mut section_idx = 0u8;
+        loop {
+            let position = reader.stream_position()? as u32;
+            if position as u64 + RelRelocRaw::STATIC_SIZE as u64 > file_len as u64 {
+                report(Error, position, "Relocation table missing R_DOLPHIN_END".to_string());
+                break;
+            }
+            let reloc = RelRelocRaw::from_reader(reader, Endian::Big)?;
+            address += reloc.offset as u32;
+            let (size, align, pc_rel_range) = match reloc.kind as u32 {
+                elf::R_PPC_NONE | R_DOLPHIN_NOP => continue,
+                R_DOLPHIN_SECTION => {
+                    address = 0;
+                    section_idx = reloc.section;
+                    section = sections.get(reloc.section as usize);
+                    match section {
+                        None => report(
+                            Error,
+                            position,
+                            format!("R_DOLPHIN_SECTION with invalid section {}", reloc.section),
+                        ),
+                        Some(s) if s.offset() == 0 => report(
+                            Error,
+                            position,
+                            format!("R_DOLPHIN_SECTION references BSS section {}", reloc.section),
+                        ),
+                        _ => {}
+                    }
+                    continue;
+                }
+                R_DOLPHIN_END => break,
+                elf::R_PPC_ADDR32 => (4, 4, None),
+                elf::R_PPC_ADDR24 => (4, 4, None),
+                elf::R_PPC_ADDR16
+                | elf::R_PPC_ADDR16_LO
+                | elf::R_PPC_ADDR16_HI
+                | elf::R_PPC_ADDR16_HA => (2, 2, None),
+                elf::R_PPC_ADDR14 | elf::R_PPC_ADDR14_BRTAKEN | elf::R_PPC_ADDR14_BRNTAKEN => {
+                    (4, 4, None)
+                }
+                elf::R_PPC_REL24 => (4, 4, Some(0x2000000i64)),
+                elf::R_PPC_REL14 | elf::R_PPC_REL14_BRTAKEN | elf::R_PPC_REL14_BRNTAKEN => {
+                    (4, 4, Some(0x8000i64))
+                }
+                kind => {
+                    report(
+                        Error,
+                        position,
+                        format!("Relocation type {} unsupported by OSLink", kind),
+                    );
+                    continue;
+                }
+            };
+            let Some(source) = section.filter(|s| s.offset() != 0) else {
+                report(Error, position, "Relocation before R_DOLPHIN_SECTION".to_string());
+                continue;
+            };
+            // 16-bit relocations point at the low half of the instruction
+            let field_address = if size == 2 { address } else { address & !3 };
+            if address % align != 0 || field_address + size > source.size() {
+                report(
+                    Error,
+                    position,
+                    format!(
+                        "Relocation at {}:{:#X} invalid for section size {:#X}",
+                        section_idx,
+                        address,
+                        source.size()
+                    ),
+                );
+                continue;
+            }
+            let target_address = if import.module_id == header.module_id {
+                let Some(target) = sections.get(reloc.section as usize).filter(|s| s.size() > 0)
+                else {
+                    report(
+                        Error,
+                        position,
+                        format!("Relocation against invalid section {}", reloc.section),
+                    );
+                    continue;
+                };
+                if reloc.addend > target.size() {
+                    report(
+                        Error,
+                        position,
+                        format!(
+                            "Relocation target {}:{:#X} out of bounds",
+                            reloc.section, reloc.addend
+                        ),
+                    );
+                    continue;
+                }
+                // BSS is placed independently of the module
+                (target.offset() != 0).then(|| {
+                    load_address.unwrap_or(0) as i64 + (target.offset() + reloc.addend) as i64
+                })
+            } else if import.module_id == 0 {
+                load_address.map(|_| reloc.addend as i64)
+            } else {
+                None
+            };
+            if let (Some(range), Some(target_address)) = (pc_rel_range, target_address) {
+                let source_address =
+                    load_address.unwrap_or(0) as i64 + (source.offset() + field_address) as i64;
+                let diff = target_address - source_address;
+                if !(-range..range).contains(&diff) {
+                    report(
+                        Error,
+                        position,
+                        format!(
+                            "Branch at {}:{:#X} out of range ({}{:#X})",
+                            section_idx,
+                            field_address,
+                            if diff < 0 { "-" } else { "" },
+                            diff.unsigned_abs()
+                        ),
+                    );
+                }
+            }
+        }
+        let end = reader.stream_position()? as u32;
+        reloc_end = reloc_end.max(end);
+
+        if let Some(fix_size) = header.fix_size {
+            // Everything after fix_size may be reused once linked with OSLinkFixed
+            if !is_fixed_import(import.module_id) && end > fix_size {
+                report(
+                    Error,
+                    import.offset,
+                    format!(
+                        "Relocations for module {} end at {:#X}, after fix_size {:#X}",
+                        import.module_id, end, fix_size
+                    ),
+                );
+            }
+        }
+    }
+    if header.imp_size > 0 && reloc_end > header.rel_offset {
+        ranges.push((header.rel_offset, reloc_end, "relocations".to_string()));
+    }
+
+    if let Some(fix_size) = header.fix_size {
+        if fix_size > file_len {
+            report(Error, 0x48, format!("fix_size {:#X} beyond end of file", fix_size));
+        }
+        for (start, end, name) in &ranges {
+            if name != "relocations" && *end > fix_size && *start < *end {
+                report(
+                    Error,
+                    *start,
+                    format!(
+                        "{} ({:#X}..{:#X}) would be clobbered after fix_size {:#X}",
+                        name, start, end, fix_size
+                    ),
+                );
+            }
+        }
+        let fixed_import =
+            imports.iter().find(|(_, i)| is_fixed_import(i.module_id)).map(|(_, i)| i.offset);
+        if let Some(offset) = fixed_import.filter(|&offset| offset != fix_size) {
+            report(
+                Warning,
+                0x48,
+                format!(
+                    "fix_size {:#X} != first DOL/self relocation offset {:#X}",
+                    fix_size, offset
+                ),
+            );
+        }
+    }
+
+    // Overlapping data
+    ranges.sort_by_key(|&(start, end, _)| (start, end));
+    for (a, b) in ranges.iter().tuple_windows() {
+        if b.0 < a.1 && a.0 < a.1 && b.0 < b.1 {
+            report(Error, b.0, format!("{} overlaps {}", b.2, a.2));
+        }
+    }
+
+    violations.sort_by_key(|v| v.offset);
+    Ok(violations)
+}
+
 /// REL relocation.
 #[derive(Debug, Clone)]
 pub struct RelReloc {
@@ -640,22 +1161,58 @@ fn apply_relocation(
     rel_reloc: &RelReloc,
     header: &RelHeader,
 ) -> Result<()> {
-    let diff = if rel_reloc.module_id == module_id && rel_reloc.section == rel_reloc.target_section
-    {
-        rel_reloc.addend as i32 - rel_reloc.address as i32
-    } else if header.unresolved_section == rel_reloc.section {
-        header.unresolved_offset as i32 - rel_reloc.address as i32
-    } else {
-        return Ok(());
-    };
-    let ins_ref = array_ref_mut!(data, rel_reloc.address as usize, 4);
-    let mut ins = u32::from_be_bytes(*ins_ref);
+    let target =
+        if rel_reloc.module_id == module_id && rel_reloc.section == rel_reloc.target_section {
+            rel_reloc.addend
+        } else if header.unresolved_section == rel_reloc.section {
+            header.unresolved_offset
+        } else {
+            return Ok(());
+        };
     match rel_reloc.kind {
+        ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14 => patch_relocation(
+            data,
+            rel_reloc.address as usize,
+            rel_reloc.kind,
+            rel_reloc.address,
+            target,
+        ),
+        kind => bail!("Unsupported relocation kind {:?}", kind),
+    }
+}
+
+/// Patches the instruction or word at `data[offset..offset + 4]` as `OSLink` would,
+/// for a relocation at `source_address` resolving to `target_address`.
+pub fn patch_relocation(
+    data: &mut [u8],
+    offset: usize,
+    kind: ObjRelocKind,
+    source_address: u32,
+    target_address: u32,
+) -> Result<()> {
+    ensure!(offset + 4 <= data.len(), "Relocation offset {:#X} out of bounds", offset);
+    let ins_ref = array_ref_mut!(data, offset, 4);
+    let mut ins = u32::from_be_bytes(*ins_ref);
+    match kind {
+        ObjRelocKind::Absolute => {
+            ins = target_address;
+        }
+        ObjRelocKind::PpcAddr16Hi => {
+            ins = (ins & 0xffff0000) | ((target_address >> 16) & 0xffff);
+        }
+        ObjRelocKind::PpcAddr16Ha => {
+            ins = (ins & 0xffff0000) | ((target_address.wrapping_add(0x8000) >> 16) & 0xffff);
+        }
+        ObjRelocKind::PpcAddr16Lo => {
+            ins = (ins & 0xffff0000) | (target_address & 0xffff);
+        }
         ObjRelocKind::PpcRel24 => {
+            let diff = target_address.wrapping_sub(source_address) as i32;
             ensure!((-0x2000000..0x2000000).contains(&diff), "R_PPC_REL24 relocation out of range");
             ins = (ins & !0x3fffffc) | (diff as u32 & 0x3fffffc);
         }
         ObjRelocKind::PpcRel14 => {
+            let diff = target_address.wrapping_sub(source_address) as i32;
             ensure!((-0x2000..0x2000).contains(&diff), "R_PPC_REL14 relocation out of range");
             ins = (ins & !0xfffc) | (diff as u32 & 0xfffc);
         }
@@ -687,7 +1244,11 @@ pub struct RelWriteInfo {
     /// If true, don't print warnings about overriding values.
     pub quiet: bool,
     /// Override individual section alignment in the file.
+    /// Indexed by the order of permitted sections in the REL.
     pub section_align: Option<Vec<u32>>,
+    /// Maps ELF section indices to REL section indices.
+    /// Useful for matching RELs when the ELF is missing sections or orders them differently.
+    pub section_map: Option<BTreeMap<usize, usize>>,
 }
 
 pub const PERMITTED_SECTIONS: [&str; 7] =
@@ -761,9 +1322,57 @@ where
         }
     });
 
+    let mut num_sections = file.sections().count() as u32;
+    if let Some(section_count) = info.section_count {
+        if section_count != num_sections as usize && !info.quiet {
+            warn!(from = num_sections, to = section_count, "Overriding section count");
+        }
+        num_sections = section_count as u32;
+    }
+
+    // Permitted sections in REL section order
+    let rel_section_index = |index: object::SectionIndex| -> Result<usize> {
+        match &info.section_map {
+            Some(map) => map
+                .get(&index.0)
+                .copied()
+                .ok_or_else(|| anyhow!("No REL section index for ELF section {}", index.0)),
+            None => Ok(index.0),
+        }
+    };
+    let mut section_order = vec![None; num_sections as usize];
+    for section in file.sections().filter(is_permitted_section) {
+        if section.size() == 0
+            && info.section_map.as_ref().is_some_and(|map| !map.contains_key(&section.index().0))
+        {
+            // Empty section that isn't present in the original REL
+            continue;
+        }
+        let rel_index = rel_section_index(section.index())?;
+        let name = section.name().unwrap_or("[invalid]");
+        ensure!(
+            rel_index < section_order.len(),
+            "Section {} maps to REL section {}, but the REL has {} sections",
+            name,
+            rel_index,
+            num_sections
+        );
+        ensure!(
+            section_order[rel_index].is_none(),
+            "Multiple sections map to REL section {}",
+            rel_index
+        );
+        section_order[rel_index] = Some(section.index());
+    }
+    let permitted_sections = section_order
+        .iter()
+        .flatten()
+        .map(|&index| file.section_by_index(index))
+        .collect::<object::Result<Vec<_>>>()?;
+
     /// Get the alignment of a section, checking for overrides.
-    /// permitted_section_idx increments whenever a permitted section is encountered,
-    /// rather than being the raw ELF section index.
+    /// permitted_section_idx increments whenever a permitted section is encountered
+    /// in REL section order, rather than being the raw ELF section index.
     fn section_align(
         permitted_section_idx: usize,
         section: &object::Section,
@@ -776,28 +1385,16 @@ where
             .unwrap_or(section.align() as u32)
     }
 
-    let mut align = file
-        .sections()
-        .filter(is_permitted_section)
+    let mut align = permitted_sections
+        .iter()
         .enumerate()
-        .map(|(i, s)| section_align(i, &s, info))
+        .map(|(i, s)| section_align(i, s, info))
         .max()
         .unwrap_or(0);
-    let bss = file
-        .sections()
-        .filter(is_permitted_section)
-        .enumerate()
-        .find(|(_, s)| s.name() == Ok(".bss"));
+    let bss = permitted_sections.iter().enumerate().find(|(_, s)| s.name() == Ok(".bss"));
     let mut bss_align = bss.as_ref().map(|(i, s)| section_align(*i, s, info)).unwrap_or(1);
-    let mut num_sections = file.sections().count() as u32;
 
     // Apply overrides
-    if let Some(section_count) = info.section_count {
-        if section_count != num_sections as usize && !info.quiet {
-            warn!(from = num_sections, to = section_count, "Overriding section count");
-        }
-        num_sections = section_count as u32;
-    }
     if info.version >= 2 {
         if let Some(align_override) = info.align {
             if align_override != align && !info.quiet {
@@ -838,13 +1435,10 @@ where
     header.section_info_offset = offset;
     offset += num_sections * RelSectionHeader::STATIC_SIZE as u32;
     let section_data_offset = offset;
-    for (idx, section) in file
-        .sections()
-        .filter(is_permitted_section)
-        .enumerate()
-        .filter(|(_, s)| should_write_section(s))
+    for (idx, section) in
+        permitted_sections.iter().enumerate().filter(|(_, s)| should_write_section(s))
     {
-        let align = section_align(idx, &section, info) - 1;
+        let align = section_align(idx, section, info) - 1;
         offset = (offset + align) & !align;
         offset += section.size() as u32;
     }
@@ -967,15 +1561,15 @@ where
         };
         match symbol.name() {
             Ok("_prolog") => {
-                header.prolog_section = symbol_section.0 as u8;
+                header.prolog_section = rel_section_index(symbol_section)? as u8;
                 header.prolog_offset = symbol.address() as u32;
             }
             Ok("_epilog") => {
-                header.epilog_section = symbol_section.0 as u8;
+                header.epilog_section = rel_section_index(symbol_section)? as u8;
                 header.epilog_offset = symbol.address() as u32;
             }
             Ok("_unresolved") => {
-                header.unresolved_section = symbol_section.0 as u8;
+                header.unresolved_section = rel_section_index(symbol_section)? as u8;
                 header.unresolved_offset = symbol.address() as u32;
             }
             _ => {}
@@ -986,44 +1580,38 @@ where
     ensure!(w.stream_position()? as u32 == header.section_info_offset);
     let mut current_data_offset = section_data_offset;
     let mut permitted_section_idx = 0;
-    for section_index in 0..num_sections {
-        let Ok(section) = file.section_by_index(object::SectionIndex(section_index as usize))
-        else {
+    for section_index in section_order {
+        let Some(section_index) = section_index else {
             RelSectionHeader::new(0, 0, false).to_writer(w, Endian::Big)?;
             continue;
         };
-        if is_permitted_section(&section) {
-            let mut offset = 0;
-            if should_write_section(&section) {
-                let align = section_align(permitted_section_idx, &section, info) - 1;
-                current_data_offset = (current_data_offset + align) & !align;
-                offset = current_data_offset;
-                current_data_offset += section.size() as u32;
-            }
-            RelSectionHeader::new(
-                offset,
-                section.size() as u32,
-                section.kind() == object::SectionKind::Text,
-            )
-            .to_writer(w, Endian::Big)?;
-            permitted_section_idx += 1;
-        } else {
-            RelSectionHeader::new(0, 0, false).to_writer(w, Endian::Big)?;
+        let section = &permitted_sections[permitted_section_idx];
+        debug_assert_eq!(section.index(), section_index);
+        let mut offset = 0;
+        if should_write_section(section) {
+            let align = section_align(permitted_section_idx, section, info) - 1;
+            current_data_offset = (current_data_offset + align) & !align;
+            offset = current_data_offset;
+            current_data_offset += section.size() as u32;
         }
+        RelSectionHeader::new(
+            offset,
+            section.size() as u32,
+            section.kind() == object::SectionKind::Text,
+        )
+        .to_writer(w, Endian::Big)?;
+        permitted_section_idx += 1;
     }
     ensure!(w.stream_position()? as u32 == section_data_offset);
-    for (idx, section) in file
-        .sections()
-        .filter(is_permitted_section)
-        .enumerate()
-        .filter(|(_, s)| should_write_section(s))
+    for (idx, section) in
+        permitted_sections.iter().enumerate().filter(|(_, s)| should_write_section(s))
     {
         fn calculate_padding(position: u64, align: u64) -> u64 {
             let align = align - 1;
             ((position + align) & !align) - position
         }
         let position = w.stream_position()?;
-        let align = section_align(idx, &section, info);
+        let align = section_align(idx, section, info);
         w.write_all(&vec![0u8; calculate_padding(position, align as u64) as usize])?;
 
         let section_index = section.index().0 as u8;
@@ -1103,3 +1691,398 @@ pub fn update_rel_section_alignment(obj: &mut ObjInfo, header: &RelHeader) -> Re
     }
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use object::{
+        write::{Object as WriteObject, Symbol, SymbolSection},
+        Architecture, BinaryFormat, Endianness, SectionKind, SymbolFlags, SymbolKind, SymbolScope,
+    };
+
+    use super::*;
+
+    const MODULE_ID: u32 = 5;
+    const LOAD_ADDRESS: u32 = 0x80500000;
+
+    /// Builds a relocatable ELF with `.text` (containing `_prolog`, `_epilog` and
+    /// `_unresolved`), `.data` and `.bss`. (ELF sections 1, 2 and 3)
+    fn test_elf() -> Vec<u8> {
+        let mut obj = WriteObject::new(BinaryFormat::Elf, Architecture::PowerPc, Endianness::Big);
+        let text = obj.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        // blr; blr; blr; bl 0
+        let code = [0x4E800020u32, 0x4E800020, 0x4E800020, 0x48000001];
+        obj.append_section_data(text, &code.map(u32::to_be_bytes).concat(), 4);
+        let data = obj.add_section(vec![], b".data".to_vec(), SectionKind::Data);
+        obj.append_section_data(data, &[0; 8], 8);
+        let bss = obj.add_section(vec![], b".bss".to_vec(), SectionKind::UninitializedData);
+        obj.append_section_bss(bss, 0x10, 8);
+        for (name, value) in [("_prolog", 0), ("_epilog", 4), ("_unresolved", 8)] {
+            obj.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size: 4,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Dynamic,
+                weak: false,
+                section: SymbolSection::Section(text),
+                flags: SymbolFlags::None,
+            });
+        }
+        obj.write().unwrap()
+    }
+
+    fn reloc(
+        kind: ObjRelocKind,
+        section: u8,
+        address: u32,
+        module_id: u32,
+        target_section: u8,
+        addend: u32,
+    ) -> RelReloc {
+        RelReloc {
+            kind,
+            section,
+            address,
+            module_id,
+            target_section,
+            addend,
+            original_section: section,
+            original_target_section: target_section,
+        }
+    }
+
+    pub(crate) fn write_test_rel(module_id: u32, version: u32) -> Vec<u8> {
+        let elf = test_elf();
+        let file = object::File::parse(&*elf).unwrap();
+        let info = RelWriteInfo {
+            module_id,
+            version,
+            name_offset: None,
+            name_size: None,
+            align: None,
+            bss_align: None,
+            section_count: None,
+            quiet: true,
+            section_align: None,
+            section_map: None,
+        };
+        let relocations = vec![
+            // Call into the DOL
+            reloc(ObjRelocKind::PpcRel24, 1, 0xC, 0, 0, 0x80003000),
+            // Pointer to _epilog
+            reloc(ObjRelocKind::Absolute, 2, 0, module_id, 1, 4),
+            // Pointer into another module
+            reloc(ObjRelocKind::Absolute, 2, 4, 3, 1, 0x10),
+        ];
+        let mut out = Cursor::new(vec![]);
+        write_rel(&mut out, &info, &file, relocations).unwrap();
+        out.into_inner()
+    }
+
+    fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn verify(data: &[u8], load_address: Option<u32>) -> Vec<RelViolation> {
+        verify_rel(&mut Cursor::new(data), load_address).unwrap()
+    }
+
+    fn find<'a>(violations: &'a [RelViolation], message: &str) -> Option<&'a RelViolation> {
+        violations.iter().find(|v| v.message.contains(message))
+    }
+
+    #[test]
+    fn test_verify_written_rel() {
+        for version in 1..=3 {
+            let data = write_test_rel(MODULE_ID, version);
+            let violations = verify(&data, Some(LOAD_ADDRESS));
+            assert!(violations.is_empty(), "version {}: {:?}", version, violations);
+        }
+    }
+
+    #[test]
+    fn test_verify_bss_align() {
+        let mut data = write_test_rel(MODULE_ID, 2);
+        write_u32(&mut data, 0x44, 0x40);
+        let violations = verify(&data, None);
+        let violation = find(&violations, "exceeds the 32-byte alignment").unwrap();
+        assert_eq!((violation.severity, violation.offset), (RelSeverity::Warning, 0x44));
+
+        write_u32(&mut data, 0x44, 0x18);
+        let violations = verify(&data, None);
+        let violation = find(&violations, "bss_align 0x18 is not a power of two").unwrap();
+        assert_eq!((violation.severity, violation.offset), (RelSeverity::Error, 0x44));
+    }
+
+    #[test]
+    fn test_verify_rel24_range() {
+        let data = write_test_rel(MODULE_ID, 3);
+        // Loaded in MEM2, too far from the DOL
+        let violations = verify(&data, Some(0x93000000));
+        let violation = find(&violations, "Branch at 1:0xC out of range (-0x").unwrap();
+        assert_eq!(violation.severity, RelSeverity::Error);
+        assert_eq!(violations.len(), 1);
+    }
+
+    #[test]
+    fn test_verify_prolog_section() {
+        let mut data = write_test_rel(MODULE_ID, 1);
+        // .data
+        data[0x30] = 2;
+        let violations = verify(&data, None);
+        let violation = find(&violations, "_prolog section 2 is not executable").unwrap();
+        assert_eq!((violation.severity, violation.offset), (RelSeverity::Error, 0x30));
+
+        data[0x30] = 0x40;
+        let violations = verify(&data, None);
+        assert!(find(&violations, "_prolog section 64 out of range").is_some());
+    }
+
+    #[test]
+    fn test_verify_fix_size() {
+        let data = write_test_rel(MODULE_ID, 3);
+        let header = process_rel_header(&mut Cursor::new(&data)).unwrap();
+        // Module 3, then self, then the DOL
+        let imports = (0..3)
+            .map(|i| read_u32(&data, (header.imp_offset + i * 8) as usize))
+            .collect::<Vec<_>>();
+        assert_eq!(imports, [3, MODULE_ID, 0]);
+
+        // Relocations for module 3 would be clobbered
+        let mut clobbered = data.clone();
+        write_u32(&mut clobbered, 0x48, header.rel_offset);
+        let violations = verify(&clobbered, None);
+        let violation = find(&violations, "Relocations for module 3 end at").unwrap();
+        assert_eq!((violation.severity, violation.offset), (RelSeverity::Error, header.rel_offset));
+
+        // Module 3 imported after the DOL, dropped by OSLinkFixed
+        let mut reordered = data;
+        let entries = header.imp_offset as usize..(header.imp_offset + header.imp_size) as usize;
+        reordered[entries.clone()].rotate_left(8);
+        let violations = verify(&reordered, None);
+        let violation = find(&violations, "Import of module 3 follows DOL/self imports").unwrap();
+        assert_eq!(
+            (violation.severity, violation.offset),
+            (RelSeverity::Error, entries.start as u32 + 16)
+        );
+        assert!(find(&violations, "Relocations for module 3 end at").is_none());
+    }
+
+    #[test]
+    fn test_rewrite_rel_header_round_trip() {
+        for version in 1..=3 {
+            let data = write_test_rel(MODULE_ID, version);
+            let header = process_rel_header(&mut Cursor::new(&data)).unwrap();
+            let mut rewritten = data.clone();
+            rewrite_rel_header(&mut rewritten, &header).unwrap();
+            assert_eq!(rewritten, data, "version {}", version);
+
+            if version >= 2 {
+                let mut edited = data.clone();
+                let bss_align = RelHeader { bss_align: Some(0x20), ..header.clone() };
+                rewrite_rel_header(&mut edited, &bss_align).unwrap();
+                assert_eq!(read_u32(&edited, 0x44), 0x20);
+                rewrite_rel_header(&mut edited, &header).unwrap();
+                assert_eq!(edited, data, "version {}", version);
+            }
+
+            // Same as writing with the new module ID. (Imports move for versions 1 and 2)
+            for module_id in [2, 7] {
+                let mut edited = data.clone();
+                rewrite_rel_header(&mut edited, &RelHeader { module_id, ..header.clone() })
+                    .unwrap();
+                let expected = write_test_rel(module_id, version);
+                assert_eq!(edited, expected, "version {}, module {}", version, module_id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rewrite_rel_header_module_id() {
+        let data = write_test_rel(MODULE_ID, 2);
+        let header = process_rel_header(&mut Cursor::new(&data)).unwrap();
+        // DOL and imported module
+        for module_id in [0, 3] {
+            let mut edited = data.clone();
+            let result =
+                rewrite_rel_header(&mut edited, &RelHeader { module_id, ..header.clone() });
+            assert!(result.is_err(), "module {}", module_id);
+        }
+        let mut edited = data;
+        let version = RelHeader { version: 3, fix_size: Some(0), ..header };
+        assert!(rewrite_rel_header(&mut edited, &version).is_err());
+    }
+
+    /// Rebuilds a relocatable ELF from a processed REL, naming sections by kind.
+    fn rebuild_elf(obj: &ObjInfo) -> Vec<u8> {
+        let mut out = WriteObject::new(BinaryFormat::Elf, Architecture::PowerPc, Endianness::Big);
+        let mut section_ids = BTreeMap::new();
+        for (section_index, section) in obj.sections.iter() {
+            let (name, kind) = match section.kind {
+                ObjSectionKind::Code => (".text", SectionKind::Text),
+                ObjSectionKind::Data | ObjSectionKind::ReadOnlyData => (".data", SectionKind::Data),
+                ObjSectionKind::Bss => (".bss", SectionKind::UninitializedData),
+            };
+            let id = out.add_section(vec![], name.as_bytes().to_vec(), kind);
+            if section.kind == ObjSectionKind::Bss {
+                out.append_section_bss(id, section.size, section.align);
+            } else {
+                out.append_section_data(id, &section.data, section.align);
+            }
+            section_ids.insert(section_index, id);
+        }
+        for symbol in obj.symbols.iter() {
+            out.add_symbol(Symbol {
+                name: symbol.name.as_bytes().to_vec(),
+                value: symbol.address,
+                size: 4,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Dynamic,
+                weak: false,
+                section: SymbolSection::Section(section_ids[&symbol.section.unwrap()]),
+                flags: SymbolFlags::None,
+            });
+        }
+        out.write().unwrap()
+    }
+
+    #[test]
+    fn test_write_rel_round_trip() {
+        for version in 1..=3 {
+            let data = write_test_rel(MODULE_ID, version);
+            let (header, mut obj) = process_rel(&mut Cursor::new(&data), "test").unwrap();
+            for (_, section) in obj.sections.iter_mut() {
+                if section.kind == ObjSectionKind::Bss && section.align == 0 {
+                    // Version 1 RELs don't record BSS alignment
+                    section.align = default_section_align(section);
+                }
+            }
+            update_rel_section_alignment(&mut obj, &header).unwrap();
+
+            let elf = rebuild_elf(&obj);
+            let file = object::File::parse(&*elf).unwrap();
+            let info = RelWriteInfo {
+                module_id: header.module_id,
+                version: header.version,
+                name_offset: Some(header.name_offset),
+                name_size: Some(header.name_size),
+                align: header.align,
+                bss_align: header.bss_align,
+                section_count: Some(header.num_sections as usize),
+                quiet: true,
+                section_align: None,
+                section_map: None,
+            };
+            let mut out = Cursor::new(vec![]);
+            write_rel(&mut out, &info, &file, obj.unresolved_relocations.clone()).unwrap();
+            assert_eq!(out.into_inner(), data, "version {}", version);
+        }
+    }
+
+    #[test]
+    fn test_write_rel_section_map() {
+        let elf = test_elf();
+        let file = object::File::parse(&*elf).unwrap();
+        // .text, .bss and .data go in REL sections 1, 2 and 4
+        let info = RelWriteInfo {
+            module_id: MODULE_ID,
+            version: 2,
+            name_offset: None,
+            name_size: None,
+            align: None,
+            bss_align: None,
+            section_count: Some(5),
+            quiet: true,
+            section_align: None,
+            section_map: Some(BTreeMap::from([(1, 1), (2, 4), (3, 2)])),
+        };
+        // Pointer into another module from .data
+        let relocations = vec![RelReloc {
+            original_section: 2,
+            ..reloc(ObjRelocKind::Absolute, 4, 4, 3, 1, 0x10)
+        }];
+        let mut out = Cursor::new(vec![]);
+        write_rel(&mut out, &info, &file, relocations).unwrap();
+        let data = out.into_inner();
+
+        let header = process_rel_header(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(header.num_sections, 5);
+        assert_eq!(header.bss_size, 0x10);
+        assert_eq!((header.prolog_section, header.epilog_section), (1, 1));
+        let sections = process_rel_sections(&mut Cursor::new(&data), &header).unwrap();
+        let layout = sections.iter().map(|s| (s.offset() != 0, s.size(), s.exec())).collect_vec();
+        assert_eq!(layout, [
+            (false, 0, false),
+            (true, 0x10, true),
+            (false, 0x10, false),
+            (false, 0, false),
+            (true, 8, false),
+        ]);
+
+        let (_, obj) = process_rel(&mut Cursor::new(&data), "test").unwrap();
+        let reloc = &obj.unresolved_relocations[0];
+        assert_eq!((reloc.section, reloc.address, reloc.module_id), (4, 4, 3));
+        assert!(verify(&data, Some(LOAD_ADDRESS)).is_empty());
+    }
+
+    #[test]
+    fn test_verify_section_align() {
+        let elf = test_elf();
+        let file = object::File::parse(&*elf).unwrap();
+        // .data is padded to 64 bytes, more than the module alignment guarantees
+        let write = |version: u32, align: Option<u32>| {
+            let info = RelWriteInfo {
+                module_id: MODULE_ID,
+                version,
+                name_offset: None,
+                name_size: None,
+                align,
+                bss_align: None,
+                section_count: None,
+                quiet: true,
+                section_align: Some(vec![4, 64, 8]),
+                section_map: None,
+            };
+            let mut out = Cursor::new(vec![]);
+            write_rel(&mut out, &info, &file, vec![]).unwrap();
+            out.into_inner()
+        };
+        let data_entry = |data: &[u8]| {
+            process_rel_header(&mut Cursor::new(data)).unwrap().section_info_offset + 2 * 8
+        };
+        for (version, align) in [(1, None), (2, Some(32))] {
+            let data = write(version, align);
+            let violations = verify(&data, None);
+            let violation =
+                find(&violations, "Section 2 aligned to 0x40, but the module is only 0x20-aligned")
+                    .unwrap();
+            assert_eq!(
+                (violation.severity, violation.offset),
+                (RelSeverity::Warning, data_entry(&data))
+            );
+        }
+        // Guaranteed by the header alignment
+        assert!(find(&verify(&write(2, None), None), "Section 2 aligned").is_none());
+
+        // A second BSS section that isn't 8-byte aligned within the BSS (version 1)
+        let mut data = write_test_rel(MODULE_ID, 1);
+        let header = process_rel_header(&mut Cursor::new(&data)).unwrap();
+        let bss_entry = header.section_info_offset as usize + 3 * 8;
+        write_u32(&mut data, bss_entry + 4, 0xC);
+        let next_entry = header.section_info_offset as usize + 4 * 8;
+        assert_eq!(read_u32(&data, next_entry + 4), 0);
+        write_u32(&mut data, next_entry, 0);
+        write_u32(&mut data, next_entry + 4, 4);
+        let violations = verify(&data, None);
+        let violation =
+            find(&violations, "BSS section 4 at BSS offset 0xC not aligned to 0x8").unwrap();
+        assert_eq!(
+            (violation.severity, violation.offset),
+            (RelSeverity::Warning, next_entry as u32)
+        );
+    }
+}
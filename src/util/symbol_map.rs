@@ -0,0 +1,459 @@
+use std::{
+    ffi::OsStr,
+    io::{BufRead, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use argp::FromArgValue;
+use cwdemangle::{demangle, DemangleOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    obj::{ObjInfo, ObjKind, ObjSectionKind, ObjSymbol, ObjSymbolKind},
+    util::{config::is_skip_symbol, file::map_file},
+};
+
+/// Symbol map formats used by other tools.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SymbolMapFormat {
+    /// Dolphin's "Generate Symbol Map" output
+    Dolphin,
+    /// `name,address[,size[,type[,section]]]`, with optional header row (IDA, Ghidra)
+    Csv,
+    /// Array of `{ "name", "address", "size", "type", "section" }` objects
+    Json,
+}
+
+impl FromStr for SymbolMapFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "dolphin" | "map" => Self::Dolphin,
+            "csv" => Self::Csv,
+            "json" => Self::Json,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl FromArgValue for SymbolMapFormat {
+    fn from_arg_value(value: &OsStr) -> Result<Self, String> {
+        String::from_arg_value(value)
+            .and_then(|s| Self::from_str(&s).map_err(|_| "Invalid symbol map format".to_string()))
+    }
+}
+
+impl SymbolMapFormat {
+    /// Guesses the format from the file extension.
+    pub fn from_path<P>(path: P) -> Option<Self>
+    where P: AsRef<Path> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        Self::from_str(&ext).ok()
+    }
+}
+
+/// A symbol read from an external symbol map.
+#[derive(Debug, Clone)]
+pub struct SymbolMapEntry {
+    pub name: String,
+    pub address: u32,
+    pub size: Option<u32>,
+    pub kind: ObjSymbolKind,
+    pub section: Option<String>,
+}
+
+pub fn read_symbol_map<R>(reader: &mut R, format: SymbolMapFormat) -> Result<Vec<SymbolMapEntry>>
+where R: BufRead + ?Sized {
+    match format {
+        SymbolMapFormat::Dolphin => read_dolphin_map(reader),
+        SymbolMapFormat::Csv => read_csv(reader),
+        SymbolMapFormat::Json => read_json(reader),
+    }
+}
+
+/// Reads a symbol map and merges it into the object. Returns the number of symbols added.
+pub fn apply_symbol_map_file<P>(
+    path: P,
+    obj: &mut ObjInfo,
+    format: Option<SymbolMapFormat>,
+    replace: bool,
+) -> Result<usize>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let Some(format) = format.or_else(|| SymbolMapFormat::from_path(path)) else {
+        bail!("Unknown symbol map format for '{}'", path.display());
+    };
+    let file = map_file(path)?;
+    let entries = read_symbol_map(&mut file.as_reader(), format)
+        .with_context(|| format!("Failed to read symbol map '{}'", path.display()))?;
+    apply_symbol_map(obj, &entries, replace)
+}
+
+pub fn apply_symbol_map(
+    obj: &mut ObjInfo,
+    entries: &[SymbolMapEntry],
+    replace: bool,
+) -> Result<usize> {
+    let mut count = 0;
+    for entry in entries {
+        let section = if let Some(name) = &entry.section {
+            obj.sections.by_name(name)?
+        } else if obj.kind == ObjKind::Executable {
+            obj.sections.at_address(entry.address).ok()
+        } else {
+            None
+        };
+        let Some((section_index, section_kind)) = section.map(|(idx, s)| (idx, s.kind)) else {
+            log::warn!("Skipping symbol {} @ {:#010X}: no section", entry.name, entry.address);
+            continue;
+        };
+        let kind = match entry.kind {
+            ObjSymbolKind::Unknown if section_kind == ObjSectionKind::Code => {
+                ObjSymbolKind::Function
+            }
+            ObjSymbolKind::Unknown => ObjSymbolKind::Object,
+            kind => kind,
+        };
+        obj.add_symbol(
+            ObjSymbol {
+                name: entry.name.clone(),
+                demangled_name: demangle(&entry.name, &DemangleOptions::default()),
+                address: entry.address as u64,
+                section: Some(section_index),
+                size: entry.size.unwrap_or(0) as u64,
+                size_known: entry.size.is_some(),
+                kind,
+                ..Default::default()
+            },
+            replace,
+        )?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub fn write_symbol_map<W>(w: &mut W, obj: &ObjInfo, format: SymbolMapFormat) -> Result<()>
+where W: Write + ?Sized {
+    match format {
+        SymbolMapFormat::Dolphin => write_dolphin_map(w, obj),
+        SymbolMapFormat::Csv => write_csv(w, obj),
+        SymbolMapFormat::Json => write_json(w, obj),
+    }
+}
+
+/// Symbols worth sharing with other tools: sized functions and objects.
+fn exported_symbols(obj: &ObjInfo) -> impl Iterator<Item = &ObjSymbol> {
+    obj.symbols.iter_ordered().map(|(_, s)| s).filter(|s| {
+        s.section.is_some()
+            && matches!(s.kind, ObjSymbolKind::Function | ObjSymbolKind::Object)
+            && !is_skip_symbol(s)
+    })
+}
+
+fn symbol_unit<'a>(obj: &'a ObjInfo, symbol: &ObjSymbol) -> Option<&'a str> {
+    let section = &obj.sections[symbol.section?];
+    section.splits.for_address(symbol.address as u32).map(|(_, split)| split.unit.as_str())
+}
+
+fn parse_kind(s: &str) -> ObjSymbolKind {
+    let s = s.to_ascii_lowercase();
+    if s.contains("func") || s == "code" {
+        ObjSymbolKind::Function
+    } else if s.contains("data") || s.contains("object") {
+        ObjSymbolKind::Object
+    } else {
+        ObjSymbolKind::Unknown
+    }
+}
+
+#[inline]
+fn kind_str(kind: ObjSymbolKind) -> &'static str {
+    match kind {
+        ObjSymbolKind::Function => "function",
+        ObjSymbolKind::Object => "object",
+        _ => "label",
+    }
+}
+
+/// Parses an address as hex, allowing a `0x` or Ghidra-style `ram:` prefix.
+fn parse_address(s: &str) -> Option<u32> {
+    let s = s.trim();
+    let s = s.rsplit_once(':').map(|(_, s)| s).unwrap_or(s);
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+    u32::from_str_radix(s, 16).ok()
+}
+
+/// Parses a size as decimal, or hex with a `0x` prefix.
+fn parse_size(s: &str) -> Option<u32> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn read_dolphin_map<R>(reader: &mut R) -> Result<Vec<SymbolMapEntry>>
+where R: BufRead + ?Sized {
+    let mut entries = vec![];
+    let mut current_section: Option<String> = None;
+    for line in reader.lines() {
+        let line = line?;
+        let trimmed = line.trim();
+        if trimmed.is_empty()
+            || trimmed.starts_with("Starting")
+            || trimmed.starts_with("address")
+            || trimmed.starts_with("---")
+        {
+            continue;
+        }
+        if trimmed == "Memory map:" {
+            break;
+        }
+        if let Some(section) = trimmed.strip_suffix(" section layout") {
+            current_section = Some(section.to_string());
+            continue;
+        }
+
+        // `address size virtual_address [align] name [\tobject]`
+        let columns = trimmed.split_once('\t').map(|(c, _)| c).unwrap_or(trimmed);
+        let tokens = columns.split_whitespace().collect::<Vec<_>>();
+        if tokens.len() < 4 {
+            bail!("Unexpected line in Dolphin map: '{line}'");
+        }
+        let (Ok(size), Ok(address)) =
+            (u32::from_str_radix(tokens[1], 16), u32::from_str_radix(tokens[2], 16))
+        else {
+            bail!("Unexpected line in Dolphin map: '{line}'");
+        };
+        let name_start =
+            if tokens.len() > 4 && tokens[3].chars().all(|c| c.is_ascii_digit()) { 4 } else { 3 };
+        let name = tokens[name_start..].join(" ");
+        // Names generated by Dolphin's function scanner
+        if name.starts_with("zz_") {
+            continue;
+        }
+        let kind = match current_section.as_deref() {
+            Some(".text" | ".init") => ObjSymbolKind::Function,
+            Some(_) => ObjSymbolKind::Object,
+            None => ObjSymbolKind::Unknown,
+        };
+        entries.push(SymbolMapEntry {
+            name,
+            address,
+            size: (size != 0).then_some(size),
+            kind,
+            section: None,
+        });
+    }
+    Ok(entries)
+}
+
+fn write_dolphin_map<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    for (header, kind) in [(".text", ObjSymbolKind::Function), (".data", ObjSymbolKind::Object)] {
+        writeln!(w, "{} section layout", header)?;
+        for symbol in exported_symbols(obj).filter(|s| s.kind == kind) {
+            write!(
+                w,
+                "{:08x} {:06x} {:08x} {} {}",
+                symbol.address, symbol.size, symbol.address, 0, symbol.name
+            )?;
+            if let Some(unit) = symbol_unit(obj, symbol) {
+                write!(w, " \t{}", unit)?;
+            }
+            writeln!(w)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Splits a CSV line, handling quoted fields.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct CsvColumns {
+    name: usize,
+    address: usize,
+    size: Option<usize>,
+    kind: Option<usize>,
+    section: Option<usize>,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        Self { name: 0, address: 1, size: Some(2), kind: Some(3), section: Some(4) }
+    }
+}
+
+impl CsvColumns {
+    fn from_header(fields: &[String]) -> Option<Self> {
+        let find = |names: &[&str]| {
+            fields.iter().position(|f| names.contains(&f.trim().to_ascii_lowercase().as_str()))
+        };
+        Some(Self {
+            name: find(&["name", "symbol", "label"])?,
+            address: find(&["address", "location", "addr", "start"])?,
+            size: find(&["size", "length"]),
+            kind: find(&["type", "kind"]),
+            section: find(&["section", "segment", "block"]),
+        })
+    }
+}
+
+fn read_csv<R>(reader: &mut R) -> Result<Vec<SymbolMapEntry>>
+where R: BufRead + ?Sized {
+    let mut entries = vec![];
+    let mut columns: Option<CsvColumns> = None;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_csv_line(&line);
+        let columns = match columns {
+            Some(columns) => columns,
+            None => {
+                // The first row is either a header or a symbol
+                let header = CsvColumns::from_header(&fields);
+                columns = Some(header.unwrap_or_default());
+                if header.is_some() {
+                    continue;
+                }
+                CsvColumns::default()
+            }
+        };
+        let field = |idx: Option<usize>| idx.and_then(|i| fields.get(i)).map(|s| s.trim());
+        let (Some(name), Some(address)) = (field(Some(columns.name)), field(Some(columns.address)))
+        else {
+            bail!("Unexpected line in CSV: '{line}'");
+        };
+        let Some(address) = parse_address(address) else {
+            // e.g. external symbols in Ghidra exports
+            log::debug!("Skipping symbol {} with address '{}'", name, address);
+            continue;
+        };
+        entries.push(SymbolMapEntry {
+            name: name.to_string(),
+            address,
+            size: field(columns.size).and_then(parse_size).filter(|&s| s != 0),
+            kind: field(columns.kind).map(parse_kind).unwrap_or(ObjSymbolKind::Unknown),
+            section: field(columns.section).filter(|s| !s.is_empty()).map(str::to_string),
+        });
+    }
+    Ok(entries)
+}
+
+fn write_csv<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    writeln!(w, "name,address,size,type,section")?;
+    for symbol in exported_symbols(obj) {
+        let section = &obj.sections[symbol.section.unwrap()];
+        writeln!(
+            w,
+            "{},{:#010X},{:#X},{},{}",
+            csv_field(&symbol.name),
+            symbol.address,
+            symbol.size,
+            kind_str(symbol.kind),
+            csv_field(&section.name)
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonNumber {
+    Int(u32),
+    Str(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSymbol {
+    name: String,
+    #[serde(alias = "addr", alias = "location", alias = "start")]
+    address: JsonNumber,
+    #[serde(default, alias = "length", skip_serializing_if = "Option::is_none")]
+    size: Option<JsonNumber>,
+    #[serde(default, rename = "type", alias = "kind", skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section: Option<String>,
+}
+
+fn read_json<R>(reader: &mut R) -> Result<Vec<SymbolMapEntry>>
+where R: BufRead + ?Sized {
+    let symbols: Vec<JsonSymbol> = serde_json::from_reader(reader)?;
+    let mut entries = Vec::with_capacity(symbols.len());
+    for symbol in symbols {
+        let address = match &symbol.address {
+            JsonNumber::Int(address) => *address,
+            JsonNumber::Str(s) => match parse_address(s) {
+                Some(address) => address,
+                None => bail!("Invalid address '{}' for symbol {}", s, symbol.name),
+            },
+        };
+        let size = match &symbol.size {
+            Some(JsonNumber::Int(size)) => Some(*size),
+            Some(JsonNumber::Str(s)) => parse_size(s),
+            None => None,
+        };
+        entries.push(SymbolMapEntry {
+            name: symbol.name,
+            address,
+            size: size.filter(|&s| s != 0),
+            kind: symbol.kind.as_deref().map(parse_kind).unwrap_or(ObjSymbolKind::Unknown),
+            section: symbol.section,
+        });
+    }
+    Ok(entries)
+}
+
+fn write_json<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    let symbols = exported_symbols(obj)
+        .map(|symbol| JsonSymbol {
+            name: symbol.name.clone(),
+            address: JsonNumber::Str(format!("{:#010X}", symbol.address)),
+            size: Some(JsonNumber::Int(symbol.size as u32)),
+            kind: Some(kind_str(symbol.kind).to_string()),
+            section: Some(obj.sections[symbol.section.unwrap()].name.clone()),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_writer_pretty(&mut *w, &symbols)?;
+    writeln!(w)?;
+    Ok(())
+}
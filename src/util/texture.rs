@@ -0,0 +1,519 @@
+use std::io::{Cursor, Seek, SeekFrom, Write};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use num_enum::TryFromPrimitive;
+
+use crate::util::reader::{Endian, FromReader};
+
+pub const TPL_MAGIC: u32 = 0x0020AF30;
+
+/// GX texture formats.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, TryFromPrimitive)]
+#[repr(u32)]
+pub enum GXTexFmt {
+    I4 = 0x0,
+    I8 = 0x1,
+    IA4 = 0x2,
+    IA8 = 0x3,
+    RGB565 = 0x4,
+    RGB5A3 = 0x5,
+    RGBA8 = 0x6,
+    C4 = 0x8,
+    C8 = 0x9,
+    C14X2 = 0xA,
+    CMPR = 0xE,
+}
+
+/// GX palette (TLUT) formats.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, TryFromPrimitive)]
+#[repr(u32)]
+pub enum GXTlutFmt {
+    IA8 = 0x0,
+    RGB565 = 0x1,
+    RGB5A3 = 0x2,
+}
+
+impl GXTexFmt {
+    /// Block dimensions in pixels.
+    fn block_size(self) -> (u32, u32) {
+        match self {
+            GXTexFmt::I4 | GXTexFmt::C4 | GXTexFmt::CMPR => (8, 8),
+            GXTexFmt::I8 | GXTexFmt::IA4 | GXTexFmt::C8 => (8, 4),
+            _ => (4, 4),
+        }
+    }
+
+    /// Size of a single block in bytes.
+    fn block_bytes(self) -> usize {
+        match self {
+            GXTexFmt::RGBA8 => 64,
+            _ => 32,
+        }
+    }
+
+    /// Size of the texture data in bytes.
+    pub fn data_size(self, width: u32, height: u32) -> usize {
+        let (bw, bh) = self.block_size();
+        let blocks = ((width + bw - 1) / bw) as usize * ((height + bh - 1) / bh) as usize;
+        blocks * self.block_bytes()
+    }
+
+    fn is_indexed(self) -> bool { matches!(self, GXTexFmt::C4 | GXTexFmt::C8 | GXTexFmt::C14X2) }
+}
+
+/// Decoded RGBA8 image.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+type Color = [u8; 4];
+
+#[inline]
+fn expand4(v: u16) -> u8 { (v as u8 & 0xF) * 0x11 }
+
+#[inline]
+fn expand5(v: u16) -> u8 {
+    let v = v as u8 & 0x1F;
+    (v << 3) | (v >> 2)
+}
+
+#[inline]
+fn expand6(v: u16) -> u8 {
+    let v = v as u8 & 0x3F;
+    (v << 2) | (v >> 4)
+}
+
+#[inline]
+fn expand3(v: u16) -> u8 {
+    let v = v as u8 & 0x7;
+    (v << 5) | (v << 2) | (v >> 1)
+}
+
+fn rgb565(v: u16) -> Color { [expand5(v >> 11), expand6(v >> 5), expand5(v), 0xFF] }
+
+fn rgb5a3(v: u16) -> Color {
+    if v & 0x8000 != 0 {
+        [expand5(v >> 10), expand5(v >> 5), expand5(v), 0xFF]
+    } else {
+        [expand4(v >> 8), expand4(v >> 4), expand4(v), expand3(v >> 12)]
+    }
+}
+
+fn ia8(v: u16) -> Color {
+    let i = v as u8;
+    [i, i, i, (v >> 8) as u8]
+}
+
+#[inline]
+fn be16(data: &[u8], offset: usize) -> u16 { u16::from_be_bytes([data[offset], data[offset + 1]]) }
+
+/// Decodes a GX palette into RGBA colors.
+pub fn decode_palette(data: &[u8], format: GXTlutFmt, count: usize) -> Result<Vec<Color>> {
+    ensure!(data.len() >= count * 2, "Palette data too short");
+    Ok((0..count)
+        .map(|i| {
+            let v = be16(data, i * 2);
+            match format {
+                GXTlutFmt::IA8 => ia8(v),
+                GXTlutFmt::RGB565 => rgb565(v),
+                GXTlutFmt::RGB5A3 => rgb5a3(v),
+            }
+        })
+        .collect())
+}
+
+fn decode_cmpr_block(data: &[u8], out: &mut [Color; 16]) {
+    let c0 = be16(data, 0);
+    let c1 = be16(data, 2);
+    let a = rgb565(c0);
+    let b = rgb565(c1);
+    let mix = |wa: u16, wb: u16| -> Color {
+        let c = |i: usize| ((a[i] as u16 * wa + b[i] as u16 * wb) / (wa + wb)) as u8;
+        [c(0), c(1), c(2), 0xFF]
+    };
+    let colors = if c0 > c1 { [a, b, mix(2, 1), mix(1, 2)] } else { [a, b, mix(1, 1), [0; 4]] };
+    for (y, &row) in data[4..8].iter().enumerate() {
+        for x in 0..4 {
+            out[y * 4 + x] = colors[((row >> (6 - x * 2)) & 3) as usize];
+        }
+    }
+}
+
+/// Decodes GX texture data into an RGBA8 image. Indexed formats require a palette.
+pub fn decode_texture(
+    data: &[u8],
+    format: GXTexFmt,
+    width: u32,
+    height: u32,
+    palette: Option<&[Color]>,
+) -> Result<Image> {
+    ensure!(width > 0 && height > 0, "Invalid texture dimensions {}x{}", width, height);
+    let size = format.data_size(width, height);
+    ensure!(
+        data.len() >= size,
+        "Texture data too short: {:#X} < {:#X} ({:?} {}x{})",
+        data.len(),
+        size,
+        format,
+        width,
+        height
+    );
+    let palette = match palette {
+        Some(palette) => palette,
+        None if format.is_indexed() => bail!("Texture format {:?} requires a palette", format),
+        None => &[],
+    };
+    let lookup = |index: usize| palette.get(index).copied().unwrap_or_default();
+
+    let (bw, bh) = format.block_size();
+    let mut rgba = vec![0u8; width as usize * height as usize * 4];
+    let mut offset = 0usize;
+    let mut block = [[0u8; 4]; 64];
+    for by in (0..height).step_by(bh as usize) {
+        for bx in (0..width).step_by(bw as usize) {
+            let src = &data[offset..offset + format.block_bytes()];
+            offset += format.block_bytes();
+            let pixels = (bw * bh) as usize;
+            match format {
+                GXTexFmt::I4 | GXTexFmt::C4 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        let v = (src[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0xF;
+                        *px =
+                            if format == GXTexFmt::C4 { lookup(v as usize) } else { [v * 0x11; 4] };
+                    }
+                }
+                GXTexFmt::I8 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        *px = [src[i]; 4];
+                    }
+                }
+                GXTexFmt::C8 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        *px = lookup(src[i] as usize);
+                    }
+                }
+                GXTexFmt::IA4 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        let v = src[i] as u16;
+                        let l = expand4(v);
+                        *px = [l, l, l, expand4(v >> 4)];
+                    }
+                }
+                GXTexFmt::IA8 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        *px = ia8(be16(src, i * 2));
+                    }
+                }
+                GXTexFmt::RGB565 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        *px = rgb565(be16(src, i * 2));
+                    }
+                }
+                GXTexFmt::RGB5A3 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        *px = rgb5a3(be16(src, i * 2));
+                    }
+                }
+                GXTexFmt::C14X2 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        *px = lookup((be16(src, i * 2) & 0x3FFF) as usize);
+                    }
+                }
+                GXTexFmt::RGBA8 => {
+                    for (i, px) in block.iter_mut().take(pixels).enumerate() {
+                        *px = [src[i * 2 + 1], src[32 + i * 2], src[32 + i * 2 + 1], src[i * 2]];
+                    }
+                }
+                GXTexFmt::CMPR => {
+                    // 2x2 DXT1 sub-blocks
+                    let mut sub = [[0u8; 4]; 16];
+                    for s in 0..4 {
+                        decode_cmpr_block(&src[s * 8..s * 8 + 8], &mut sub);
+                        let (sx, sy) = ((s % 2) * 4, (s / 2) * 4);
+                        for y in 0..4 {
+                            for x in 0..4 {
+                                block[(sy + y) * 8 + sx + x] = sub[y * 4 + x];
+                            }
+                        }
+                    }
+                }
+            }
+            for py in 0..bh {
+                for px in 0..bw {
+                    let (x, y) = (bx + px, by + py);
+                    if x >= width || y >= height {
+                        continue;
+                    }
+                    let dst = (y * width + x) as usize * 4;
+                    rgba[dst..dst + 4].copy_from_slice(&block[(py * bw + px) as usize]);
+                }
+            }
+        }
+    }
+    Ok(Image { width, height, rgba })
+}
+
+fn sub_slice(data: &[u8], offset: u32) -> Result<&[u8]> {
+    data.get(offset as usize..).ok_or_else(|| anyhow!("Offset {:#X} out of bounds", offset))
+}
+
+fn read_palette(data: &[u8], format: u32, count: u32, offset: u32) -> Result<Vec<Color>> {
+    let format =
+        GXTlutFmt::try_from(format).map_err(|_| anyhow!("Unknown palette format {}", format))?;
+    decode_palette(sub_slice(data, offset)?, format, count as usize)
+}
+
+/// Decodes all images in a TPL file.
+pub fn read_tpl(data: &[u8]) -> Result<Vec<Image>> {
+    let mut reader = Cursor::new(data);
+    let e = Endian::Big;
+    let magic = u32::from_reader(&mut reader, e)?;
+    ensure!(magic == TPL_MAGIC, "Invalid TPL magic: {:#010X}", magic);
+    let count = u32::from_reader(&mut reader, e)?;
+    let table_offset = u32::from_reader(&mut reader, e)?;
+    // The count isn't trusted for an allocation hint, the table is validated as it's read
+    let mut images = vec![];
+    for i in 0..count {
+        reader.seek(SeekFrom::Start(table_offset as u64 + i as u64 * 8))?;
+        let image_offset = u32::from_reader(&mut reader, e)?;
+        let palette_offset = u32::from_reader(&mut reader, e)?;
+
+        let palette = if palette_offset != 0 {
+            reader.seek(SeekFrom::Start(palette_offset as u64))?;
+            let count = u16::from_reader(&mut reader, e)?;
+            reader.seek(SeekFrom::Current(2))?; // unpacked, padding
+            let format = u32::from_reader(&mut reader, e)?;
+            let offset = u32::from_reader(&mut reader, e)?;
+            Some(read_palette(data, format, count as u32, offset)?)
+        } else {
+            None
+        };
+
+        reader.seek(SeekFrom::Start(image_offset as u64))?;
+        let height = u16::from_reader(&mut reader, e)?;
+        let width = u16::from_reader(&mut reader, e)?;
+        let format = u32::from_reader(&mut reader, e)?;
+        let offset = u32::from_reader(&mut reader, e)?;
+        let format =
+            GXTexFmt::try_from(format).map_err(|_| anyhow!("Unknown texture format {}", format))?;
+        let image = decode_texture(
+            sub_slice(data, offset)?,
+            format,
+            width as u32,
+            height as u32,
+            palette.as_deref(),
+        )
+        .with_context(|| format!("While decoding TPL image {}", i))?;
+        images.push(image);
+    }
+    Ok(images)
+}
+
+/// Decodes the first mip level of a BTI texture.
+pub fn read_bti(data: &[u8]) -> Result<Image> {
+    let mut reader = Cursor::new(data);
+    let e = Endian::Big;
+    let format = u8::from_reader(&mut reader, e)? as u32;
+    reader.seek(SeekFrom::Current(1))?; // alpha
+    let width = u16::from_reader(&mut reader, e)?;
+    let height = u16::from_reader(&mut reader, e)?;
+    reader.seek(SeekFrom::Current(3))?; // wrap_s, wrap_t, palettes_enabled
+    let palette_format = u8::from_reader(&mut reader, e)? as u32;
+    let palette_count = u16::from_reader(&mut reader, e)?;
+    let palette_offset = u32::from_reader(&mut reader, e)?;
+    reader.seek(SeekFrom::Start(0x1C))?;
+    let image_offset = u32::from_reader(&mut reader, e)?;
+
+    let format =
+        GXTexFmt::try_from(format).map_err(|_| anyhow!("Unknown texture format {}", format))?;
+    let palette = if format.is_indexed() {
+        Some(read_palette(data, palette_format, palette_count as u32, palette_offset)?)
+    } else {
+        None
+    };
+    decode_texture(
+        sub_slice(data, image_offset)?,
+        format,
+        width as u32,
+        height as u32,
+        palette.as_deref(),
+    )
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Encodes an image as an RGBA8 PNG.
+pub fn encode_png(image: &Image) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&image.width.to_be_bytes());
+    ihdr.extend_from_slice(&image.height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]); // 8-bit RGBA, no interlace
+    write_png_chunk(&mut out, b"IHDR", &ihdr);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in image.rgba.chunks_exact(image.width as usize * 4) {
+        encoder.write_all(&[0])?; // filter: none
+        encoder.write_all(row)?;
+    }
+    write_png_chunk(&mut out, b"IDAT", &encoder.finish()?);
+    write_png_chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(image: &Image, x: u32, y: u32) -> Color {
+        let offset = (y * image.width + x) as usize * 4;
+        image.rgba[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_decode_i4() {
+        let mut data = [0u8; 32];
+        data[0] = 0x1F;
+        data[4] = 0x80; // second row
+        let image = decode_texture(&data, GXTexFmt::I4, 8, 8, None).unwrap();
+        assert_eq!(pixel(&image, 0, 0), [0x11; 4]);
+        assert_eq!(pixel(&image, 1, 0), [0xFF; 4]);
+        assert_eq!(pixel(&image, 0, 1), [0x88; 4]);
+        assert_eq!(pixel(&image, 7, 7), [0; 4]);
+    }
+
+    #[test]
+    fn test_decode_ia8() {
+        let mut data = [0u8; 32];
+        data[0..2].copy_from_slice(&[0x80, 0xFF]);
+        data[30..32].copy_from_slice(&[0xFF, 0x40]);
+        let image = decode_texture(&data, GXTexFmt::IA8, 4, 4, None).unwrap();
+        assert_eq!(pixel(&image, 0, 0), [0xFF, 0xFF, 0xFF, 0x80]);
+        assert_eq!(pixel(&image, 3, 3), [0x40, 0x40, 0x40, 0xFF]);
+    }
+
+    #[test]
+    fn test_decode_rgb5a3() {
+        let mut data = [0u8; 32];
+        data[0..2].copy_from_slice(&0xFC00u16.to_be_bytes()); // opaque RGB555
+        data[2..4].copy_from_slice(&0x3F00u16.to_be_bytes()); // translucent ARGB3444
+        let image = decode_texture(&data, GXTexFmt::RGB5A3, 4, 4, None).unwrap();
+        assert_eq!(pixel(&image, 0, 0), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(&image, 1, 0), [0xFF, 0x00, 0x00, 0x6D]);
+    }
+
+    #[test]
+    fn test_decode_rgba8() {
+        // AR pairs are followed by GB pairs in the second half of the block
+        let mut data = [0u8; 64];
+        data[0..2].copy_from_slice(&[0x40, 0x10]);
+        data[32..34].copy_from_slice(&[0x20, 0x30]);
+        let image = decode_texture(&data, GXTexFmt::RGBA8, 4, 4, None).unwrap();
+        assert_eq!(pixel(&image, 0, 0), [0x10, 0x20, 0x30, 0x40]);
+        assert_eq!(pixel(&image, 1, 0), [0; 4]);
+    }
+
+    #[test]
+    fn test_decode_cmpr() {
+        let mut data = [0u8; 32];
+        data[0..2].copy_from_slice(&0xF800u16.to_be_bytes());
+        data[2..4].copy_from_slice(&0x001Fu16.to_be_bytes());
+        data[4] = 0b00_01_10_11;
+        // Second sub-block: c0 <= c1, index 3 is transparent
+        data[12] = 0xFF;
+        let image = decode_texture(&data, GXTexFmt::CMPR, 8, 8, None).unwrap();
+        assert_eq!(pixel(&image, 0, 0), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(&image, 1, 0), [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(pixel(&image, 2, 0), [0xAA, 0x00, 0x55, 0xFF]);
+        assert_eq!(pixel(&image, 3, 0), [0x55, 0x00, 0xAA, 0xFF]);
+        assert_eq!(pixel(&image, 4, 0), [0; 4]);
+        assert_eq!(pixel(&image, 4, 1), [0x00, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_decode_c8() {
+        let palette = decode_palette(&[0xFC, 0x00, 0x80, 0x1F], GXTlutFmt::RGB5A3, 2).unwrap();
+        let mut data = [0u8; 32];
+        data[0] = 1;
+        data[8] = 2; // out of range
+        let image = decode_texture(&data, GXTexFmt::C8, 8, 4, Some(&palette)).unwrap();
+        assert_eq!(pixel(&image, 0, 0), [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(pixel(&image, 1, 0), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(&image, 0, 1), [0; 4]);
+        assert!(decode_texture(&data, GXTexFmt::C8, 8, 4, None).is_err());
+    }
+
+    #[test]
+    fn test_read_tpl() {
+        let mut data = vec![0u8; 0x60];
+        data[0x00..0x04].copy_from_slice(&TPL_MAGIC.to_be_bytes());
+        data[0x04..0x08].copy_from_slice(&1u32.to_be_bytes());
+        data[0x08..0x0C].copy_from_slice(&0xCu32.to_be_bytes());
+        // Image table
+        data[0x0C..0x10].copy_from_slice(&0x14u32.to_be_bytes());
+        data[0x10..0x14].copy_from_slice(&0x24u32.to_be_bytes());
+        // Image header
+        data[0x14..0x16].copy_from_slice(&4u16.to_be_bytes());
+        data[0x16..0x18].copy_from_slice(&8u16.to_be_bytes());
+        data[0x18..0x1C].copy_from_slice(&(GXTexFmt::C8 as u32).to_be_bytes());
+        data[0x1C..0x20].copy_from_slice(&0x40u32.to_be_bytes());
+        // Palette header
+        data[0x24..0x26].copy_from_slice(&2u16.to_be_bytes());
+        data[0x28..0x2C].copy_from_slice(&(GXTlutFmt::IA8 as u32).to_be_bytes());
+        data[0x2C..0x30].copy_from_slice(&0x30u32.to_be_bytes());
+        data[0x30..0x34].copy_from_slice(&[0xFF, 0x00, 0x80, 0x40]);
+        data[0x40] = 1;
+
+        let images = read_tpl(&data).unwrap();
+        assert_eq!(images.len(), 1);
+        let image = &images[0];
+        assert_eq!((image.width, image.height), (8, 4));
+        assert_eq!(pixel(image, 0, 0), [0x40, 0x40, 0x40, 0x80]);
+        assert_eq!(pixel(image, 1, 0), [0x00, 0x00, 0x00, 0xFF]);
+
+        // A huge image count fails on the truncated table
+        data[0x04..0x08].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(read_tpl(&data).is_err());
+    }
+
+    #[test]
+    fn test_read_bti() {
+        let mut data = vec![0u8; 0x40];
+        data[0x00] = GXTexFmt::IA8 as u8;
+        data[0x02..0x04].copy_from_slice(&4u16.to_be_bytes());
+        data[0x04..0x06].copy_from_slice(&4u16.to_be_bytes());
+        data[0x1C..0x20].copy_from_slice(&0x20u32.to_be_bytes());
+        data[0x20..0x22].copy_from_slice(&[0x80, 0xFF]);
+        let image = read_bti(&data).unwrap();
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(pixel(&image, 0, 0), [0xFF, 0xFF, 0xFF, 0x80]);
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let image = Image { width: 1, height: 1, rgba: vec![0; 4] };
+        let png = encode_png(&image).unwrap();
+        assert_eq!(png[..8], *b"\x89PNG\r\n\x1a\n");
+        // IHDR of a 1x1 RGBA8 image
+        assert_eq!(png[8..33], [
+            0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R', 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F, 0x15, 0xC4, 0x89
+        ]);
+        assert_eq!(png[png.len() - 12..], [
+            0x00, 0x00, 0x00, 0x00, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82
+        ]);
+    }
+}